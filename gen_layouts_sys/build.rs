@@ -16,6 +16,8 @@ const KEY_LAYOUTS_HEADER: &'static str = include_str!("keylayouts.h");
 #[cfg(feature = "generate")]
 const N_ASCII_CHARS_SUPPORTED: usize = 96;
 #[cfg(feature = "generate")]
+const N_ISO_8859_1_CHARS_SUPPORTED: usize = 96;
+#[cfg(feature = "generate")]
 const N_NUMPAD_KEYS: usize = 10;
 
 #[cfg(feature = "generate")]
@@ -118,6 +120,24 @@ fn generate() {
                 N_ASCII_CHARS_SUPPORTED
             );
 
+            // 0 for the characters the layout can't type, all of them when it has no table
+            let mut iso_8859_1_keycodes = extract_iso_8859_1_keycodes(&layout)
+                .iter()
+                .map(|k| k & keycode_mask)
+                .collect::<Vec<u16>>();
+            if iso_8859_1_keycodes.is_empty() {
+                iso_8859_1_keycodes = vec![0; N_ISO_8859_1_CHARS_SUPPORTED];
+            }
+
+            assert_eq!(
+                N_ISO_8859_1_CHARS_SUPPORTED,
+                iso_8859_1_keycodes.len(),
+                "Not enough ISO-8859-1 keycodes extracted from {}: {}/{}",
+                def,
+                iso_8859_1_keycodes.len(),
+                N_ISO_8859_1_CHARS_SUPPORTED
+            );
+
             let layout_key = def.to_string();
             let quote_alt_mask = quote_option(alt_mask);
             let quote_ctrl_mask = quote_option(ctrl_mask);
@@ -147,6 +167,7 @@ fn generate() {
                         #quote_dead_keys_mask,
                         #keycode_mask,
                         vec![#(#keycodes),*],
+                        vec![#(#iso_8859_1_keycodes),*],
                         DeadKeys::new(
                             #quote_acute_accent_bits,
                             #quote_deadkey_accute_accent,
@@ -237,6 +258,15 @@ fn extract_ascii_keycodes(definitions: &syn::File) -> Vec<u16> {
         .collect()
 }
 
+#[cfg(feature = "generate")]
+fn extract_iso_8859_1_keycodes(definitions: &syn::File) -> Vec<u16> {
+    definitions
+        .items
+        .iter()
+        .filter_map(|item| find_const_u16_with_name_containing(item, "ISO_8859_1_"))
+        .collect()
+}
+
 #[cfg(feature = "generate")]
 fn extract_layout_masks(definitions: &syn::File, layout: &str) -> LayoutMasks {
     LayoutMasks {
@@ -255,7 +285,7 @@ fn extract_layout_masks(definitions: &syn::File, layout: &str) -> LayoutMasks {
 fn extract_layout_deadkeys(definitions: &syn::File) -> LayoutDeadKeys {
     LayoutDeadKeys {
         acute_accent_bits: find_key_definition(definitions, "ACUTE_ACCENT_BITS"),
-        deadkey_accute_accent: find_key_definition(definitions, "DEADKEY_ACCUTE_ACCENT"),
+        deadkey_accute_accent: find_key_definition(definitions, "DEADKEY_ACUTE_ACCENT"),
         cedilla_bits: find_key_definition(definitions, "CEDILLA_BITS"),
         deadkey_cedilla: find_key_definition(definitions, "DEADKEY_CEDILLA"),
         diaeresis_bits: find_key_definition(definitions, "DIAERESIS_BITS"),
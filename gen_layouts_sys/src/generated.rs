@@ -36,6 +36,16 @@ lazy_static! {
                     15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16, 25u16,
                     26u16, 27u16, 28u16, 29u16, 111u16, 113u16, 112u16, 117u16, 42u16,
                 ],
+                vec![
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16,
+                ],
                 DeadKeys::new(
                     None, None, None, None, None, None, None, None, None, None, None, None,
                 ),
@@ -61,9 +71,21 @@ lazy_static! {
                     15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16, 25u16,
                     26u16, 27u16, 28u16, 29u16, 180u16, 117u16, 177u16, 179u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 161u16, 160u16, 162u16, 0u16, 164u16, 146u16, 0u16, 0u16, 0u16,
+                    63u16, 163u16, 183u16, 0u16, 182u16, 191u16, 158u16, 165u16, 166u16, 0u16,
+                    144u16, 147u16, 0u16, 0u16, 0u16, 0u16, 127u16, 167u16, 173u16, 174u16, 0u16,
+                    836u16, 580u16, 324u16, 0u16, 1092u16, 0u16, 0u16, 1350u16, 840u16, 120u16,
+                    328u16, 1096u16, 844u16, 588u16, 332u16, 1100u16, 0u16, 0u16, 850u16, 594u16,
+                    338u16, 0u16, 1106u16, 0u16, 0u16, 856u16, 600u16, 344u16, 1048u16, 604u16,
+                    0u16, 0u16, 772u16, 516u16, 260u16, 0u16, 1028u16, 0u16, 0u16, 1286u16, 776u16,
+                    56u16, 264u16, 1032u16, 780u16, 524u16, 268u16, 1036u16, 0u16, 0u16, 786u16,
+                    530u16, 274u16, 0u16, 1042u16, 0u16, 0u16, 792u16, 536u16, 280u16, 1048u16,
+                    540u16, 0u16, 1052u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61624u16),
                     Some(1280u16),
                     Some(61488u16),
                     Some(1024u16),
@@ -97,9 +119,22 @@ lazy_static! {
                     15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16, 25u16,
                     26u16, 27u16, 28u16, 29u16, 164u16, 181u16, 165u16, 2096u16, 42u16,
                 ],
+                vec![
+                    172u16, 2142u16, 2054u16, 2144u16, 2145u16, 2140u16, 2175u16, 2134u16, 556u16,
+                    2118u16, 2121u16, 157u16, 174u16, 2165u16, 2133u16, 0u16, 179u16, 2150u16,
+                    2079u16, 2080u16, 812u16, 2064u16, 2069u16, 0u16, 1068u16, 2078u16, 2128u16,
+                    155u16, 2081u16, 2082u16, 2083u16, 2157u16, 113u16, 836u16, 324u16, 1604u16,
+                    580u16, 1860u16, 2116u16, 112u16, 116u16, 120u16, 328u16, 584u16, 1356u16,
+                    844u16, 332u16, 588u16, 2119u16, 1617u16, 1362u16, 850u16, 338u16, 1618u16,
+                    594u16, 2166u16, 2130u16, 127u16, 856u16, 344u16, 536u16, 860u16, 2131u16,
+                    2070u16, 49u16, 772u16, 260u16, 1540u16, 516u16, 1796u16, 2052u16, 48u16,
+                    52u16, 56u16, 264u16, 520u16, 1292u16, 780u16, 268u16, 524u16, 2055u16,
+                    1553u16, 1298u16, 786u16, 274u16, 1554u16, 530u16, 2167u16, 2066u16, 63u16,
+                    792u16, 280u16, 536u16, 796u16, 2067u16, 540u16,
+                ],
                 DeadKeys::new(
                     Some(768u16),
-                    None,
+                    Some(63539u16),
                     Some(1024u16),
                     Some(63534u16),
                     Some(512u16),
@@ -133,9 +168,21 @@ lazy_static! {
                     14u16, 15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 26u16, 27u16, 28u16, 29u16, 164u16, 174u16, 167u16, 1068u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 0u16, 160u16, 97u16, 0u16, 0u16, 117u16, 1324u16, 0u16, 0u16,
+                    33u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 556u16, 144u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 53u16, 0u16, 0u16, 836u16, 580u16, 324u16,
+                    1092u16, 1348u16, 111u16, 115u16, 0u16, 840u16, 584u16, 328u16, 1352u16,
+                    844u16, 588u16, 332u16, 1356u16, 199u16, 1105u16, 850u16, 594u16, 338u16,
+                    1106u16, 1362u16, 0u16, 116u16, 856u16, 600u16, 344u16, 1368u16, 604u16,
+                    215u16, 150u16, 772u16, 516u16, 260u16, 1028u16, 1284u16, 47u16, 51u16, 0u16,
+                    776u16, 520u16, 264u16, 1288u16, 780u16, 524u16, 268u16, 1292u16, 135u16,
+                    1041u16, 786u16, 530u16, 274u16, 1042u16, 1298u16, 0u16, 52u16, 792u16, 536u16,
+                    280u16, 1304u16, 540u16, 151u16, 1308u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61486u16),
                     None,
                     None,
                     Some(1280u16),
@@ -169,9 +216,21 @@ lazy_static! {
                     14u16, 15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 26u16, 27u16, 28u16, 29u16, 164u16, 191u16, 167u16, 1068u16, 42u16,
                 ],
+                vec![
+                    172u16, 222u16, 0u16, 160u16, 97u16, 0u16, 0u16, 53u16, 1324u16, 0u16, 0u16,
+                    225u16, 0u16, 184u16, 0u16, 0u16, 231u16, 0u16, 0u16, 0u16, 556u16, 144u16,
+                    0u16, 219u16, 0u16, 0u16, 0u16, 224u16, 0u16, 117u16, 0u16, 237u16, 836u16,
+                    580u16, 324u16, 1092u16, 116u16, 111u16, 244u16, 0u16, 840u16, 584u16, 328u16,
+                    1352u16, 844u16, 588u16, 332u16, 1356u16, 199u16, 1105u16, 850u16, 594u16,
+                    338u16, 1106u16, 115u16, 155u16, 243u16, 856u16, 600u16, 344u16, 1368u16,
+                    604u16, 215u16, 150u16, 772u16, 516u16, 260u16, 1028u16, 52u16, 47u16, 180u16,
+                    0u16, 776u16, 520u16, 264u16, 1288u16, 780u16, 524u16, 268u16, 1292u16, 135u16,
+                    1041u16, 786u16, 530u16, 274u16, 1042u16, 51u16, 0u16, 179u16, 792u16, 536u16,
+                    280u16, 1304u16, 540u16, 151u16, 1308u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61486u16),
                     None,
                     None,
                     Some(1280u16),
@@ -205,6 +264,17 @@ lazy_static! {
                     14u16, 15u16, 51u16, 17u16, 18u16, 19u16, 4u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 29u16, 27u16, 28u16, 26u16, 161u16, 163u16, 174u16, 1068u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 0u16, 112u16, 176u16, 0u16, 0u16, 120u16, 812u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 109u16, 0u16, 53u16, 0u16, 0u16, 113u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 596u16, 0u16, 340u16,
+                    1108u16, 852u16, 0u16, 0u16, 0u16, 584u16, 0u16, 328u16, 840u16, 588u16, 0u16,
+                    332u16, 844u16, 0u16, 1105u16, 594u16, 0u16, 338u16, 1106u16, 850u16, 0u16,
+                    0u16, 600u16, 0u16, 344u16, 792u16, 0u16, 0u16, 0u16, 39u16, 0u16, 276u16,
+                    1044u16, 788u16, 0u16, 0u16, 38u16, 36u16, 31u16, 264u16, 776u16, 524u16, 0u16,
+                    268u16, 780u16, 0u16, 1041u16, 530u16, 0u16, 274u16, 1042u16, 786u16, 0u16,
+                    0u16, 51u16, 0u16, 280u16, 792u16, 0u16, 0u16, 796u16,
+                ],
                 DeadKeys::new(
                     None,
                     None,
@@ -242,9 +312,21 @@ lazy_static! {
                     24u16, 25u16, 29u16, 27u16, 28u16, 26u16, 166u16, 158u16, 167u16, 1068u16,
                     42u16,
                 ],
+                vec![
+                    44u16, 0u16, 0u16, 113u16, 0u16, 0u16, 0u16, 35u16, 812u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 109u16, 0u16, 53u16, 117u16, 1324u16, 49u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 596u16, 1364u16, 340u16,
+                    1108u16, 852u16, 0u16, 0u16, 0u16, 584u16, 1352u16, 328u16, 840u16, 588u16,
+                    1356u16, 332u16, 844u16, 0u16, 1105u16, 594u16, 1362u16, 338u16, 1106u16,
+                    850u16, 0u16, 0u16, 600u16, 1368u16, 344u16, 792u16, 1372u16, 0u16, 0u16,
+                    39u16, 1300u16, 276u16, 1044u16, 788u16, 0u16, 0u16, 38u16, 36u16, 31u16,
+                    264u16, 776u16, 524u16, 1292u16, 268u16, 780u16, 0u16, 1041u16, 530u16,
+                    1298u16, 274u16, 1042u16, 786u16, 0u16, 0u16, 52u16, 1304u16, 280u16, 792u16,
+                    1308u16, 0u16, 796u16,
+                ],
                 DeadKeys::new(
                     Some(1280u16),
-                    None,
+                    Some(61620u16),
                     None,
                     None,
                     Some(768u16),
@@ -278,9 +360,21 @@ lazy_static! {
                     14u16, 15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 26u16, 27u16, 29u16, 28u16, 180u16, 164u16, 177u16, 1068u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 165u16, 113u16, 0u16, 0u16, 158u16, 162u16, 812u16, 0u16, 0u16,
+                    0u16, 163u16, 0u16, 0u16, 0u16, 161u16, 0u16, 0u16, 0u16, 1324u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 580u16, 1348u16, 324u16,
+                    1092u16, 836u16, 0u16, 0u16, 0u16, 584u16, 1352u16, 328u16, 840u16, 588u16,
+                    1356u16, 332u16, 844u16, 0u16, 1105u16, 594u16, 1362u16, 338u16, 1106u16,
+                    850u16, 0u16, 0u16, 600u16, 1368u16, 344u16, 856u16, 1372u16, 0u16, 0u16,
+                    52u16, 1284u16, 260u16, 1028u16, 116u16, 0u16, 0u16, 97u16, 47u16, 51u16,
+                    264u16, 776u16, 524u16, 1292u16, 268u16, 780u16, 0u16, 1041u16, 530u16,
+                    1298u16, 274u16, 1042u16, 115u16, 0u16, 0u16, 536u16, 1304u16, 280u16, 111u16,
+                    1308u16, 0u16, 796u16,
+                ],
                 DeadKeys::new(
                     Some(1280u16),
-                    None,
+                    Some(61613u16),
                     None,
                     None,
                     Some(768u16),
@@ -315,9 +409,20 @@ lazy_static! {
                     24u16, 25u16, 26u16, 27u16, 29u16, 28u16, 164u16, 191u16, 167u16, 176u16,
                     42u16,
                 ],
+                vec![
+                    44u16, 0u16, 0u16, 0u16, 136u16, 0u16, 0u16, 96u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 117u16, 0u16, 159u16, 160u16, 0u16, 144u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 1348u16, 1092u16, 836u16, 0u16,
+                    116u16, 0u16, 0u16, 0u16, 1352u16, 1096u16, 840u16, 0u16, 1356u16, 1100u16,
+                    844u16, 0u16, 0u16, 0u16, 1362u16, 1106u16, 850u16, 0u16, 115u16, 0u16, 0u16,
+                    1368u16, 1112u16, 856u16, 111u16, 1117u16, 0u16, 45u16, 1284u16, 1028u16,
+                    772u16, 0u16, 52u16, 0u16, 0u16, 0u16, 1288u16, 1032u16, 776u16, 0u16, 1292u16,
+                    1036u16, 780u16, 0u16, 0u16, 0u16, 1298u16, 1042u16, 786u16, 0u16, 51u16, 0u16,
+                    0u16, 1304u16, 1048u16, 792u16, 47u16, 1053u16, 0u16, 0u16,
+                ],
                 DeadKeys::new(
                     Some(1024u16),
-                    None,
+                    Some(61486u16),
                     None,
                     None,
                     None,
@@ -351,9 +456,21 @@ lazy_static! {
                     14u16, 15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 26u16, 27u16, 29u16, 28u16, 165u16, 164u16, 166u16, 1324u16, 42u16,
                 ],
+                vec![
+                    44u16, 158u16, 161u16, 225u16, 136u16, 157u16, 0u16, 96u16, 1068u16, 138u16,
+                    139u16, 148u16, 0u16, 0u16, 149u16, 231u16, 239u16, 176u16, 0u16, 0u16, 300u16,
+                    144u16, 160u16, 230u16, 0u16, 0u16, 141u16, 212u16, 0u16, 0u16, 0u16, 173u16,
+                    580u16, 324u16, 836u16, 1348u16, 116u16, 196u16, 244u16, 198u16, 584u16,
+                    328u16, 840u16, 1096u16, 588u16, 332u16, 844u16, 1100u16, 0u16, 1361u16,
+                    594u16, 338u16, 850u16, 1362u16, 115u16, 0u16, 210u16, 600u16, 344u16, 856u16,
+                    111u16, 349u16, 0u16, 45u16, 516u16, 260u16, 772u16, 1284u16, 52u16, 132u16,
+                    180u16, 134u16, 520u16, 264u16, 776u16, 1032u16, 524u16, 268u16, 780u16,
+                    1036u16, 0u16, 1297u16, 530u16, 274u16, 786u16, 1298u16, 51u16, 0u16, 146u16,
+                    536u16, 280u16, 792u16, 47u16, 285u16, 0u16, 1053u16,
+                ],
                 DeadKeys::new(
                     Some(256u16),
-                    None,
+                    Some(61486u16),
                     None,
                     None,
                     Some(1024u16),
@@ -387,9 +504,21 @@ lazy_static! {
                     14u16, 15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 26u16, 27u16, 29u16, 28u16, 180u16, 164u16, 177u16, 1068u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 165u16, 113u16, 0u16, 0u16, 158u16, 162u16, 812u16, 0u16, 0u16,
+                    0u16, 163u16, 0u16, 0u16, 0u16, 161u16, 0u16, 0u16, 0u16, 1324u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 580u16, 1348u16, 324u16,
+                    1092u16, 836u16, 0u16, 0u16, 0u16, 584u16, 1352u16, 328u16, 840u16, 588u16,
+                    1356u16, 332u16, 844u16, 0u16, 1105u16, 594u16, 1362u16, 338u16, 1106u16,
+                    850u16, 0u16, 0u16, 600u16, 1368u16, 344u16, 856u16, 1372u16, 0u16, 0u16,
+                    116u16, 1284u16, 260u16, 1028u16, 52u16, 0u16, 0u16, 97u16, 111u16, 115u16,
+                    264u16, 776u16, 524u16, 1292u16, 268u16, 780u16, 0u16, 1041u16, 530u16,
+                    1298u16, 274u16, 1042u16, 51u16, 0u16, 0u16, 536u16, 1304u16, 280u16, 47u16,
+                    1308u16, 0u16, 796u16,
+                ],
                 DeadKeys::new(
                     Some(1280u16),
-                    None,
+                    Some(61613u16),
                     None,
                     None,
                     Some(768u16),
@@ -423,9 +552,21 @@ lazy_static! {
                     14u16, 15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 26u16, 27u16, 28u16, 29u16, 164u16, 191u16, 167u16, 176u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 1068u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 181u16, 0u16, 0u16, 0u16, 556u16, 144u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 836u16, 644u16, 324u16, 0u16,
+                    1092u16, 1348u16, 115u16, 0u16, 840u16, 584u16, 328u16, 1096u16, 844u16,
+                    588u16, 332u16, 1100u16, 111u16, 0u16, 850u16, 594u16, 338u16, 0u16, 1106u16,
+                    0u16, 0u16, 856u16, 600u16, 344u16, 1112u16, 604u16, 120u16, 0u16, 772u16,
+                    516u16, 260u16, 0u16, 1028u16, 1284u16, 51u16, 0u16, 776u16, 520u16, 264u16,
+                    1032u16, 780u16, 524u16, 268u16, 1036u16, 47u16, 0u16, 786u16, 530u16, 274u16,
+                    0u16, 1042u16, 0u16, 0u16, 792u16, 536u16, 280u16, 1048u16, 540u16, 56u16,
+                    1052u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61492u16),
                     None,
                     None,
                     Some(1024u16),
@@ -459,9 +600,19 @@ lazy_static! {
                     15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16, 25u16,
                     26u16, 27u16, 28u16, 29u16, 111u16, 127u16, 112u16, 113u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 0u16, 96u16, 0u16, 0u16, 181u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    117u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 260u16, 196u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 264u16, 200u16, 0u16, 0u16, 268u16, 204u16, 0u16, 0u16, 0u16,
+                    0u16, 274u16, 210u16, 0u16, 0u16, 0u16, 0u16, 0u16, 280u16, 216u16, 0u16, 0u16,
+                    604u16, 0u16, 0u16, 260u16, 132u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 264u16,
+                    136u16, 0u16, 0u16, 268u16, 140u16, 0u16, 0u16, 0u16, 0u16, 274u16, 146u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 280u16, 152u16, 0u16, 0u16, 540u16, 0u16, 0u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61620u16),
                     None,
                     None,
                     None,
@@ -495,6 +646,16 @@ lazy_static! {
                     14u16, 15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 26u16, 27u16, 28u16, 29u16, 239u16, 117u16, 240u16, 0u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 0u16, 96u16, 0u16, 0u16, 0u16, 113u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 116u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    52u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 115u16, 47u16, 111u16, 0u16, 0u16,
+                    46u16, 0u16, 0u16, 0u16, 0u16, 0u16, 51u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    49u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                ],
                 DeadKeys::new(
                     None, None, None, None, None, None, None, None, None, None, None, None,
                 ),
@@ -520,9 +681,21 @@ lazy_static! {
                     14u16, 15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 26u16, 27u16, 28u16, 29u16, 164u16, 53u16, 167u16, 1068u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 0u16, 160u16, 97u16, 0u16, 0u16, 117u16, 1324u16, 0u16, 0u16,
+                    33u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 556u16, 144u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 53u16, 0u16, 0u16, 836u16, 580u16, 324u16,
+                    1092u16, 1348u16, 111u16, 116u16, 0u16, 840u16, 584u16, 328u16, 1352u16,
+                    844u16, 588u16, 332u16, 1356u16, 199u16, 1105u16, 850u16, 594u16, 338u16,
+                    1106u16, 1362u16, 0u16, 115u16, 856u16, 600u16, 344u16, 1368u16, 604u16,
+                    215u16, 150u16, 772u16, 516u16, 260u16, 1028u16, 1284u16, 47u16, 52u16, 0u16,
+                    776u16, 520u16, 264u16, 1288u16, 780u16, 524u16, 268u16, 1292u16, 135u16,
+                    1041u16, 786u16, 530u16, 274u16, 1042u16, 1298u16, 0u16, 51u16, 792u16, 536u16,
+                    280u16, 1304u16, 540u16, 151u16, 1308u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61614u16),
                     None,
                     None,
                     Some(1280u16),
@@ -556,9 +729,21 @@ lazy_static! {
                     15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16, 25u16,
                     26u16, 27u16, 28u16, 29u16, 164u16, 117u16, 167u16, 1068u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 0u16, 160u16, 0u16, 0u16, 0u16, 161u16, 1324u16, 0u16, 116u16,
+                    46u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 556u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 52u16, 110u16, 0u16, 0u16, 0u16, 0u16, 836u16, 580u16,
+                    324u16, 1092u16, 1348u16, 0u16, 0u16, 115u16, 840u16, 584u16, 328u16, 1352u16,
+                    844u16, 588u16, 332u16, 1356u16, 0u16, 1105u16, 850u16, 594u16, 338u16,
+                    1106u16, 1362u16, 0u16, 0u16, 856u16, 600u16, 344u16, 1368u16, 604u16, 0u16,
+                    0u16, 772u16, 516u16, 260u16, 1028u16, 1284u16, 0u16, 0u16, 51u16, 776u16,
+                    520u16, 264u16, 1288u16, 780u16, 524u16, 268u16, 1292u16, 0u16, 1041u16,
+                    786u16, 530u16, 274u16, 1042u16, 1298u16, 0u16, 0u16, 792u16, 536u16, 280u16,
+                    1304u16, 540u16, 0u16, 1308u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61552u16),
                     None,
                     None,
                     Some(1280u16),
@@ -592,9 +777,21 @@ lazy_static! {
                     15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16, 25u16,
                     26u16, 27u16, 28u16, 29u16, 112u16, 127u16, 113u16, 1068u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 162u16, 161u16, 0u16, 0u16, 0u16, 174u16, 1324u16, 0u16, 176u16,
+                    0u16, 163u16, 0u16, 0u16, 0u16, 136u16, 0u16, 159u16, 159u16, 556u16, 0u16,
+                    0u16, 0u16, 0u16, 158u16, 177u16, 0u16, 0u16, 0u16, 0u16, 0u16, 836u16, 580u16,
+                    324u16, 1092u16, 1348u16, 0u16, 0u16, 0u16, 840u16, 584u16, 328u16, 1352u16,
+                    844u16, 588u16, 332u16, 1356u16, 0u16, 1105u16, 850u16, 594u16, 338u16,
+                    1106u16, 1362u16, 0u16, 0u16, 856u16, 600u16, 344u16, 1368u16, 604u16, 0u16,
+                    0u16, 772u16, 516u16, 260u16, 1028u16, 1284u16, 0u16, 0u16, 0u16, 776u16,
+                    520u16, 264u16, 1288u16, 780u16, 524u16, 268u16, 1292u16, 0u16, 1041u16,
+                    786u16, 530u16, 274u16, 1042u16, 1298u16, 0u16, 0u16, 792u16, 536u16, 280u16,
+                    1304u16, 540u16, 0u16, 1308u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61487u16),
                     None,
                     None,
                     Some(1280u16),
@@ -628,9 +825,21 @@ lazy_static! {
                     14u16, 15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 26u16, 27u16, 28u16, 29u16, 180u16, 158u16, 177u16, 1068u16, 42u16,
                 ],
+                vec![
+                    44u16, 46u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 1324u16, 0u16, 117u16, 0u16,
+                    163u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 556u16, 0u16, 0u16, 96u16,
+                    0u16, 0u16, 53u16, 0u16, 0u16, 0u16, 0u16, 46u16, 836u16, 580u16, 324u16,
+                    1092u16, 1348u16, 0u16, 0u16, 113u16, 840u16, 584u16, 328u16, 1352u16, 844u16,
+                    588u16, 332u16, 1356u16, 0u16, 115u16, 850u16, 594u16, 338u16, 1106u16,
+                    1362u16, 0u16, 0u16, 856u16, 600u16, 344u16, 1368u16, 604u16, 0u16, 0u16,
+                    772u16, 516u16, 260u16, 1028u16, 1284u16, 0u16, 0u16, 49u16, 776u16, 520u16,
+                    264u16, 1288u16, 780u16, 524u16, 268u16, 1292u16, 0u16, 51u16, 786u16, 530u16,
+                    274u16, 1042u16, 1298u16, 0u16, 0u16, 792u16, 536u16, 280u16, 1304u16, 540u16,
+                    0u16, 1308u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61492u16),
                     None,
                     None,
                     Some(1280u16),
@@ -664,9 +873,20 @@ lazy_static! {
                     14u16, 15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 26u16, 27u16, 28u16, 29u16, 52u16, 53u16, 49u16, 176u16, 42u16,
                 ],
+                vec![
+                    44u16, 110u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 1068u16, 0u16, 0u16, 0u16,
+                    181u16, 0u16, 0u16, 0u16, 117u16, 0u16, 0u16, 0u16, 556u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 46u16, 836u16, 580u16, 324u16, 0u16,
+                    1092u16, 0u16, 0u16, 0u16, 840u16, 584u16, 328u16, 1096u16, 844u16, 588u16,
+                    332u16, 1100u16, 0u16, 115u16, 850u16, 594u16, 338u16, 0u16, 1106u16, 0u16,
+                    0u16, 856u16, 600u16, 344u16, 1112u16, 604u16, 0u16, 0u16, 772u16, 516u16,
+                    260u16, 0u16, 1028u16, 0u16, 0u16, 0u16, 776u16, 520u16, 264u16, 1032u16,
+                    780u16, 524u16, 268u16, 1036u16, 0u16, 51u16, 786u16, 530u16, 274u16, 0u16,
+                    1042u16, 0u16, 0u16, 792u16, 536u16, 280u16, 1048u16, 540u16, 0u16, 1052u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61487u16),
                     None,
                     None,
                     Some(1024u16),
@@ -700,9 +920,21 @@ lazy_static! {
                     14u16, 15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 26u16, 27u16, 28u16, 29u16, 164u16, 191u16, 167u16, 1068u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 0u16, 160u16, 97u16, 0u16, 0u16, 53u16, 1324u16, 0u16, 0u16,
+                    33u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 556u16, 144u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 117u16, 0u16, 0u16, 836u16, 580u16, 324u16,
+                    1092u16, 116u16, 111u16, 0u16, 0u16, 840u16, 584u16, 328u16, 1352u16, 844u16,
+                    588u16, 332u16, 1356u16, 199u16, 1105u16, 850u16, 594u16, 338u16, 1106u16,
+                    115u16, 0u16, 0u16, 856u16, 600u16, 344u16, 1368u16, 604u16, 215u16, 150u16,
+                    772u16, 516u16, 260u16, 1028u16, 52u16, 47u16, 0u16, 0u16, 776u16, 520u16,
+                    264u16, 1288u16, 780u16, 524u16, 268u16, 1292u16, 135u16, 1041u16, 786u16,
+                    530u16, 274u16, 1042u16, 51u16, 0u16, 0u16, 792u16, 536u16, 280u16, 1304u16,
+                    540u16, 151u16, 1308u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61486u16),
                     None,
                     None,
                     Some(1280u16),
@@ -736,9 +968,21 @@ lazy_static! {
                     14u16, 15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16,
                     25u16, 26u16, 27u16, 28u16, 29u16, 164u16, 174u16, 167u16, 1068u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 0u16, 159u16, 0u16, 0u16, 0u16, 0u16, 1324u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 556u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 162u16, 0u16, 0u16, 836u16, 580u16, 324u16, 1092u16,
+                    1348u16, 0u16, 132u16, 119u16, 840u16, 584u16, 328u16, 1352u16, 844u16, 588u16,
+                    332u16, 1356u16, 0u16, 1105u16, 850u16, 594u16, 338u16, 1106u16, 118u16, 0u16,
+                    0u16, 856u16, 600u16, 344u16, 112u16, 604u16, 0u16, 150u16, 772u16, 516u16,
+                    260u16, 1028u16, 1284u16, 0u16, 0u16, 55u16, 776u16, 117u16, 264u16, 1288u16,
+                    780u16, 524u16, 268u16, 1292u16, 0u16, 1041u16, 786u16, 530u16, 274u16,
+                    1042u16, 54u16, 0u16, 0u16, 792u16, 536u16, 280u16, 48u16, 540u16, 0u16,
+                    1308u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61619u16),
                     None,
                     None,
                     Some(1280u16),
@@ -772,6 +1016,16 @@ lazy_static! {
                     15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16, 25u16,
                     26u16, 27u16, 28u16, 29u16, 111u16, 127u16, 112u16, 113u16, 42u16,
                 ],
+                vec![
+                    44u16, 0u16, 0u16, 96u16, 0u16, 0u16, 181u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    117u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 196u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 200u16, 0u16, 0u16, 0u16, 204u16, 0u16, 0u16, 0u16, 0u16,
+                    0u16, 210u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 216u16, 0u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 132u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 0u16, 136u16,
+                    0u16, 0u16, 0u16, 140u16, 0u16, 0u16, 0u16, 0u16, 0u16, 146u16, 0u16, 0u16,
+                    0u16, 0u16, 0u16, 0u16, 152u16, 0u16, 0u16, 0u16, 0u16, 0u16,
+                ],
                 DeadKeys::new(
                     None, None, None, None, None, None, None, None, None, None, None, None,
                 ),
@@ -797,9 +1051,22 @@ lazy_static! {
                     15u16, 16u16, 17u16, 18u16, 19u16, 20u16, 21u16, 22u16, 23u16, 24u16, 25u16,
                     26u16, 27u16, 28u16, 29u16, 111u16, 113u16, 112u16, 1068u16, 42u16,
                 ],
+                vec![
+                    44u16, 158u16, 198u16, 225u16, 161u16, 173u16, 241u16, 214u16, 244u16, 134u16,
+                    0u16, 175u16, 177u16, 0u16, 149u16, 0u16, 243u16, 0u16, 159u16, 160u16, 180u16,
+                    144u16, 179u16, 0u16, 0u16, 222u16, 0u16, 176u16, 163u16, 164u16, 165u16,
+                    184u16, 836u16, 196u16, 324u16, 1092u16, 212u16, 218u16, 221u16, 246u16,
+                    840u16, 200u16, 328u16, 1352u16, 844u16, 204u16, 332u16, 1356u16, 199u16,
+                    209u16, 850u16, 210u16, 338u16, 1106u16, 211u16, 174u16, 207u16, 856u16,
+                    216u16, 344u16, 220u16, 604u16, 215u16, 150u16, 772u16, 132u16, 260u16,
+                    1028u16, 1284u16, 154u16, 157u16, 182u16, 776u16, 520u16, 264u16, 1288u16,
+                    780u16, 140u16, 268u16, 1292u16, 135u16, 145u16, 786u16, 146u16, 274u16,
+                    1042u16, 147u16, 238u16, 143u16, 792u16, 152u16, 280u16, 156u16, 540u16,
+                    151u16, 1308u16,
+                ],
                 DeadKeys::new(
                     Some(512u16),
-                    None,
+                    Some(61492u16),
                     None,
                     None,
                     Some(1280u16),
@@ -21,6 +21,7 @@ pub struct Layout {
     pub dead_keys_mask: Option<u16>,
     pub keycode_mask: u16,
     pub keycodes: Box<[u16]>,
+    pub iso_8859_1_keycodes: Box<[u16]>,
     pub deadkeys: DeadKeys,
 }
 
@@ -65,9 +66,11 @@ impl Layout {
         dead_keys_mask: Option<u16>,
         keycode_mask: u16,
         keycodes: Vec<u16>,
+        iso_8859_1_keycodes: Vec<u16>,
         deadkeys: DeadKeys,
     ) -> Layout {
         let keycodes = keycodes.into_boxed_slice();
+        let iso_8859_1_keycodes = iso_8859_1_keycodes.into_boxed_slice();
         Layout {
             shift_mask,
             alt_mask,
@@ -76,6 +79,7 @@ impl Layout {
             dead_keys_mask,
             keycode_mask,
             keycodes,
+            iso_8859_1_keycodes,
             deadkeys,
         }
     }
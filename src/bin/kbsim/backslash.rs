@@ -0,0 +1,109 @@
+//! Backslash escapes in STRING, such as `\n` for a newline, so a single shell argument can type
+//! several lines
+
+/// Replace the escapes in the string: `\n`, `\t`, `\r`, `\\` and `\u{1F600}` for a Unicode code
+/// point. Anything else after a backslash is an error giving the byte it starts at.
+///
+/// With `key_escapes` a `{` from `\u{7B}` is doubled, so it's typed as a brace rather than
+/// starting a `{NAME}` key escape when the result is parsed for keys.
+pub fn unescape(string: &str, key_escapes: bool) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(string.len());
+    let mut chars = string.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some((_, 'n')) => unescaped.push('\n'),
+            Some((_, 't')) => unescaped.push('\t'),
+            Some((_, 'r')) => unescaped.push('\r'),
+            Some((_, '\\')) => unescaped.push('\\'),
+            Some((_, 'u')) => {
+                let start = offset + 2;
+                let end = match (chars.next(), string[start..].find('}')) {
+                    (Some((_, '{')), Some(end)) => start + end,
+                    _ => {
+                        return Err(format!(
+                            "Invalid escape at byte {}, \\u is followed by a code point in braces such as \\u{{1F600}}",
+                            offset
+                        ))
+                    }
+                };
+                let digits = &string[start + 1..end];
+                let c = u32::from_str_radix(digits, 16)
+                    .ok()
+                    .filter(|_| (1..=6).contains(&digits.len()))
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| {
+                        format!(
+                            "Invalid escape \\u{{{}}} at byte {}, which isn't a Unicode code point",
+                            digits, offset
+                        )
+                    })?;
+                if key_escapes && c == '{' {
+                    unescaped.push('{');
+                }
+                unescaped.push(c);
+                while chars.next_if(|(i, _)| *i <= end).is_some() {}
+            }
+            Some((_, other)) => {
+                return Err(format!(
+                    "Invalid escape \\{} at byte {}, expected \\n, \\t, \\r, \\\\ or \\u{{...}}",
+                    other, offset
+                ))
+            }
+            None => {
+                return Err(format!(
+                    "Incomplete escape at byte {}, STRING ends with a backslash. Use \\\\ for a backslash",
+                    offset
+                ))
+            }
+        }
+    }
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_are_replaced() {
+        assert_eq!(
+            unescape(r"one\ntwo\tthree\r\\ \u{1F600}\u{e9}!", false).unwrap(),
+            "one\ntwo\tthree\r\\ \u{1F600}\u{e9}!"
+        );
+        assert_eq!(
+            unescape("no escapes {ENTER}", false).unwrap(),
+            "no escapes {ENTER}"
+        );
+    }
+
+    #[test]
+    fn braces_from_code_points_stay_text_with_key_escapes() {
+        assert_eq!(
+            unescape(r"\u{7B}ENTER\u{7D} {ENTER}", true).unwrap(),
+            "{{ENTER} {ENTER}"
+        );
+        assert_eq!(unescape(r"\u{7B}ENTER\u{7D}", false).unwrap(), "{ENTER}");
+    }
+
+    #[test]
+    fn invalid_escapes_give_their_position() {
+        assert_eq!(
+            unescape(r"ab\q", false).unwrap_err(),
+            r"Invalid escape \q at byte 2, expected \n, \t, \r, \\ or \u{...}"
+        );
+        assert!(unescape(r"ab\", false).unwrap_err().contains("byte 2"));
+        assert!(unescape(r"\u1F600", false).unwrap_err().contains("byte 0"));
+        assert!(unescape(r"x\u{1F600", false)
+            .unwrap_err()
+            .contains("byte 1"));
+        assert!(unescape(r"x\u{D800}", false)
+            .unwrap_err()
+            .contains("isn't a Unicode code point"));
+        assert!(unescape(r"x\u{}", false).is_err());
+        assert!(unescape(r"x\u{1234567}", false).is_err());
+    }
+}
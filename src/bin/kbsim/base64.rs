@@ -0,0 +1,141 @@
+//! Base64 for sharing packets as a single string, e.g. from `--format base64` to `kbsim raw
+//! --packets-base64`
+
+use keyboard_layouts::writer::HidWriter;
+
+use std::io::{self, Write};
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode the bytes with the standard alphabet and padding
+pub fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, &b)| group | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode base64 with the standard alphabet, ignoring whitespace such as line breaks from pasting
+/// it. The padding can be left out.
+pub fn decode(encoded: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(encoded.len() / 4 * 3);
+    let mut group = 0u32;
+    let mut digits = 0;
+    let mut padding = 0;
+    for (offset, c) in encoded.char_indices() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c == '=' {
+            padding += 1;
+            continue;
+        }
+        let value = ALPHABET
+            .iter()
+            .position(|&digit| digit as char == c)
+            .filter(|_| padding == 0)
+            .ok_or_else(|| format!("Invalid base64 {:?} at byte {}", c, offset))?;
+        group = group << 6 | value as u32;
+        digits += 1;
+        if digits == 4 {
+            bytes.extend_from_slice(&group.to_be_bytes()[1..]);
+            group = 0;
+            digits = 0;
+        }
+    }
+    match digits {
+        0 if padding == 0 => {}
+        2 if padding <= 2 => bytes.push((group >> 4) as u8),
+        3 if padding <= 1 => bytes.extend_from_slice(&((group >> 2) as u16).to_be_bytes()),
+        _ => return Err("Invalid base64, it ends part way through a byte".to_string()),
+    }
+    Ok(bytes)
+}
+
+/// Writes all the HID packets as a single line of base64 when finished or dropped
+pub struct Base64Dump<W: Write> {
+    // None once the base64 has been written
+    output: Option<W>,
+    bytes: Vec<u8>,
+}
+
+impl<W: Write> Base64Dump<W> {
+    pub fn new(output: W) -> Self {
+        Base64Dump {
+            output: Some(output),
+            bytes: Vec::new(),
+        }
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        match self.output.take() {
+            Some(mut output) => {
+                writeln!(output, "{}", encode(&self.bytes))?;
+                output.flush()
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> HidWriter for Base64Dump<W> {
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.bytes.extend_from_slice(packet);
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for Base64Dump<W> {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_round_trip() {
+        for bytes in [&b""[..], b"f", b"fo", b"foo", b"foob", b"fooba", b"foobar"] {
+            assert_eq!(decode(&encode(bytes)).unwrap(), bytes);
+        }
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode(b"fooba"), "Zm9vYmE=");
+        assert_eq!(encode(&[0, 0, 0x0b, 0xff]), "AAAL/w==");
+        assert_eq!(decode("Zm9v\nYmE").unwrap(), b"fooba");
+    }
+
+    #[test]
+    fn invalid_base64_is_an_error() {
+        assert_eq!(
+            decode("Zm9v!mFy").unwrap_err(),
+            "Invalid base64 '!' at byte 4"
+        );
+        assert!(decode("Zm9vY").is_err());
+        assert!(decode("Zm=9v").is_err());
+        assert!(decode("Zg===").is_err());
+    }
+
+    #[test]
+    fn packets_are_dumped_on_one_line() {
+        let mut output = Vec::new();
+        {
+            let mut dump = Base64Dump::new(&mut output);
+            dump.write_packet(&[0, 0, 0x0b, 0, 0, 0, 0, 0]).unwrap();
+            dump.write_packet(&[0; 8]).unwrap();
+        }
+        assert_eq!(output, b"AAALAAAAAAAAAAAAAAAAAA==\n");
+    }
+}
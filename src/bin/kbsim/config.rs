@@ -0,0 +1,222 @@
+//! Default options read from a TOML config file, e.g.
+//!
+//! ```toml
+//! hid_file = "/dev/hidg1"
+//! layout = "LAYOUT_GERMAN"
+//! cooldown = 10
+//! delay = "1.5s"
+//! ```
+
+use crate::duration;
+
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The options that can be set in a config file. Options the file doesn't set are `None`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    /// A single file, or an array of them to write to all of them
+    pub hid_file: Option<Vec<String>>,
+    pub layout: Option<String>,
+    pub cooldown: Option<Duration>,
+    pub delay: Option<Duration>,
+    pub newline: Option<bool>,
+    pub wpm: Option<u32>,
+    pub write_retries: Option<u32>,
+    pub write_timeout: Option<Duration>,
+}
+
+impl Config {
+    /// The config file used when no path is given, `$XDG_CONFIG_HOME/kbsim/config.toml` or
+    /// `~/.config/kbsim/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("kbsim").join("config.toml"))
+    }
+
+    /// Read the config file at the given path. A missing file is only an error when it was
+    /// explicitly requested.
+    pub fn load(path: &Path, explicit: bool) -> Result<Config, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text, &path.display().to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !explicit => Ok(Config::default()),
+            Err(e) => Err(format!(
+                "Could not read config file {}: {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+
+    /// Parse the contents of a config file, warning about any keys that aren't options.
+    pub fn parse(text: &str, source: &str) -> Result<Config, String> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|e| format!("Invalid config file {}: {}", source, e))?;
+
+        let mut config = Config::default();
+        for (key, value) in table {
+            let invalid = |expected| {
+                format!(
+                    "Invalid value for {} in config file {}: expected {}",
+                    key, source, expected
+                )
+            };
+            let string = || {
+                value
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| invalid("a string"))
+            };
+            let number = || {
+                value
+                    .as_integer()
+                    .and_then(|n| u32::try_from(n).ok())
+                    .ok_or_else(|| invalid("a positive integer"))
+            };
+            // A number in the option's unit, or a string with its own unit
+            let duration = |bare_unit: Duration| {
+                let expected = "a positive integer or a duration such as \"1.5s\"";
+                match (value.as_integer(), value.as_str()) {
+                    (Some(n), _) => u32::try_from(n)
+                        .map(|n| bare_unit * n)
+                        .map_err(|_| invalid(expected)),
+                    (_, Some(text)) => {
+                        duration::parse(text, bare_unit).map_err(|_| invalid(expected))
+                    }
+                    _ => Err(invalid(expected)),
+                }
+            };
+
+            match key.as_str() {
+                "hid_file" => {
+                    config.hid_file = Some(match value.as_array() {
+                        Some(files) => files
+                            .iter()
+                            .map(|file| file.as_str().map(str::to_string))
+                            .collect::<Option<Vec<String>>>()
+                            .filter(|files| !files.is_empty())
+                            .ok_or_else(|| invalid("a string or an array of strings"))?,
+                        None => value
+                            .as_str()
+                            .map(|file| vec![file.to_string()])
+                            .ok_or_else(|| invalid("a string or an array of strings"))?,
+                    })
+                }
+                "layout" => config.layout = Some(string()?),
+                "cooldown" => config.cooldown = Some(duration(Duration::from_millis(1))?),
+                "delay" => config.delay = Some(duration(Duration::from_secs(1))?),
+                "newline" => {
+                    config.newline = Some(value.as_bool().ok_or_else(|| invalid("true or false"))?)
+                }
+                "wpm" => config.wpm = Some(number()?),
+                "write_retries" => config.write_retries = Some(number()?),
+                "write_timeout" => config.write_timeout = Some(duration(Duration::from_millis(1))?),
+                _ => eprintln!("Warning: unknown key {} in config file {}", key, source),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_options() {
+        let config = Config::parse(
+            "hid_file = \"/dev/hidg1\"\nlayout = \"LAYOUT_GERMAN\"\ncooldown = 10\nnewline = true\nfavourite_colour = \"blue\"\n",
+            "config.toml",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                hid_file: Some(vec!["/dev/hidg1".to_string()]),
+                layout: Some("LAYOUT_GERMAN".to_string()),
+                cooldown: Some(Duration::from_millis(10)),
+                newline: Some(true),
+                ..Config::default()
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_file_names_the_line() {
+        let err = Config::parse(
+            "layout = \"LAYOUT_GERMAN\"\ncooldown = = 10\n",
+            "config.toml",
+        )
+        .unwrap_err();
+
+        assert!(err.contains("line 2"), "{}", err);
+    }
+
+    #[test]
+    fn wrong_types_are_errors() {
+        assert_eq!(
+            Config::parse("wpm = \"fast\"", "config.toml").unwrap_err(),
+            "Invalid value for wpm in config file config.toml: expected a positive integer"
+        );
+        // Numbers that don't fit aren't wrapped around
+        for value in &["-1", "4294967296"] {
+            let config = format!("write_retries = {}", value);
+            assert_eq!(
+                Config::parse(&config, "config.toml").unwrap_err(),
+                "Invalid value for write_retries in config file config.toml: expected a positive integer"
+            );
+        }
+        for value in &["\"soon\"", "true", "-1"] {
+            let config = format!("delay = {}", value);
+            assert_eq!(
+                Config::parse(&config, "config.toml").unwrap_err(),
+                "Invalid value for delay in config file config.toml: expected a positive integer or a duration such as \"1.5s\""
+            );
+        }
+    }
+
+    #[test]
+    fn durations_can_have_units() {
+        let config =
+            Config::parse("delay = \"1.5s\"\ncooldown = \"250us\"", "config.toml").unwrap();
+
+        assert_eq!(config.delay, Some(Duration::from_millis(1500)));
+        assert_eq!(config.cooldown, Some(Duration::from_micros(250)));
+    }
+
+    #[test]
+    fn hid_file_can_be_an_array() {
+        let config = Config::parse("hid_file = [\"/dev/hidg0\", \"/dev/hidg2\"]", "config.toml");
+        assert_eq!(
+            config.unwrap().hid_file,
+            Some(vec!["/dev/hidg0".to_string(), "/dev/hidg2".to_string()])
+        );
+
+        for value in &["[]", "[1]", "1"] {
+            let config = format!("hid_file = {}", value);
+            assert_eq!(
+                Config::parse(&config, "config.toml").unwrap_err(),
+                "Invalid value for hid_file in config file config.toml: expected a string or an array of strings"
+            );
+        }
+    }
+
+    #[test]
+    fn missing_default_file_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        assert_eq!(Config::load(&path, false).unwrap(), Config::default());
+        assert!(Config::load(&path, true).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+//! Decoding input given as base64 or hex before it is typed, for text that is awkward to quote in
+//! a shell or has to pass through something that isn't binary safe
+
+use crate::base64;
+
+use std::str::FromStr;
+
+/// How the input to `--decode` is encoded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Base64,
+    Hex,
+}
+
+impl Encoding {
+    /// Decode the input, ignoring whitespace such as line breaks, into the text to type
+    pub fn decode(self, encoded: &str) -> Result<String, String> {
+        let bytes = match self {
+            Encoding::Base64 => base64::decode(encoded)?,
+            Encoding::Hex => decode_hex(encoded)?,
+        };
+        String::from_utf8(bytes).map_err(|e| {
+            format!(
+                "The decoded input is not valid UTF-8 (invalid byte at offset {})",
+                e.utf8_error().valid_up_to()
+            )
+        })
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(encoding: &str) -> Result<Self, Self::Err> {
+        match encoding {
+            "base64" => Ok(Encoding::Base64),
+            "hex" => Ok(Encoding::Hex),
+            _ => Err(format!(
+                "Unknown encoding {}, expected base64 or hex",
+                encoding
+            )),
+        }
+    }
+}
+
+/// Decode hex digits in pairs, which can be split by whitespace anywhere
+fn decode_hex(encoded: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(encoded.len() / 2);
+    let mut high: Option<u8> = None;
+    for (offset, c) in encoded.char_indices() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let digit = c
+            .to_digit(16)
+            .ok_or_else(|| format!("Invalid hex {:?} at byte {}", c, offset))?
+            as u8;
+        match high.take() {
+            Some(high) => bytes.push(high << 4 | digit),
+            None => high = Some(digit),
+        }
+    }
+    match high {
+        Some(_) => Err("Invalid hex, it ends part way through a byte".to_string()),
+        None => Ok(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_is_decoded_to_text() {
+        assert_eq!(
+            Encoding::Base64.decode("ZWNobyAn\naGknCg==\n"),
+            Ok("echo 'hi'\n".to_string())
+        );
+        assert_eq!(
+            Encoding::Hex.decode("48 65 6c\n6C 6\n f"),
+            Ok("Hello".to_string())
+        );
+        assert_eq!(Encoding::Hex.decode("c3a9"), Ok("\u{e9}".to_string()));
+    }
+
+    #[test]
+    fn invalid_input_is_an_error() {
+        assert_eq!(
+            Encoding::Hex.decode("48 6g"),
+            Err("Invalid hex 'g' at byte 4".to_string())
+        );
+        assert!(Encoding::Hex.decode("486").is_err());
+        assert!(Encoding::Base64.decode("Zm9v!").is_err());
+        assert_eq!(
+            Encoding::Hex.decode("41ff42"),
+            Err("The decoded input is not valid UTF-8 (invalid byte at offset 1)".to_string())
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Durations given on the command line or in the config file, such as `1500ms`, `1.5s`, `1m30s`
+//! or `250us`, or a bare number in the option's own unit
+
+use std::time::Duration;
+
+const UNITS: &[(&str, f64)] = &[
+    ("ns", 1e-9),
+    ("us", 1e-6),
+    ("µs", 1e-6),
+    ("ms", 1e-3),
+    ("s", 1.0),
+    ("m", 60.0),
+    ("h", 3600.0),
+];
+
+/// Parse a duration with units, such as `250ms` or `1m30s`, or a number of `bare_unit`s, e.g.
+/// seconds for `--delay`, which can have a fraction such as `0.25`
+pub fn parse(duration: &str, bare_unit: Duration) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let invalid = || {
+        format!(
+            "Invalid duration {:?}, expected a number of {} such as 1.5, or a duration with units \
+             such as 250ms, 2s or 1m30s",
+            duration,
+            unit_name(bare_unit)
+        )
+    };
+    if let Ok(count) = duration.parse::<u32>() {
+        return Ok(bare_unit * count);
+    }
+    if duration.is_empty() {
+        return Err(invalid());
+    }
+    if duration.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        let count: f64 = duration.parse().map_err(|_| invalid())?;
+        return Duration::try_from_secs_f64(count * bare_unit.as_secs_f64()).map_err(|_| invalid());
+    }
+
+    // A sequence of numbers each followed by a unit
+    let mut total = Duration::from_secs(0);
+    let mut rest = duration;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .ok_or_else(invalid)?;
+        let (number, after) = rest.split_at(split);
+        let unit_len = after
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(after.len());
+        let (unit, after) = after.split_at(unit_len);
+        let scale = UNITS
+            .iter()
+            .find(|(name, _)| *name == unit.trim())
+            .map(|(_, scale)| *scale)
+            .ok_or_else(invalid)?;
+        let number: f64 = number.parse().map_err(|_| invalid())?;
+        let part = Duration::try_from_secs_f64(number * scale).map_err(|_| invalid())?;
+        total = total.checked_add(part).ok_or_else(invalid)?;
+        rest = after;
+    }
+    Ok(total)
+}
+
+/// The name of the unit of bare numbers in errors
+fn unit_name(bare_unit: Duration) -> String {
+    if bare_unit == Duration::from_secs(1) {
+        "seconds".to_string()
+    } else if bare_unit == Duration::from_millis(1) {
+        "milliseconds".to_string()
+    } else {
+        format!("{}s", format(bare_unit))
+    }
+}
+
+/// Format a duration in the largest unit that it's a whole number of, e.g. `1500ms`
+pub fn format(duration: Duration) -> String {
+    let nanos = duration.as_nanos();
+    if nanos == 0 {
+        return "0s".to_string();
+    }
+    let (name, unit) = [
+        ("h", 3_600_000_000_000),
+        ("m", 60_000_000_000),
+        ("s", 1_000_000_000),
+        ("ms", 1_000_000),
+        ("us", 1_000),
+    ]
+    .iter()
+    .find(|(_, unit)| nanos.is_multiple_of(*unit))
+    .copied()
+    .unwrap_or(("ns", 1));
+    format!("{}{}", nanos / unit, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+    const MILLISECOND: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn durations_are_parsed() {
+        assert_eq!(parse("1500ms", SECOND), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse("1.5s", SECOND), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse("2m", SECOND), Ok(Duration::from_secs(120)));
+        assert_eq!(parse("250us", MILLISECOND), Ok(Duration::from_micros(250)));
+        assert_eq!(parse("250µs", MILLISECOND), Ok(Duration::from_micros(250)));
+        assert_eq!(parse("0", SECOND), Ok(Duration::from_secs(0)));
+        assert_eq!(parse("1m30s", SECOND), Ok(Duration::from_secs(90)));
+        assert_eq!(parse("1h 2m 3s", SECOND), Ok(Duration::from_secs(3723)));
+        assert_eq!(parse("1s500ms", SECOND), Ok(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn bare_numbers_use_the_option_unit() {
+        assert_eq!(parse("2", SECOND), Ok(Duration::from_secs(2)));
+        assert_eq!(parse("10", MILLISECOND), Ok(Duration::from_millis(10)));
+        assert_eq!(parse("0.25", SECOND), Ok(Duration::from_millis(250)));
+        assert_eq!(parse("1.5", MILLISECOND), Ok(Duration::from_micros(1500)));
+    }
+
+    #[test]
+    fn invalid_durations_are_errors() {
+        for duration in &[
+            "",
+            "soon",
+            "-1s",
+            "1e3",
+            "inf",
+            ".",
+            "1m30",
+            "1s 2",
+            "1.2.3s",
+            "10 parsecs",
+            "s",
+            "1e400s",
+        ] {
+            assert!(parse(duration, SECOND).is_err(), "{:?}", duration);
+        }
+        assert_eq!(
+            parse("5x", SECOND).unwrap_err(),
+            "Invalid duration \"5x\", expected a number of seconds such as 1.5, or a duration with \
+             units such as 250ms, 2s or 1m30s"
+        );
+        assert!(parse("5x", MILLISECOND)
+            .unwrap_err()
+            .contains("a number of milliseconds"));
+    }
+
+    #[test]
+    fn durations_are_formatted_in_whole_units() {
+        assert_eq!(format(Duration::from_millis(1500)), "1500ms");
+        assert_eq!(format(Duration::from_secs(120)), "2m");
+        assert_eq!(format(Duration::from_micros(250)), "250us");
+        assert_eq!(format(Duration::from_secs(0)), "0s");
+    }
+}
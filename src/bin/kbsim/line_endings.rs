@@ -0,0 +1,166 @@
+//! Converting the line endings of the input, so text from Windows with `\r\n` types a single Enter
+//! at the end of each line rather than two
+
+use std::str::FromStr;
+
+/// What each line break in the input is typed as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Cr,
+    /// The line breaks are typed as they are
+    Keep,
+}
+
+impl LineEnding {
+    /// The line break typed, or None to keep those in the input. `--newline` adds this, or `\n`.
+    pub fn ending(self) -> Option<&'static str> {
+        match self {
+            LineEnding::Lf => Some("\n"),
+            LineEnding::Crlf => Some("\r\n"),
+            LineEnding::Cr => Some("\r"),
+            LineEnding::Keep => None,
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = String;
+
+    fn from_str(ending: &str) -> Result<Self, Self::Err> {
+        match ending {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            "cr" => Ok(LineEnding::Cr),
+            "keep" => Ok(LineEnding::Keep),
+            _ => Err(format!(
+                "Unknown line ending {}, expected lf, crlf, cr or keep",
+                ending
+            )),
+        }
+    }
+}
+
+/// Converts `\r\n`, `\n` and a lone `\r` to the same line ending, in text that can be split
+/// anywhere, e.g. between reads from stdin
+#[derive(Debug, Clone)]
+pub struct Converter {
+    ending: LineEnding,
+    // A \r was the last character converted, so a \n straight after it is part of the same break
+    after_cr: bool,
+}
+
+impl Converter {
+    pub fn new(ending: LineEnding) -> Self {
+        Converter {
+            ending,
+            after_cr: false,
+        }
+    }
+
+    /// Convert the line breaks in the next piece of text. A `\r` at the end is converted straight
+    /// away rather than waiting to see if a `\n` follows, which is then left out.
+    pub fn convert(&mut self, text: &str) -> String {
+        let ending = match self.ending.ending() {
+            Some(ending) => ending,
+            None => return text.to_string(),
+        };
+        let mut converted = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '\n' if self.after_cr => {}
+                '\r' | '\n' => converted.push_str(ending),
+                _ => converted.push(c),
+            }
+            self.after_cr = c == '\r';
+        }
+        converted
+    }
+}
+
+/// Splits text into lines for `--line-mode`, which presses Enter after each one. The Enter after
+/// the last line so far is held back until more text arrives, so none is pressed after the final
+/// line unless `--newline` adds it.
+#[derive(Debug, Clone)]
+pub struct LineMode {
+    converter: Converter,
+    // The last text ended with a line break, which hasn't been typed yet
+    pending_enter: bool,
+}
+
+impl LineMode {
+    pub fn new() -> Self {
+        LineMode {
+            converter: Converter::new(LineEnding::Lf),
+            pending_enter: false,
+        }
+    }
+
+    /// The next piece of text, with a `\n` for the Enter after each line, whatever its line break
+    pub fn lines(&mut self, text: &str) -> String {
+        let converted = self.converter.convert(text);
+        let mut lines = String::with_capacity(converted.len() + 1);
+        if self.pending_enter && !converted.is_empty() {
+            lines.push('\n');
+            self.pending_enter = false;
+        }
+        match converted.strip_suffix('\n') {
+            Some(line) => {
+                lines.push_str(line);
+                self.pending_enter = true;
+            }
+            None => lines.push_str(&converted),
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIXED: &str = "one\r\ntwo\nthree\rfour\r\n\r\nfive\n\r";
+
+    #[test]
+    fn mixed_line_endings_are_converted() {
+        assert_eq!(
+            Converter::new(LineEnding::Lf).convert(MIXED),
+            "one\ntwo\nthree\nfour\n\nfive\n\n"
+        );
+        assert_eq!(
+            Converter::new(LineEnding::Crlf).convert(MIXED),
+            "one\r\ntwo\r\nthree\r\nfour\r\n\r\nfive\r\n\r\n"
+        );
+        assert_eq!(
+            Converter::new(LineEnding::Cr).convert("a\r\nb\nc"),
+            "a\rb\rc"
+        );
+        assert_eq!(Converter::new(LineEnding::Keep).convert(MIXED), MIXED);
+    }
+
+    #[test]
+    fn line_breaks_can_be_split_between_pieces() {
+        let mut converter = Converter::new(LineEnding::Lf);
+        let converted: Vec<String> = ["one\r", "\ntwo\r", "three\r", "\r", "\n"]
+            .iter()
+            .map(|piece| converter.convert(piece))
+            .collect();
+
+        assert_eq!(converted, ["one\n", "two\n", "three\n", "\n", ""]);
+    }
+
+    #[test]
+    fn line_mode_holds_back_the_last_enter() {
+        let mut lines = LineMode::new();
+        assert_eq!(
+            lines.lines("echo one\r\necho two\r\n"),
+            "echo one\necho two"
+        );
+        assert_eq!(lines.lines(""), "");
+        assert_eq!(lines.lines("\nls"), "\n\nls");
+        assert_eq!(lines.lines("\n"), "");
+
+        assert_eq!(LineMode::new().lines("a\n\nb"), "a\n\nb");
+    }
+}
@@ -0,0 +1,5240 @@
+/// Print an informational message to stderr, unless --quiet is given
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::QUIET.load(std::sync::atomic::Ordering::SeqCst) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+mod backslash;
+mod base64;
+mod config;
+mod decode;
+mod duration;
+mod line_endings;
+mod progress;
+mod stats;
+mod tail;
+mod utf8;
+mod vt;
+
+use base64::Base64Dump;
+use config::Config;
+use decode::Encoding;
+use keyboard_layouts::capslock;
+use keyboard_layouts::custom::{self, CustomLayout};
+use keyboard_layouts::escapes::{self, Token};
+use keyboard_layouts::humanize::{Distribution, Humanizer};
+use keyboard_layouts::keys::{self, Chord, MediaKey, CONSUMER_PACKET_LEN};
+use keyboard_layouts::leds::{LedReader, Leds, LED_CAPS_LOCK, LED_NAMES, LED_NUM_LOCK};
+use keyboard_layouts::modifiers;
+use keyboard_layouts::mouse::{Button, Mouse, MOUSE_PACKET_LEN};
+use keyboard_layouts::normalize::{self, Fold};
+use keyboard_layouts::rollover;
+use keyboard_layouts::typos::{self, Typos};
+use keyboard_layouts::unicode;
+use keyboard_layouts::writer::{
+    CArrayDump, FanOut, HexDump, HidWriter, JsonDump, PacketLog, ReadBack,
+};
+use keyboard_layouts::{
+    ducky, DeviceFile, HidKeyboard, KeyboardError, Keystroke, ReleaseGuard, HID_PACKET_LEN,
+};
+use line_endings::{Converter, LineEnding, LineMode};
+use progress::Progress;
+use stats::{Estimate, Summary};
+use structopt::clap::{App, Arg, ArgGroup, ArgMatches, Shell};
+use structopt::StructOpt;
+use tail::{Follow, Tail};
+use utf8::{Chunk, Utf8Decoder};
+use vt::{Input, KeyDecoder};
+
+use std::convert::TryFrom;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Arc;
+#[cfg(unix)]
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long an interrupted run has to release the keys itself before the signal handler does
+#[cfg(unix)]
+const INTERRUPT_GRACE: Duration = Duration::from_millis(500);
+
+/// How long typing has to be expected to take for the progress bar to be shown without --progress
+const AUTO_PROGRESS_AFTER: Duration = Duration::from_secs(10);
+
+/// The subcommands, recognised so that anything else is typed as before they existed
+const COMMANDS: &[&str] = &[
+    "type",
+    "list",
+    "raw",
+    "dump",
+    "inspect",
+    "char",
+    "serve",
+    "send",
+    "mouse",
+    "leds",
+    "config-dump",
+    "help",
+];
+
+/// How long to wait after ESC for the rest of an escape sequence before it is the escape key
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// The character a terminal sends for ctrl+c
+const CTRL_C: char = '\x03';
+
+/// The HID file used when none is given
+const DEFAULT_HID_FILE: &str = "/dev/hidg0";
+
+/// The HID file of the consumer control device for --media when none is given
+const DEFAULT_CONSUMER_FILE: &str = "/dev/hidg1";
+
+/// The HID file of the mouse when none is given, the next function of a composite gadget
+const DEFAULT_MOUSE_FILE: &str = "/dev/hidg1";
+
+/// How long to wait for the host's LED report before typing, when it hasn't sent one since the HID
+/// file was last read
+const LED_REPORT_WAIT: Duration = Duration::from_millis(200);
+
+/// Whether this build can type with the operating system's input API
+const NATIVE_BACKEND: bool = cfg!(all(feature = "native", any(windows, target_os = "macos")));
+
+/// Whether --quiet was given, leaving only warnings and errors on stderr
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Whether the terminal is in raw mode, so the signal handler knows to restore it
+static RAW_MODE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The Unix socket serve is listening on, so the signal handler can remove it
+#[cfg(unix)]
+static SOCKET_PATH: std::sync::Mutex<Option<std::path::PathBuf>> = std::sync::Mutex::new(None);
+
+/// The --log-file every keyboard of the run appends its packets to, with the instant its times
+/// are measured from, so the media keys are logged in the same file. The signal handler flushes it
+/// before exiting.
+static PACKET_LOG: std::sync::Mutex<Option<(Instant, io::BufWriter<fs::File>)>> =
+    std::sync::Mutex::new(None);
+
+/// Whether SIGTERM lets the text being typed finish before exiting, instead of interrupting it
+static FINISH_ON_SIGTERM: AtomicBool = AtomicBool::new(false);
+
+/// Held while serve types a line, so the signal handler can wait for it to finish
+static IN_FLIGHT: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Whether the arguments are being built to generate completions, so they can list the layouts
+static GENERATING_COMPLETIONS: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "kbsim",
+    about = "Simulates a HID keyboard by writing a string to a HID device file. It supports a number of different keyboard layouts.",
+    after_help = "Running kbsim without a subcommand is the same as running kbsim type"
+)]
+struct CliOpt {
+    #[structopt(
+        long = "layout-file",
+        help = "Load a custom layout from a TOML or JSON file, which can then be used by its name. Can be repeated to load several",
+        raw(number_of_values = "1", global = "true")
+    )]
+    layout_files: Vec<String>,
+    #[structopt(
+        long = "config",
+        help = "Read default options from this TOML file instead of ~/.config/kbsim/config.toml. Options given on the command line take precedence",
+        raw(global = "true")
+    )]
+    config: Option<String>,
+    #[structopt(
+        long = "quiet",
+        short = "q",
+        help = "Don't print informational messages, such as which layout was detected or that stdin is being read, to stderr. Warnings and errors are still printed",
+        raw(global = "true")
+    )]
+    quiet: bool,
+    #[structopt(
+        long = "verbose",
+        short = "v",
+        help = "Log what kbsim is doing to stderr, in more detail with each -v: the layout, devices and timing used, then the packets and time taken to type each piece of text, then every packet written with a timestamp. RUST_LOG filters the log further, e.g. RUST_LOG=keyboard_layouts=trace",
+        parse(from_occurrences),
+        raw(global = "true")
+    )]
+    verbose: u64,
+    #[structopt(
+        long = "generate-completions",
+        help = "Print a completion script for the shell to stdout, then exit",
+        raw(value_name = r#""SHELL""#, possible_values = "&Shell::variants()")
+    )]
+    generate_completions: Option<Shell>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Type STRING, or stdin when there is no STRING
+    #[structopt(name = "type")]
+    Type(TypeOpt),
+    /// Show the available layouts
+    #[structopt(name = "list")]
+    List(ListOpt),
+    /// Send HID packets given as hex bytes, without going through a layout
+    #[structopt(name = "raw")]
+    Raw(RawOpt),
+    /// Show the keys and modifiers used to type each character a layout supports
+    #[structopt(name = "dump")]
+    Dump(DumpOpt),
+    /// Show the modifiers, key and HID report that type each character of STRING, or of printable ASCII
+    #[structopt(name = "inspect")]
+    Inspect(InspectOpt),
+    /// Show the key presses and every HID packet, including the releases, that type CHAR
+    #[structopt(name = "char")]
+    Char(CharOpt),
+    /// Type the text received over TCP connections, a line at a time
+    #[structopt(name = "serve")]
+    Serve(ServeOpt),
+    /// Send text to kbsim serve to type
+    #[structopt(name = "send")]
+    Send(SendOpt),
+    /// Move, click and scroll with a mouse gadget, /dev/hidg1 unless --hid-file is given
+    #[structopt(name = "mouse")]
+    Mouse(MouseOpt),
+    /// Print the lock keys the host has on, from the LED reports it sends the gadget
+    #[structopt(name = "leds")]
+    Leds(LedsOpt),
+    /// Print the options for type after merging the config file with the command line, then exit
+    #[structopt(name = "config-dump")]
+    ConfigDump(TypeOpt),
+}
+
+/// The options for opening and writing to the HID device
+#[derive(Debug, StructOpt)]
+struct DeviceOpt {
+    #[structopt(
+        long = "hid-file",
+        short = "f",
+        help = "The HID file to write to. Can be repeated to write every packet to several devices. Defaults to /dev/hidg0",
+        raw(number_of_values = "1")
+    )]
+    hid_file: Vec<String>,
+    #[structopt(
+        long = "backend",
+        help = "Where the key presses go: hidg writes HID packets to the --hid-file of a USB gadget, uinput creates a virtual keyboard on this machine with /dev/uinput, and native types on this machine with the Windows or macOS input API in builds with the native feature. Defaults to native on those systems when there is no --hid-file or /dev/hidg0, otherwise hidg",
+        raw(possible_values = r#"&["hidg", "uinput", "native"]"#)
+    )]
+    backend: Option<Backend>,
+    #[structopt(
+        long = "strict",
+        help = "Stop at the first error from any of several --hid-file devices, instead of reporting it and continuing with the others"
+    )]
+    strict: bool,
+    #[structopt(
+        long = "verify",
+        help = "Read each packet back from the HID file after writing it, failing if it doesn't match. Devices that can't be read back are written to without checking, with a warning"
+    )]
+    verify: bool,
+    #[structopt(
+        long = "verify-file",
+        help = "Read the packets back from this file for --verify instead of the HID file",
+        raw(requires = r#""verify""#)
+    )]
+    verify_file: Option<String>,
+    #[structopt(
+        long = "delay",
+        short = "d",
+        help = "How long to wait before writing, such as 1.5s or 500ms. A number without a unit is seconds",
+        default_value = "0",
+        parse(try_from_str = "parse_delay")
+    )]
+    delay: Duration,
+    #[structopt(
+        long = "cooldown",
+        short = "c",
+        help = "How long to wait between sending each HID packet to the device file, such as 10ms or 250us. A number without a unit is milliseconds",
+        default_value = "0",
+        parse(try_from_str = "parse_cooldown")
+    )]
+    cooldown: Duration,
+    #[structopt(
+        long = "press-duration",
+        help = "How long to hold each key down before sending the packet releasing it, instead of the cooldown, for targets that miss keys pressed and released back to back. A number without a unit is milliseconds",
+        raw(value_name = r#""DURATION""#),
+        parse(try_from_str = "parse_cooldown")
+    )]
+    press_duration: Option<Duration>,
+    #[structopt(
+        long = "write-retries",
+        help = "Retry writing a HID packet this many times while the device is temporarily unavailable, e.g. while the host is suspended",
+        default_value = "5"
+    )]
+    write_retries: u32,
+    #[structopt(
+        long = "write-timeout",
+        help = "Give up retrying a HID packet after this long, such as 2s. A number without a unit is milliseconds",
+        raw(value_name = r#""DURATION""#),
+        parse(try_from_str = "parse_cooldown")
+    )]
+    write_timeout: Option<Duration>,
+    #[structopt(
+        long = "wait-for-device",
+        help = "Wait for the HID file to be created and become writable before the delay, and for it to come back if it goes away while typing, giving up after TIMEOUT seconds if one is given",
+        raw(
+            value_name = r#""TIMEOUT""#,
+            min_values = "0",
+            max_values = "1",
+            require_equals = "true"
+        )
+    )]
+    wait_for_device: Option<Option<u64>>,
+    #[structopt(
+        long = "dry-run",
+        short = "D",
+        help = "Print the HID packets to stdout as hex instead of writing them to the HID file"
+    )]
+    dry_run: bool,
+    #[structopt(
+        long = "format",
+        help = "Print the packets of a dry run as lines of hex, as a JSON array of objects with each packet's bytes, the character it types, and its modifiers and keys, as a C array of bytes to embed in firmware, or as a single line of base64 that kbsim raw --packets-base64 sends. Plain by default",
+        raw(
+            possible_values = r#"&["plain", "json", "c-array", "base64"]"#,
+            requires = r#""dry_run""#
+        )
+    )]
+    format: Option<PacketFormat>,
+    #[structopt(
+        long = "log-file",
+        help = "Append a line for each HID packet written to this file, with the seconds since the run started, its bytes, the character it types and whether it was written, retried or failed with an errno. Characters typed from stdin with --no-echo are left out. serve also logs each connection",
+        raw(value_name = r#""PATH""#)
+    )]
+    log_file: Option<String>,
+    #[structopt(
+        long = "log-format",
+        help = "Log the packets as text, or as JSON lines with each packet's time, bytes, char, result, and the retries or errno of one that was retried or failed. Text by default",
+        raw(possible_values = r#"&["text", "jsonl"]"#, requires = r#""log_file""#)
+    )]
+    log_format: Option<LogFormat>,
+}
+
+impl DeviceOpt {
+    /// The files the signal handler writes a release packet to
+    fn release_files(&self) -> Vec<String> {
+        // Closing uinput removes its keyboard, releasing the keys, and the native backend has no
+        // files
+        if self.dry_run || self.backend() != Backend::Hidg {
+            Vec::new()
+        } else {
+            self.hid_files().into_iter().map(String::from).collect()
+        }
+    }
+
+    fn backend(&self) -> Backend {
+        match self.backend {
+            Some(backend) => backend,
+            None if NATIVE_BACKEND
+                && self.hid_file.is_empty()
+                && !Path::new(DEFAULT_HID_FILE).exists() =>
+            {
+                Backend::Native
+            }
+            None => Backend::Hidg,
+        }
+    }
+
+    /// Where the key presses go, for telling the user
+    fn destination(&self) -> String {
+        match self.backend() {
+            Backend::Hidg => self.hid_files().join(", "),
+            Backend::Uinput => "a uinput keyboard on this machine".to_string(),
+            Backend::Native => "this machine".to_string(),
+        }
+    }
+
+    fn hid_files(&self) -> Vec<&str> {
+        if self.hid_file.is_empty() {
+            vec![DEFAULT_HID_FILE]
+        } else {
+            self.hid_file.iter().map(String::as_str).collect()
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct TypeOpt {
+    #[structopt(flatten)]
+    device: DeviceOpt,
+    #[structopt(flatten)]
+    layout: LayoutOpt,
+    #[structopt(
+        long = "newline",
+        short = "n",
+        help = "Hit the 'Enter' key after writing the string, typing the --line-endings"
+    )]
+    newline: bool,
+    #[structopt(
+        long = "no-newline",
+        help = "Don't hit the 'Enter' key after the string, when the config file sets newline",
+        raw(conflicts_with = r#""newline""#)
+    )]
+    no_newline: bool,
+    #[structopt(
+        long = "line-endings",
+        help = "Type each line break in the input, whether \\r\\n, \\n or a lone \\r, as lf, crlf or cr, or keep them as they are, so text from Windows doesn't type two Enters at the end of each line",
+        default_value = "lf",
+        raw(possible_values = r#"&["lf", "crlf", "cr", "keep"]"#)
+    )]
+    line_endings: LineEnding,
+    #[structopt(
+        long = "line-mode",
+        short = "L",
+        help = "Type the input a line at a time, pressing Enter after each line whatever its line break, but not after the last line unless --newline is given",
+        raw(conflicts_with_all = r#"&["stdin", "watch", "script", "keys", "media"]"#)
+    )]
+    line_mode: bool,
+    #[structopt(
+        long = "wpm",
+        help = "Type at this many words per minute instead of using the cooldown, where a word is 5 characters",
+        raw(conflicts_with_all = r#"&["cooldown", "humanize"]"#)
+    )]
+    wpm: Option<u32>,
+    #[structopt(
+        long = "max-rate",
+        help = "Write no more than this many bytes per second to the HID file instead of using the cooldown, waiting the same time after each packet",
+        raw(
+            value_name = r#""BYTES_PER_SEC""#,
+            conflicts_with_all = r#"&["cooldown", "wpm", "humanize"]"#
+        )
+    )]
+    max_rate: Option<u32>,
+    #[structopt(
+        long = "input-file",
+        short = "i",
+        help = "Type the contents of a file instead of STRING. Specify '-' to read stdin until the end of input",
+        raw(conflicts_with = r#""STRING""#)
+    )]
+    input_file: Option<String>,
+    #[structopt(
+        long = "decode",
+        help = "Decode STRING, the --input-file or stdin from base64 or hex before typing it, ignoring whitespace in it. Stdin is read until the end of input",
+        raw(
+            value_name = r#""ENCODING""#,
+            possible_values = r#"&["base64", "hex"]"#,
+            conflicts_with_all = r#"&["watch", "script", "keys", "media"]"#
+        )
+    )]
+    decode: Option<Encoding>,
+    #[structopt(
+        long = "clipboard",
+        help = "Type the text on this machine's clipboard instead of STRING, which also keeps it out of the process list",
+        raw(
+            alias = r#""from-clipboard""#,
+            conflicts_with_all = r#"&["STRING", "input_file", "stdin", "script", "keys", "media", "watch"]"#
+        )
+    )]
+    clipboard: bool,
+    #[structopt(
+        long = "clipboard-selection",
+        help = "Which selection --clipboard reads on Linux: the clipboard text is copied to, or the primary selection of the text last selected",
+        raw(
+            value_name = r#""SELECTION""#,
+            possible_values = r#"&["clipboard", "primary"]"#,
+            requires = r#""clipboard""#
+        )
+    )]
+    clipboard_selection: Option<ClipboardSelection>,
+    #[structopt(
+        long = "stdin",
+        help = "Type stdin as a plain stream until the end of input, without raw mode, echoing or an exit key. This is the default when stdin isn't a terminal",
+        raw(conflicts_with_all = r#"&["STRING", "input_file"]"#)
+    )]
+    stdin: bool,
+    #[structopt(
+        long = "exit-key",
+        help = "Stop reading from a terminal when this key is pressed on its own, such as ctrl+] or ctrl+d. The key is typed like any other when it's part of a paste",
+        default_value = "ctrl+]",
+        parse(try_from_str = "parse_exit_key")
+    )]
+    exit_key: u8,
+    #[structopt(
+        long = "forward-ctrl-c",
+        help = "Press ctrl+c on the keyboard for each ctrl+c read from stdin, instead of typing it with the layout's control character mapping"
+    )]
+    forward_ctrl_c: bool,
+    #[structopt(
+        long = "echo-style",
+        help = "How input typed from a terminal is echoed: plain shows it as it is, dots shows a placeholder for each character, e.g. for a password, and none doesn't echo it",
+        default_value = "plain",
+        raw(possible_values = r#"&["plain", "dots", "none"]"#)
+    )]
+    echo_style: EchoStyle,
+    #[structopt(
+        long = "no-echo",
+        help = "Don't echo input typed from a terminal, the same as --echo-style none",
+        raw(conflicts_with = r#""echo_style""#)
+    )]
+    no_echo: bool,
+    #[structopt(
+        long = "script",
+        help = "Run a DuckyScript file instead of typing STRING",
+        raw(conflicts_with_all = r#"&["STRING", "input_file"]"#)
+    )]
+    script: Option<String>,
+    #[structopt(
+        long = "no-escapes",
+        help = "Type braces in STRING literally instead of treating {NAME} as a special key",
+        raw(visible_alias = r#""literal""#)
+    )]
+    no_escapes: bool,
+    #[structopt(
+        long = "backslash-escapes",
+        short = "e",
+        help = "Interpret backslash escapes in STRING: \\n, \\t and \\r for a newline, tab and carriage return, \\\\ for a backslash and \\u{1F600} for a Unicode code point",
+        raw(requires = r#""STRING""#)
+    )]
+    backslash_escapes: bool,
+    #[structopt(
+        long = "skip-unsupported",
+        help = "Leave out the characters the layout can't type, with a warning, instead of refusing to type anything. The same as --on-unmappable warn",
+        raw(conflicts_with = r#""on_unmappable""#)
+    )]
+    skip_unsupported: bool,
+    #[structopt(
+        long = "on-unmappable",
+        help = "What to do with the characters the layout can't type: error refuses to type anything, skip leaves them out, warn leaves them out with a warning giving their byte offset, and replace=CHAR types CHAR instead. kbsim exits with an error after typing the rest if it skipped any",
+        default_value = "error",
+        raw(value_name = r#""POLICY""#)
+    )]
+    on_unmappable: Unmappable,
+    #[structopt(
+        long = "unicode-fallback",
+        help = "How to type the characters the layout can't: ibus types their code point with Ctrl+Shift+U, which Linux desktops using IBus or GTK understand. alt-numpad holds Alt and types their code on the numpad, which Windows hosts understand: Alt+0NNN for the characters in Windows-1252 and the decimal code point for others, or the hex code point with --alt-numpad-hex. Characters above U+FFFF need --surrogate-pairs. Num Lock must be on, kbsim turns it on by the host's LED reports, or with --force-numlock. Any the fallback can't type either are still unsupported",
+        default_value = "none",
+        raw(possible_values = r#"&["none", "ibus", "alt-numpad"]"#)
+    )]
+    unicode_fallback: UnicodeFallback,
+    #[structopt(
+        long = "normalize",
+        help = "Compose characters written as a letter followed by combining accents, such as e and U+0301, into the single character the layout has a key for, with Unicode NFC normalization, before checking the layout can type them"
+    )]
+    normalize: bool,
+    #[structopt(
+        long = "ascii-fold",
+        help = "Replace the typographic quotes, dashes, ellipsis and non-breaking spaces the layout can't type with their plain ASCII equivalents, e.g. for text copied from a word processor"
+    )]
+    ascii_fold: bool,
+    #[structopt(
+        long = "char-map",
+        help = "Load replacements for --ascii-fold from the [fold] table of a TOML or JSON file, which override the built-in ones. Implies --ascii-fold",
+        raw(value_name = r#""FILE""#)
+    )]
+    char_map: Option<String>,
+    #[structopt(
+        long = "alt-numpad-hex",
+        help = "Type the code points of --unicode-fallback alt-numpad in hex after the numpad +, which Windows only accepts with EnableHexNumpad set in the registry, instead of in decimal"
+    )]
+    alt_numpad_hex: bool,
+    #[structopt(
+        long = "ibus-space",
+        help = "End the code points of --unicode-fallback ibus with space instead of Enter, so an Enter can't submit a form or send a message if the application isn't using the input method"
+    )]
+    ibus_space: bool,
+    #[structopt(
+        long = "surrogate-pairs",
+        help = "Type characters above U+FFFF with --unicode-fallback alt-numpad as the two halves of their UTF-16 surrogate pair, instead of refusing to"
+    )]
+    surrogate_pairs: bool,
+    #[structopt(
+        long = "force-numlock",
+        help = "Press Num Lock before typing with --unicode-fallback alt-numpad, instead of going by the host's LED reports. Only use this when Num Lock is known to be off"
+    )]
+    force_numlock: bool,
+    #[structopt(
+        long = "respect-capslock",
+        help = "Read the host's Caps Lock state from the LED reports it sends the first --hid-file, and turn Caps Lock off while typing so letters don't come out in the wrong case. It is turned back on afterwards",
+        raw(conflicts_with_all = r#"&["script", "keys", "media", "watch"]"#)
+    )]
+    respect_capslock: bool,
+    #[structopt(
+        long = "reset-modifiers",
+        help = "Send a packet releasing every key and modifier before the first one typed, so a modifier the host still has held, e.g. after a session that crashed with Shift down, doesn't change what's typed",
+        raw(conflicts_with = r#""media""#)
+    )]
+    reset_modifiers: bool,
+    #[structopt(
+        long = "uppercase-strategy",
+        help = "How uppercase letters are typed: shift holds Shift with each of them, capslock types runs of --capslock-threshold or more with Caps Lock instead, for targets that drop modifiers in fast input",
+        default_value = "shift",
+        raw(possible_values = r#"&["shift", "capslock"]"#)
+    )]
+    uppercase_strategy: UppercaseStrategy,
+    #[structopt(
+        long = "capslock-threshold",
+        help = "The number of uppercase letters in a row that --uppercase-strategy capslock types with Caps Lock",
+        default_value = "4"
+    )]
+    capslock_threshold: usize,
+    #[structopt(
+        long = "key",
+        short = "k",
+        help = "Press a key combination such as ctrl+alt+delete, gui+l or shift+f10 instead of typing STRING. Can be repeated to press several in order",
+        raw(
+            number_of_values = "1",
+            conflicts_with_all = r#"&["STRING", "input_file", "script"]"#,
+            visible_alias = r#""keys""#
+        )
+    )]
+    keys: Vec<String>,
+    #[structopt(
+        long = "hold",
+        help = "Hold these modifiers, e.g. ctrl or ctrl+alt, while typing STRING or pressing the --key combinations, on top of any the layout needs for each character",
+        parse(try_from_str = "parse_hold"),
+        raw(
+            value_name = r#""MODIFIERS""#,
+            conflicts_with_all = r#"&["script", "media", "watch", "stdin"]"#
+        )
+    )]
+    hold: Option<u8>,
+    #[structopt(
+        long = "hold-key",
+        help = "Hold a key combination such as down or shift+tab down for --for instead of typing STRING, letting the host repeat it",
+        raw(
+            value_name = r#""KEY""#,
+            requires = r#""hold_for""#,
+            conflicts_with_all = r#"&["STRING", "input_file", "script", "keys", "hold", "media", "watch", "stdin"]"#
+        )
+    )]
+    hold_key: Option<String>,
+    #[structopt(
+        long = "for",
+        help = "How long to hold the --hold-key down, such as 2s. A number without a unit is milliseconds",
+        raw(value_name = r#""DURATION""#, requires = r#""hold_key""#),
+        parse(try_from_str = "parse_cooldown")
+    )]
+    hold_for: Option<Duration>,
+    #[structopt(
+        long = "media",
+        help = "Press a media key such as volume_up, mute or play_pause on the consumer control device instead of typing STRING. Can be repeated to press several in order",
+        raw(
+            number_of_values = "1",
+            conflicts_with_all = r#"&["STRING", "input_file", "script", "keys", "stdin"]"#
+        )
+    )]
+    media: Vec<String>,
+    #[structopt(
+        long = "watch",
+        help = "Type each line appended to a file, followed by Enter, until interrupted, instead of typing STRING. The file is read from the start again when it's truncated or rotated",
+        raw(
+            value_name = r#""PATH""#,
+            conflicts_with_all = r#"&["STRING", "input_file", "script", "keys", "media", "stdin"]"#
+        )
+    )]
+    watch: Option<String>,
+    #[structopt(
+        long = "follow",
+        help = "Type everything appended to a file or written to a named pipe as it arrives, until interrupted, instead of typing STRING. The file is read from the start again when it's truncated or rotated, and the pipe is opened again when its writer closes it",
+        raw(
+            value_name = r#""PATH""#,
+            conflicts_with_all = r#"&["STRING", "input_file", "script", "keys", "media", "stdin", "watch", "line_mode", "decode", "clipboard", "respect_capslock", "hold", "hold_key", "progress", "stats", "estimate"]"#
+        )
+    )]
+    follow: Option<String>,
+    #[structopt(
+        long = "watch-interval",
+        help = "How long to wait between each check of the --watch or --follow file for new text, such as 1s. A number without a unit is milliseconds",
+        default_value = "500",
+        parse(try_from_str = "parse_cooldown")
+    )]
+    watch_interval: Duration,
+    #[structopt(
+        long = "consumer-file",
+        help = "The HID file of the consumer control device used by --media, and needed by media keys such as {VOLUME_UP} in STRING or --key. Defaults to /dev/hidg1 for --media"
+    )]
+    consumer_file: Option<String>,
+    #[structopt(
+        long = "consumer-report-id",
+        help = "The report ID to start each consumer control report with, for a gadget whose report descriptor has one"
+    )]
+    consumer_report_id: Option<u8>,
+    #[structopt(
+        long = "consumer-report-len",
+        help = "The number of bytes in each consumer control report after the report ID, starting with the 2 byte usage of the key",
+        default_value = "2",
+        parse(try_from_str = "parse_consumer_report_len")
+    )]
+    consumer_report_len: usize,
+    #[structopt(
+        long = "repeat",
+        short = "r",
+        help = "Type STRING this many times, or 0 to repeat it until interrupted",
+        default_value = "1"
+    )]
+    repeat: u64,
+    #[structopt(
+        long = "interval",
+        help = "How long to wait between each repetition of STRING, such as 1m30s. A number without a unit is milliseconds",
+        default_value = "0",
+        raw(visible_alias = r#""repeat-interval""#),
+        parse(try_from_str = "parse_cooldown")
+    )]
+    interval: Duration,
+    #[structopt(
+        long = "humanize",
+        help = "Wait a random time in the range MIN..MAX after each keystroke instead of using the cooldown, pausing a little longer after spaces and punctuation. Numbers without a unit are milliseconds, e.g. 80..200 or 80ms..0.2s",
+        raw(value_name = r#""MIN..MAX""#, conflicts_with = r#""cooldown""#),
+        parse(try_from_str = "parse_range")
+    )]
+    humanize: Option<(Duration, Duration)>,
+    #[structopt(
+        long = "jitter",
+        help = "Wait a random extra time of up to JITTER after each keystroke, on top of the cooldown, as milliseconds or a duration with a unit",
+        raw(value_name = r#""JITTER""#, conflicts_with = r#""humanize""#),
+        parse(try_from_str = "parse_cooldown")
+    )]
+    jitter: Option<Duration>,
+    #[structopt(
+        long = "word-pause",
+        help = "Wait this much longer after each space, tab or newline typed, between words. A number without a unit is milliseconds",
+        raw(value_name = r#""DURATION""#, visible_alias = r#""pause-after-space""#),
+        parse(try_from_str = "parse_cooldown")
+    )]
+    word_pause: Option<Duration>,
+    #[structopt(
+        long = "sentence-pause",
+        help = "Wait this much longer after each ., ! or ? typed, at the end of a sentence. A number without a unit is milliseconds",
+        raw(value_name = r#""DURATION""#, visible_alias = r#""pause-after-punct""#),
+        parse(try_from_str = "parse_cooldown")
+    )]
+    sentence_pause: Option<Duration>,
+    #[structopt(
+        long = "pause-after-newline",
+        help = "Wait this much longer after each newline typed instead of the --word-pause, e.g. to give a shell time to run each line of a script. A number without a unit is milliseconds",
+        raw(value_name = r#""DURATION""#),
+        parse(try_from_str = "parse_cooldown")
+    )]
+    newline_pause: Option<Duration>,
+    #[structopt(
+        long = "gaussian",
+        help = "Pick the --humanize or --jitter delays from a normal distribution around the middle of the range"
+    )]
+    gaussian: bool,
+    #[structopt(
+        long = "typo-rate",
+        help = "The chance, from 0 to 1, of each character having a typo: a key next to it typed by mistake and deleted with Backspace before the character is typed",
+        raw(value_name = r#""RATE""#, conflicts_with = r#""hold""#),
+        parse(try_from_str = "parse_typo_rate")
+    )]
+    typo_rate: Option<f64>,
+    #[structopt(
+        long = "correction-pause",
+        help = "Wait this much longer after each --typo-rate typo, before the Backspace deleting it, like a person noticing the mistake. A number without a unit is milliseconds",
+        raw(value_name = r#""DURATION""#, requires = r#""typo_rate""#),
+        parse(try_from_str = "parse_cooldown")
+    )]
+    correction_pause: Option<Duration>,
+    #[structopt(
+        long = "seed",
+        help = "Seed the --humanize or --jitter delays and the --typo-rate typos so they are the same on every run"
+    )]
+    seed: Option<u64>,
+    #[structopt(
+        long = "fast",
+        help = "Press up to 6 characters together in each packet, using the keyboard's rollover, while they're typed with the same modifiers and different keys. The host reads them in the packet's order",
+        raw(
+            conflicts_with_all = r#"&["humanize", "jitter", "word_pause", "sentence_pause", "newline_pause", "typo_rate"]"#
+        )
+    )]
+    fast: bool,
+    #[structopt(
+        long = "optimize-modifiers",
+        help = "Keep the modifiers held between consecutive characters typed with the same ones, such as shift across HELLO, releasing only their keys, the way a person types. It sends the same number of packets"
+    )]
+    optimize_modifiers: bool,
+    #[structopt(
+        long = "progress",
+        help = "Show a progress bar on stderr while typing STRING, a file or the clipboard, with how long is left. It's left out when stderr isn't a terminal, or with --quiet",
+        raw(conflicts_with_all = r#"&["stdin", "watch", "script"]"#)
+    )]
+    progress: bool,
+    #[structopt(
+        long = "no-progress",
+        help = "Don't show the progress bar, which is otherwise shown on a terminal when typing is expected to take 10 seconds or more",
+        raw(conflicts_with = r#""progress""#)
+    )]
+    no_progress: bool,
+    #[structopt(
+        long = "stats",
+        help = "Print a summary on stderr after typing STRING, a file or stdin: the characters converted, packets generated and written, bytes written, how long it took, the characters per second and WPM, and the retries and skipped characters. -v logs it too",
+        raw(conflicts_with_all = r#"&["watch", "script", "keys", "media"]"#)
+    )]
+    stats: bool,
+    #[structopt(
+        long = "estimate",
+        help = "Print how many characters and packets typing STRING or a file would take, and how long with the cooldown or --press-duration, the average --humanize or --jitter delay, the pauses, the delay and the --interval, without writing anything",
+        raw(conflicts_with_all = r#"&["watch", "script", "stdin", "keys", "media", "stats"]"#)
+    )]
+    estimate: bool,
+    #[structopt(
+        long = "yes",
+        short = "y",
+        help = "Type without asking for confirmation first. There is no question when stdin isn't a terminal, or for --dry-run"
+    )]
+    yes: bool,
+    #[structopt(
+        long = "confirm",
+        help = "Ask before typing STRING, a file or the clipboard even when stdin isn't a terminal or for --dry-run, showing the start of its first line",
+        raw(
+            conflicts_with_all = r#"&["yes", "script", "keys", "media", "hold_key", "watch", "follow", "stdin", "estimate"]"#
+        )
+    )]
+    confirm: bool,
+    #[structopt(
+        name = "STRING",
+        help = "The string to type. Special keys can be pressed using their name in braces, e.g. {TAB}, {ESC}, {F5} or {UP}, and combined with modifiers, e.g. {CTRL+ALT+DELETE}. Use {{ to type a literal brace"
+    )]
+    string: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct RawOpt {
+    #[structopt(flatten)]
+    device: DeviceOpt,
+    #[structopt(
+        name = "PACKETS",
+        help = "The packets to send as hex, either as bytes separated by spaces, e.g. '00 00 04 00 00 00 00 00', or run together, e.g. 0000040000000000. Keys still pressed after the last packet are released",
+        raw(required_unless_one = r#"&["packets_base64", "file"]"#)
+    )]
+    packets: Vec<String>,
+    #[structopt(
+        long = "packets-base64",
+        help = "Send the packets in this base64 instead, such as the output of --dry-run --format base64",
+        raw(
+            value_name = r#""BASE64""#,
+            conflicts_with_all = r#"&["PACKETS", "file"]"#
+        )
+    )]
+    packets_base64: Option<String>,
+    #[structopt(
+        long = "file",
+        help = "Send the packets in this file instead, or stdin for '-', one packet of hex per line. Blank lines and anything after a # are left out",
+        raw(
+            value_name = r#""PATH""#,
+            visible_alias = r#""raw-file""#,
+            conflicts_with = r#""PACKETS""#
+        )
+    )]
+    file: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ServeOpt {
+    #[structopt(flatten)]
+    device: DeviceOpt,
+    #[structopt(flatten)]
+    layout: LayoutOpt,
+    #[structopt(
+        long = "listen",
+        help = "The address and port to accept connections on, e.g. 0.0.0.0:7000",
+        raw(
+            value_name = r#""ADDRESS""#,
+            alias = r#""tcp""#,
+            required_unless = r#""unix""#,
+            conflicts_with = r#""unix""#
+        )
+    )]
+    listen: Option<String>,
+    #[structopt(
+        long = "unix",
+        help = "Accept connections on a Unix socket created at this path instead, which is removed on exit",
+        raw(value_name = r#""PATH""#, alias = r#""socket""#)
+    )]
+    unix: Option<String>,
+    #[structopt(
+        long = "force",
+        help = "Replace the socket at the --unix path if one already exists, e.g. left by a kbsim that was killed. Other files are never replaced",
+        raw(requires = r#""unix""#)
+    )]
+    force: bool,
+    #[structopt(
+        long = "socket-mode",
+        help = "The permissions of the --unix socket, in octal such as 660, deciding who can connect to it. Otherwise they're from the umask",
+        raw(value_name = r#""MODE""#, requires = r#""unix""#),
+        parse(try_from_str = "parse_mode")
+    )]
+    socket_mode: Option<u32>,
+    #[structopt(
+        long = "secret-file",
+        help = "Only type for clients whose first line is the first line of this file, closing the connection otherwise",
+        raw(value_name = r#""PATH""#)
+    )]
+    secret_file: Option<String>,
+    #[structopt(
+        long = "max-bytes",
+        help = "Close a connection once it has sent this many bytes, leaving out the line that went over"
+    )]
+    max_bytes: Option<u64>,
+    #[structopt(
+        long = "bind-once",
+        help = "Exit once the first connection closes instead of waiting for another"
+    )]
+    bind_once: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct SendOpt {
+    #[structopt(
+        long = "connect",
+        help = "The address and port kbsim serve is listening on, e.g. kbsim-host:7000",
+        raw(
+            value_name = r#""ADDRESS""#,
+            required_unless = r#""unix""#,
+            conflicts_with = r#""unix""#
+        )
+    )]
+    connect: Option<String>,
+    #[structopt(
+        long = "unix",
+        help = "Send to kbsim serve listening on the Unix socket at this path instead",
+        raw(value_name = r#""PATH""#, alias = r#""socket""#)
+    )]
+    unix: Option<String>,
+    #[structopt(
+        long = "secret-file",
+        help = "Send the first line of this file first, for kbsim serve --secret-file",
+        raw(value_name = r#""PATH""#)
+    )]
+    secret_file: Option<String>,
+    #[structopt(
+        name = "STRING",
+        help = "The text to send, which is typed a line at a time. Reads stdin until the end of input without it"
+    )]
+    string: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct LedsOpt {
+    #[structopt(
+        long = "hid-file",
+        short = "f",
+        help = "The HID file to read the LED reports from",
+        default_value = "/dev/hidg0"
+    )]
+    hid_file: String,
+    #[structopt(
+        long = "timeout",
+        help = "How long to wait for a report when the host hasn't sent one since the HID file was last read, such as 1s. A number without a unit is milliseconds",
+        default_value = "200",
+        parse(try_from_str = "parse_cooldown")
+    )]
+    timeout: Duration,
+    #[structopt(
+        long = "format",
+        help = "Print the names of the LEDs that are on, or a JSON object with whether each LED is on",
+        default_value = "plain",
+        raw(possible_values = r#"&["plain", "json"]"#)
+    )]
+    format: Format,
+}
+
+#[derive(Debug, StructOpt)]
+struct MouseOpt {
+    #[structopt(flatten)]
+    device: DeviceOpt,
+    #[structopt(flatten)]
+    actions: MouseActions,
+}
+
+/// Something the mouse does
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MouseAction {
+    Move(i32, i32),
+    Scroll(i32),
+    Click(Button),
+    DoubleClick(Button),
+    Press(Button),
+    Release(Button),
+}
+
+/// The options for each mouse action, with their value names and help
+const MOUSE_ACTIONS: &[(&str, &str, &str)] = &[
+    (
+        "move",
+        "X,Y",
+        "Move the pointer by X and Y, where positive Y is down, e.g. 10,-5",
+    ),
+    (
+        "click",
+        "BUTTON",
+        "Click a button: left, right or middle",
+    ),
+    ("double-click", "BUTTON", "Double click a button"),
+    (
+        "scroll",
+        "TICKS",
+        "Scroll the wheel by this many ticks, where positive is up",
+    ),
+    (
+        "press",
+        "BUTTON",
+        "Press a button and hold it, e.g. to drag with --move, until --release. Buttons still held at the end are released",
+    ),
+    ("release", "BUTTON", "Release a button held with --press"),
+];
+
+/// The mouse actions, in the order they were given. This is built by hand because the order of
+/// different options is lost by the derived parser, and each of them can be repeated.
+#[derive(Debug)]
+struct MouseActions(Vec<MouseAction>);
+
+impl MouseActions {
+    pub fn augment_clap<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let names: Vec<&str> = MOUSE_ACTIONS.iter().map(|(name, _, _)| *name).collect();
+        MOUSE_ACTIONS
+            .iter()
+            .fold(app, |app, (name, value_name, help)| {
+                app.arg(
+                    Arg::with_name(name)
+                        .long(name)
+                        .value_name(value_name)
+                        .help(help)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .allow_hyphen_values(true)
+                        .validator(move |value| parse_mouse_action(name, &value).map(|_| ())),
+                )
+            })
+            .group(
+                ArgGroup::with_name("actions")
+                    .args(&names)
+                    .multiple(true)
+                    .required(true),
+            )
+    }
+
+    pub fn is_subcommand() -> bool {
+        false
+    }
+}
+
+impl StructOpt for MouseActions {
+    fn clap<'a, 'b>() -> App<'a, 'b> {
+        MouseActions::augment_clap(App::new("mouse"))
+    }
+
+    fn from_clap(matches: &ArgMatches) -> Self {
+        let mut actions = Vec::new();
+        for (name, _, _) in MOUSE_ACTIONS {
+            if let (Some(values), Some(indices)) =
+                (matches.values_of(name), matches.indices_of(name))
+            {
+                for (value, index) in values.zip(indices) {
+                    let action = parse_mouse_action(name, value).expect("validated when parsing");
+                    actions.push((index, action));
+                }
+            }
+        }
+        actions.sort_by_key(|(index, _)| *index);
+        MouseActions(actions.into_iter().map(|(_, action)| action).collect())
+    }
+}
+
+fn parse_mouse_action(name: &str, value: &str) -> Result<MouseAction, String> {
+    let button = || {
+        Button::parse(value)
+            .map_err(|_| format!("Unknown button {}, expected left, right or middle", value))
+    };
+    match name {
+        "move" => {
+            let invalid = || format!("Expected a move such as 10,-5, got {}", value);
+            let (x, y) = value.split_once(',').ok_or_else(invalid)?;
+            let x = x.trim().parse().map_err(|_| invalid())?;
+            let y = y.trim().parse().map_err(|_| invalid())?;
+            Ok(MouseAction::Move(x, y))
+        }
+        "scroll" => value
+            .trim()
+            .parse()
+            .map(MouseAction::Scroll)
+            .map_err(|_| format!("Expected a number of ticks such as 3 or -3, got {}", value)),
+        "click" => button().map(MouseAction::Click),
+        "double-click" => button().map(MouseAction::DoubleClick),
+        "press" => button().map(MouseAction::Press),
+        "release" => button().map(MouseAction::Release),
+        _ => unreachable!("only the mouse actions are parsed"),
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct ListOpt {
+    #[structopt(
+        long = "format",
+        help = "Print the layouts as plain names, one per line, or as a JSON array of objects with their name, aliases and whether they are built in",
+        default_value = "plain",
+        raw(possible_values = r#"&["plain", "json"]"#)
+    )]
+    format: Format,
+    #[structopt(
+        name = "FILTER",
+        help = "Only show the layouts whose name or an alias contains this, ignoring case"
+    )]
+    filter: Option<String>,
+    #[structopt(
+        long = "filter",
+        help = "The same as FILTER, for scripts that name their arguments",
+        raw(value_name = r#""FILTER""#, conflicts_with = r#""FILTER""#)
+    )]
+    named_filter: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct DumpOpt {
+    #[structopt(flatten)]
+    layout: LayoutOpt,
+    #[structopt(
+        long = "format",
+        help = "Print a table of the characters, or a JSON array of objects with each character and its key presses",
+        default_value = "plain",
+        raw(possible_values = r#"&["plain", "json"]"#)
+    )]
+    format: Format,
+    #[structopt(
+        long = "template",
+        help = "Print the layout as a TOML layout file for --layout-file to start a custom layout from, or JSON with --format json"
+    )]
+    template: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct InspectOpt {
+    #[structopt(flatten)]
+    layout: LayoutOpt,
+    #[structopt(
+        long = "format",
+        help = "Print a table of the characters, or a JSON array of objects with each character and the reports that type it",
+        default_value = "plain",
+        raw(possible_values = r#"&["plain", "json"]"#)
+    )]
+    format: Format,
+    #[structopt(
+        name = "STRING",
+        help = "The characters to inspect, printable ASCII when there is no STRING"
+    )]
+    string: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct CharOpt {
+    #[structopt(flatten)]
+    layout: LayoutOpt,
+    #[structopt(
+        long = "format",
+        help = "Print the presses and packets as text, or as a JSON object with the character, its code point, presses and packets",
+        default_value = "plain",
+        raw(possible_values = r#"&["plain", "json"]"#)
+    )]
+    format: Format,
+    #[structopt(name = "CHAR", help = "The character to show, such as a or ß")]
+    character: char,
+}
+
+/// How to print the output of list, dump, inspect and char
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Plain,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "plain" => Ok(Format::Plain),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown format {}, expected plain or json", format)),
+        }
+    }
+}
+
+/// The selection --clipboard reads on Linux
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClipboardSelection {
+    Clipboard,
+    Primary,
+}
+
+impl FromStr for ClipboardSelection {
+    type Err = String;
+
+    fn from_str(selection: &str) -> Result<Self, Self::Err> {
+        match selection {
+            "clipboard" => Ok(ClipboardSelection::Clipboard),
+            "primary" => Ok(ClipboardSelection::Primary),
+            _ => Err(format!(
+                "Unknown selection {}, expected clipboard or primary",
+                selection
+            )),
+        }
+    }
+}
+
+/// How to print the packets of a dry run
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PacketFormat {
+    Plain,
+    Json,
+    CArray,
+    Base64,
+}
+
+impl FromStr for PacketFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "plain" => Ok(PacketFormat::Plain),
+            "json" => Ok(PacketFormat::Json),
+            "c-array" => Ok(PacketFormat::CArray),
+            "base64" => Ok(PacketFormat::Base64),
+            _ => Err(format!(
+                "Unknown format {}, expected plain, json, c-array or base64",
+                format
+            )),
+        }
+    }
+}
+
+/// How --log-file logs the packets
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LogFormat {
+    Text,
+    JsonLines,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(LogFormat::Text),
+            "jsonl" => Ok(LogFormat::JsonLines),
+            _ => Err(format!(
+                "Unknown log format {}, expected text or jsonl",
+                format
+            )),
+        }
+    }
+}
+
+/// Where the keyboard's key presses go
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Backend {
+    /// The HID files of USB gadgets
+    Hidg,
+    /// A virtual keyboard on this machine
+    Uinput,
+    /// The operating system's input API on Windows and macOS
+    Native,
+}
+
+impl Backend {
+    fn name(self) -> &'static str {
+        match self {
+            Backend::Hidg => "hidg",
+            Backend::Uinput => "uinput",
+            Backend::Native => "native",
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(backend: &str) -> Result<Self, Self::Err> {
+        match backend {
+            "hidg" => Ok(Backend::Hidg),
+            "uinput" => Ok(Backend::Uinput),
+            "native" => Ok(Backend::Native),
+            _ => Err(format!(
+                "Unknown backend {}, expected hidg, uinput or native",
+                backend
+            )),
+        }
+    }
+}
+
+/// How uppercase letters are typed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UppercaseStrategy {
+    /// Shift is held with each of them
+    Shift,
+    /// Runs of them are typed with Caps Lock on
+    CapsLock,
+}
+
+impl FromStr for UppercaseStrategy {
+    type Err = String;
+
+    fn from_str(strategy: &str) -> Result<Self, Self::Err> {
+        match strategy {
+            "shift" => Ok(UppercaseStrategy::Shift),
+            "capslock" => Ok(UppercaseStrategy::CapsLock),
+            _ => Err(format!(
+                "Unknown uppercase strategy {}, expected shift or capslock",
+                strategy
+            )),
+        }
+    }
+}
+
+/// What to do with the characters the layout can't type, if there's no fallback or it can't type
+/// them either
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unmappable {
+    /// Refuse to type anything
+    Error,
+    /// Leave them out
+    Skip,
+    /// Leave them out with a warning
+    Warn,
+    /// Type this character instead
+    Replace(char),
+}
+
+impl FromStr for Unmappable {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "error" => Ok(Unmappable::Error),
+            "skip" => Ok(Unmappable::Skip),
+            "warn" => Ok(Unmappable::Warn),
+            _ => {
+                let mut replacement = policy.strip_prefix("replace=").unwrap_or("").chars();
+                match (replacement.next(), replacement.next()) {
+                    (Some(c), None) => Ok(Unmappable::Replace(c)),
+                    _ => Err(format!(
+                        "Unknown policy {}, expected error, skip, warn or replace=CHAR such as replace=?",
+                        policy
+                    )),
+                }
+            }
+        }
+    }
+}
+
+impl Unmappable {
+    /// The character typed instead of the ones the layout can't type
+    fn replacement(self) -> Option<char> {
+        match self {
+            Unmappable::Replace(c) => Some(c),
+            _ => None,
+        }
+    }
+}
+
+/// Changes made to text before it is converted, so fewer of its characters are ones the layout
+/// can't type
+#[derive(Debug, Clone)]
+struct Normalizer {
+    /// Compose characters with Unicode NFC normalization
+    nfc: bool,
+    /// Replace the characters in the fold that the layout can't type
+    fold: Option<Fold>,
+}
+
+impl Normalizer {
+    fn normalize(&self, layout: &str, text: &str) -> String {
+        let text = if self.nfc {
+            normalize::nfc(text)
+        } else {
+            text.to_string()
+        };
+        match &self.fold {
+            Some(fold) => fold.apply(layout, &text),
+            None => text,
+        }
+    }
+}
+
+/// How characters the layout can't type are typed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UnicodeFallback {
+    /// They aren't, they're unsupported
+    None,
+    /// By their code point with Ctrl+Shift+U, for IBus and GTK on Linux desktops
+    Ibus(unicode::IbusCommit),
+    /// By their code with Alt+Numpad, for Windows
+    AltNumpad(unicode::AltNumpad),
+}
+
+impl UnicodeFallback {
+    /// The keystroke typing a character the layout can't, if there's a fallback
+    fn keystroke(
+        self,
+        layout: &str,
+        c: char,
+    ) -> Option<Result<Keystroke, keyboard_layouts::Error>> {
+        match self {
+            UnicodeFallback::None => None,
+            UnicodeFallback::Ibus(commit) => Some(unicode::ibus_keystroke_with(layout, c, commit)),
+            UnicodeFallback::AltNumpad(options) => {
+                Some(unicode::alt_numpad_keystroke(layout, c, options))
+            }
+        }
+    }
+}
+
+impl FromStr for UnicodeFallback {
+    type Err = String;
+
+    fn from_str(fallback: &str) -> Result<Self, Self::Err> {
+        match fallback {
+            "none" => Ok(UnicodeFallback::None),
+            "ibus" => Ok(UnicodeFallback::Ibus(unicode::IbusCommit::Enter)),
+            "alt-numpad" => Ok(UnicodeFallback::AltNumpad(unicode::AltNumpad::default())),
+            _ => Err(format!(
+                "Unknown Unicode fallback {}, expected none, ibus or alt-numpad",
+                fallback
+            )),
+        }
+    }
+}
+
+/// How input typed from a terminal is echoed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EchoStyle {
+    Plain,
+    Dots,
+    None,
+}
+
+impl FromStr for EchoStyle {
+    type Err = String;
+
+    fn from_str(style: &str) -> Result<Self, Self::Err> {
+        match style {
+            "plain" => Ok(EchoStyle::Plain),
+            "dots" => Ok(EchoStyle::Dots),
+            "none" => Ok(EchoStyle::None),
+            _ => Err(format!(
+                "Unknown echo style {}, expected plain, dots or none",
+                style
+            )),
+        }
+    }
+}
+
+impl EchoStyle {
+    /// What to write to the terminal for the text. Backspace erases the character before it, so
+    /// the terminal matches what was typed.
+    fn echo(self, text: &str) -> String {
+        let mut echo = String::new();
+        for c in text.chars() {
+            match (self, c) {
+                (EchoStyle::None, _) => {}
+                (_, '\x08') | (_, '\x7f') => echo.push_str("\x08 \x08"),
+                (EchoStyle::Dots, c) if !c.is_control() => echo.push('•'),
+                (_, c) => echo.push(c),
+            }
+        }
+        echo
+    }
+}
+
+/// The --layout option. This is built by hand so that completion scripts can list the layouts,
+/// without restricting the names accepted when parsing.
+#[derive(Debug)]
+struct LayoutOpt {
+    name: Option<String>,
+}
+
+impl LayoutOpt {
+    pub fn augment_clap<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let arg = Arg::with_name("layout")
+            .long("layout")
+            .short("l")
+            .takes_value(true)
+            .help("The keyboard layout to use, either its full name or a short alias such as uk, us, de or fr. Specify auto to pick the layout for the locale. Defaults to the last --layout-file, or LAYOUT_UNITED_KINGDOM");
+        if GENERATING_COMPLETIONS.load(atomic::Ordering::SeqCst) {
+            let mut layouts = keyboard_layouts::available_layouts();
+            layouts.sort_unstable();
+            // The values need to outlive the app, which is only built once for completions
+            let layouts = Box::leak(layouts.into_boxed_slice());
+            app.arg(arg.possible_values(layouts).hide_possible_values(true))
+        } else {
+            app.arg(arg)
+        }
+    }
+
+    pub fn is_subcommand() -> bool {
+        false
+    }
+}
+
+impl StructOpt for LayoutOpt {
+    fn clap<'a, 'b>() -> App<'a, 'b> {
+        LayoutOpt::augment_clap(App::new("layout"))
+    }
+
+    fn from_clap(matches: &ArgMatches) -> Self {
+        LayoutOpt {
+            name: matches.value_of("layout").map(str::to_string),
+        }
+    }
+}
+
+fn main() {
+    let args = with_default_command(env::args_os().collect());
+    let matches = CliOpt::clap().get_matches_from(args);
+    let mut opt = CliOpt::from_clap(&matches);
+    QUIET.store(opt.quiet, atomic::Ordering::SeqCst);
+    init_logging(opt.verbose);
+
+    if let Err(e) = apply_config(&mut opt, &matches).and_then(|()| run(opt)) {
+        eprintln!("Error: {}", e);
+        match e.downcast_ref() {
+            Some(KeyboardError::Interrupted { .. }) => process::exit(130),
+            _ => process::exit(1),
+        }
+    }
+}
+
+/// The flags for every subcommand that can come before it, such as `-q` or `-vv`
+fn is_global_flag(arg: &str) -> bool {
+    match arg.strip_prefix('-') {
+        Some("-quiet") | Some("-verbose") => true,
+        Some(flags) => !flags.is_empty() && flags.chars().all(|c| c == 'q' || c == 'v'),
+        None => false,
+    }
+}
+
+/// Log to stderr at the level given by the number of `-v`s, for kbsim and the library, with any
+/// filters in RUST_LOG on top. Every packet is logged at the highest level, so it's timestamped.
+fn init_logging(verbose: u64) {
+    let level = match verbose {
+        0 => log::LevelFilter::Off,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    let trace = level == log::LevelFilter::Trace;
+    let mut builder = env_logger::Builder::new();
+    builder
+        .filter_module("kbsim", level)
+        .filter_module("keyboard_layouts", level)
+        .default_format_timestamp(trace)
+        .default_format_timestamp_nanos(trace);
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse(&filters);
+    }
+    builder.init();
+}
+
+/// Insert the `type` subcommand when the arguments don't start with one, so kbsim can still be run
+/// as it was before it had subcommands
+fn with_default_command(mut args: Vec<OsString>) -> Vec<OsString> {
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].to_str() {
+            Some("--config") | Some("--layout-file") => i += 2,
+            Some(arg) if arg.starts_with("--config=") || arg.starts_with("--layout-file=") => {
+                i += 1
+            }
+            Some(arg) if is_global_flag(arg) => i += 1,
+            Some("-h") | Some("--help") | Some("-V") | Some("--version") => return args,
+            Some("--generate-completions") => return args,
+            Some(arg) if arg.starts_with("--generate-completions=") => return args,
+            Some(arg) if COMMANDS.contains(&arg) => return args,
+            _ => break,
+        }
+    }
+    let i = i.min(args.len());
+
+    // config-dump used to follow the options it shows
+    if i < args.len() && args.last().is_some_and(|arg| arg == "config-dump") {
+        args.pop();
+        args.insert(i, "config-dump".into());
+    } else {
+        args.insert(i, "type".into());
+    }
+    args
+}
+
+fn run(opt: CliOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let mut custom_layout = None;
+    for path in opt.layout_files {
+        let layout = CustomLayout::load(&path)?;
+        custom_layout = Some(custom::register(layout)?);
+    }
+
+    if let Some(shell) = opt.generate_completions {
+        GENERATING_COMPLETIONS.store(true, atomic::Ordering::SeqCst);
+        CliOpt::clap().gen_completions_to("kbsim", shell, &mut io::stdout());
+        return Ok(());
+    }
+
+    match opt.command.ok_or("No subcommand given")? {
+        Command::Type(opt) => type_command(opt, custom_layout),
+        Command::List(opt) => list_layouts(opt.filter.or(opt.named_filter).as_deref(), opt.format),
+        Command::Raw(opt) => raw_command(opt),
+        Command::Dump(opt) => {
+            let layout = select_layout(opt.layout.name, custom_layout)?;
+            match opt.template {
+                true => dump_template(&layout, opt.format),
+                false => dump_layout(&layout, opt.format),
+            }
+        }
+        Command::Inspect(opt) => inspect_layout(
+            &select_layout(opt.layout.name, custom_layout)?,
+            opt.string.as_deref(),
+            opt.format,
+        ),
+        Command::Char(opt) => show_char(
+            &select_layout(opt.layout.name, custom_layout)?,
+            opt.character,
+            opt.format,
+        ),
+        Command::Serve(opt) => serve_command(opt, custom_layout),
+        Command::Send(opt) => send_command(opt),
+        Command::Mouse(opt) => mouse_command(opt),
+        Command::Leds(opt) => leds_command(opt),
+        Command::ConfigDump(opt) => config_dump(opt, custom_layout),
+    }
+}
+
+/// Resolve the chosen layout, which defaults to the last custom layout loaded or else the UK layout
+fn select_layout(layout: Option<String>, custom_layout: Option<&str>) -> Result<String, String> {
+    let layout = layout
+        .or_else(|| custom_layout.map(str::to_string))
+        .unwrap_or_else(|| "LAYOUT_UNITED_KINGDOM".to_string());
+    let resolved = if layout.eq_ignore_ascii_case("auto") {
+        detect_layout().map(str::to_string)
+    } else {
+        keyboard_layouts::resolve_layout(&layout)
+            .map(str::to_string)
+            .ok_or_else(|| unknown_layout_error(&layout))
+    };
+    if let Ok(layout) = &resolved {
+        log::info!("Using layout {}", layout);
+    }
+    resolved
+}
+
+/// Pick the layout matching the locale, or failing that the X11 keyboard layout reported by
+/// localectl, saying which was chosen on stderr
+fn detect_layout() -> Result<&'static str, String> {
+    let mut tried = Vec::new();
+    for name in &["LC_ALL", "LANG"] {
+        if let Some(locale) = env::var(name).ok().filter(|locale| !locale.is_empty()) {
+            if let Some(layout) = keyboard_layouts::locale::layout_for_locale(&locale) {
+                status!("Using {} for {}={}", layout, name, locale);
+                return Ok(layout);
+            }
+            tried.push(format!("{}={}", name, locale));
+            break;
+        }
+    }
+
+    match x11_layout() {
+        Some(x11) => match keyboard_layouts::locale::layout_for_x11(&x11) {
+            Some(layout) => {
+                status!("Using {} for the X11 layout {}", layout, x11);
+                return Ok(layout);
+            }
+            None => tried.push(format!("the X11 layout {}", x11)),
+        },
+        None => tried.push("localectl".to_string()),
+    }
+
+    Err(format!(
+        "Could not detect the keyboard layout from {}. Specify it with --layout instead, e.g. --layout uk, or run kbsim list to show all available layouts",
+        tried.join(" or ")
+    ))
+}
+
+/// Get the X11 keyboard layout from localectl, if it is installed and one is set
+fn x11_layout() -> Option<String> {
+    let output = process::Command::new("localectl")
+        .arg("status")
+        .output()
+        .ok()?;
+    parse_x11_layout(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Find the X11 layout in the output of `localectl status`
+fn parse_x11_layout(status: &str) -> Option<String> {
+    status
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("X11 Layout:"))
+        .map(|layout| layout.trim().to_string())
+        .filter(|layout| !layout.is_empty())
+}
+
+/// Print the layouts whose name or an alias contains the filter
+fn list_layouts(filter: Option<&str>, format: Format) -> Result<(), Box<dyn std::error::Error>> {
+    let custom = custom::registered();
+    let layouts = matching_layouts(filter);
+
+    match format {
+        Format::Plain => {
+            for (name, _) in layouts {
+                println!("{}", name);
+            }
+        }
+        Format::Json => {
+            let layouts: Vec<serde_json::Value> = layouts
+                .into_iter()
+                .map(|(name, aliases)| {
+                    serde_json::json!({
+                        "name": name,
+                        "aliases": aliases,
+                        "builtin": !custom.contains(&name),
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&layouts)?);
+        }
+    }
+    Ok(())
+}
+
+/// Get the layouts whose name or an alias contains the filter, ignoring case, with their aliases.
+/// They are sorted by name.
+fn matching_layouts(filter: Option<&str>) -> Vec<(&'static str, Vec<String>)> {
+    let filter = filter.map(str::to_lowercase);
+    let mut layouts: Vec<_> = keyboard_layouts::available_layouts()
+        .into_iter()
+        .map(|name| {
+            let aliases: Vec<String> = keyboard_layouts::layout_aliases(name)
+                .iter()
+                .map(|alias| alias.to_lowercase())
+                .collect();
+            (name, aliases)
+        })
+        .filter(|(name, aliases)| match &filter {
+            Some(filter) => {
+                name.to_lowercase().contains(filter)
+                    || aliases.iter().any(|alias| alias.contains(filter))
+            }
+            None => true,
+        })
+        .collect();
+    layouts.sort();
+    layouts
+}
+
+/// Print each character the layout can type, with the modifiers and key of each press it takes
+fn dump_layout(layout: &str, format: Format) -> Result<(), Box<dyn std::error::Error>> {
+    let keymap = keyboard_layouts::keymap(layout)?;
+
+    match format {
+        Format::Plain => {
+            println!("{:<10}PRESSES (MODIFIERS KEY)", "CHARACTER");
+            for (c, presses) in keymap {
+                let presses = presses
+                    .iter()
+                    .map(|press| format!("{:02x} {:02x}", press.modifier, press.key))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                println!("{:<10}{}", format!("{:?}", c), presses);
+            }
+        }
+        Format::Json => {
+            let keymap: Vec<serde_json::Value> = keymap
+                .into_iter()
+                .map(|(c, presses)| {
+                    let presses: Vec<serde_json::Value> = presses
+                        .iter()
+                        .map(|press| serde_json::json!({ "modifiers": press.modifier, "key": press.key }))
+                        .collect();
+                    serde_json::json!({ "char": c, "presses": presses })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&keymap)?);
+        }
+    }
+    Ok(())
+}
+
+/// Print the layout as a layout file, named after the layout, that loads as the same layout apart
+/// from the characters typed with a dead key
+fn dump_template(layout: &str, format: Format) -> Result<(), Box<dyn std::error::Error>> {
+    let name = format!("CUSTOM_{}", layout.trim_start_matches("LAYOUT_"));
+    let format = match format {
+        Format::Plain => custom::TemplateFormat::Toml,
+        Format::Json => custom::TemplateFormat::Json,
+    };
+    println!("{}", custom::template(layout, &name, format)?.trim_end());
+    Ok(())
+}
+
+/// Print how the layout types each character of the text, or of printable ASCII, with the
+/// modifiers, key and HID report of each press, flagging the characters it can't type
+fn inspect_layout(
+    layout: &str,
+    text: Option<&str>,
+    format: Format,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let characters: Vec<char> = match text {
+        Some(text) => text.chars().collect(),
+        None => (' '..='~').collect(),
+    };
+    let mut inspected = Vec::with_capacity(characters.len());
+    for c in characters {
+        inspected.push((c, char_presses(layout, c)?));
+    }
+    // The report pressing the key, the release that follows it is always the same
+    let report = |modifier: u8, key: u8| {
+        let mut report = [0u8; HID_PACKET_LEN];
+        report[0] = modifier;
+        report[2] = key;
+        hex_bytes(&report)
+    };
+
+    match format {
+        Format::Plain => {
+            println!("{:<10}{:<11}{:<9}REPORT", "CHARACTER", "MODIFIERS", "KEY");
+            for (c, presses) in inspected {
+                let presses = match presses {
+                    Some(presses) => presses,
+                    None => {
+                        println!("{:<10}unmappable", format!("{:?}", c));
+                        continue;
+                    }
+                };
+                let modifiers: Vec<String> = presses
+                    .iter()
+                    .map(|press| format!("{:02x}", press.modifier))
+                    .collect();
+                let keys: Vec<String> = presses
+                    .iter()
+                    .map(|press| format!("{:02x}", press.key))
+                    .collect();
+                let reports: Vec<String> = presses
+                    .iter()
+                    .map(|press| report(press.modifier, press.key))
+                    .collect();
+                println!(
+                    "{:<10}{:<11}{:<9}{}",
+                    format!("{:?}", c),
+                    modifiers.join(", "),
+                    keys.join(", "),
+                    reports.join(", ")
+                );
+            }
+        }
+        Format::Json => {
+            let inspected: Vec<serde_json::Value> = inspected
+                .into_iter()
+                .map(|(c, presses)| match presses {
+                    Some(presses) => {
+                        let presses: Vec<serde_json::Value> = presses
+                            .iter()
+                            .map(|press| {
+                                serde_json::json!({
+                                    "modifiers": press.modifier,
+                                    "key": press.key,
+                                    "report": report(press.modifier, press.key),
+                                })
+                            })
+                            .collect();
+                        serde_json::json!({ "char": c, "mappable": true, "presses": presses })
+                    }
+                    None => serde_json::json!({ "char": c, "mappable": false, "presses": [] }),
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&inspected)?);
+        }
+    }
+    Ok(())
+}
+
+/// Print every packet typing the character takes, pressing and releasing each key, failing if the
+/// layout can't type it
+fn show_char(layout: &str, c: char, format: Format) -> Result<(), Box<dyn std::error::Error>> {
+    let presses = match char_presses(layout, c)? {
+        Some(presses) => presses,
+        None => return Err(format!("{} can't type {:?}", layout, c).into()),
+    };
+    let keystrokes = keyboard_layouts::string_to_keystrokes(layout, &c.to_string())?;
+    let packets: Vec<(&[u8], &str)> = keystrokes
+        .iter()
+        .flat_map(|keystroke| keystroke.packets.chunks(HID_PACKET_LEN))
+        .map(|packet| {
+            let pressed = packet[2..].iter().any(|&key| key != 0);
+            (packet, if pressed { "press" } else { "release" })
+        })
+        .collect();
+
+    match format {
+        Format::Plain => {
+            println!("{:<11}{:?} (U+{:04X})", "CHARACTER", c, u32::from(c));
+            let presses: Vec<String> = presses
+                .iter()
+                .map(|press| format!("{:02x} {:02x}", press.modifier, press.key))
+                .collect();
+            println!("{:<11}{}", "PRESSES", presses.join(", "));
+            println!("PACKETS");
+            for (packet, kind) in packets {
+                println!("  {}  {}", hex_bytes(packet), kind);
+            }
+        }
+        Format::Json => {
+            let presses: Vec<serde_json::Value> = presses
+                .iter()
+                .map(|press| serde_json::json!({ "modifiers": press.modifier, "key": press.key }))
+                .collect();
+            let packets: Vec<serde_json::Value> = packets
+                .iter()
+                .map(|(packet, kind)| serde_json::json!({ "bytes": hex_bytes(packet), "kind": kind }))
+                .collect();
+            let shown = serde_json::json!({
+                "char": c,
+                "code_point": u32::from(c),
+                "presses": presses,
+                "packets": packets,
+            });
+            println!("{}", serde_json::to_string_pretty(&shown)?);
+        }
+    }
+    Ok(())
+}
+
+/// The key presses typing the character, without the releases between them, or none if the
+/// layout can't type it
+fn char_presses(
+    layout: &str,
+    c: char,
+) -> Result<Option<Vec<keyboard_layouts::KeyMod>>, keyboard_layouts::Error> {
+    let mut buf = [0u8; 4];
+    match keyboard_layouts::string_to_keys_and_modifiers(layout, c.encode_utf8(&mut buf)) {
+        Ok(keys) => Ok(Some(keys.into_iter().filter(|key| key.key != 0).collect())),
+        Err(keyboard_layouts::Error::InvalidCharacter(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn config_dump(
+    opt: TypeOpt,
+    custom_layout: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let layout = select_layout(opt.layout.name, custom_layout)?;
+    let device = opt.device;
+
+    let mut config = toml::Table::new();
+    let hid_files = device.hid_files();
+    if let [hid_file] = hid_files[..] {
+        config.insert("hid_file".into(), hid_file.into());
+    } else {
+        config.insert("hid_file".into(), hid_files.into());
+    }
+    config.insert("layout".into(), layout.into());
+    let cooldown = match opt.max_rate {
+        Some(0) => return Err("--max-rate must be at least 1".into()),
+        Some(max_rate) => max_rate_cooldown(max_rate),
+        None => device.cooldown,
+    };
+    config.insert("cooldown".into(), config_duration(cooldown, 1_000_000));
+    config.insert("delay".into(), config_duration(device.delay, 1_000_000_000));
+    config.insert("newline".into(), opt.newline.into());
+    if let Some(wpm) = opt.wpm {
+        config.insert("wpm".into(), i64::from(wpm).into());
+    }
+    config.insert(
+        "write_retries".into(),
+        i64::from(device.write_retries).into(),
+    );
+    if let Some(timeout) = device.write_timeout {
+        config.insert("write_timeout".into(), config_duration(timeout, 1_000_000));
+    }
+    print!("{}", toml::to_string(&config)?);
+    Ok(())
+}
+
+fn raw_command(opt: RawOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let packets = match (&opt.packets_base64, &opt.file) {
+        (Some(encoded), _) => parse_base64_packets(encoded)?,
+        (None, Some(path)) => parse_hex_file(path, &read_input_file(path)?)?,
+        (None, None) => parse_hex_packets(&opt.packets)?,
+    };
+
+    let device = opt.device;
+    let interrupt = handle_signals(device.release_files())?;
+    let mut keyboard = open_keyboard(
+        &device,
+        &device.hid_files(),
+        device.cooldown,
+        &interrupt,
+        "\n",
+    )?
+    .with_delay(device.delay)
+    .release_on_drop();
+    keyboard.send_packets(&packets)?;
+    Ok(())
+}
+
+fn leds_command(opt: LedsOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let mut leds = LedReader::open(&opt.hid_file).map_err(|e| {
+        format!(
+            "Could not read the LED reports from {}: {}",
+            opt.hid_file, e
+        )
+    })?;
+    let leds = leds
+        .wait(opt.timeout)?
+        .ok_or_else(|| {
+            format!(
+                "The host hasn't sent an LED report on {} since it was last read. It sends one when a lock key changes",
+                opt.hid_file
+            )
+        })?;
+
+    match opt.format {
+        Format::Plain => println!("{}", leds),
+        Format::Json => {
+            let object: serde_json::Map<String, serde_json::Value> = LED_NAMES
+                .iter()
+                .map(|(name, led)| (name.to_string(), leds.is_on(*led).into()))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&object)?);
+        }
+    }
+    Ok(())
+}
+
+fn mouse_command(opt: MouseOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let mut device = opt.device;
+    if device.hid_file.is_empty() {
+        device.hid_file = vec![DEFAULT_MOUSE_FILE.to_string()];
+    }
+    if device.backend() != Backend::Hidg {
+        return Err(format!(
+            "The mouse needs a mouse gadget, it can't be used with --backend {}",
+            device.backend().name()
+        )
+        .into());
+    }
+
+    let interrupt = handle_signals(device.release_files())?;
+    let mut keyboard = open_keyboard(
+        &device,
+        &device.hid_files(),
+        device.cooldown,
+        &interrupt,
+        "\n",
+    )?
+    .with_delay(device.delay);
+    let mut mouse = Mouse::new();
+    let result = opt.actions.0.iter().try_for_each(|action| {
+        let packets = match *action {
+            MouseAction::Move(x, y) => mouse.move_by(x, y),
+            MouseAction::Scroll(ticks) => mouse.scroll(ticks),
+            MouseAction::Click(button) => mouse.click(button),
+            MouseAction::DoubleClick(button) => mouse.double_click(button),
+            MouseAction::Press(button) => mouse.press(button),
+            MouseAction::Release(button) => mouse.release(button),
+        };
+        packets
+            .chunks(MOUSE_PACKET_LEN)
+            .try_for_each(|packet| keyboard.send_packet(packet))
+    });
+    match result {
+        Ok(()) if mouse.buttons_held() => keyboard.send_packet(&mouse.release_all())?,
+        Ok(()) => {}
+        Err(e) => {
+            // Don't leave a button held, which would keep dragging
+            let _ = keyboard.get_mut().write_packet(&mouse.release_all());
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+fn serve_command(
+    opt: ServeOpt,
+    custom_layout: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let layout = select_layout(opt.layout.name, custom_layout)?;
+    let options = ServeOptions {
+        secret: opt.secret_file.as_deref().map(read_secret).transpose()?,
+        max_bytes: opt.max_bytes,
+        dry_run: opt.device.dry_run,
+    };
+    let listener = match (&opt.listen, &opt.unix) {
+        (Some(listen), _) => Listener::tcp(listen)?,
+        (None, Some(path)) => Listener::unix(Path::new(path), opt.force, opt.socket_mode)?,
+        (None, None) => return Err("Either --listen or --unix is required".into()),
+    };
+
+    let device = opt.device;
+    // SIGTERM, e.g. from systemd stopping the service, lets the line being typed finish
+    FINISH_ON_SIGTERM.store(true, atomic::Ordering::SeqCst);
+    let interrupt = handle_signals(device.release_files())?;
+    let mut keyboard = open_keyboard(
+        &device,
+        &device.hid_files(),
+        device.cooldown,
+        &interrupt,
+        "\n",
+    )?
+    .with_delay(device.delay)
+    .release_on_drop();
+
+    loop {
+        let (stream, peer) = match listener.accept() {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("Could not accept a connection: {}", e);
+                continue;
+            }
+        };
+        status!("Connection from {}", peer);
+
+        let mut received = Received::default();
+        let result = type_connection(
+            &mut keyboard,
+            &layout,
+            stream,
+            &peer,
+            &options,
+            &mut received,
+        );
+        let end = result.as_ref().map_or("failed", ConnectionEnd::as_str);
+        log_connection(
+            &peer,
+            &received,
+            end,
+            device.log_format.unwrap_or(LogFormat::Text),
+        );
+        // A client leaving part way through a line mustn't leave a modifier held
+        if keyboard.keys_held() {
+            keyboard.release_all()?;
+        }
+        result?;
+        log::info!(
+            "Connection from {} sent {} bytes, {}",
+            peer,
+            received.bytes,
+            end
+        );
+        status!("{} disconnected after {} line(s)", peer, received.lines);
+
+        if opt.bind_once {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Send the text, or stdin, to kbsim serve
+fn send_command(opt: SendOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let mut text = match &opt.secret_file {
+        Some(path) => format!("{}\n", read_secret(path)?).into_bytes(),
+        None => Vec::new(),
+    };
+    match opt.string {
+        Some(string) => text.extend(string.into_bytes()),
+        None => {
+            io::stdin().read_to_end(&mut text)?;
+        }
+    }
+    match (&opt.connect, &opt.unix) {
+        (Some(address), _) => {
+            let mut stream = std::net::TcpStream::connect(address)
+                .map_err(|e| format!("Could not connect to {}: {}", address, e))?;
+            stream.write_all(&text)?;
+        }
+        (None, Some(path)) => send_unix(path, &text)?,
+        (None, None) => return Err("Either --connect or --unix is required".into()),
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn send_unix(path: &str, text: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stream = std::os::unix::net::UnixStream::connect(path)
+        .map_err(|e| format!("Could not connect to {}: {}", path, e))?;
+    stream.write_all(text)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_unix(_path: &str, _text: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    Err("--unix is only supported on Unix".into())
+}
+
+/// Where serve accepts connections
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener, SocketFile),
+}
+
+impl Listener {
+    fn tcp(address: &str) -> Result<Listener, Box<dyn std::error::Error>> {
+        let listener = TcpListener::bind(address)
+            .map_err(|e| format!("Could not listen on {}: {}", address, e))?;
+        status!("Listening on {}", listener.local_addr()?);
+        Ok(Listener::Tcp(listener))
+    }
+
+    /// Listen on a Unix socket, refusing to replace an existing file unless forced, with the given
+    /// permissions
+    #[cfg(unix)]
+    fn unix(
+        path: &Path,
+        force: bool,
+        mode: Option<u32>,
+    ) -> Result<Listener, Box<dyn std::error::Error>> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            use std::os::unix::fs::FileTypeExt;
+
+            // A mistyped path mustn't delete a file that isn't a socket
+            if !metadata.file_type().is_socket() {
+                return Err(format!(
+                    "{} already exists and isn't a socket, refusing to replace it",
+                    path.display()
+                )
+                .into());
+            }
+            if !force {
+                return Err(format!(
+                    "{} already exists, use --force to replace it",
+                    path.display()
+                )
+                .into());
+            }
+            fs::remove_file(path)
+                .map_err(|e| format!("Could not remove {}: {}", path.display(), e))?;
+        }
+        // Create the socket with no more permissions than the mode gives, so nobody else can
+        // connect before they're set
+        let umask = mode.map(|mode| unsafe { libc::umask(0o777 & !mode as libc::mode_t) });
+        let listener = std::os::unix::net::UnixListener::bind(path);
+        if let Some(umask) = umask {
+            unsafe { libc::umask(umask) };
+        }
+        let listener =
+            listener.map_err(|e| format!("Could not listen on {}: {}", path.display(), e))?;
+        let socket_file = SocketFile::new(path);
+        if let Some(mode) = mode {
+            use std::os::unix::fs::PermissionsExt;
+
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))
+                .map_err(|e| format!("Could not set the mode of {}: {}", path.display(), e))?;
+        }
+        status!("Listening on {}", path.display());
+        Ok(Listener::Unix(listener, socket_file))
+    }
+
+    #[cfg(not(unix))]
+    fn unix(
+        _path: &Path,
+        _force: bool,
+        _mode: Option<u32>,
+    ) -> Result<Listener, Box<dyn std::error::Error>> {
+        Err("--unix is only supported on Unix".into())
+    }
+
+    /// Wait for the next connection, returning it with a description of the client
+    fn accept(&self) -> io::Result<(Box<dyn Read>, String)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, addr) = listener.accept()?;
+                Ok((Box::new(stream), addr.to_string()))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => {
+                let (stream, _) = listener.accept()?;
+                Ok((Box::new(stream), "a local client".to_string()))
+            }
+        }
+    }
+}
+
+/// Removes the Unix socket file when serve exits, or from the signal handler when interrupted
+#[cfg(unix)]
+struct SocketFile;
+
+#[cfg(unix)]
+impl SocketFile {
+    fn new(path: &Path) -> Self {
+        *SOCKET_PATH.lock().unwrap() = Some(path.to_path_buf());
+        SocketFile
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        remove_socket_file();
+    }
+}
+
+#[cfg(unix)]
+fn remove_socket_file() {
+    if let Some(path) = SOCKET_PATH.lock().ok().and_then(|mut path| path.take()) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// The first line of the --secret-file
+fn read_secret(path: &str) -> Result<String, String> {
+    let secret = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read the secret from {}: {}", path, e))?;
+    match secret.lines().next() {
+        Some(secret) if !secret.is_empty() => Ok(secret.to_string()),
+        _ => Err(format!("{} doesn't start with a secret", path)),
+    }
+}
+
+/// Compare the secrets without stopping at the first difference, so how long it takes doesn't
+/// give away how much of a guess was right
+fn secrets_match(given: &[u8], secret: &[u8]) -> bool {
+    given.len() == secret.len()
+        && given
+            .iter()
+            .zip(secret)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+struct ServeOptions {
+    secret: Option<String>,
+    max_bytes: Option<u64>,
+    dry_run: bool,
+}
+
+/// The lines and bytes a client sent serve
+#[derive(Debug, Default)]
+struct Received {
+    lines: usize,
+    bytes: u64,
+}
+
+/// Why serve stopped reading from a connection, other than failing to type
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConnectionEnd {
+    Closed,
+    ReadFailed,
+    NoSecret,
+    MaxBytes,
+}
+
+impl ConnectionEnd {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionEnd::Closed => "closed",
+            ConnectionEnd::ReadFailed => "read-failed",
+            ConnectionEnd::NoSecret => "no-secret",
+            ConnectionEnd::MaxBytes => "max-bytes",
+        }
+    }
+}
+
+/// Type each line received on the connection until it closes, counting the lines and bytes
+/// received. A connection without the secret is closed before anything is typed. An error reading
+/// from the connection ends it, only errors writing to the keyboard are returned.
+fn type_connection(
+    keyboard: &mut HidKeyboard<Output>,
+    layout: &str,
+    stream: Box<dyn Read>,
+    peer: &str,
+    options: &ServeOptions,
+    received: &mut Received,
+) -> Result<ConnectionEnd, KeyboardError> {
+    // Reading one byte more than allowed shows that the client sent too much
+    let limit = options
+        .max_bytes
+        .map_or(u64::MAX, |max| max.saturating_add(1));
+    let mut reader = io::BufReader::new(stream.take(limit));
+    let mut line = Vec::new();
+    let mut authenticated = options.secret.is_none();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => return Ok(ConnectionEnd::Closed),
+            Ok(read) => received.bytes += read as u64,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                eprintln!("Could not read from the connection: {}", e);
+                return Ok(ConnectionEnd::ReadFailed);
+            }
+        }
+        if let (false, Some(secret)) = (authenticated, &options.secret) {
+            let given = line.strip_suffix(b"\n").unwrap_or(&line);
+            let given = given.strip_suffix(b"\r").unwrap_or(given);
+            if !secrets_match(given, secret.as_bytes()) {
+                eprintln!(
+                    "Closing the connection from {}, which didn't send the secret",
+                    peer
+                );
+                return Ok(ConnectionEnd::NoSecret);
+            }
+            authenticated = true;
+            continue;
+        }
+        if let Some(max) = options.max_bytes.filter(|&max| received.bytes > max) {
+            eprintln!(
+                "Closing the connection from {} after --max-bytes {}",
+                peer, max
+            );
+            return Ok(ConnectionEnd::MaxBytes);
+        }
+        received.lines += 1;
+        let lines = received.lines;
+
+        let text = match std::str::from_utf8(&line) {
+            Ok(text) => text,
+            Err(_) => {
+                eprintln!("Skipping line {}, which isn't valid UTF-8", lines);
+                continue;
+            }
+        };
+        let (keystrokes, unsupported) =
+            match to_keystrokes(layout, text, false, 0, UnicodeFallback::None, None) {
+                Ok(keystrokes) => keystrokes,
+                Err(e) => {
+                    eprintln!("Skipping line {}: {}", lines, e);
+                    continue;
+                }
+            };
+        // Characters the layout can't type are left out with a warning, rather than stopping
+        let _ = check_unsupported(layout, &unsupported, Unmappable::Warn, options.dry_run);
+        let _in_flight = IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner());
+        send_keystrokes(keyboard, &keystrokes, None, None)?;
+    }
+}
+
+struct WatchOptions {
+    interval: Duration,
+    fast: bool,
+    optimize_modifiers: bool,
+    line_ending: LineEnding,
+    normalizer: Normalizer,
+    unmappable: Unmappable,
+    unicode_fallback: UnicodeFallback,
+    dry_run: bool,
+    caps_lock_threshold: Option<usize>,
+}
+
+/// What --watch types the lines of, or --follow types everything from
+enum Watched {
+    Lines(Tail),
+    Follow(Follow, Utf8Decoder),
+}
+
+impl Watched {
+    /// The text that has arrived since the last call, with the reason each part that isn't valid
+    /// UTF-8 is skipped
+    fn read(&mut self) -> io::Result<Vec<Result<String, String>>> {
+        match self {
+            Watched::Lines(tail) => Ok(tail
+                .read_lines()?
+                .into_iter()
+                .map(|line| {
+                    String::from_utf8(line)
+                        .map_err(|_| "Skipping a line which isn't valid UTF-8".to_string())
+                })
+                .collect()),
+            // A character split between two reads is carried over to the next
+            Watched::Follow(follow, decoder) => Ok(decoder
+                .decode(&follow.read()?)
+                .into_iter()
+                .map(|chunk| match chunk {
+                    Chunk::Text(_, text) => Ok(text),
+                    Chunk::Invalid(offset, bytes) => Err(format!(
+                        "Skipping {} byte(s) at byte {}, which aren't valid UTF-8",
+                        bytes.len(),
+                        offset
+                    )),
+                })
+                .collect()),
+        }
+    }
+
+    /// Whether reading waits for more text, so there's no need to wait between reads
+    fn blocks(&self) -> bool {
+        matches!(self, Watched::Follow(follow, _) if follow.blocks())
+    }
+}
+
+/// Type the lines appended to the file, or for --follow everything that arrives, until
+/// interrupted, or until reading or typing fails. Text with characters the layout can't type is
+/// left out, unless they're being skipped or replaced.
+fn watch_file(
+    keyboard: &mut HidKeyboard<Output>,
+    layout: &str,
+    watched: &mut Watched,
+    mut humanizer: Option<&mut Humanizer>,
+    mut typos: Option<&mut Typos>,
+    mut lock_keys: Option<&mut LockKeys>,
+    options: &WatchOptions,
+) -> Result<(), KeyboardError> {
+    let mut line_endings = Converter::new(options.line_ending);
+    loop {
+        for text in watched.read()? {
+            let text = match text {
+                Ok(text) => text,
+                Err(skipping) => {
+                    eprintln!("{}", skipping);
+                    continue;
+                }
+            };
+            let text = line_endings.convert(&text);
+            let text = options.normalizer.normalize(layout, &text);
+            let converted = to_keystrokes(
+                layout,
+                &text,
+                false,
+                0,
+                options.unicode_fallback,
+                options.unmappable.replacement(),
+            )
+            .and_then(|(keystrokes, unsupported)| {
+                let keystrokes =
+                    with_uppercase_strategy(layout, keystrokes, options.caps_lock_threshold)?;
+                let keystrokes = with_typos(typos.as_deref_mut(), keystrokes);
+                let keystrokes = with_rollover(options.fast, keystrokes);
+                let keystrokes = with_optimized_modifiers(options.optimize_modifiers, keystrokes);
+                Ok((keystrokes, unsupported))
+            });
+            let (keystrokes, unsupported) = match converted {
+                Ok(keystrokes) => keystrokes,
+                Err(e) => {
+                    eprintln!("Skipping {:?}: {}", text.trim_end(), e);
+                    continue;
+                }
+            };
+            if let Err(e) =
+                check_unsupported(layout, &unsupported, options.unmappable, options.dry_run)
+            {
+                eprintln!("Skipping {:?}: {}", text.trim_end(), e);
+                continue;
+            }
+            if let Some(lock_keys) = lock_keys.as_deref_mut() {
+                lock_keys.set(keyboard)?;
+            }
+            send_keystrokes(keyboard, &keystrokes, humanizer.as_deref_mut(), None)?;
+        }
+        if !watched.blocks() {
+            keyboard.wait(options.interval)?;
+        }
+    }
+}
+
+/// Parse HID packets written as base64
+fn parse_base64_packets(encoded: &str) -> Result<Vec<u8>, String> {
+    let bytes = base64::decode(encoded)?;
+    check_packet_len(&bytes)?;
+    Ok(bytes)
+}
+
+/// Parse HID packets written as hex, where the bytes can be separated by whitespace or not
+fn parse_hex_packets(args: &[String]) -> Result<Vec<u8>, String> {
+    let bytes = parse_hex(args.iter().map(String::as_str))?;
+    check_packet_len(&bytes)?;
+    Ok(bytes)
+}
+
+/// Parse a file of HID packets, one per line as hex, leaving out blank lines and `#` comments
+fn parse_hex_file(path: &str, text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |e: String| format!("Line {} of {}: {}", number + 1, path, e);
+        let packet = parse_hex(std::iter::once(line)).map_err(invalid)?;
+        if packet.len() != HID_PACKET_LEN {
+            return Err(invalid(format!(
+                "expected a packet of {} bytes, got {} byte(s)",
+                HID_PACKET_LEN,
+                packet.len()
+            )));
+        }
+        bytes.extend(packet);
+    }
+    if bytes.is_empty() {
+        return Err(format!("No packets in {}", path));
+    }
+    Ok(bytes)
+}
+
+/// Parse bytes written as hex, separated by whitespace or not
+fn parse_hex<'a>(text: impl Iterator<Item = &'a str>) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for word in text.flat_map(str::split_whitespace) {
+        if !word.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid hex in {}", word));
+        }
+        if word.len() % 2 != 0 {
+            return Err(format!("Odd number of hex digits in {}", word));
+        }
+        for i in (0..word.len()).step_by(2) {
+            // All ASCII hex digits, so this can't fail
+            bytes.push(u8::from_str_radix(&word[i..i + 2], 16).unwrap());
+        }
+    }
+    Ok(bytes)
+}
+
+fn check_packet_len(bytes: &[u8]) -> Result<(), String> {
+    if bytes.is_empty() || !bytes.len().is_multiple_of(HID_PACKET_LEN) {
+        return Err(format!(
+            "Expected a whole number of {} byte packets, got {} byte(s)",
+            HID_PACKET_LEN,
+            bytes.len()
+        ));
+    }
+    Ok(())
+}
+
+fn type_command(
+    opt: TypeOpt,
+    custom_layout: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let TypeOpt {
+        device,
+        layout,
+        newline,
+        no_newline: _,
+        line_endings,
+        line_mode,
+        wpm,
+        max_rate,
+        input_file,
+        decode,
+        clipboard,
+        clipboard_selection,
+        stdin,
+        exit_key,
+        forward_ctrl_c,
+        echo_style,
+        no_echo,
+        script,
+        no_escapes,
+        backslash_escapes,
+        skip_unsupported,
+        on_unmappable,
+        unicode_fallback,
+        normalize,
+        ascii_fold,
+        char_map,
+        alt_numpad_hex,
+        ibus_space,
+        surrogate_pairs,
+        force_numlock,
+        respect_capslock,
+        reset_modifiers,
+        uppercase_strategy,
+        capslock_threshold,
+        keys,
+        hold,
+        hold_key,
+        hold_for,
+        media,
+        consumer_file,
+        consumer_report_id,
+        consumer_report_len,
+        watch,
+        follow,
+        watch_interval,
+        repeat,
+        interval,
+        humanize,
+        jitter,
+        word_pause,
+        sentence_pause,
+        newline_pause,
+        gaussian,
+        typo_rate,
+        correction_pause,
+        seed,
+        fast,
+        optimize_modifiers,
+        progress,
+        no_progress,
+        stats,
+        estimate,
+        yes,
+        confirm,
+        string,
+    } = opt;
+
+    // Layouts used to be listed with --layout list, or --layout list:FILTER
+    if let Some(name) = &layout.name {
+        let lower = name.to_lowercase();
+        if lower == "list" || lower.starts_with("list:") {
+            return list_layouts(name.get(5..).filter(|f| !f.is_empty()), Format::Plain);
+        }
+    }
+    let layout = select_layout(layout.name, custom_layout)?;
+
+    let cooldown = match (wpm, max_rate) {
+        (Some(0), _) => return Err("--wpm must be at least 1".into()),
+        (_, Some(0)) => return Err("--max-rate must be at least 1".into()),
+        (Some(wpm), _) => {
+            let per_character = wpm_to_character_delay(wpm);
+            log::info!("Typing at {} WPM: {:?} per character", wpm, per_character);
+            if jitter.is_some_and(|jitter| jitter / 2 > per_character) {
+                eprintln!(
+                    "Warning: the --jitter adds more than the {:?} per character of {} WPM, so typing will be slower",
+                    per_character, wpm
+                );
+            }
+            wpm_cooldown(per_character, jitter)
+        }
+        (None, Some(max_rate)) => {
+            let cooldown = max_rate_cooldown(max_rate);
+            log::info!(
+                "Writing at most {} bytes per second: one {} byte packet every {:?}",
+                max_rate,
+                HID_PACKET_LEN,
+                cooldown
+            );
+            cooldown
+        }
+        (None, None) => device.cooldown,
+    };
+    match (humanize, jitter) {
+        (Some((min, max)), _) => log::info!("Waiting {:?} to {:?} after each keystroke", min, max),
+        (None, Some(jitter)) => log::info!(
+            "Waiting {:?} after each packet, and up to {:?} more after each keystroke",
+            cooldown,
+            jitter
+        ),
+        (None, None) => log::info!("Waiting {:?} after each packet", cooldown),
+    }
+    if gaussian && humanize.is_none() && jitter.is_none() {
+        return Err("--gaussian is only used with --humanize or --jitter".into());
+    }
+    if seed.is_some() && humanize.is_none() && jitter.is_none() && typo_rate.is_none() {
+        return Err("--seed is only used with --humanize, --jitter or --typo-rate".into());
+    }
+    let delay = device.delay;
+    let unicode_fallback = match unicode_fallback {
+        UnicodeFallback::AltNumpad(_) => UnicodeFallback::AltNumpad(unicode::AltNumpad {
+            hex: alt_numpad_hex,
+            surrogate_pairs,
+        }),
+        _ if alt_numpad_hex || surrogate_pairs || force_numlock => {
+            return Err("--alt-numpad-hex, --surrogate-pairs and --force-numlock are only used with --unicode-fallback alt-numpad".into())
+        }
+        UnicodeFallback::Ibus(_) if ibus_space => UnicodeFallback::Ibus(unicode::IbusCommit::Space),
+        _ if ibus_space => {
+            return Err("--ibus-space is only used with --unicode-fallback ibus".into())
+        }
+        fallback => fallback,
+    };
+    let unmappable = if skip_unsupported {
+        Unmappable::Warn
+    } else {
+        on_unmappable
+    };
+    if let Some(replacement) = unmappable.replacement() {
+        if keyboard_layouts::string_to_keystrokes(&layout, &replacement.to_string()).is_err() {
+            return Err(format!(
+                "{} can't type the replacement {:?} either, choose another with --on-unmappable replace=CHAR",
+                layout, replacement
+            )
+            .into());
+        }
+    }
+    let fold = match (char_map, ascii_fold) {
+        (Some(path), _) => Some(Fold::ascii().with_char_map(path)?),
+        (None, true) => Some(Fold::ascii()),
+        (None, false) => None,
+    };
+    let normalizer = Normalizer {
+        nfc: normalize,
+        fold,
+    };
+    // Alt+Numpad input needs Num Lock on, which is pressed without checking with --force-numlock
+    let mut lock_keys = Vec::new();
+    if respect_capslock {
+        lock_keys.push(Lock::CapsLock);
+    }
+    let alt_numpad = matches!(unicode_fallback, UnicodeFallback::AltNumpad(_));
+    if alt_numpad && !force_numlock && device.backend() == Backend::Hidg {
+        lock_keys.push(Lock::NumLock);
+    }
+    let caps_lock_threshold = match uppercase_strategy {
+        UppercaseStrategy::Shift => None,
+        UppercaseStrategy::CapsLock if capslock_threshold == 0 => {
+            return Err("--capslock-threshold must be at least 1".into())
+        }
+        UppercaseStrategy::CapsLock => Some(capslock_threshold),
+    };
+    // The jitter and pauses are waited after the keyboard's cooldown, so their range starts at no
+    // extra time
+    let pauses = word_pause.is_some()
+        || sentence_pause.is_some()
+        || newline_pause.is_some()
+        || correction_pause.is_some();
+    let range = humanize.or_else(|| match (jitter, pauses) {
+        (Some(jitter), _) => Some((Duration::from_secs(0), jitter)),
+        (None, true) => Some((Duration::from_secs(0), Duration::from_secs(0))),
+        (None, false) => None,
+    });
+    let mut humanizer = range.map(|(min, max)| {
+        let distribution = if gaussian {
+            Distribution::Gaussian
+        } else {
+            Distribution::Uniform
+        };
+        let humanizer = Humanizer::new(min, max).with_distribution(distribution);
+        let humanizer = if humanize.is_some() {
+            humanizer
+        } else {
+            humanizer.without_punctuation_pauses()
+        };
+        let humanizer = humanizer
+            .with_word_pause(word_pause.unwrap_or_default())
+            .with_sentence_pause(sentence_pause.unwrap_or_default())
+            .with_correction_pause(correction_pause.unwrap_or_default());
+        let humanizer = match newline_pause {
+            Some(pause) => humanizer.with_line_pause(pause),
+            None => humanizer,
+        };
+        match seed {
+            Some(seed) => humanizer.with_seed(seed),
+            None => humanizer,
+        }
+    });
+
+    let mut typos = match typo_rate {
+        Some(rate) => {
+            let typos = Typos::new(&layout, rate)?;
+            Some(match seed {
+                Some(seed) => typos.with_seed(seed),
+                None => typos,
+            })
+        }
+        None => None,
+    };
+
+    let interrupt = handle_signals(device.release_files())?;
+    let open = |line_ending| {
+        let keyboard = open_keyboard(
+            &device,
+            &device.hid_files(),
+            cooldown,
+            &interrupt,
+            line_ending,
+        )?;
+        let keyboard = match reset_modifiers {
+            true => keyboard.with_modifier_reset(),
+            false => keyboard,
+        };
+        // Caps Lock is only left on part way through a run, when typing stops early
+        Ok::<_, KeyboardError>(match caps_lock_threshold {
+            Some(_) => keyboard.restore_caps_lock(),
+            None => keyboard,
+        })
+    };
+
+    let open_consumer = |consumer_file: &str| {
+        let report = (consumer_report_id, consumer_report_len);
+        Consumer::open(&device, consumer_file, report, cooldown, &interrupt)
+    };
+    // Media keys in STRING or --key are only sent to a consumer control device that was asked for
+    let open_consumer_for =
+        |parts: &[Part]| -> Result<Option<Consumer>, Box<dyn std::error::Error>> {
+            let key = match parts.iter().find_map(|part| match part {
+                Part::Media(key) => Some(key),
+                Part::Keystrokes(_) => None,
+            }) {
+                Some(key) => key,
+                None => return Ok(None),
+            };
+            match (&consumer_file, device.dry_run) {
+            (Some(consumer_file), _) => open_consumer(consumer_file).map(Some),
+            (None, true) => open_consumer(DEFAULT_CONSUMER_FILE).map(Some),
+            (None, false) => Err(format!(
+                "{{{}}} is a media key, which needs a consumer control device. Give its HID file with --consumer-file",
+                key.name()
+            )
+            .into()),
+        }
+        };
+
+    if let Some(script) = script {
+        let script = fs::read_to_string(&script)
+            .map_err(|e| format!("Could not read script {}: {}", script, e))?;
+        let actions = ducky::parse(&layout, &script)?;
+        let packets = ducky::packet_count(&actions);
+        confirm_typing(&device, yes, &script, packets, &device.destination())?;
+
+        let mut keyboard = open("\n")?.with_delay(delay).release_on_drop();
+        ducky::run(&mut keyboard, &actions)?;
+        return Ok(());
+    }
+
+    if !media.is_empty() {
+        let media_keys = media
+            .iter()
+            .map(|name| MediaKey::parse(name).map_err(invalid_media_key_error))
+            .collect::<Result<Vec<MediaKey>, String>>()?;
+
+        let consumer_file = consumer_file.as_deref().unwrap_or(DEFAULT_CONSUMER_FILE);
+        let what = format!("{} media key(s)", media_keys.len());
+        confirm_typing(&device, yes, &what, media_keys.len() * 2, consumer_file)?;
+        let mut consumer = open_consumer(consumer_file)?;
+        consumer.keyboard.wait(delay)?;
+        for key in media_keys {
+            consumer.press(key)?;
+        }
+        return Ok(());
+    }
+
+    if !keys.is_empty() {
+        let parts = keys
+            .iter()
+            .map(|key| match Chord::parse(&layout, key) {
+                Ok(chord) => Ok(Part::Keystrokes(vec![Keystroke {
+                    character: None,
+                    packets: chord.to_hid_packets(),
+                }])),
+                Err(e) => MediaKey::parse(key)
+                    .map(Part::Media)
+                    .map_err(|_| invalid_key_error(e)),
+            })
+            .collect::<Result<Vec<Part>, String>>()?;
+        let what = format!("{} key(s)", parts.len());
+        let parts = with_held_modifiers(parts, hold);
+        confirm_typing(
+            &device,
+            yes,
+            &what,
+            packet_count(&parts),
+            &device.destination(),
+        )?;
+
+        let mut consumer = open_consumer_for(&parts)?;
+        let mut keyboard = open("\n")?.release_on_drop();
+        keyboard.wait(delay)?;
+        send_parts(&mut keyboard, consumer.as_mut(), &parts, None, None)?;
+        return Ok(());
+    }
+
+    if let (Some(key), Some(duration)) = (hold_key, hold_for) {
+        let chord = Chord::parse(&layout, &key).map_err(invalid_key_error)?;
+        let what = format!("{} held for {}", key, duration::format(duration));
+        confirm_typing(&device, yes, &what, 2, &device.destination())?;
+
+        // The host repeats the key while it's held, and an interrupt while waiting releases it
+        let mut keyboard = open("\n")?.release_on_drop();
+        keyboard.wait(delay)?;
+        keyboard.send_packet(&chord.press_packet())?;
+        keyboard.wait(duration)?;
+        keyboard.release_all()?;
+        return Ok(());
+    }
+
+    if let Some(path) = watch.as_ref().or(follow.as_ref()) {
+        let following = follow.is_some();
+        let (verb, started, doing) = if following {
+            ("follow", "Following", "following")
+        } else {
+            ("watch", "Watching", "watching")
+        };
+        let opened = if following {
+            Follow::open(Path::new(path)).map(|follow| Watched::Follow(follow, Utf8Decoder::new()))
+        } else {
+            Tail::open(Path::new(path)).map(Watched::Lines)
+        };
+        let mut watched = opened.map_err(|e| format!("Could not {} {}: {}", verb, path, e))?;
+        let mut lock_keys = LockKeys::open(&device, &lock_keys)?;
+        let mut keyboard = open("\n")?.with_delay(delay).release_on_drop();
+        if force_numlock {
+            press_lock_key(&mut keyboard, Lock::NumLock)?;
+        }
+        status!("{} {}", started, path);
+
+        let options = WatchOptions {
+            interval: watch_interval,
+            fast,
+            optimize_modifiers,
+            line_ending: line_endings,
+            normalizer,
+            unmappable,
+            unicode_fallback,
+            dry_run: device.dry_run,
+            caps_lock_threshold,
+        };
+        match watch_file(
+            &mut keyboard,
+            &layout,
+            &mut watched,
+            humanizer.as_mut(),
+            typos.as_mut(),
+            lock_keys.as_mut(),
+            &options,
+        ) {
+            // Interrupting is how watching stops, and the keys have been released
+            Ok(()) | Err(KeyboardError::Interrupted { .. }) => {
+                status!("Stopped {} {}", doing, path);
+                return Ok(());
+            }
+            Err(e) => return Err(format!("Could not {} {}: {}", verb, path, e).into()),
+        }
+    }
+
+    // Escapes are only supported in STRING, files and the clipboard can contain braces as they are
+    let (string, escapes) = match input_file {
+        Some(path) => (Some(read_input_file(&path)?), false),
+        None if clipboard => (
+            Some(read_clipboard(
+                clipboard_selection.unwrap_or(ClipboardSelection::Clipboard),
+            )?),
+            false,
+        ),
+        None if backslash_escapes => (
+            string
+                .as_deref()
+                .map(|string| backslash::unescape(string, !no_escapes))
+                .transpose()?,
+            !no_escapes,
+        ),
+        None => (string, !no_escapes),
+    };
+    // Decoded text is typed as it is, like a file
+    let (string, escapes) = match decode {
+        Some(encoding) => {
+            let encoded = match string {
+                Some(string) => string,
+                None => read_input_file("-")?,
+            };
+            (Some(encoding.decode(&encoded)?), false)
+        }
+        None => (string, escapes),
+    };
+
+    if let Some(string) = string {
+        let mut string = if line_mode {
+            LineMode::new().lines(&string)
+        } else {
+            Converter::new(line_endings).convert(&string)
+        };
+        if newline {
+            string.push_str(enter(line_endings, line_mode));
+        }
+        let string = normalizer.normalize(&layout, &string);
+
+        let (mut parts, unsupported) = to_parts(
+            &layout,
+            &string,
+            escapes,
+            0,
+            unicode_fallback,
+            unmappable.replacement(),
+        )
+        .map_err(surrogate_pair_hint)?;
+        for part in parts.iter_mut() {
+            if let Part::Keystrokes(keystrokes) = part {
+                let shifted = std::mem::take(keystrokes);
+                let cased = with_uppercase_strategy(&layout, shifted, caps_lock_threshold)?;
+                *keystrokes = with_typos(typos.as_mut(), cased);
+            }
+        }
+        let parts = with_held_modifiers(parts, hold);
+        let unmapped = check_unsupported(&layout, &unsupported, unmappable, device.dry_run)?;
+        let characters: usize = parts
+            .iter()
+            .map(|part| match part {
+                Part::Keystrokes(keystrokes) => keystrokes.len(),
+                Part::Media(_) => 1,
+            })
+            .sum();
+        let what = match repeat {
+            0 => format!("{} characters over and over", characters),
+            1 => format!("{} characters", characters),
+            _ => format!("{} characters {} times", characters, repeat),
+        };
+        let parts: Vec<Part> = parts
+            .into_iter()
+            .map(|part| match part {
+                Part::Keystrokes(keystrokes) => Part::Keystrokes(with_optimized_modifiers(
+                    optimize_modifiers,
+                    with_rollover(fast, keystrokes),
+                )),
+                media => media,
+            })
+            .collect();
+        if estimate {
+            if repeat == 0 {
+                return Err("--estimate can't estimate typing STRING over and over".into());
+            }
+            let typing = typing_time(&parts, cooldown, device.press_duration, humanizer.as_ref());
+            let times = (delay, typing, interval);
+            let estimate = Estimate {
+                skipped: unmapped,
+                ..Estimate::new(characters, packet_count(&parts), repeat, times)
+            };
+            for line in estimate.lines() {
+                println!("{}", line);
+            }
+            return Ok(());
+        }
+        let packets = packet_count(&parts) * repeat.max(1) as usize;
+        match confirm {
+            true => confirm_text(&what, &string, packets, &device.destination())?,
+            false => confirm_typing(&device, yes, &what, packets, &device.destination())?,
+        }
+
+        let mut lock_keys = LockKeys::open(&device, &lock_keys)?;
+        let mut consumer = open_consumer_for(&parts)?;
+        let mut keyboard = open("\n")?.release_on_drop();
+        keyboard.wait(delay)?;
+        if force_numlock {
+            press_lock_key(&mut keyboard, Lock::NumLock)?;
+        }
+        if let Some(lock_keys) = lock_keys.as_mut() {
+            lock_keys.set(&mut keyboard)?;
+        }
+        // Only drawn for someone watching, and when typing STRING over and over, it never finishes.
+        // The packets logged with -vv or more would be drawn over.
+        let long = cooldown
+            .checked_mul(packets.min(u32::MAX as usize) as u32)
+            .is_none_or(|expected| expected >= AUTO_PROGRESS_AFTER);
+        let show_progress = (progress || (long && !no_progress))
+            && repeat != 0
+            && io::stderr().is_terminal()
+            && !QUIET.load(atomic::Ordering::SeqCst)
+            && !log::log_enabled!(log::Level::Debug);
+        let mut progress = if show_progress {
+            Some(Progress::new(packets, cooldown))
+        } else {
+            None
+        };
+        let started = Instant::now();
+        let mut completed = 0;
+        let mut attempted = 0;
+        let result = loop {
+            if repeat != 0 && completed >= repeat {
+                break Ok(());
+            }
+            if completed > 0 {
+                if let Err(e) = keyboard.wait(interval) {
+                    break Err(e);
+                }
+            }
+            attempted += 1;
+            if let Err(e) = send_parts(
+                &mut keyboard,
+                consumer.as_mut(),
+                &parts,
+                humanizer.as_mut(),
+                progress.as_mut(),
+            ) {
+                break Err(e);
+            }
+            completed += 1;
+        };
+        drop(progress);
+        log::debug!(
+            "Typed STRING in {} packets, taking {:?}",
+            keyboard.packets_sent(),
+            started.elapsed()
+        );
+        if repeat != 1 {
+            status!("Typed STRING {} time(s)", completed);
+        }
+        // Also reported when typing fails, to show how far it got
+        let mut summary = Summary {
+            characters: characters * attempted,
+            generated: packet_count(&parts) * attempted,
+            skipped: unmapped,
+            written: keyboard.stats(),
+        };
+        if let Some(consumer) = &consumer {
+            summary.add_writes(consumer.keyboard.stats());
+        }
+        summary.report(stats);
+        result?;
+        if let Some(lock_keys) = lock_keys.as_mut() {
+            lock_keys.restore(&mut keyboard)?;
+        }
+        unmapped_error(unmapped)
+    } else {
+        if hold.is_some() {
+            return Err("--hold needs STRING, --key, --input-file or --clipboard".into());
+        }
+        if estimate {
+            return Err("--estimate needs STRING, --input-file or --clipboard".into());
+        }
+        // Input from a pipe or file is typed as it is, only a terminal needs raw mode
+        let interactive = !stdin && io::stdin().is_terminal();
+        if interactive && line_mode {
+            return Err("--line-mode needs STRING, --input-file or input piped to stdin".into());
+        }
+        if interactive {
+            status!("Reading from stdin");
+        }
+
+        // Raw mode needs an explicit carriage return
+        let line_ending = if interactive { "\r\n" } else { "\n" };
+        let lock_keys = LockKeys::open(&device, &lock_keys)?;
+        let echo = if no_echo { EchoStyle::None } else { echo_style };
+        let mut keyboard = open(line_ending)?.release_on_drop();
+        if echo == EchoStyle::None {
+            keyboard.get_mut().hide_logged_characters();
+        }
+        if force_numlock {
+            press_lock_key(&mut keyboard, Lock::NumLock)?;
+        }
+        let options = StdinOptions {
+            unmappable,
+            unicode_fallback,
+            dry_run: device.dry_run,
+            newline,
+            fast,
+            optimize_modifiers,
+            line_ending: line_endings,
+            line_mode,
+            interactive,
+            exit_key,
+            forward_ctrl_c,
+            echo,
+            caps_lock_threshold,
+            stats,
+        };
+        let unmapped = type_stdin(
+            keyboard,
+            &layout,
+            humanizer.as_mut(),
+            typos.as_mut(),
+            lock_keys,
+            &normalizer,
+            &options,
+        )?;
+        unmapped_error(unmapped)
+    }
+}
+
+/// Use the options from the config file that weren't given on the command line
+fn apply_config(opt: &mut CliOpt, matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let config = match &opt.config {
+        Some(path) => Config::load(Path::new(path), true)?,
+        None => match Config::default_path() {
+            Some(path) => Config::load(&path, false)?,
+            None => Config::default(),
+        },
+    };
+    let matches = match matches.subcommand() {
+        (_, Some(matches)) => matches,
+        _ => return Ok(()),
+    };
+    let unset = |name| matches.occurrences_of(name) == 0;
+    // Any way of setting the typing speed on the command line overrides both of these
+    let speed_unset = unset("cooldown") && unset("wpm") && unset("max_rate") && unset("humanize");
+
+    // The config file's HID files are keyboards
+    let keyboard = !matches!(opt.command, Some(Command::Mouse(_)));
+    let device = match &mut opt.command {
+        None => return Ok(()),
+        Some(Command::Type(opt)) | Some(Command::ConfigDump(opt)) => {
+            if let (true, Some(layout)) = (unset("layout"), config.layout) {
+                opt.layout.name = Some(layout);
+            }
+            if let (true, Some(newline)) = (unset("newline"), config.newline) {
+                opt.newline = newline && !opt.no_newline;
+            }
+            if let (true, Some(wpm)) = (speed_unset, config.wpm) {
+                opt.wpm = Some(wpm);
+            }
+            &mut opt.device
+        }
+        Some(Command::Raw(opt)) => &mut opt.device,
+        Some(Command::Mouse(opt)) => &mut opt.device,
+        Some(Command::Serve(opt)) => {
+            if let (true, Some(layout)) = (unset("layout"), config.layout) {
+                opt.layout.name = Some(layout);
+            }
+            &mut opt.device
+        }
+        Some(Command::Dump(opt)) => {
+            if let (true, Some(layout)) = (unset("layout"), config.layout) {
+                opt.layout.name = Some(layout);
+            }
+            return Ok(());
+        }
+        Some(Command::Inspect(opt)) => {
+            if let (true, Some(layout)) = (unset("layout"), config.layout) {
+                opt.layout.name = Some(layout);
+            }
+            return Ok(());
+        }
+        Some(Command::Char(opt)) => {
+            if let (true, Some(layout)) = (unset("layout"), config.layout) {
+                opt.layout.name = Some(layout);
+            }
+            return Ok(());
+        }
+        Some(Command::List(_)) | Some(Command::Leds(_)) | Some(Command::Send(_)) => return Ok(()),
+    };
+
+    if let (true, true, Some(hid_files)) = (keyboard, unset("hid_file"), config.hid_file) {
+        device.hid_file = hid_files;
+    }
+    if let (true, Some(delay)) = (unset("delay"), config.delay) {
+        device.delay = delay;
+    }
+    if let (true, Some(cooldown)) = (speed_unset, config.cooldown) {
+        device.cooldown = cooldown;
+    }
+    if let (true, Some(retries)) = (unset("write_retries"), config.write_retries) {
+        device.write_retries = retries;
+    }
+    if let (true, Some(timeout)) = (unset("write_timeout"), config.write_timeout) {
+        device.write_timeout = Some(timeout);
+    }
+
+    Ok(())
+}
+
+/// Set the returned flag on SIGINT or SIGTERM, so typing stops and all keys are released. If the
+/// run hasn't finished shortly afterwards, e.g. because it is waiting for input, the terminal is
+/// restored and the keys are released from here instead. With [`FINISH_ON_SIGTERM`], SIGTERM
+/// waits for the line being typed and exits without typing another.
+#[cfg(unix)]
+fn handle_signals(hid_files: Vec<String>) -> io::Result<Arc<AtomicBool>> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    let flag = interrupt.clone();
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            let finish = signal == SIGTERM && FINISH_ON_SIGTERM.load(atomic::Ordering::SeqCst);
+            // Never released, so nothing more is typed
+            let _in_flight = if finish {
+                Some(IN_FLIGHT.lock())
+            } else {
+                progress::clear();
+                flag.store(true, atomic::Ordering::SeqCst);
+                thread::sleep(INTERRUPT_GRACE);
+                None
+            };
+
+            if RAW_MODE_ENABLED.load(atomic::Ordering::SeqCst) {
+                let _ = terminal::stdout().act(terminal::Action::DisableRawMode);
+            }
+            remove_socket_file();
+            let _ = flush_packet_log();
+            for hid_file in hid_files {
+                if let Ok(mut device) = fs::OpenOptions::new().write(true).open(hid_file) {
+                    let _ = device.write_all(&[0; HID_PACKET_LEN]);
+                }
+            }
+            eprintln!("\r\n{}", if finish { "Stopped" } else { "Interrupted" });
+            process::exit(if finish { 0 } else { 128 + signal });
+        }
+    });
+    Ok(interrupt)
+}
+
+#[cfg(not(unix))]
+fn handle_signals(_hid_files: Vec<String>) -> io::Result<Arc<AtomicBool>> {
+    Ok(Arc::new(AtomicBool::new(false)))
+}
+
+/// A character the layout can't type, with its byte offset in the input
+type Unsupported = (usize, char);
+
+/// A part of the input: keystrokes on the keyboard, or a media key on the consumer control device
+#[derive(Debug)]
+enum Part {
+    Keystrokes(Vec<Keystroke>),
+    Media(MediaKey),
+}
+
+/// Convert the string to keystrokes, typing `replacement` instead of the characters the layout
+/// can't type or leaving them out without one. Those left out are returned with their byte offset
+/// in the input, where the string starts at `offset`. A media key in an escape is an error, use
+/// [`to_parts`] where they are supported.
+fn to_keystrokes(
+    layout: &str,
+    string: &str,
+    escapes: bool,
+    offset: usize,
+    fallback: UnicodeFallback,
+    replacement: Option<char>,
+) -> Result<(Vec<Keystroke>, Vec<Unsupported>), keyboard_layouts::Error> {
+    let (parts, unsupported) = to_parts(layout, string, escapes, offset, fallback, replacement)?;
+    let mut keystrokes = Vec::new();
+    for part in parts {
+        match part {
+            Part::Keystrokes(part) => keystrokes.extend(part),
+            Part::Media(key) => {
+                return Err(keyboard_layouts::Error::MediaKey(key.name().to_string()))
+            }
+        }
+    }
+    Ok((keystrokes, unsupported))
+}
+
+/// Like [`to_keystrokes`], with the media keys in escapes between the keystrokes around them
+fn to_parts(
+    layout: &str,
+    string: &str,
+    escapes: bool,
+    offset: usize,
+    fallback: UnicodeFallback,
+    replacement: Option<char>,
+) -> Result<(Vec<Part>, Vec<Unsupported>), keyboard_layouts::Error> {
+    let tokens = if escapes {
+        escapes::parse_with_offsets(layout, string)?
+    } else {
+        vec![(0, Token::Text(string.to_string()))]
+    };
+
+    let mut parts = Vec::new();
+    let mut keystrokes = Vec::new();
+    let mut unsupported = Vec::new();
+    let mut buf = [0u8; 4];
+    for (start, token) in tokens {
+        match token {
+            Token::Text(text) => {
+                for (idx, c) in text.char_indices() {
+                    let typed = match keyboard_layouts::string_to_keystrokes(
+                        layout,
+                        c.encode_utf8(&mut buf),
+                    ) {
+                        Err(keyboard_layouts::Error::InvalidCharacter(c)) => {
+                            match fallback.keystroke(layout, c) {
+                                Some(typed) => typed.map(|keystroke| vec![keystroke]),
+                                None => Err(keyboard_layouts::Error::InvalidCharacter(c)),
+                            }
+                        }
+                        typed => typed,
+                    };
+                    let typed = match (typed, replacement) {
+                        (Err(keyboard_layouts::Error::InvalidCharacter(c)), Some(replacement)) => {
+                            keyboard_layouts::string_to_keystrokes(
+                                layout,
+                                replacement.encode_utf8(&mut buf),
+                            )
+                            .map_err(|_| keyboard_layouts::Error::InvalidCharacter(c))
+                        }
+                        (typed, _) => typed,
+                    };
+                    match typed {
+                        Ok(keystroke) => keystrokes.extend(keystroke),
+                        Err(keyboard_layouts::Error::InvalidCharacter(c)) => {
+                            unsupported.push((offset + start + idx, c))
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+            Token::Key(chord) => keystrokes.push(Keystroke {
+                character: None,
+                packets: chord.to_hid_packets(),
+            }),
+            Token::Media(key) => {
+                if !keystrokes.is_empty() {
+                    parts.push(Part::Keystrokes(std::mem::take(&mut keystrokes)));
+                }
+                parts.push(Part::Media(key));
+            }
+        }
+    }
+    if !keystrokes.is_empty() {
+        parts.push(Part::Keystrokes(keystrokes));
+    }
+
+    Ok((parts, unsupported))
+}
+
+/// Report the characters the layout can't type. Unless they're being skipped this is an error,
+/// except in a dry run where it is deferred until the rest of the input has been shown. Returns
+/// the number of characters for the deferred error, which skipped characters also get once the
+/// rest has been typed.
+fn check_unsupported(
+    layout: &str,
+    unsupported: &[Unsupported],
+    policy: Unmappable,
+    dry_run: bool,
+) -> Result<usize, String> {
+    let describe = |(offset, c): &Unsupported| format!("{:?} at byte {}", c, offset);
+
+    match policy {
+        Unmappable::Skip => return Ok(unsupported.len()),
+        Unmappable::Warn => {
+            for character in unsupported {
+                eprintln!(
+                    "Warning: skipping {}, which {} can't type",
+                    describe(character),
+                    layout
+                );
+            }
+            return Ok(unsupported.len());
+        }
+        Unmappable::Error | Unmappable::Replace(_) if dry_run => {
+            for character in unsupported {
+                eprintln!("{} can't type {}", layout, describe(character));
+            }
+            return Ok(unsupported.len());
+        }
+        Unmappable::Error | Unmappable::Replace(_) => {}
+    }
+
+    if unsupported.is_empty() {
+        Ok(0)
+    } else {
+        Err(format!(
+            "{} can't type {} character(s): {}. Use --on-unmappable skip, warn or replace=CHAR to type the rest",
+            layout,
+            unsupported.len(),
+            unsupported
+                .iter()
+                .map(describe)
+                .collect::<Vec<String>>()
+                .join(", ")
+        ))
+    }
+}
+
+/// Send the keystrokes, waiting for the humanizer's delay after each of them, or its delay before
+/// a correction when a typo's Backspace is next
+fn send_keystrokes<W: HidWriter>(
+    keyboard: &mut HidKeyboard<W>,
+    keystrokes: &[Keystroke],
+    mut humanizer: Option<&mut Humanizer>,
+    mut progress: Option<&mut Progress>,
+) -> Result<usize, KeyboardError> {
+    let started = Instant::now();
+    let mut sent = 0;
+    for (i, keystroke) in keystrokes.iter().enumerate() {
+        let packets = keyboard.send_keystroke(keystroke)?;
+        sent += packets;
+        if let Some(humanizer) = humanizer.as_mut() {
+            let delay = match keystrokes.get(i + 1) {
+                Some(next) if typos::is_correction(next) => {
+                    humanizer.delay_before_correction(keystroke.character)
+                }
+                _ => humanizer.delay_after(keystroke.character),
+            };
+            keyboard.wait(delay)?;
+        }
+        if let Some(progress) = progress.as_mut() {
+            progress.add(packets);
+        }
+    }
+    log::debug!(
+        "Typed {} keystrokes in {} packets, taking {:?}",
+        keystrokes.len(),
+        sent,
+        started.elapsed()
+    );
+    Ok(sent)
+}
+
+/// How long typing the parts once takes on average, with the cooldown, or the press duration after
+/// each packet pressing keys, and the humanizer's delay after each keystroke
+fn typing_time(
+    parts: &[Part],
+    cooldown: Duration,
+    press_duration: Option<Duration>,
+    humanizer: Option<&Humanizer>,
+) -> Duration {
+    let mut time = Duration::from_secs(0);
+    for part in parts {
+        match part {
+            Part::Keystrokes(keystrokes) => {
+                for (i, keystroke) in keystrokes.iter().enumerate() {
+                    for packet in keystroke.packets.chunks(HID_PACKET_LEN) {
+                        let pressed = packet.iter().any(|&b| b != 0);
+                        time += match press_duration {
+                            Some(press_duration) if pressed => press_duration,
+                            _ => cooldown,
+                        };
+                    }
+                    time += match (humanizer, keystrokes.get(i + 1)) {
+                        (Some(humanizer), Some(next)) if typos::is_correction(next) => {
+                            humanizer.expected_delay_before_correction(keystroke.character)
+                        }
+                        (Some(humanizer), _) => humanizer.expected_delay_after(keystroke.character),
+                        (None, _) => Duration::from_secs(0),
+                    };
+                }
+            }
+            // Pressed and released on the consumer control device, with the same cooldown
+            Part::Media(_) => time += cooldown * 2,
+        }
+    }
+    time
+}
+
+/// Hold the modifiers throughout the parts, for --hold: they're pressed on their own first, kept
+/// in every packet, and released after the last one
+fn with_held_modifiers(parts: Vec<Part>, hold: Option<u8>) -> Vec<Part> {
+    let modifier = match hold {
+        Some(modifier) => modifier,
+        None => return parts,
+    };
+    let packet = |modifier| Keystroke {
+        character: None,
+        packets: keys::hold_modifiers(&[0; HID_PACKET_LEN], modifier),
+    };
+
+    let mut held = vec![Part::Keystrokes(vec![packet(modifier)])];
+    held.extend(parts.into_iter().map(|part| {
+        match part {
+            Part::Keystrokes(keystrokes) => Part::Keystrokes(
+                keystrokes
+                    .into_iter()
+                    .map(|keystroke| Keystroke {
+                        packets: keys::hold_modifiers(&keystroke.packets, modifier),
+                        ..keystroke
+                    })
+                    .collect(),
+            ),
+            Part::Media(key) => Part::Media(key),
+        }
+    }));
+    held.push(Part::Keystrokes(vec![packet(0)]));
+    held
+}
+
+/// Add typos to the keystrokes, if there are any to make
+fn with_typos(typos: Option<&mut Typos>, keystrokes: Vec<Keystroke>) -> Vec<Keystroke> {
+    match typos {
+        Some(typos) => typos.add(keystrokes),
+        None => keystrokes,
+    }
+}
+
+/// Press characters together in each packet, if typing --fast
+fn with_rollover(fast: bool, keystrokes: Vec<Keystroke>) -> Vec<Keystroke> {
+    if fast {
+        rollover::pack(keystrokes)
+    } else {
+        keystrokes
+    }
+}
+
+/// Hold modifiers between characters typed with the same ones, if --optimize-modifiers is given
+fn with_optimized_modifiers(
+    optimize_modifiers: bool,
+    keystrokes: Vec<Keystroke>,
+) -> Vec<Keystroke> {
+    if optimize_modifiers {
+        modifiers::hold(keystrokes)
+    } else {
+        keystrokes
+    }
+}
+
+/// Type the runs of uppercase letters with Caps Lock when there is a threshold for them, for
+/// --uppercase-strategy capslock
+fn with_uppercase_strategy(
+    layout: &str,
+    keystrokes: Vec<Keystroke>,
+    caps_lock_threshold: Option<usize>,
+) -> Result<Vec<Keystroke>, keyboard_layouts::Error> {
+    match caps_lock_threshold {
+        Some(threshold) => capslock::caps_lock_runs(layout, keystrokes, threshold),
+        None => Ok(keystrokes),
+    }
+}
+
+/// Send the parts, pressing media keys on the consumer control device
+fn send_parts(
+    keyboard: &mut HidKeyboard<Output>,
+    mut consumer: Option<&mut Consumer>,
+    parts: &[Part],
+    mut humanizer: Option<&mut Humanizer>,
+    mut progress: Option<&mut Progress>,
+) -> Result<(), KeyboardError> {
+    for part in parts {
+        match part {
+            Part::Keystrokes(keystrokes) => {
+                send_keystrokes(
+                    keyboard,
+                    keystrokes,
+                    humanizer.as_deref_mut(),
+                    progress.as_deref_mut(),
+                )?;
+            }
+            Part::Media(key) => match consumer.as_deref_mut() {
+                Some(consumer) => {
+                    consumer.press(*key)?;
+                    if let Some(progress) = progress.as_deref_mut() {
+                        progress.add(2);
+                    }
+                }
+                None => unreachable!("a consumer control device is opened for media keys"),
+            },
+        }
+    }
+    Ok(())
+}
+
+/// The consumer control device media keys are pressed on
+struct Consumer {
+    keyboard: HidKeyboard<Output>,
+    report_id: Option<u8>,
+    report_len: usize,
+}
+
+impl Consumer {
+    fn open(
+        device: &DeviceOpt,
+        consumer_file: &str,
+        report: (Option<u8>, usize),
+        cooldown: Duration,
+        interrupt: &Arc<AtomicBool>,
+    ) -> Result<Consumer, Box<dyn std::error::Error>> {
+        if device.backend() != Backend::Hidg {
+            return Err(format!(
+                "Media keys need a consumer control gadget, they can't be used with --backend {}",
+                device.backend().name()
+            )
+            .into());
+        }
+        let (report_id, report_len) = report;
+        Ok(Consumer {
+            keyboard: open_keyboard(device, &[consumer_file], cooldown, interrupt, "\n")?,
+            report_id,
+            report_len,
+        })
+    }
+
+    fn press(&mut self, key: MediaKey) -> Result<(), KeyboardError> {
+        let packets = key.to_report_packets(self.report_id, self.report_len);
+        let (press, release) = packets.split_at(packets.len() / 2);
+        let result = self
+            .keyboard
+            .send_packet(press)
+            .and_then(|()| self.keyboard.send_packet(release));
+        if result.is_err() {
+            // Don't leave the key held, which would keep changing the volume
+            let _ = self.keyboard.get_mut().write_packet(release);
+        }
+        result
+    }
+}
+
+/// A lock key typing needs in a particular state on the host
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lock {
+    /// Off, so letters come out in the case they were given
+    CapsLock,
+    /// On, so the numpad types digits for Alt+Numpad input
+    NumLock,
+}
+
+impl Lock {
+    fn led(self) -> u8 {
+        match self {
+            Lock::CapsLock => LED_CAPS_LOCK,
+            Lock::NumLock => LED_NUM_LOCK,
+        }
+    }
+
+    fn wanted_on(self) -> bool {
+        self == Lock::NumLock
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Lock::CapsLock => "Caps Lock",
+            Lock::NumLock => "Num Lock",
+        }
+    }
+
+    /// The option that needs the key in this state
+    fn option(self) -> &'static str {
+        match self {
+            Lock::CapsLock => "--respect-capslock",
+            Lock::NumLock => "--unicode-fallback alt-numpad",
+        }
+    }
+}
+
+/// Keeps lock keys in the state typing needs on the host, going by the LED reports it sends. Each
+/// key that was pressed to get there is pressed again afterwards.
+struct LockKeys {
+    leds: LedReader<fs::File>,
+    hid_file: String,
+    // The keys, and whether each was pressed and needs restoring
+    keys: Vec<(Lock, bool)>,
+    checked: bool,
+}
+
+impl LockKeys {
+    /// Read the LED reports of the first HID file, or nothing for a dry run, which has no host, or
+    /// when there are no keys to keep
+    fn open(device: &DeviceOpt, keys: &[Lock]) -> Result<Option<LockKeys>, String> {
+        if device.dry_run || keys.is_empty() {
+            return Ok(None);
+        }
+        if device.backend() != Backend::Hidg {
+            return Err(format!(
+                "{} reads the LED reports of a USB gadget, it can't be used with --backend {}",
+                keys[0].option(),
+                device.backend().name()
+            ));
+        }
+        let hid_file = device.hid_files()[0];
+        let leds = LedReader::open(hid_file)
+            .map_err(|e| format!("Could not read the LED reports from {}: {}", hid_file, e))?;
+        Ok(Some(LockKeys {
+            leds,
+            hid_file: hid_file.to_string(),
+            keys: keys.iter().map(|&key| (key, false)).collect(),
+            checked: false,
+        }))
+    }
+
+    /// Press the keys the host has in the wrong state. The first time, the host may not have sent
+    /// a report since the HID file was last read, so wait briefly for one.
+    fn set(&mut self, keyboard: &mut HidKeyboard<Output>) -> Result<(), KeyboardError> {
+        let timeout = if self.checked {
+            Duration::from_millis(0)
+        } else {
+            LED_REPORT_WAIT
+        };
+        let leds = self.leds.wait(timeout)?;
+        if !self.checked && leds.is_none() {
+            for (key, _) in &self.keys {
+                eprintln!(
+                    "\r\nNo LED report from the host on {}, assuming {} is {}",
+                    self.hid_file,
+                    key.name(),
+                    if key.wanted_on() { "on" } else { "off" }
+                );
+            }
+        }
+        self.checked = true;
+
+        if let Some(mut leds) = leds {
+            for (key, restore) in self.keys.iter_mut() {
+                if leds.is_on(key.led()) != key.wanted_on() {
+                    press_lock_key(keyboard, *key)?;
+                    leds = Leds(leds.0 ^ key.led());
+                    self.leds.assume(leds);
+                    *restore = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Press the keys that were pressed again, unless the host has changed them back already
+    fn restore(&mut self, keyboard: &mut HidKeyboard<Output>) -> Result<(), KeyboardError> {
+        let leds = self.leds.poll()?;
+        for (key, restore) in self.keys.iter_mut() {
+            let changed_back = leds.is_some_and(|leds| leds.is_on(key.led()) != key.wanted_on());
+            if *restore && !changed_back {
+                press_lock_key(keyboard, *key)?;
+            }
+            *restore = false;
+        }
+        Ok(())
+    }
+}
+
+fn press_lock_key(keyboard: &mut HidKeyboard<Output>, key: Lock) -> Result<usize, KeyboardError> {
+    let name = match key {
+        Lock::CapsLock => "CAPSLOCK",
+        Lock::NumLock => "NUMLOCK",
+    };
+    let chord = Chord {
+        modifier: 0,
+        key: keys::key_usage(name).expect("lock keys have names"),
+    };
+    keyboard.send_packets(&chord.to_hid_packets())
+}
+
+/// The number of HID packets the parts are sent as, on the keyboard and consumer control device
+fn packet_count(parts: &[Part]) -> usize {
+    parts
+        .iter()
+        .map(|part| match part {
+            Part::Keystrokes(keystrokes) => keystroke_packets(keystrokes),
+            Part::Media(_) => 2,
+        })
+        .sum()
+}
+
+fn keystroke_packets(keystrokes: &[Keystroke]) -> usize {
+    keystrokes
+        .iter()
+        .map(|keystroke| keystroke.packets.len() / HID_PACKET_LEN)
+        .sum()
+}
+
+/// Ask on the terminal whether to type what's about to be typed, so a mistyped command doesn't
+/// type into whatever window the host has focused. Nobody is there to answer when stdin isn't a
+/// terminal, e.g. in a script, and a dry run types nothing.
+fn confirm_typing(
+    device: &DeviceOpt,
+    yes: bool,
+    what: &str,
+    packets: usize,
+    destination: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if yes || device.dry_run || !io::stdin().is_terminal() {
+        return Ok(());
+    }
+    let question = format!(
+        "About to type {} ({} packets) to {} — continue? [y/N] ",
+        what, packets, destination
+    );
+    if ask(&question)? {
+        Ok(())
+    } else {
+        Err("Not confirmed, nothing was typed. Use --yes to type without asking".into())
+    }
+}
+
+/// Ask whether to type the text for --confirm, whether or not stdin is a terminal, showing how it
+/// starts so a clipboard holding something unexpected isn't typed
+fn confirm_text(
+    what: &str,
+    text: &str,
+    packets: usize,
+    destination: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let question = format!(
+        "About to type {} ({} packets) to {}, starting {} — continue? [y/N] ",
+        what,
+        packets,
+        destination,
+        preview(text)
+    );
+    if ask(&question)? {
+        Ok(())
+    } else {
+        Err("Not confirmed, nothing was typed".into())
+    }
+}
+
+/// The start of the first line of the text, quoted, with an ellipsis when there's more
+fn preview(text: &str) -> String {
+    const PREVIEW_LEN: usize = 40;
+    let line = text.lines().next().unwrap_or_default();
+    let shown: String = line.chars().take(PREVIEW_LEN).collect();
+    let more = shown.len() < line.len() || text.trim_end_matches(['\r', '\n']).len() > line.len();
+    format!("{:?}{}", shown, if more { "…" } else { "" })
+}
+
+/// Ask a yes or no question on the controlling terminal, falling back to stdin and stderr where
+/// there isn't one. Anything but yes is no.
+fn ask(question: &str) -> io::Result<bool> {
+    let mut answer = String::new();
+    match fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+    {
+        Ok(mut tty) => {
+            tty.write_all(question.as_bytes())?;
+            io::BufReader::new(tty).read_line(&mut answer)?;
+        }
+        Err(_) => {
+            eprint!("{}", question);
+            io::stdin().read_line(&mut answer)?;
+        }
+    }
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn unmapped_error(unmapped: usize) -> Result<(), Box<dyn std::error::Error>> {
+    if unmapped > 0 {
+        Err(format!(
+            "{} character(s) could not be mapped to the layout",
+            unmapped
+        )
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Describe a layout name that couldn't be resolved, suggesting similar names
+fn unknown_layout_error(layout: &str) -> String {
+    let similar = keyboard_layouts::similar_layouts(layout);
+    let mut error = keyboard_layouts::Error::InvalidLayoutKey(layout.to_string()).to_string();
+    if !similar.is_empty() {
+        error.push_str(&format!("\nDid you mean {}?", similar.join(", ")));
+    }
+    error.push_str("\nRun kbsim list to show all available layouts");
+    error
+}
+
+/// Describe an invalid key combination, including the names that are supported
+fn invalid_key_error(e: keyboard_layouts::Error) -> String {
+    format!(
+        "{}\nSupported modifiers: {}\nSupported keys: {} or a single character",
+        e,
+        keys::modifier_names().join(", "),
+        keys::key_names().join(", ")
+    )
+}
+
+fn surrogate_pair_hint(e: keyboard_layouts::Error) -> Box<dyn std::error::Error> {
+    match e {
+        keyboard_layouts::Error::NeedsSurrogatePair(_) => format!(
+            "{}. Pass --surrogate-pairs to type it as one, which some applications combine",
+            e
+        )
+        .into(),
+        e => e.into(),
+    }
+}
+
+fn invalid_media_key_error(e: keyboard_layouts::Error) -> String {
+    format!(
+        "{}\nSupported media keys: {}",
+        e,
+        keys::media_key_names().join(", ")
+    )
+}
+
+/// Open the HID files, waiting for them if requested, or for a dry run a keyboard printing the
+/// packets to stdout instead. With several files every packet is written to each of them.
+fn open_keyboard(
+    opt: &DeviceOpt,
+    hid_files: &[&str],
+    cooldown: Duration,
+    interrupt: &Arc<AtomicBool>,
+    line_ending: &'static str,
+) -> Result<HidKeyboard<Output>, KeyboardError> {
+    log::info!(
+        "Writing to {}",
+        match opt.backend() {
+            _ if opt.dry_run => "stdout, for a dry run".to_string(),
+            Backend::Hidg => hid_files.join(", "),
+            _ => opt.destination(),
+        }
+    );
+    let keyboard = if opt.dry_run {
+        match opt.format.unwrap_or(PacketFormat::Plain) {
+            PacketFormat::Plain => {
+                let dump = HexDump::new(io::stdout()).with_line_ending(line_ending);
+                HidKeyboard::new(Output::Dump(dump))
+            }
+            PacketFormat::Json => {
+                let dump = JsonDump::new(io::stdout()).with_line_ending(line_ending);
+                HidKeyboard::new(Output::Json(dump))
+            }
+            PacketFormat::CArray => HidKeyboard::new(Output::CArray(CArrayDump::new(io::stdout()))),
+            PacketFormat::Base64 => HidKeyboard::new(Output::Base64(Base64Dump::new(io::stdout()))),
+        }
+    } else if opt.backend() == Backend::Uinput {
+        HidKeyboard::new(open_uinput()?)
+    } else if opt.backend() == Backend::Native {
+        HidKeyboard::new(open_native()?)
+    } else if let [hid_file] = hid_files {
+        let verify_file = opt.verify_file.as_deref().unwrap_or(hid_file);
+        HidKeyboard::new(Output::Device(open_device(opt, hid_file, verify_file)?))
+    } else {
+        if opt.verify_file.is_some() {
+            return Err(KeyboardError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--verify-file can only be used with a single --hid-file",
+            )));
+        }
+        let mut fan_out = FanOut::new().strict(opt.strict).on_error(|name, e| {
+            eprintln!(
+                "\r\nCould not write to {}, continuing with the other devices: {}",
+                name, e
+            )
+        });
+        for hid_file in hid_files {
+            fan_out = fan_out.with_writer(*hid_file, open_device(opt, hid_file, hid_file)?);
+        }
+        HidKeyboard::new(Output::Mirror(fan_out))
+    };
+    let keyboard = match &opt.log_file {
+        Some(path) => HidKeyboard::new(Output::Logged(Box::new(log_packets(
+            keyboard.into_inner(),
+            path,
+            opt.log_format.unwrap_or(LogFormat::Text),
+        )?))),
+        None => keyboard,
+    };
+
+    let keyboard = keyboard
+        .with_interrupt(interrupt.clone())
+        .with_cooldown(cooldown)
+        .with_write_retries(opt.write_retries);
+    let keyboard = match opt.press_duration {
+        Some(press_duration) => keyboard.with_press_duration(press_duration),
+        None => keyboard,
+    };
+    Ok(match opt.write_timeout {
+        Some(timeout) => keyboard.with_write_timeout(timeout),
+        None => keyboard,
+    })
+}
+
+/// Log the packets written to the output in the --log-file, opening it for the first keyboard of
+/// the run
+fn log_packets(
+    output: Output,
+    path: &str,
+    format: LogFormat,
+) -> Result<PacketLog<Output, LogFile>, KeyboardError> {
+    let mut log = PACKET_LOG
+        .lock()
+        .expect("the packet log is never left poisoned");
+    let started = match log.as_ref() {
+        Some((started, _)) => *started,
+        None => {
+            let file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| {
+                    KeyboardError::Io(io::Error::new(
+                        e.kind(),
+                        format!("Could not open the log file {}: {}", path, e),
+                    ))
+                })?;
+            let started = Instant::now();
+            *log = Some((started, io::BufWriter::new(file)));
+            started
+        }
+    };
+    let log = PacketLog::new(output, LogFile).started_at(started);
+    Ok(match format {
+        LogFormat::Text => log,
+        LogFormat::JsonLines => log.json_lines(),
+    })
+}
+
+/// Writes to the shared --log-file
+struct LogFile;
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match PACKET_LOG.lock().ok().as_mut().and_then(|log| log.as_mut()) {
+            Some((_, file)) => file.write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        flush_packet_log()
+    }
+}
+
+/// Record a connection to serve in the --log-file, if there is one, with the client, what it sent
+/// and how the connection ended
+fn log_connection(peer: &str, received: &Received, end: &str, format: LogFormat) {
+    let mut log = PACKET_LOG.lock().unwrap_or_else(|e| e.into_inner());
+    let (started, file) = match log.as_mut() {
+        Some(log) => log,
+        None => return,
+    };
+    let time = started.elapsed().as_secs_f64();
+    let result = match format {
+        LogFormat::Text => writeln!(
+            file,
+            "{:.6} connection {:?} {} bytes {} lines {}",
+            time, peer, received.bytes, received.lines, end
+        ),
+        LogFormat::JsonLines => writeln!(
+            file,
+            "{}",
+            serde_json::json!({
+                "time": (time * 1e6).round() / 1e6,
+                "connection": peer,
+                "bytes": received.bytes,
+                "lines": received.lines,
+                "result": end,
+            })
+        ),
+    };
+    if let Err(e) = result.and_then(|_| file.flush()) {
+        log::warn!("Could not log the connection from {}: {}", peer, e);
+    }
+}
+
+fn flush_packet_log() -> io::Result<()> {
+    match PACKET_LOG.lock().ok().as_mut().and_then(|log| log.as_mut()) {
+        Some((_, file)) => file.flush(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_uinput() -> Result<Output, KeyboardError> {
+    use keyboard_layouts::uinput::{Uinput, UINPUT_PATH};
+
+    let uinput = Uinput::create(UINPUT_PATH)
+        .map_err(|e| KeyboardError::DeviceOpen(UINPUT_PATH.into(), e))?;
+    Ok(Output::Uinput(uinput))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_uinput() -> Result<Output, KeyboardError> {
+    Err(KeyboardError::Io(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the uinput backend is only available on Linux",
+    )))
+}
+
+#[cfg(all(feature = "native", any(windows, target_os = "macos")))]
+fn open_native() -> Result<Output, KeyboardError> {
+    Ok(Output::Native(keyboard_layouts::native::Native::new()))
+}
+
+#[cfg(not(all(feature = "native", any(windows, target_os = "macos"))))]
+fn open_native() -> Result<Output, KeyboardError> {
+    Err(KeyboardError::Io(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the native backend is only available on Windows and macOS, in builds with the native feature",
+    )))
+}
+
+/// Open a HID file, waiting for it if requested, and with --verify the file its packets are read
+/// back from
+fn open_device(
+    opt: &DeviceOpt,
+    hid_file: &str,
+    verify_file: &str,
+) -> Result<Device, KeyboardError> {
+    check_character_device(opt, hid_file)?;
+    let keyboard = match (HidKeyboard::open(hid_file), opt.wait_for_device) {
+        (Err(KeyboardError::DeviceOpen(..)), Some(timeout)) => {
+            status!("Waiting for {}...", hid_file);
+            HidKeyboard::wait_for(hid_file, timeout.map(Duration::from_secs))
+        }
+        (result, _) => result,
+    };
+    let device = DeviceFile::new(keyboard?.into_inner(), hid_file);
+    // The gadget may also go away while typing, e.g. when it's unbound and bound again
+    let device = match opt.wait_for_device {
+        Some(timeout) => device.reopen_when_gone(timeout.map(Duration::from_secs)),
+        None => device,
+    };
+    if !opt.verify {
+        return Ok(Device::File(device));
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.read(true);
+    // Reading must not wait for a packet that never comes
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::custom_flags(&mut options, libc::O_NONBLOCK);
+    match options.open(verify_file) {
+        Ok(reader) => {
+            let (path, hid_file) = (verify_file.to_string(), hid_file.to_string());
+            let read_back = ReadBack::new(device, reader)
+                .on_unsupported(move |e| no_read_back_warning(&path, &hid_file, e));
+            Ok(Device::Verified(read_back))
+        }
+        Err(e) => {
+            no_read_back_warning(verify_file, hid_file, &e);
+            Ok(Device::File(device))
+        }
+    }
+}
+
+/// Warn when the HID file isn't a character device, which is usually a mistake that types nothing
+/// on the host, or refuse to write to it with --strict. Writing packets to a regular file still
+/// works, e.g. to capture them.
+fn check_character_device(opt: &DeviceOpt, hid_file: &str) -> Result<(), KeyboardError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        // A file that doesn't exist yet is reported when it's opened
+        let kind = match fs::metadata(hid_file) {
+            Ok(metadata) if metadata.file_type().is_char_device() => return Ok(()),
+            Ok(metadata) if metadata.is_dir() => "a directory",
+            Ok(metadata) if metadata.is_file() => "a regular file",
+            Ok(_) => "something other than a character device",
+            Err(_) => return Ok(()),
+        };
+        let message = format!(
+            "is {}, but HID gadgets are character devices such as /dev/hidg0, so nothing will be typed on the host",
+            kind
+        );
+        if opt.strict {
+            let e = io::Error::new(io::ErrorKind::InvalidInput, format!("it {}", message));
+            return Err(KeyboardError::DeviceOpen(hid_file.into(), e));
+        }
+        eprintln!("\r\nWarning: {} {}", hid_file, message);
+    }
+    #[cfg(not(unix))]
+    let _ = (opt, hid_file);
+    Ok(())
+}
+
+fn no_read_back_warning(path: &str, hid_file: &str, e: &io::Error) {
+    eprintln!(
+        "\r\nWarning: can't read packets back from {}, writing to {} without verifying them: {}",
+        path, hid_file, e
+    );
+}
+
+/// The time taken to type each character at the given words per minute, using the convention of 5
+/// characters per word
+fn wpm_to_character_delay(wpm: u32) -> Duration {
+    Duration::from_secs(60 / 5) / wpm
+}
+
+/// The cooldown after each packet that types a character in the given time, where each character
+/// is a press and a release packet. The jitter after each character adds half of it on average,
+/// so that is taken out of the cooldown to keep to the same speed.
+fn wpm_cooldown(per_character: Duration, jitter: Option<Duration>) -> Duration {
+    let average_jitter = jitter.map_or(Duration::from_secs(0), |jitter| jitter / 2);
+    per_character.saturating_sub(average_jitter) / 2
+}
+
+/// The cooldown after each packet that writes no more than the given number of bytes per second
+fn max_rate_cooldown(bytes_per_sec: u32) -> Duration {
+    Duration::from_secs(HID_PACKET_LEN as u64) / bytes_per_sec
+}
+
+fn parse_consumer_report_len(len: &str) -> Result<usize, String> {
+    match len.parse() {
+        Ok(len) if len >= CONSUMER_PACKET_LEN => Ok(len),
+        _ => Err(format!(
+            "Expected a number of bytes of at least {}, got {}",
+            CONSUMER_PACKET_LEN, len
+        )),
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<u32, String> {
+    match u32::from_str_radix(mode, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!(
+            "Expected permissions in octal such as 660, got {}",
+            mode
+        )),
+    }
+}
+
+fn parse_delay(delay: &str) -> Result<Duration, String> {
+    duration::parse(delay, Duration::from_secs(1))
+}
+
+fn parse_cooldown(cooldown: &str) -> Result<Duration, String> {
+    duration::parse(cooldown, Duration::from_millis(1))
+}
+
+/// A duration for the config file, a number in the option's unit, given in nanoseconds, when it's
+/// a whole number of them, otherwise a string with a unit
+fn config_duration(duration: Duration, unit_nanos: u128) -> toml::Value {
+    let nanos = duration.as_nanos();
+    match i64::try_from(nanos / unit_nanos) {
+        Ok(count) if nanos.is_multiple_of(unit_nanos) => count.into(),
+        _ => duration::format(duration).into(),
+    }
+}
+
+fn parse_hold(modifiers: &str) -> Result<u8, String> {
+    keys::parse_modifiers(modifiers).map_err(|e| {
+        format!(
+            "{}, expected modifiers such as ctrl or ctrl+alt. Supported modifiers: {}",
+            e,
+            keys::modifier_names().join(", ")
+        )
+    })
+}
+
+fn parse_typo_rate(rate: &str) -> Result<f64, String> {
+    match rate.parse() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => Err(format!(
+            "Invalid typo rate {}, expected a number from 0 to 1 such as 0.05",
+            rate
+        )),
+    }
+}
+
+/// Parse a range of durations such as 80..200 or 0.1s..0.2s, each bound milliseconds without a
+/// unit like --cooldown
+fn parse_range(range: &str) -> Result<(Duration, Duration), String> {
+    let invalid = |e: String| {
+        format!(
+            "Expected a range of durations such as 80..200 or 80ms..0.2s, got {}: {}",
+            range, e
+        )
+    };
+    let (min, max) = range
+        .split_once("..")
+        .ok_or_else(|| invalid("there's no .. between the bounds".to_string()))?;
+    Ok((
+        parse_cooldown(min).map_err(invalid)?,
+        parse_cooldown(max).map_err(invalid)?,
+    ))
+}
+
+/// Read the text to type from a file, or from stdin until the end of input when the path is '-'
+fn read_input_file(path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let bytes = if path == "-" {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        fs::read(path).map_err(|e| format!("Could not read input file {}: {}", path, e))?
+    };
+
+    if bytes.contains(&0) {
+        return Err(format!(
+            "Input file {} appears to be binary, refusing to type it",
+            path
+        )
+        .into());
+    }
+
+    String::from_utf8(bytes).map_err(|e| {
+        format!(
+            "Input file {} is not valid UTF-8 (invalid byte at offset {})",
+            path,
+            e.utf8_error().valid_up_to()
+        )
+        .into()
+    })
+}
+
+/// Read the text on the clipboard, or the primary selection. On Linux a Wayland session's
+/// clipboard is read with wl-paste and an X11 one directly. Without either display there's no
+/// clipboard to read, e.g. in an SSH session without X forwarding.
+fn read_clipboard(selection: ClipboardSelection) -> Result<String, String> {
+    #[cfg(target_os = "linux")]
+    let text = {
+        let set = |name| env::var_os(name).is_some_and(|display| !display.is_empty());
+        if set("WAYLAND_DISPLAY") {
+            wl_paste(selection)?
+        } else if set("DISPLAY") {
+            read_x11_clipboard(selection)?
+        } else {
+            return Err(
+                "There is no clipboard to read, neither WAYLAND_DISPLAY nor DISPLAY is set. Run kbsim in the desktop session, or with X forwarding over SSH"
+                    .to_string(),
+            );
+        }
+    };
+    #[cfg(not(target_os = "linux"))]
+    let text = read_x11_clipboard(selection)?;
+
+    if text.is_empty() {
+        return Err("The clipboard is empty".to_string());
+    }
+    Ok(text)
+}
+
+/// Read the clipboard with arboard, which talks to the X server on Linux and uses the system
+/// clipboard elsewhere
+fn read_x11_clipboard(selection: ClipboardSelection) -> Result<String, String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| get_selection(&mut clipboard, selection))
+        .map_err(|e| match e {
+            arboard::Error::ContentNotAvailable => {
+                "The clipboard is empty or doesn't hold text, refusing to type it".to_string()
+            }
+            e => format!("Could not read the clipboard: {}", e),
+        })
+}
+
+/// Read a Wayland clipboard with wl-paste, from wl-clipboard
+#[cfg(target_os = "linux")]
+fn wl_paste(selection: ClipboardSelection) -> Result<String, String> {
+    let mut command = process::Command::new("wl-paste");
+    command.args(["--no-newline", "--type", "text"]);
+    if selection == ClipboardSelection::Primary {
+        command.arg("--primary");
+    }
+    let output = command.output().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => {
+            "Reading the clipboard of a Wayland session needs wl-paste, install wl-clipboard"
+                .to_string()
+        }
+        _ => format!("Could not run wl-paste: {}", e),
+    })?;
+    // It fails when nothing is copied, or nothing that's text
+    if !output.status.success() {
+        return Err(format!(
+            "The clipboard is empty or doesn't hold text, refusing to type it: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| "The clipboard doesn't hold UTF-8 text, refusing to type it".to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn get_selection(
+    clipboard: &mut arboard::Clipboard,
+    selection: ClipboardSelection,
+) -> Result<String, arboard::Error> {
+    use arboard::{GetExtLinux, LinuxClipboardKind};
+
+    let kind = match selection {
+        ClipboardSelection::Clipboard => LinuxClipboardKind::Clipboard,
+        ClipboardSelection::Primary => LinuxClipboardKind::Primary,
+    };
+    clipboard.get().clipboard(kind).text()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn get_selection(
+    clipboard: &mut arboard::Clipboard,
+    selection: ClipboardSelection,
+) -> Result<String, arboard::Error> {
+    match selection {
+        ClipboardSelection::Clipboard => clipboard.get_text(),
+        ClipboardSelection::Primary => Err(arboard::Error::ClipboardNotSupported),
+    }
+}
+
+/// Type stdin on the keyboard until the end of input. When stdin is a terminal it is echoed in raw
+/// mode, special keys such as the arrows are pressed instead of typing their escape sequences, and
+/// the exit key also ends the input.
+fn type_stdin(
+    keyboard: ReleaseGuard<Output>,
+    layout: &str,
+    humanizer: Option<&mut Humanizer>,
+    typos: Option<&mut Typos>,
+    lock_keys: Option<LockKeys>,
+    normalizer: &Normalizer,
+    options: &StdinOptions,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let ctrl_c = if options.forward_ctrl_c {
+        Some(Chord::parse(layout, "ctrl+c")?)
+    } else {
+        None
+    };
+    let raw_mode = if options.interactive {
+        Some(RawMode::enable()?)
+    } else {
+        None
+    };
+    let mut typer = StdinTyper {
+        keyboard,
+        layout,
+        humanizer,
+        typos,
+        lock_keys,
+        normalizer,
+        options: *options,
+        ctrl_c,
+        raw_mode,
+        decoder: Utf8Decoder::new(),
+        line_endings: Converter::new(options.line_ending),
+        line_mode: LineMode::new(),
+        unmapped: 0,
+        characters: 0,
+        generated: 0,
+    };
+
+    let mut stdin = std::io::stdin();
+    let mut buf = [0u8; 128];
+    let mut keys = KeyDecoder::new();
+    loop {
+        // The escape key on its own is the start of a sequence until nothing follows it
+        if keys.is_pending() && !stdin_ready(ESCAPE_TIMEOUT) {
+            if let Some(input) = keys.flush() {
+                typer.type_input(input)?;
+            }
+        }
+        let n = match stdin.read(&mut buf[..]) {
+            // End of input
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        if options.interactive {
+            let (input, exit) = split_exit_key(&buf[..n], options.exit_key);
+            for input in keys.decode(input) {
+                typer.type_input(input)?;
+            }
+            if exit {
+                break;
+            }
+        } else {
+            typer.type_input(Input::Bytes(buf[..n].to_vec()))?;
+        }
+    }
+    if let Some(input) = keys.flush() {
+        typer.type_input(input)?;
+    }
+    typer.finish()
+}
+
+/// Types the input read from stdin, decoding the text as it arrives
+struct StdinTyper<'a> {
+    keyboard: ReleaseGuard<Output>,
+    layout: &'a str,
+    humanizer: Option<&'a mut Humanizer>,
+    typos: Option<&'a mut Typos>,
+    // Checked before each piece of text, as the host's lock keys can change at any time
+    lock_keys: Option<LockKeys>,
+    normalizer: &'a Normalizer,
+    options: StdinOptions,
+    ctrl_c: Option<Chord>,
+    raw_mode: Option<RawMode>,
+    decoder: Utf8Decoder,
+    // Kept between reads, which can split a \r\n
+    line_endings: Converter,
+    // Used instead of the line endings with --line-mode
+    line_mode: LineMode,
+    unmapped: usize,
+    // Counted for the summary
+    characters: usize,
+    generated: usize,
+}
+
+impl StdinTyper<'_> {
+    fn type_input(&mut self, input: Input) -> Result<(), Box<dyn std::error::Error>> {
+        match input {
+            Input::Bytes(bytes) => {
+                for chunk in self.decoder.decode(&bytes) {
+                    match chunk {
+                        Chunk::Text(offset, text) => self.type_text(offset, &text)?,
+                        Chunk::Invalid(offset, bytes) => report_invalid(offset, &bytes),
+                    }
+                }
+            }
+            Input::Key(chord) => {
+                self.send_chord(&chord)?;
+            }
+            Input::Unknown(sequence) => eprintln!(
+                "\r\nIgnoring unrecognised escape sequence {:?}",
+                String::from_utf8_lossy(&sequence)
+            ),
+        }
+        Ok(())
+    }
+
+    fn type_text(
+        &mut self,
+        mut offset: usize,
+        text: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(raw_mode) = self.raw_mode.as_mut() {
+            let echo = self.options.echo.echo(text);
+            if !echo.is_empty() {
+                raw_mode.term.write_all(echo.as_bytes())?;
+                raw_mode.term.flush()?;
+            }
+        }
+        let text = if self.options.line_mode {
+            self.line_mode.lines(text)
+        } else {
+            self.line_endings.convert(text)
+        };
+        let parts = match &self.ctrl_c {
+            Some(_) => text.split(CTRL_C).collect(),
+            None => vec![text.as_str()],
+        };
+        for (i, part) in parts.into_iter().enumerate() {
+            if let (true, Some(ctrl_c)) = (i > 0, self.ctrl_c) {
+                self.send_chord(&ctrl_c)?;
+                offset += 1;
+            }
+            let normalized = self.normalizer.normalize(self.layout, part);
+            let (keystrokes, unsupported) = to_keystrokes(
+                self.layout,
+                &normalized,
+                false,
+                offset,
+                self.options.unicode_fallback,
+                self.options.unmappable.replacement(),
+            )
+            .map_err(surrogate_pair_hint)?;
+            let keystrokes =
+                with_uppercase_strategy(self.layout, keystrokes, self.options.caps_lock_threshold)?;
+            let keystrokes = with_typos(self.typos.as_deref_mut(), keystrokes);
+            self.characters += keystrokes.len();
+            let keystrokes = with_rollover(self.options.fast, keystrokes);
+            let keystrokes = with_optimized_modifiers(self.options.optimize_modifiers, keystrokes);
+            self.generated += keystroke_packets(&keystrokes);
+            self.unmapped += check_unsupported(
+                self.layout,
+                &unsupported,
+                self.options.unmappable,
+                self.options.dry_run,
+            )?;
+            if let Some(lock_keys) = self.lock_keys.as_mut() {
+                lock_keys.set(&mut self.keyboard)?;
+            }
+            send_keystrokes(
+                &mut self.keyboard,
+                &keystrokes,
+                self.humanizer.as_deref_mut(),
+                None,
+            )?;
+            offset += part.len();
+        }
+        Ok(())
+    }
+
+    fn send_chord(&mut self, chord: &Chord) -> Result<(), KeyboardError> {
+        let packets = chord.to_hid_packets();
+        self.characters += 1;
+        self.generated += packets.len() / HID_PACKET_LEN;
+        self.keyboard.send_packets(&packets)?;
+        Ok(())
+    }
+
+    /// Finish at the end of input, returning the number of characters that couldn't be typed
+    fn finish(mut self) -> Result<usize, Box<dyn std::error::Error>> {
+        if let Some(Chunk::Invalid(offset, bytes)) = self.decoder.finish() {
+            report_invalid(offset, &bytes);
+        }
+        if self.options.newline {
+            let newline = enter(self.options.line_ending, self.options.line_mode);
+            let (keystrokes, _) =
+                to_keystrokes(self.layout, newline, false, 0, UnicodeFallback::None, None)?;
+            self.characters += keystrokes.len();
+            self.generated += keystroke_packets(&keystrokes);
+            send_keystrokes(&mut self.keyboard, &keystrokes, self.humanizer, None)?;
+        }
+        if let Some(lock_keys) = self.lock_keys.as_mut() {
+            lock_keys.restore(&mut self.keyboard)?;
+        }
+
+        if let Some(mut raw_mode) = self.raw_mode {
+            raw_mode.disable()?;
+        }
+        Summary {
+            characters: self.characters,
+            generated: self.generated,
+            skipped: self.unmapped,
+            written: self.keyboard.stats(),
+        }
+        .report(self.options.stats);
+        Ok(self.unmapped)
+    }
+}
+
+/// What `--newline` types at the end, a single Enter in line mode
+fn enter(line_ending: LineEnding, line_mode: bool) -> &'static str {
+    match line_ending.ending() {
+        Some(ending) if !line_mode => ending,
+        _ => "\n",
+    }
+}
+
+/// Whether stdin has input to read within the timeout
+#[cfg(unix)]
+fn stdin_ready(timeout: Duration) -> bool {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // An error is treated as ready, so the read reports it
+    unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as libc::c_int) != 0 }
+}
+
+#[cfg(not(unix))]
+fn stdin_ready(_timeout: Duration) -> bool {
+    true
+}
+
+/// Split the exit key off the end of a read from a terminal. A key pressed on its own arrives at
+/// the end of a read, in the middle it's part of a paste and is typed like the rest of it.
+fn split_exit_key(input: &[u8], exit_key: u8) -> (&[u8], bool) {
+    match input.split_last() {
+        Some((&last, rest)) if last == exit_key => (rest, true),
+        _ => (input, false),
+    }
+}
+
+/// Parse an exit key such as `ctrl+]` into the control character a terminal sends for it
+fn parse_exit_key(key: &str) -> Result<u8, String> {
+    let invalid = || {
+        format!(
+            "Expected ctrl+ followed by a letter or one of @[\\]^_ as the exit key, got {}",
+            key
+        )
+    };
+    let lower = key.to_lowercase();
+    let name = lower.strip_prefix("ctrl+").ok_or_else(invalid)?;
+    match name.as_bytes() {
+        [c @ b'a'..=b'z'] => Ok(c - b'a' + 1),
+        [c @ (b'@' | b'[' | b'\\' | b']' | b'^' | b'_')] => Ok(c & 0x1f),
+        _ => Err(invalid()),
+    }
+}
+
+fn report_invalid(offset: usize, bytes: &[u8]) {
+    eprintln!(
+        "\r\nCould not decode character {:02x?} at byte {}",
+        bytes, offset
+    );
+}
+
+/// How stdin is read and typed
+#[derive(Clone, Copy)]
+struct StdinOptions {
+    unmappable: Unmappable,
+    unicode_fallback: UnicodeFallback,
+    dry_run: bool,
+    /// Type a newline at the end of input
+    newline: bool,
+    /// Press characters together with the keyboard's rollover
+    fast: bool,
+    /// Hold modifiers between characters typed with the same ones
+    optimize_modifiers: bool,
+    line_ending: LineEnding,
+    /// Press Enter after each line, except the last
+    line_mode: bool,
+    /// Read from a terminal in raw mode, echoing the input and stopping at the exit key
+    interactive: bool,
+    exit_key: u8,
+    /// Press ctrl+c on the keyboard for 0x03 instead of typing it with the layout
+    forward_ctrl_c: bool,
+    echo: EchoStyle,
+    /// Type runs of this many uppercase letters with Caps Lock
+    caps_lock_threshold: Option<usize>,
+    /// Print a summary at the end of input
+    stats: bool,
+}
+
+/// Keeps the terminal in raw mode until it is disabled or dropped, so the terminal is restored
+/// even when typing fails part way through
+struct RawMode {
+    term: terminal::Terminal<io::Stdout>,
+    enabled: bool,
+}
+
+impl RawMode {
+    fn enable() -> terminal::error::Result<Self> {
+        let term = terminal::stdout();
+        term.act(terminal::Action::EnableRawMode)?;
+        RAW_MODE_ENABLED.store(true, atomic::Ordering::SeqCst);
+        Ok(RawMode {
+            term,
+            enabled: true,
+        })
+    }
+
+    fn disable(&mut self) -> terminal::error::Result<()> {
+        if self.enabled {
+            self.enabled = false;
+            self.term.act(terminal::Action::DisableRawMode)?;
+            RAW_MODE_ENABLED.store(false, atomic::Ordering::SeqCst);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = self.disable();
+    }
+}
+
+/// Where the keyboard writes its HID packets
+enum Output {
+    Device(Device),
+    /// Several devices getting the same packets
+    Mirror(FanOut<Device>),
+    /// A virtual keyboard on this machine
+    #[cfg(target_os = "linux")]
+    Uinput(keyboard_layouts::uinput::Uinput),
+    /// The operating system's input API
+    #[cfg(all(feature = "native", any(windows, target_os = "macos")))]
+    Native(keyboard_layouts::native::Native),
+    Dump(HexDump<io::Stdout>),
+    Json(JsonDump<io::Stdout>),
+    CArray(CArrayDump<io::Stdout>),
+    Base64(Base64Dump<io::Stdout>),
+    /// Another output, with its packets logged to --log-file
+    Logged(Box<PacketLog<Output, LogFile>>),
+}
+
+impl Output {
+    /// Leave the characters out of the --log-file, still logging their packets
+    fn hide_logged_characters(&mut self) {
+        if let Output::Logged(log) = self {
+            log.hide_characters();
+        }
+    }
+}
+
+/// A HID device, with its packets read back for --verify
+enum Device {
+    File(DeviceFile),
+    Verified(ReadBack<DeviceFile, fs::File>),
+}
+
+impl HidWriter for Device {
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        match self {
+            Device::File(file) => file.write_packet(packet),
+            Device::Verified(file) => file.write_packet(packet),
+        }
+    }
+
+    fn write_packets(&mut self, packets: &[u8]) -> io::Result<usize> {
+        match self {
+            Device::File(file) => file.write_packets(packets),
+            Device::Verified(file) => file.write_packets(packets),
+        }
+    }
+}
+
+impl HidWriter for Output {
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        match self {
+            Output::Device(device) => device.write_packet(packet),
+            Output::Mirror(devices) => devices.write_packet(packet),
+            #[cfg(target_os = "linux")]
+            Output::Uinput(uinput) => uinput.write_packet(packet),
+            #[cfg(all(feature = "native", any(windows, target_os = "macos")))]
+            Output::Native(native) => native.write_packet(packet),
+            Output::Dump(dump) => dump.write_packet(packet),
+            Output::Json(dump) => dump.write_packet(packet),
+            Output::CArray(dump) => dump.write_packet(packet),
+            Output::Base64(dump) => dump.write_packet(packet),
+            Output::Logged(log) => log.write_packet(packet),
+        }
+    }
+
+    fn write_packets(&mut self, packets: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Device(device) => device.write_packets(packets),
+            Output::Mirror(devices) => devices.write_packets(packets),
+            #[cfg(target_os = "linux")]
+            Output::Uinput(uinput) => uinput.write_packets(packets),
+            #[cfg(all(feature = "native", any(windows, target_os = "macos")))]
+            Output::Native(native) => native.write_packets(packets),
+            Output::Dump(dump) => dump.write_packets(packets),
+            Output::Json(dump) => dump.write_packets(packets),
+            Output::CArray(dump) => dump.write_packets(packets),
+            Output::Base64(dump) => dump.write_packets(packets),
+            Output::Logged(log) => log.write_packets(packets),
+        }
+    }
+
+    fn start_keystroke(&mut self, character: Option<char>) {
+        match self {
+            Output::Device(device) => device.start_keystroke(character),
+            Output::Mirror(devices) => devices.start_keystroke(character),
+            #[cfg(target_os = "linux")]
+            Output::Uinput(uinput) => uinput.start_keystroke(character),
+            #[cfg(all(feature = "native", any(windows, target_os = "macos")))]
+            Output::Native(native) => native.start_keystroke(character),
+            Output::Dump(dump) => dump.start_keystroke(character),
+            Output::Json(dump) => dump.start_keystroke(character),
+            Output::CArray(dump) => dump.start_keystroke(character),
+            Output::Base64(dump) => dump.start_keystroke(character),
+            Output::Logged(log) => log.start_keystroke(character),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn arguments_without_a_subcommand_are_typed() {
+        let cases: &[(&[&str], &[&str])] = &[
+            (&["kbsim"], &["kbsim", "type"]),
+            (
+                &["kbsim", "-l", "de", "hallo"],
+                &["kbsim", "type", "-l", "de", "hallo"],
+            ),
+            (
+                &["kbsim", "--layout-file", "bepo.toml", "-n", "hi"],
+                &["kbsim", "--layout-file", "bepo.toml", "type", "-n", "hi"],
+            ),
+            (
+                &["kbsim", "-l", "de", "config-dump"],
+                &["kbsim", "config-dump", "-l", "de"],
+            ),
+            (&["kbsim", "list"], &["kbsim", "list"]),
+            (&["kbsim", "-q", "list"], &["kbsim", "-q", "list"]),
+            (&["kbsim", "-q", "hi"], &["kbsim", "-q", "type", "hi"]),
+            (
+                &["kbsim", "-vv", "--verbose", "dump"],
+                &["kbsim", "-vv", "--verbose", "dump"],
+            ),
+            (
+                &["kbsim", "--config=kbsim.toml", "raw", "00"],
+                &["kbsim", "--config=kbsim.toml", "raw", "00"],
+            ),
+            (&["kbsim", "--help"], &["kbsim", "--help"]),
+            (
+                &["kbsim", "--generate-completions", "bash"],
+                &["kbsim", "--generate-completions", "bash"],
+            ),
+        ];
+
+        for (given, expected) in cases {
+            assert_eq!(with_default_command(args(given)), args(expected));
+        }
+    }
+
+    #[test]
+    fn completions_list_the_layouts() {
+        GENERATING_COMPLETIONS.store(true, atomic::Ordering::SeqCst);
+        let mut script = Vec::new();
+        CliOpt::clap().gen_completions_to("kbsim", Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+
+        assert!(script.contains("--layout)"), "{}", script);
+        assert!(script.contains("LAYOUT_GERMAN_SWISS"), "{}", script);
+    }
+
+    #[test]
+    fn layouts_are_filtered_by_name_or_alias() {
+        let names = |filter| {
+            matching_layouts(Some(filter))
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<&str>>()
+        };
+
+        assert_eq!(
+            names("Swiss"),
+            vec!["LAYOUT_FRENCH_SWISS", "LAYOUT_GERMAN_SWISS"]
+        );
+        assert_eq!(names("gb"), vec!["LAYOUT_UNITED_KINGDOM"]);
+        assert!(names("klingon").is_empty());
+        assert_eq!(
+            matching_layouts(None).len(),
+            keyboard_layouts::available_layouts().len()
+        );
+    }
+
+    #[test]
+    fn echo_styles_erase_backspaces() {
+        assert_eq!(EchoStyle::Plain.echo("ab\x7fc\r"), "ab\x08 \x08c\r");
+        assert_eq!(EchoStyle::Dots.echo("pä\x08s\r"), "••\x08 \x08•\r");
+        assert_eq!(EchoStyle::None.echo("secret\r"), "");
+    }
+
+    #[test]
+    fn exit_keys_are_control_characters() {
+        assert_eq!(parse_exit_key("ctrl+]"), Ok(0x1d));
+        assert_eq!(parse_exit_key("CTRL+D"), Ok(0x04));
+        assert_eq!(parse_exit_key("ctrl+c"), Ok(0x03));
+        assert_eq!(parse_exit_key("ctrl+@"), Ok(0x00));
+        assert!(parse_exit_key("ctrl+1").is_err());
+        assert!(parse_exit_key("ctrl+ab").is_err());
+        assert!(parse_exit_key("]").is_err());
+    }
+
+    #[test]
+    fn exit_key_only_ends_a_read() {
+        assert_eq!(split_exit_key(b"\x1d", 0x1d), (&b""[..], true));
+        assert_eq!(split_exit_key(b"abc\x1d", 0x1d), (&b"abc"[..], true));
+        // Pasted in the middle of some text
+        assert_eq!(split_exit_key(b"a\x1db", 0x1d), (&b"a\x1db"[..], false));
+        assert_eq!(split_exit_key(b"a\x03", 0x1d), (&b"a\x03"[..], false));
+        assert_eq!(split_exit_key(b"", 0x1d), (&b""[..], false));
+    }
+
+    #[test]
+    fn hex_packets_are_parsed() {
+        let packet = vec![0, 0, 0x04, 0, 0, 0, 0, 0];
+        let release = vec![0; HID_PACKET_LEN];
+        let parse = |args: &[&str]| {
+            parse_hex_packets(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+        };
+
+        assert_eq!(parse(&["00 00 04 00 00 00 00 00"]), Ok(packet.clone()));
+        assert_eq!(
+            parse(&["0000040000000000", "00", "00 00 00 00000000"]),
+            Ok([packet, release].concat())
+        );
+        assert_eq!(
+            parse(&["00 00 04"]),
+            Err("Expected a whole number of 8 byte packets, got 3 byte(s)".to_string())
+        );
+        assert_eq!(
+            parse(&["000"]),
+            Err("Odd number of hex digits in 000".to_string())
+        );
+        assert_eq!(
+            parse(&["00 0g 00 00 00 00 00 00"]),
+            Err("Invalid hex in 0g".to_string())
+        );
+        assert_eq!(parse(&["+f"]), Err("Invalid hex in +f".to_string()));
+        assert!(parse(&[]).is_err());
+    }
+
+    #[test]
+    fn hex_files_have_a_packet_per_line() {
+        let file = "# Press a, then release it\n\
+                    00 00 04 00 00 00 00 00\n\
+                    \n\
+                    0000000000000000 # release\n";
+        assert_eq!(
+            parse_hex_file("keys.hex", file),
+            Ok([vec![0, 0, 0x04, 0, 0, 0, 0, 0], vec![0; HID_PACKET_LEN]].concat())
+        );
+
+        assert_eq!(
+            parse_hex_file(
+                "keys.hex",
+                "0000040000000000\n# next\n00 00 04 00 00 00 00\n"
+            ),
+            Err("Line 3 of keys.hex: expected a packet of 8 bytes, got 7 byte(s)".to_string())
+        );
+        assert_eq!(
+            parse_hex_file("keys.hex", "00 0g 00 00 00 00 00 00"),
+            Err("Line 1 of keys.hex: Invalid hex in 0g".to_string())
+        );
+        assert_eq!(
+            parse_hex_file("keys.hex", "# nothing\n"),
+            Err("No packets in keys.hex".to_string())
+        );
+    }
+
+    #[test]
+    fn x11_layout_is_read_from_localectl() {
+        let status = "   System Locale: LANG=C.UTF-8\n       VC Keymap: uk\n      X11 Layout: gb\n       X11 Model: pc105\n";
+
+        assert_eq!(parse_x11_layout(status), Some("gb".to_string()));
+        assert_eq!(
+            parse_x11_layout("   System Locale: LANG=C\n      X11 Layout: \n"),
+            None
+        );
+    }
+
+    #[test]
+    fn unknown_layouts_suggest_similar_names() {
+        assert_eq!(
+            unknown_layout_error("unitedkingdm"),
+            "No layout defined for unitedkingdm\nDid you mean LAYOUT_UNITED_KINGDOM?\nRun kbsim list to show all available layouts"
+        );
+        assert_eq!(
+            unknown_layout_error("klingon"),
+            "No layout defined for klingon\nRun kbsim list to show all available layouts"
+        );
+    }
+
+    #[test]
+    fn wpm_keeps_its_speed_with_jitter() {
+        let per_character = wpm_to_character_delay(60);
+        assert_eq!(per_character, Duration::from_millis(200));
+
+        assert_eq!(
+            wpm_cooldown(per_character, None),
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            wpm_cooldown(per_character, Some(Duration::from_millis(80))),
+            Duration::from_millis(80)
+        );
+        assert_eq!(
+            wpm_cooldown(per_character, Some(Duration::from_secs(1))),
+            Duration::from_secs(0)
+        );
+    }
+
+    #[test]
+    fn typos_wait_for_the_correction_pause() {
+        let layout = "LAYOUT_US_ENGLISH";
+        let keystrokes = keyboard_layouts::string_to_keystrokes(layout, "hi").unwrap();
+        let keystrokes = Typos::new(layout, 1.0)
+            .unwrap()
+            .with_seed(4)
+            .add(keystrokes);
+        let parts = [Part::Keystrokes(keystrokes)];
+        let cooldown = Duration::from_millis(10);
+        let humanizer = Humanizer::new(cooldown, cooldown);
+        let correcting = humanizer
+            .clone()
+            .with_correction_pause(Duration::from_millis(500));
+
+        // Two typos, each with a Backspace after it
+        assert_eq!(
+            typing_time(&parts, cooldown, None, Some(&correcting)),
+            typing_time(&parts, cooldown, None, Some(&humanizer)) + Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn humanize_ranges_take_units() {
+        let range = (Duration::from_millis(80), Duration::from_millis(200));
+
+        assert_eq!(parse_range("80..200"), Ok(range));
+        assert_eq!(parse_range("80ms..200ms"), Ok(range));
+        assert_eq!(parse_range("0.08s..0.2s"), Ok(range));
+        assert!(parse_range("80").unwrap_err().contains("no .."));
+        assert!(parse_range("80..soon").is_err());
+    }
+
+    #[test]
+    fn max_rate_spaces_out_the_packets() {
+        assert_eq!(max_rate_cooldown(80), Duration::from_millis(100));
+        assert_eq!(max_rate_cooldown(1000), Duration::from_millis(8));
+        assert_eq!(max_rate_cooldown(3), Duration::from_nanos(2_666_666_666));
+    }
+
+    #[test]
+    fn unmappable_characters_can_be_replaced() {
+        assert_eq!("skip".parse(), Ok(Unmappable::Skip));
+        assert_eq!("replace=?".parse(), Ok(Unmappable::Replace('?')));
+        assert!("replace=".parse::<Unmappable>().is_err());
+        assert!("replace=ab".parse::<Unmappable>().is_err());
+        assert!("drop".parse::<Unmappable>().is_err());
+
+        let layout = "LAYOUT_US_ENGLISH";
+        let (keystrokes, unsupported) =
+            to_keystrokes(layout, "a€b", false, 0, UnicodeFallback::None, Some('?')).unwrap();
+        assert!(unsupported.is_empty());
+        assert_eq!(
+            keystrokes,
+            keyboard_layouts::string_to_keystrokes(layout, "a?b").unwrap()
+        );
+    }
+
+    #[test]
+    fn packets_are_counted_across_devices() {
+        let (parts, _) = to_parts(
+            "LAYOUT_US_ENGLISH",
+            "A{MUTE}{CTRL+C}",
+            true,
+            0,
+            UnicodeFallback::None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(parts.len(), 3);
+        assert_eq!(packet_count(&parts), 6);
+    }
+
+    #[test]
+    fn the_preview_is_the_start_of_the_first_line() {
+        assert_eq!(preview("Hi\n"), "\"Hi\"");
+        assert_eq!(preview("Hi\nthere"), "\"Hi\"…");
+        assert_eq!(preview("\ttab"), "\"\\ttab\"");
+        let long = "é".repeat(50);
+        assert_eq!(preview(&long), format!("{:?}…", "é".repeat(40)));
+    }
+}
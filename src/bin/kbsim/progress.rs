@@ -0,0 +1,147 @@
+//! A progress bar on stderr for `--progress`, while typing STRING or a file, where the number of
+//! packets is known before typing starts
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+const WIDTH: usize = 30;
+
+/// How often the bar is redrawn, so a short cooldown doesn't spend its time writing to stderr
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Whether a bar is on the last line of stderr
+static DRAWN: AtomicBool = AtomicBool::new(false);
+/// Set once the bar has been cleared for good, so it isn't drawn again
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// Counts the packets written towards the total, redrawing the bar as they are. The last line is
+/// finished when it's dropped, so an error is printed below it.
+pub struct Progress {
+    total: usize,
+    sent: usize,
+    // The time each packet is expected to take before any have been sent
+    cooldown: Duration,
+    started: Instant,
+    drawn: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(total: usize, cooldown: Duration) -> Self {
+        Progress {
+            total,
+            sent: 0,
+            cooldown,
+            started: Instant::now(),
+            drawn: None,
+        }
+    }
+
+    /// Count more packets written, redrawing the bar if it's due
+    pub fn add(&mut self, packets: usize) {
+        self.sent += packets;
+        let due = self
+            .drawn
+            .is_none_or(|drawn| drawn.elapsed() >= REDRAW_INTERVAL);
+        if due || self.sent >= self.total {
+            self.draw();
+        }
+    }
+
+    /// The time left, from the average time taken by each packet so far
+    fn eta(&self) -> Duration {
+        let per_packet = match self.sent {
+            0 => self.cooldown,
+            sent => self.started.elapsed() / sent as u32,
+        };
+        per_packet.mul_f64(self.total.saturating_sub(self.sent) as f64)
+    }
+
+    fn line(&self, elapsed: Duration, eta: Duration) -> String {
+        let sent = self.sent.min(self.total);
+        let filled = match self.total {
+            0 => WIDTH,
+            total => sent * WIDTH / total,
+        };
+        let bar = match filled {
+            WIDTH => "=".repeat(WIDTH),
+            _ => format!("{}>{}", "=".repeat(filled), " ".repeat(WIDTH - filled - 1)),
+        };
+        format!(
+            "[{}] {:>3}% {}/{} packets, {} elapsed, {} left",
+            bar,
+            (sent * 100).checked_div(self.total).unwrap_or(100),
+            sent,
+            self.total,
+            format_time(elapsed),
+            format_time(eta)
+        )
+    }
+
+    fn draw(&mut self) {
+        self.drawn = Some(Instant::now());
+        if STOPPED.load(Ordering::SeqCst) {
+            return;
+        }
+        let line = self.line(self.started.elapsed(), self.eta());
+        let mut stderr = io::stderr();
+        let _ = write!(stderr, "\r{}", line);
+        let _ = stderr.flush();
+        DRAWN.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if DRAWN.swap(false, Ordering::SeqCst) {
+            eprintln!();
+        }
+    }
+}
+
+/// Erase the bar and stop drawing it, when typing is interrupted, so what's printed while the keys
+/// are released starts on an empty line
+#[cfg(unix)]
+pub fn clear() {
+    STOPPED.store(true, Ordering::SeqCst);
+    if DRAWN.swap(false, Ordering::SeqCst) {
+        let mut stderr = io::stderr();
+        let _ = write!(stderr, "\r\x1b[2K");
+        let _ = stderr.flush();
+    }
+}
+
+/// A duration in whole seconds, with minutes once there's more than one
+fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs_f64().round() as u64;
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        _ => format!("{}m{:02}s", seconds / 60, seconds % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_bar_shows_the_packets_sent() {
+        let mut progress = Progress::new(400, Duration::from_millis(10));
+        assert_eq!(
+            progress.line(Duration::from_secs(0), progress.eta()),
+            "[>                             ]   0% 0/400 packets, 0s elapsed, 4s left"
+        );
+
+        progress.sent = 100;
+        assert_eq!(
+            progress.line(Duration::from_secs(25), Duration::from_secs(75)),
+            "[=======>                      ]  25% 100/400 packets, 25s elapsed, 1m15s left"
+        );
+
+        progress.sent = 400;
+        assert_eq!(
+            progress.line(Duration::from_secs(100), progress.eta()),
+            "[==============================] 100% 400/400 packets, 1m40s elapsed, 0s left"
+        );
+    }
+}
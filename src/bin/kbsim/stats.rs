@@ -0,0 +1,186 @@
+//! The summary of a run printed on stderr with `--stats`, or logged with -v, of what was converted
+//! and what was actually written to the device, and the estimate printed by `--estimate` instead of
+//! typing
+
+use keyboard_layouts::Stats;
+
+use std::time::Duration;
+
+/// What was converted from the text, with the writes counted by the keyboard
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub characters: usize,
+    pub generated: usize,
+    pub skipped: usize,
+    pub written: Stats,
+}
+
+impl Summary {
+    /// Add the writes to another device, such as the consumer control device media keys are
+    /// pressed on, which are made during the same run
+    pub fn add_writes(&mut self, written: Stats) {
+        self.written.packets += written.packets;
+        self.written.bytes += written.bytes;
+        self.written.retries += written.retries;
+        self.written.elapsed = self.written.elapsed.max(written.elapsed);
+    }
+
+    /// Print the summary with --stats, otherwise it's only logged
+    pub fn report(&self, print: bool) {
+        for line in self.lines() {
+            if print {
+                eprintln!("{}", line);
+            } else {
+                log::info!("{}", line);
+            }
+        }
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Characters converted: {}", self.characters),
+            format!("Packets generated:    {}", self.generated),
+            format!("Packets written:      {}", self.written.packets),
+            format!("Bytes written:        {}", self.written.bytes),
+            format!("Duration:             {:.2?}", self.written.elapsed),
+        ];
+        // Packets written back to back take no measurable time
+        if self.written.elapsed > Duration::from_millis(0) {
+            let per_second = self.characters as f64 / self.written.elapsed.as_secs_f64();
+            lines.push(format!(
+                "Speed:                {:.1} characters/s, {:.0} WPM",
+                per_second,
+                // Using the convention of 5 characters per word
+                per_second * 60.0 / 5.0
+            ));
+        }
+        lines.push(format!("Retries:              {}", self.written.retries));
+        lines.push(format!("Skipped characters:   {}", self.skipped));
+        lines
+    }
+}
+
+/// What typing would send, and how long it would take with the waits between packets
+#[derive(Debug)]
+pub struct Estimate {
+    pub characters: usize,
+    pub packets: usize,
+    pub skipped: usize,
+    pub duration: Duration,
+}
+
+impl Estimate {
+    /// The estimate for typing the packets the given number of times, each taking `typing` with
+    /// the waits after its packets and keystrokes, after the delay before starting and with the
+    /// interval between each time
+    pub fn new(
+        characters: usize,
+        packets: usize,
+        repeat: u64,
+        (delay, typing, interval): (Duration, Duration, Duration),
+    ) -> Self {
+        Estimate {
+            characters: characters * repeat as usize,
+            packets: packets * repeat as usize,
+            skipped: 0,
+            duration: delay
+                + typing.mul_f64(repeat as f64)
+                + interval.mul_f64(repeat.saturating_sub(1) as f64),
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!("Characters:         {}", self.characters),
+            format!("Packets:            {}", self.packets),
+            format!("Estimated duration: {:.2?}", self.duration),
+            format!("Skipped characters: {}", self.skipped),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_summary_includes_the_speed() {
+        let summary = Summary {
+            characters: 100,
+            generated: 200,
+            skipped: 2,
+            written: Stats {
+                packets: 198,
+                bytes: 1584,
+                retries: 1,
+                elapsed: Duration::from_secs(4),
+            },
+        };
+
+        assert_eq!(
+            summary.lines(),
+            [
+                "Characters converted: 100",
+                "Packets generated:    200",
+                "Packets written:      198",
+                "Bytes written:        1584",
+                "Duration:             4.00s",
+                "Speed:                25.0 characters/s, 300 WPM",
+                "Retries:              1",
+                "Skipped characters:   2",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_estimate_includes_the_delay_and_intervals() {
+        // 10 packets waiting a 10ms cooldown after each
+        let times = (
+            Duration::from_secs(1),
+            Duration::from_millis(100),
+            Duration::from_millis(500),
+        );
+        let estimate = Estimate::new(5, 10, 3, times);
+
+        assert_eq!(
+            estimate.lines(),
+            [
+                "Characters:         15",
+                "Packets:            30",
+                "Estimated duration: 2.30s",
+                "Skipped characters: 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_to_several_devices_are_added() {
+        let mut summary = Summary::default();
+        let written = Stats {
+            packets: 4,
+            bytes: 32,
+            retries: 0,
+            elapsed: Duration::from_millis(30),
+        };
+        summary.add_writes(written);
+        summary.add_writes(Stats {
+            packets: 2,
+            bytes: 6,
+            retries: 1,
+            elapsed: Duration::from_millis(10),
+        });
+
+        assert_eq!(
+            summary.written,
+            Stats {
+                packets: 6,
+                bytes: 38,
+                retries: 1,
+                elapsed: Duration::from_millis(30),
+            }
+        );
+        // Nothing took any time, so there's no speed
+        summary.written.elapsed = Duration::from_millis(0);
+        assert!(!summary.lines().iter().any(|line| line.starts_with("Speed")));
+    }
+}
@@ -0,0 +1,288 @@
+//! Following the lines appended to a file, like `tail -f`, including when the file is truncated
+//! or replaced by log rotation, and for `--follow` everything appended to a file or written to a
+//! named pipe
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// The lines appended to a file since it was opened
+pub struct Tail {
+    path: PathBuf,
+    file: File,
+    position: u64,
+    // The end of the file after the last complete line
+    partial: Vec<u8>,
+}
+
+impl Tail {
+    /// Open the file, ignoring the lines it already has
+    pub fn open(path: &Path) -> io::Result<Tail> {
+        let mut file = File::open(path)?;
+        let position = file.seek(SeekFrom::End(0))?;
+        Ok(Tail {
+            path: path.to_path_buf(),
+            file,
+            position,
+            partial: Vec::new(),
+        })
+    }
+
+    /// Read the complete lines appended since the last call, each ending with its newline. When
+    /// the file has been truncated or replaced, it is read again from the start.
+    pub fn read_lines(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        match self.change()? {
+            Change::None => {}
+            Change::Replaced => {
+                // Finish the lines written before the file was replaced
+                let mut lines = self.read_to_end()?;
+                self.reopen()?;
+                lines.extend(self.read_to_end()?);
+                return Ok(lines);
+            }
+            Change::Truncated => self.restart()?,
+        }
+        self.read_to_end()
+    }
+
+    /// Read everything appended since the last call, including an incomplete last line. When the
+    /// file has been truncated or replaced, it is read again from the start.
+    pub fn read_appended(&mut self) -> io::Result<Vec<u8>> {
+        let mut appended = Vec::new();
+        match self.change()? {
+            Change::None => {}
+            Change::Replaced => {
+                self.read_rest(&mut appended)?;
+                self.reopen()?;
+            }
+            Change::Truncated => self.restart()?,
+        }
+        self.read_rest(&mut appended)?;
+        Ok(appended)
+    }
+
+    /// How the file at the path has changed since it was last read
+    fn change(&mut self) -> io::Result<Change> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // Rotated away, and the new file isn't there yet, but the old one can still be read
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Change::None),
+            Err(e) => return Err(e),
+        };
+        if !same_file(&metadata, &self.file.metadata()?) {
+            status!(
+                "{} was replaced, reading it from the start",
+                self.path.display()
+            );
+            return Ok(Change::Replaced);
+        }
+        if metadata.len() < self.position {
+            status!(
+                "{} was truncated, reading it from the start",
+                self.path.display()
+            );
+            return Ok(Change::Truncated);
+        }
+        Ok(Change::None)
+    }
+
+    fn read_rest(&mut self, buf: &mut Vec<u8>) -> io::Result<()> {
+        let read = self.file.read_to_end(buf)?;
+        self.position += read as u64;
+        Ok(())
+    }
+
+    // Read the rest of the file, returning the complete lines
+    fn read_to_end(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let mut partial = std::mem::take(&mut self.partial);
+        let read = self.read_rest(&mut partial);
+        self.partial = partial;
+        read?;
+
+        let end = match self.partial.iter().rposition(|b| *b == b'\n') {
+            Some(end) => end + 1,
+            None => return Ok(Vec::new()),
+        };
+        let rest = self.partial.split_off(end);
+        let complete = std::mem::replace(&mut self.partial, rest);
+        Ok(complete
+            .split_inclusive(|b| *b == b'\n')
+            .map(<[u8]>::to_vec)
+            .collect())
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        self.file = File::open(&self.path)?;
+        self.restart()
+    }
+
+    fn restart(&mut self) -> io::Result<()> {
+        self.position = self.file.seek(SeekFrom::Start(0))?;
+        self.partial.clear();
+        Ok(())
+    }
+}
+
+/// How a followed file has changed
+enum Change {
+    None,
+    /// Truncated to less than has been read
+    Truncated,
+    /// Replaced by another file at the same path
+    Replaced,
+}
+
+/// What --follow reads from: the content appended to a file, or written to a named pipe
+pub enum Follow {
+    File(Tail),
+    /// A named pipe, which is opened again once its writer closes it, and isn't open while
+    /// waiting for the next one
+    Fifo(PathBuf, Option<File>),
+}
+
+impl Follow {
+    /// Follow the file, ignoring what it already has, or the named pipe
+    pub fn open(path: &Path) -> io::Result<Follow> {
+        if is_fifo(&fs::metadata(path)?) {
+            return Ok(Follow::Fifo(path.to_path_buf(), None));
+        }
+        Tail::open(path).map(Follow::File)
+    }
+
+    /// Whether there's nothing to wait for when nothing was read, as reading blocks until there is
+    pub fn blocks(&self) -> bool {
+        matches!(self, Follow::Fifo(..))
+    }
+
+    /// Read what has arrived since the last call. A named pipe is read as soon as its writer has
+    /// written something, waiting for a writer to open it if there isn't one, and reading nothing
+    /// when the writer has closed it.
+    pub fn read(&mut self) -> io::Result<Vec<u8>> {
+        let (path, fifo) = match self {
+            Follow::File(tail) => return tail.read_appended(),
+            Follow::Fifo(path, fifo) => (path, fifo),
+        };
+        let file = match fifo {
+            Some(file) => file,
+            // Opening a named pipe waits for a writer
+            None => fifo.insert(File::open(&*path)?),
+        };
+        let mut buf = vec![0; 4096];
+        let read = loop {
+            match file.read(&mut buf) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                read => break read?,
+            }
+        };
+        if read == 0 {
+            log::debug!("{} was closed, waiting for another writer", path.display());
+            *fifo = None;
+        }
+        buf.truncate(read);
+        Ok(buf)
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
+
+// Without inodes a replaced file can only be noticed when it's shorter
+#[cfg(not(unix))]
+fn same_file(_a: &fs::Metadata, _b: &fs::Metadata) -> bool {
+    true
+}
+
+#[cfg(unix)]
+fn is_fifo(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    metadata.file_type().is_fifo()
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    fn append(path: &Path, text: &str) {
+        let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    fn lines(tail: &mut Tail) -> Vec<String> {
+        tail.read_lines()
+            .unwrap()
+            .into_iter()
+            .map(|line| String::from_utf8(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn appended_lines_are_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("build.log");
+        fs::write(&path, "old\n").unwrap();
+        let mut tail = Tail::open(&path).unwrap();
+
+        assert_eq!(lines(&mut tail), Vec::<String>::new());
+        append(&path, "one\ntwo\nthr");
+        assert_eq!(lines(&mut tail), vec!["one\n", "two\n"]);
+        append(&path, "ee\n");
+        assert_eq!(lines(&mut tail), vec!["three\n"]);
+    }
+
+    #[test]
+    fn truncated_files_are_read_from_the_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("build.log");
+        fs::write(&path, "a long first line\n").unwrap();
+        let mut tail = Tail::open(&path).unwrap();
+
+        fs::write(&path, "new\n").unwrap();
+        assert_eq!(lines(&mut tail), vec!["new\n"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn rotated_files_are_reopened() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("build.log");
+        fs::write(&path, "old\n").unwrap();
+        let mut tail = Tail::open(&path).unwrap();
+
+        append(&path, "last\n");
+        fs::rename(&path, dir.path().join("build.log.1")).unwrap();
+        assert_eq!(lines(&mut tail), vec!["last\n"]);
+        fs::write(&path, "rotated and longer\n").unwrap();
+        assert_eq!(lines(&mut tail), vec!["rotated and longer\n"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn everything_appended_is_followed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("typeme");
+        fs::write(&path, "old\n").unwrap();
+        let mut follow = Follow::open(&path).unwrap();
+
+        assert!(!follow.blocks());
+        assert_eq!(follow.read().unwrap(), b"");
+        append(&path, "one\ntw");
+        assert_eq!(follow.read().unwrap(), b"one\ntw");
+        append(&path, "o\n");
+        fs::rename(&path, dir.path().join("typeme.1")).unwrap();
+        fs::write(&path, "new").unwrap();
+        // The end of the old file comes before the new one
+        assert_eq!(follow.read().unwrap(), b"o\nnew");
+    }
+}
@@ -0,0 +1,135 @@
+//! Decoding UTF-8 that arrives in chunks, such as reads from stdin, where a multi-byte character
+//! can be split between two chunks
+
+/// A piece of decoded input, with the byte offset in the input where it starts
+#[derive(Debug, PartialEq)]
+pub enum Chunk {
+    Text(usize, String),
+    /// Bytes that aren't valid UTF-8
+    Invalid(usize, Vec<u8>),
+}
+
+/// Decodes UTF-8 chunk by chunk, carrying an incomplete character at the end of a chunk over to
+/// the next one
+#[derive(Debug, Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+    // The byte offset in the input of the start of pending
+    offset: usize,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Utf8Decoder::default()
+    }
+
+    /// Decode as much as possible of the input so far, returning the text and any invalid
+    /// sequences in the order they appear.
+    pub fn decode(&mut self, bytes: &[u8]) -> Vec<Chunk> {
+        self.pending.extend_from_slice(bytes);
+
+        let mut chunks = Vec::new();
+        loop {
+            let (valid, invalid) = match std::str::from_utf8(&self.pending) {
+                Ok(_) => (self.pending.len(), None),
+                Err(e) => (e.valid_up_to(), e.error_len()),
+            };
+            if valid > 0 {
+                let text =
+                    String::from_utf8(self.pending[..valid].to_vec()).expect("valid up to here");
+                chunks.push(Chunk::Text(self.offset, text));
+            }
+            // An error without a length is an incomplete character, which may be completed by the
+            // next chunk
+            let consumed = valid + invalid.unwrap_or(0);
+            if let Some(len) = invalid {
+                chunks.push(Chunk::Invalid(
+                    self.offset + valid,
+                    self.pending[valid..valid + len].to_vec(),
+                ));
+            }
+            self.pending.drain(..consumed);
+            self.offset += consumed;
+            if invalid.is_none() {
+                return chunks;
+            }
+        }
+    }
+
+    /// Finish decoding at the end of the input, returning the bytes of an incomplete character
+    /// left at the end, if there is one.
+    pub fn finish(self) -> Option<Chunk> {
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(Chunk::Invalid(self.offset, self.pending))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "héllo wörld 😀 测试";
+
+    /// Decode the chunks, returning the decoded text and the chunks for any invalid sequences
+    fn decode_all(input: &[&[u8]]) -> (String, Vec<Chunk>) {
+        let mut decoder = Utf8Decoder::new();
+        let mut text = String::new();
+        let mut invalid = Vec::new();
+        let chunks = input.iter().flat_map(|bytes| decoder.decode(bytes));
+        for chunk in chunks
+            .collect::<Vec<Chunk>>()
+            .into_iter()
+            .chain(decoder.finish())
+        {
+            match chunk {
+                // Only valid input is split, so the text is contiguous
+                Chunk::Text(offset, t) => {
+                    assert_eq!(offset, text.len());
+                    text.push_str(&t);
+                }
+                chunk => invalid.push(chunk),
+            }
+        }
+        (text, invalid)
+    }
+
+    #[test]
+    fn characters_split_between_chunks_are_decoded() {
+        let bytes = TEXT.as_bytes();
+        for i in 0..=bytes.len() {
+            let (text, invalid) = decode_all(&[&bytes[..i], &bytes[i..]]);
+            assert_eq!((text.as_str(), invalid), (TEXT, vec![]), "split at {}", i);
+        }
+    }
+
+    #[test]
+    fn characters_split_between_three_chunks_are_decoded() {
+        let bytes = "😀".as_bytes();
+        for i in 0..bytes.len() {
+            for j in i..bytes.len() {
+                let (text, invalid) = decode_all(&[&bytes[..i], &bytes[i..j], &bytes[j..]]);
+                assert_eq!((text.as_str(), invalid), ("😀", vec![]));
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_sequences_are_reported_in_place() {
+        let mut decoder = Utf8Decoder::new();
+
+        assert_eq!(
+            decoder.decode(b"ab\xffc"),
+            vec![
+                Chunk::Text(0, "ab".to_string()),
+                Chunk::Invalid(2, vec![0xff]),
+                Chunk::Text(3, "c".to_string()),
+            ]
+        );
+        // An incomplete character is only invalid if the input ends
+        assert_eq!(decoder.decode(b"\xe6\xb5"), vec![]);
+        assert_eq!(decoder.finish(), Some(Chunk::Invalid(4, vec![0xe6, 0xb5])));
+    }
+}
@@ -0,0 +1,304 @@
+//! Recognising the escape sequences a terminal sends for special keys, such as `ESC [ A` for the
+//! up arrow, in input that arrives in chunks where a sequence can be split between two chunks
+
+use keyboard_layouts::keys::{self, Chord};
+
+const ESC: u8 = 0x1b;
+// Anything longer than this isn't a key, and is dropped rather than waiting for its end
+const MAX_SEQUENCE_LEN: usize = 32;
+
+/// A piece of terminal input
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    /// Bytes that aren't part of an escape sequence
+    Bytes(Vec<u8>),
+    /// A special key, with any modifiers held
+    Key(Chord),
+    /// An escape sequence that isn't a known key
+    Unknown(Vec<u8>),
+}
+
+/// Splits terminal input into text and special keys, carrying an incomplete escape sequence at
+/// the end of a chunk over to the next one
+#[derive(Debug, Default)]
+pub struct KeyDecoder {
+    pending: Vec<u8>,
+}
+
+impl KeyDecoder {
+    pub fn new() -> Self {
+        KeyDecoder::default()
+    }
+
+    /// Whether the input so far ends part way through an escape sequence
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Decode as much as possible of the input so far, in the order it appears.
+    pub fn decode(&mut self, bytes: &[u8]) -> Vec<Input> {
+        self.pending.extend_from_slice(bytes);
+
+        let mut inputs = Vec::new();
+        let mut start = 0;
+        while let Some(esc) = self.pending[start..].iter().position(|b| *b == ESC) {
+            let esc = start + esc;
+            if esc > start {
+                inputs.push(Input::Bytes(self.pending[start..esc].to_vec()));
+            }
+            match parse_sequence(&self.pending[esc..]) {
+                Some((len, input)) => {
+                    inputs.push(input);
+                    start = esc + len;
+                }
+                None => {
+                    start = esc;
+                    break;
+                }
+            }
+        }
+        if start < self.pending.len() && self.pending[start] != ESC {
+            inputs.push(Input::Bytes(self.pending[start..].to_vec()));
+            start = self.pending.len();
+        }
+        self.pending.drain(..start);
+        inputs
+    }
+
+    /// Treat the end of the input so far as complete, e.g. when no more input arrives shortly
+    /// after an escape key press. A lone ESC is the escape key, anything longer is unknown.
+    pub fn flush(&mut self) -> Option<Input> {
+        match self.pending.len() {
+            0 => None,
+            1 => {
+                self.pending.clear();
+                Some(Input::Key(named_key("ESC", 0)))
+            }
+            _ => Some(Input::Unknown(self.pending.split_off(0))),
+        }
+    }
+}
+
+/// Parse the escape sequence at the start of the input, returning its length and what it is, or
+/// None if the input ends before the sequence does
+fn parse_sequence(input: &[u8]) -> Option<(usize, Input)> {
+    let unknown = |len: usize| Some((len, Input::Unknown(input[..len].to_vec())));
+    match input.get(1) {
+        None => None,
+        Some(b'[') => {
+            // Parameters and intermediate bytes, then a final byte
+            let end = input[2..]
+                .iter()
+                .take(MAX_SEQUENCE_LEN)
+                .position(|b| !(0x20..=0x3f).contains(b));
+            let end = match end {
+                Some(end) => end + 2,
+                None if input.len() >= MAX_SEQUENCE_LEN => return unknown(MAX_SEQUENCE_LEN),
+                None => return None,
+            };
+            let len = end + 1;
+            if !(0x40..=0x7e).contains(&input[end]) {
+                return unknown(end);
+            }
+            match csi_key(&input[2..end], input[end]) {
+                Some(chord) => Some((len, Input::Key(chord))),
+                None => unknown(len),
+            }
+        }
+        Some(b'O') => {
+            let name = match input.get(2)? {
+                b'P' => "F1",
+                b'Q' => "F2",
+                b'R' => "F3",
+                b'S' => "F4",
+                b'A' => "UP",
+                b'B' => "DOWN",
+                b'C' => "RIGHT",
+                b'D' => "LEFT",
+                b'H' => "HOME",
+                b'F' => "END",
+                _ => return unknown(3),
+            };
+            Some((3, Input::Key(named_key(name, 0))))
+        }
+        // Not a sequence, the escape key was pressed on its own
+        Some(_) => Some((1, Input::Key(named_key("ESC", 0)))),
+    }
+}
+
+/// The key for a control sequence such as `1;5A`, ctrl+up
+fn csi_key(params: &[u8], final_byte: u8) -> Option<Chord> {
+    let params = std::str::from_utf8(params).ok()?;
+    let mut params = params.split(';');
+    let first = params.next().filter(|p| !p.is_empty());
+    let modifier = match params.next() {
+        Some(m) => xterm_modifier(m.parse().ok()?),
+        None => 0,
+    };
+    if params.next().is_some() {
+        return None;
+    }
+
+    let name = match (final_byte, first) {
+        (b'A', _) => "UP",
+        (b'B', _) => "DOWN",
+        (b'C', _) => "RIGHT",
+        (b'D', _) => "LEFT",
+        (b'H', _) => "HOME",
+        (b'F', _) => "END",
+        (b'P', _) => "F1",
+        (b'Q', _) => "F2",
+        (b'R', _) => "F3",
+        (b'S', _) => "F4",
+        (b'~', Some(number)) => match number.parse::<u8>().ok()? {
+            1 | 7 => "HOME",
+            2 => "INSERT",
+            3 => "DELETE",
+            4 | 8 => "END",
+            5 => "PAGEUP",
+            6 => "PAGEDOWN",
+            11 => "F1",
+            12 => "F2",
+            13 => "F3",
+            14 => "F4",
+            15 => "F5",
+            17 => "F6",
+            18 => "F7",
+            19 => "F8",
+            20 => "F9",
+            21 => "F10",
+            23 => "F11",
+            24 => "F12",
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(named_key(name, modifier))
+}
+
+/// The modifier bits for xterm's modifier parameter, which is 1 plus shift 1, alt 2, ctrl 4 and
+/// meta 8
+fn xterm_modifier(param: u8) -> u8 {
+    let bits = param.saturating_sub(1);
+    [
+        (1, keys::MODIFIER_LEFT_SHIFT),
+        (2, keys::MODIFIER_LEFT_ALT),
+        (4, keys::MODIFIER_LEFT_CTRL),
+        (8, keys::MODIFIER_LEFT_GUI),
+    ]
+    .iter()
+    .filter(|(bit, _)| bits & bit != 0)
+    .fold(0, |modifier, (_, m)| modifier | m)
+}
+
+fn named_key(name: &str, modifier: u8) -> Chord {
+    Chord {
+        modifier,
+        key: keys::key_usage(name).expect("all the keys recognised have names"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> Input {
+        Input::Key(named_key(name, 0))
+    }
+
+    #[test]
+    fn keys_are_recognised() {
+        for (sequence, name) in &[
+            ("\x1b[A", "UP"),
+            ("\x1b[B", "DOWN"),
+            ("\x1b[C", "RIGHT"),
+            ("\x1b[D", "LEFT"),
+            ("\x1b[H", "HOME"),
+            ("\x1b[F", "END"),
+            ("\x1bOH", "HOME"),
+            ("\x1b[1~", "HOME"),
+            ("\x1b[4~", "END"),
+            ("\x1b[2~", "INSERT"),
+            ("\x1b[3~", "DELETE"),
+            ("\x1b[5~", "PAGEUP"),
+            ("\x1b[6~", "PAGEDOWN"),
+            ("\x1bOP", "F1"),
+            ("\x1bOS", "F4"),
+            ("\x1b[15~", "F5"),
+            ("\x1b[24~", "F12"),
+        ] {
+            let mut decoder = KeyDecoder::new();
+            assert_eq!(
+                decoder.decode(sequence.as_bytes()),
+                vec![key(name)],
+                "{:?}",
+                sequence
+            );
+            assert!(!decoder.is_pending());
+        }
+    }
+
+    #[test]
+    fn modifiers_are_recognised() {
+        let mut decoder = KeyDecoder::new();
+        assert_eq!(
+            decoder.decode(b"\x1b[1;5A\x1b[3;2~"),
+            vec![
+                Input::Key(named_key("UP", keys::MODIFIER_LEFT_CTRL)),
+                Input::Key(named_key("DELETE", keys::MODIFIER_LEFT_SHIFT)),
+            ]
+        );
+    }
+
+    #[test]
+    fn sequences_split_between_chunks_are_reassembled() {
+        let input = b"a\x1b[15~b";
+        for i in 0..=input.len() {
+            let mut decoder = KeyDecoder::new();
+            let mut inputs = decoder.decode(&input[..i]);
+            inputs.extend(decoder.decode(&input[i..]));
+            let bytes: Vec<u8> = inputs
+                .iter()
+                .filter_map(|input| match input {
+                    Input::Bytes(bytes) => Some(bytes.clone()),
+                    _ => None,
+                })
+                .flatten()
+                .collect();
+            assert_eq!(bytes, b"ab", "split at {}", i);
+            assert!(inputs.contains(&key("F5")), "split at {}", i);
+        }
+    }
+
+    #[test]
+    fn lone_escape_is_the_escape_key() {
+        let mut decoder = KeyDecoder::new();
+
+        assert_eq!(decoder.decode(b"\x1b"), vec![]);
+        assert!(decoder.is_pending());
+        assert_eq!(decoder.flush(), Some(key("ESC")));
+        assert_eq!(decoder.flush(), None);
+
+        assert_eq!(
+            decoder.decode(b"\x1bx"),
+            vec![key("ESC"), Input::Bytes(b"x".to_vec())]
+        );
+    }
+
+    #[test]
+    fn unknown_sequences_are_reported() {
+        let mut decoder = KeyDecoder::new();
+
+        assert_eq!(
+            decoder.decode(b"\x1b[200~hi\x1b[99~\x1bOz"),
+            vec![
+                Input::Unknown(b"\x1b[200~".to_vec()),
+                Input::Bytes(b"hi".to_vec()),
+                Input::Unknown(b"\x1b[99~".to_vec()),
+                Input::Unknown(b"\x1bOz".to_vec()),
+            ]
+        );
+        assert_eq!(decoder.decode(b"\x1b["), vec![]);
+        assert_eq!(decoder.flush(), Some(Input::Unknown(b"\x1b[".to_vec())));
+    }
+}
@@ -0,0 +1,194 @@
+//! Typing runs of uppercase letters with Caps Lock instead of holding Shift for each of them, for
+//! targets such as firmware setup screens that drop modifiers when they are sent quickly
+
+use crate::keys::{MODIFIER_LEFT_SHIFT, MODIFIER_RIGHT_SHIFT};
+use crate::{string_to_keys_and_modifiers, Error, KeyMod, Keystroke, Release};
+
+use bytes::Bytes;
+
+const SHIFT: u8 = MODIFIER_LEFT_SHIFT | MODIFIER_RIGHT_SHIFT;
+
+/// Replace each run of at least `threshold` uppercase letters with Caps Lock being pressed, the
+/// letters' keys without Shift, and Caps Lock being pressed again. Letters that the layout doesn't
+/// type as their lowercase letter with Shift added are left as they are, and end a run.
+pub fn caps_lock_runs(
+    layout_key: &str,
+    keystrokes: Vec<Keystroke>,
+    threshold: usize,
+) -> Result<Vec<Keystroke>, Error> {
+    let mut result = Vec::with_capacity(keystrokes.len());
+    let mut run: Vec<(Keystroke, Keystroke)> = Vec::new();
+    for keystroke in keystrokes {
+        match unshifted(layout_key, &keystroke)? {
+            Some(plain) => run.push((keystroke, plain)),
+            None => {
+                end_run(&mut result, &mut run, threshold);
+                result.push(keystroke);
+            }
+        }
+    }
+    end_run(&mut result, &mut run, threshold);
+    Ok(result)
+}
+
+fn end_run(result: &mut Vec<Keystroke>, run: &mut Vec<(Keystroke, Keystroke)>, threshold: usize) {
+    if run.len() < threshold.max(1) {
+        result.extend(run.drain(..).map(|(shifted, _)| shifted));
+        return;
+    }
+    result.push(caps_lock());
+    result.extend(run.drain(..).map(|(_, plain)| plain));
+    result.push(caps_lock());
+}
+
+/// The keystroke typing an uppercase letter while Caps Lock is on, which is its lowercase
+/// letter's keystroke, if the letter is typed by adding Shift to it
+fn unshifted(layout_key: &str, keystroke: &Keystroke) -> Result<Option<Keystroke>, Error> {
+    let upper = match keystroke.character {
+        Some(c) if c.is_uppercase() => c,
+        _ => return Ok(None),
+    };
+    let mut lower = upper.to_lowercase();
+    let lower = match (lower.next(), lower.next()) {
+        (Some(lower), None) if lower != upper => lower,
+        _ => return Ok(None),
+    };
+
+    let mut buf = [0; 4];
+    // Letters the layout can't type, such as those typed by their code point, are left as they are
+    let keys = |c: char, buf: &mut [u8; 4]| match string_to_keys_and_modifiers(
+        layout_key,
+        c.encode_utf8(buf),
+    ) {
+        Ok(keys) => Ok(Some(keys)),
+        Err(Error::InvalidCharacter(_)) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let (upper_keys, lower_keys) = match (keys(upper, &mut buf)?, keys(lower, &mut buf)?) {
+        (Some(upper_keys), Some(lower_keys)) => (upper_keys, lower_keys),
+        _ => return Ok(None),
+    };
+    match (upper_keys.as_slice(), lower_keys.as_slice()) {
+        (
+            [KeyMod {
+                key: upper_key,
+                modifier: upper_modifier,
+                release: Release::All,
+            }],
+            [KeyMod {
+                key: lower_key,
+                modifier: lower_modifier,
+                release: Release::All,
+            }],
+        ) if upper_key == lower_key
+            && upper_modifier & SHIFT != 0
+            && lower_modifier & SHIFT == 0
+            && upper_modifier & !SHIFT == *lower_modifier => {}
+        _ => return Ok(None),
+    }
+
+    let packets = crate::string_to_hid_packets(layout_key, lower.encode_utf8(&mut buf))?;
+    Ok(Some(Keystroke {
+        character: Some(upper),
+        packets,
+    }))
+}
+
+fn caps_lock() -> Keystroke {
+    let mut packets = vec![0; crate::HID_PACKET_LEN * 2];
+    packets[2] = 0x39;
+    Keystroke {
+        character: None,
+        packets: Bytes::from(packets),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{key_characters, string_to_keystrokes, HID_PACKET_LEN};
+
+    const LAYOUT: &str = "LAYOUT_US_ENGLISH";
+
+    /// The text a host with the layout types from the packets, with Caps Lock off to start with
+    fn typed(keystrokes: &[Keystroke]) -> String {
+        let characters = key_characters(LAYOUT).unwrap();
+
+        let mut caps_lock = false;
+        let mut text = String::new();
+        let packets: Vec<u8> = keystrokes.iter().flat_map(|k| k.packets.to_vec()).collect();
+        for packet in packets.chunks(HID_PACKET_LEN) {
+            match (packet[0], packet[2]) {
+                (_, 0) => {}
+                (_, 0x39) => caps_lock = !caps_lock,
+                (modifier, key) => {
+                    let c = characters[&(modifier, key)];
+                    match (caps_lock, c.is_uppercase()) {
+                        (true, true) => text.extend(c.to_lowercase()),
+                        (true, false) => text.extend(c.to_uppercase()),
+                        (false, _) => text.push(c),
+                    }
+                }
+            }
+        }
+        text
+    }
+
+    fn shift_count(keystrokes: &[Keystroke]) -> usize {
+        keystrokes
+            .iter()
+            .flat_map(|k| {
+                k.packets
+                    .chunks(HID_PACKET_LEN)
+                    .map(|p| p[0])
+                    .collect::<Vec<_>>()
+            })
+            .filter(|modifier| modifier & SHIFT != 0)
+            .count()
+    }
+
+    #[test]
+    fn mixed_case_text_is_typed_the_same() {
+        for text in &[
+            "Hello WORLD, this is KBSIM typing SHOUTED text!",
+            "ABC DEFG Hi",
+            "ALL CAPS TO THE END",
+            "lowercase only",
+        ] {
+            let shifted = string_to_keystrokes(LAYOUT, text).unwrap();
+            let caps = caps_lock_runs(LAYOUT, shifted.clone(), 4).unwrap();
+
+            assert_eq!(typed(&shifted), *text);
+            assert_eq!(typed(&caps), *text);
+        }
+
+        // The layout can't type Ł, so it can't be typed with Caps Lock either
+        let keystrokes = vec![Keystroke {
+            character: Some('Ł'),
+            packets: Bytes::new(),
+        }];
+        assert_eq!(
+            caps_lock_runs(LAYOUT, keystrokes.clone(), 1).unwrap(),
+            keystrokes
+        );
+    }
+
+    #[test]
+    fn only_long_runs_use_caps_lock() {
+        let keystrokes = string_to_keystrokes(LAYOUT, "ABC DEFG").unwrap();
+
+        let caps = caps_lock_runs(LAYOUT, keystrokes.clone(), 4).unwrap();
+
+        // ABC is held with Shift, DEFG between two Caps Lock presses
+        assert_eq!(caps.len(), keystrokes.len() + 2);
+        assert_eq!(shift_count(&caps), 3);
+        assert_eq!(caps[4], caps_lock());
+        assert_eq!(caps[5].character, Some('D'));
+        assert_eq!(caps[9], caps_lock());
+        assert_eq!(
+            caps_lock_runs(LAYOUT, keystrokes.clone(), 9).unwrap(),
+            keystrokes
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Keyboard layouts loaded at runtime from a TOML or JSON file, e.g.
+//!
+//! ```toml
+//! name = "BEPO"
+//! inherits = "LAYOUT_FRENCH"
+//!
+//! [[keys]]
+//! char = "b"
+//! key = 0x14
+//!
+//! [[keys]]
+//! char = "B"
+//! key = 0x14
+//! modifiers = ["shift"]
+//! ```
+//!
+//! Each entry maps a character to the HID usage ID of the key that types it and the modifiers
+//! held while pressing it. Characters without an entry are typed using the inherited built-in
+//! layout, if there is one. Once registered, a layout can be used by name anywhere a built-in
+//! layout can. [`template`] writes a layout out in this format to start a file from.
+
+use crate::keys::{self, modifier_bit};
+use crate::{Error, KeyMod, Release, LAYOUT_MAP};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+// The keyboard page usage IDs of real keys, from 'a' up to right GUI
+const FIRST_KEY_USAGE: u8 = 0x04;
+const LAST_KEY_USAGE: u8 = 0xE7;
+
+// The names the modifier bits are written with in a template, lowest bit first
+const MODIFIER_NAMES: [(u8, &str); 8] = [
+    (keys::MODIFIER_LEFT_CTRL, "ctrl"),
+    (keys::MODIFIER_LEFT_SHIFT, "shift"),
+    (keys::MODIFIER_LEFT_ALT, "alt"),
+    (keys::MODIFIER_LEFT_GUI, "gui"),
+    (keys::MODIFIER_RIGHT_CTRL, "rctrl"),
+    (keys::MODIFIER_RIGHT_SHIFT, "rshift"),
+    (keys::MODIFIER_RIGHT_ALT, "altgr"),
+    (keys::MODIFIER_RIGHT_GUI, "rgui"),
+];
+
+static REGISTRY: RwLock<Vec<(&'static str, Arc<CustomLayout>)>> = RwLock::new(Vec::new());
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct LayoutFile {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inherits: Option<String>,
+    #[serde(default)]
+    keys: Vec<KeyEntry>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct KeyEntry {
+    char: char,
+    key: u8,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    modifiers: Vec<String>,
+}
+
+/// The format to write a [`template`] in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateFormat {
+    Toml,
+    Json,
+}
+
+/// A keyboard layout described by a file rather than built in
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomLayout {
+    name: String,
+    inherits: Option<&'static str>,
+    keys: HashMap<char, (u8, u8)>,
+}
+
+impl CustomLayout {
+    /// Parse a layout from TOML.
+    pub fn from_toml(text: &str) -> Result<Self, Error> {
+        let file = toml::from_str(text).map_err(|e| Error::InvalidLayoutFile(e.to_string()))?;
+        CustomLayout::from_file(file)
+    }
+
+    /// Parse a layout from JSON.
+    pub fn from_json(text: &str) -> Result<Self, Error> {
+        let file =
+            serde_json::from_str(text).map_err(|e| Error::InvalidLayoutFile(e.to_string()))?;
+        CustomLayout::from_file(file)
+    }
+
+    /// Read a layout from a file, which is parsed as JSON if it has a `.json` extension and TOML
+    /// otherwise.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::InvalidLayoutFile(format!("{}: {}", path.display(), e)))?;
+        let layout = match path.extension() {
+            Some(extension) if extension == "json" => CustomLayout::from_json(&text),
+            _ => CustomLayout::from_toml(&text),
+        };
+        layout.map_err(|e| match e {
+            Error::InvalidLayoutFile(message) => {
+                Error::InvalidLayoutFile(format!("{}: {}", path.display(), message))
+            }
+            e => e,
+        })
+    }
+
+    fn from_file(file: LayoutFile) -> Result<Self, Error> {
+        let invalid = |message: String| Error::InvalidLayoutFile(message);
+
+        let inherits = match file.inherits {
+            Some(base) => Some(
+                LAYOUT_MAP
+                    .get_key_value(base.as_str())
+                    .map(|(key, _)| *key)
+                    .ok_or_else(|| invalid(format!("No built-in layout named {}", base)))?,
+            ),
+            None => None,
+        };
+
+        let mut keys = HashMap::new();
+        for entry in file.keys {
+            if !(FIRST_KEY_USAGE..=LAST_KEY_USAGE).contains(&entry.key) {
+                return Err(invalid(format!(
+                    "Key 0x{:02x} for {:?} is not a keyboard key",
+                    entry.key, entry.char
+                )));
+            }
+            let mut modifier = 0;
+            for name in &entry.modifiers {
+                modifier |= modifier_bit(name).ok_or_else(|| {
+                    invalid(format!("Unknown modifier {} for {:?}", name, entry.char))
+                })?;
+            }
+            if keys.insert(entry.char, (modifier, entry.key)).is_some() {
+                return Err(invalid(format!("Duplicate entry for {:?}", entry.char)));
+            }
+        }
+
+        Ok(CustomLayout {
+            name: file.name,
+            inherits,
+            keys,
+        })
+    }
+
+    /// The name the layout is registered under
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The characters the layout has its own entries for
+    pub(crate) fn characters(&self) -> impl Iterator<Item = char> + '_ {
+        self.keys.keys().copied()
+    }
+
+    /// Get the key and modifier pairs required to type the given string.
+    pub(crate) fn keys_and_modifiers(&self, string: &str) -> Result<Vec<KeyMod>, Error> {
+        let mut keys_and_modifiers = Vec::with_capacity(string.len());
+        let mut buf = [0u8; 4];
+        for c in string.chars() {
+            match (self.keys.get(&c), self.inherits) {
+                (Some((modifier, key)), _) => keys_and_modifiers.push(KeyMod {
+                    key: *key,
+                    modifier: *modifier,
+                    release: Release::All,
+                }),
+                (None, Some(base)) => keys_and_modifiers.extend(
+                    crate::string_to_keys_and_modifiers(base, c.encode_utf8(&mut buf))?,
+                ),
+                (None, None) => return Err(Error::InvalidCharacter(c)),
+            }
+        }
+        Ok(keys_and_modifiers)
+    }
+}
+
+/// Write the characters a layout types with a single key press as a layout file named `name`, to
+/// start a custom layout from. Characters typed with a dead key take two presses, which an entry
+/// can't describe, so they are left out.
+pub fn template(layout_key: &str, name: &str, format: TemplateFormat) -> Result<String, Error> {
+    let keys = crate::keymap(layout_key)?
+        .into_iter()
+        .filter_map(|(c, presses)| match &presses[..] {
+            [press] => Some(KeyEntry {
+                char: c,
+                key: press.key,
+                modifiers: MODIFIER_NAMES
+                    .iter()
+                    .filter(|(bit, _)| press.modifier & bit != 0)
+                    .map(|(_, name)| name.to_string())
+                    .collect(),
+            }),
+            _ => None,
+        })
+        .collect();
+    let file = LayoutFile {
+        name: name.to_string(),
+        inherits: None,
+        keys,
+    };
+
+    let text = match format {
+        TemplateFormat::Toml => toml::to_string(&file).map_err(|e| e.to_string()),
+        TemplateFormat::Json => serde_json::to_string_pretty(&file).map_err(|e| e.to_string()),
+    };
+    text.map_err(Error::InvalidLayoutFile)
+}
+
+/// Make the layout available by its name, returning the name. Layouts can't be unregistered, and
+/// the name mustn't already be used by another layout.
+pub fn register(layout: CustomLayout) -> Result<&'static str, Error> {
+    let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+    if LAYOUT_MAP.contains_key(layout.name.as_str())
+        || registry.iter().any(|(name, _)| *name == layout.name)
+    {
+        return Err(Error::InvalidLayoutFile(format!(
+            "A layout named {} already exists",
+            layout.name
+        )));
+    }
+
+    let name: &'static str = Box::leak(layout.name.clone().into_boxed_str());
+    registry.push((name, Arc::new(layout)));
+    Ok(name)
+}
+
+/// Get the names of the registered layouts
+pub fn registered() -> Vec<&'static str> {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    registry.iter().map(|(name, _)| *name).collect()
+}
+
+/// Get the registered layout with the given name
+pub(crate) fn get(name: &str) -> Option<Arc<CustomLayout>> {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    registry
+        .iter()
+        .find(|(key, _)| *key == name)
+        .map(|(_, layout)| layout.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::string_to_hid_packets;
+
+    const BEPO: &str = r#"
+name = "TEST_BEPO"
+inherits = "LAYOUT_FRENCH"
+
+[[keys]]
+char = "b"
+key = 0x14
+
+[[keys]]
+char = "é"
+key = 0x1a
+modifiers = ["shift", "altgr"]
+"#;
+
+    #[test]
+    fn overrides_the_inherited_layout() {
+        let name = register(CustomLayout::from_toml(BEPO).unwrap()).unwrap();
+
+        assert_eq!(
+            string_to_hid_packets(name, "bé").unwrap().to_vec(),
+            vec![
+                0, 0, 0x14, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // b
+                0x42, 0, 0x1a, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // é
+            ]
+        );
+        // Inherited from the French layout, where 'a' is where 'q' is on a QWERTY keyboard
+        assert_eq!(
+            string_to_hid_packets(name, "a").unwrap(),
+            string_to_hid_packets("LAYOUT_FRENCH", "a").unwrap()
+        );
+        assert!(registered().contains(&"TEST_BEPO"));
+        assert!(register(CustomLayout::from_toml(BEPO).unwrap()).is_err());
+    }
+
+    #[test]
+    fn parses_json() {
+        let layout =
+            CustomLayout::from_json(r#"{"name": "TEST_JSON", "keys": [{"char": "x", "key": 4}]}"#)
+                .unwrap();
+
+        assert_eq!(layout.keys_and_modifiers("x").unwrap()[0].key, 4);
+        match layout.keys_and_modifiers("y") {
+            Err(Error::InvalidCharacter('y')) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        let duplicate =
+            r#"{"name": "T", "keys": [{"char": "x", "key": 4}, {"char": "x", "key": 5}]}"#;
+        let out_of_range = r#"{"name": "T", "keys": [{"char": "x", "key": 240}]}"#;
+        let too_large = r#"{"name": "T", "keys": [{"char": "x", "key": 300}]}"#;
+        let unknown_base = r#"{"name": "T", "inherits": "LAYOUT_KLINGON"}"#;
+
+        for (file, message) in &[
+            (duplicate, "Duplicate entry for 'x'"),
+            (out_of_range, "Key 0xf0 for 'x' is not a keyboard key"),
+            (too_large, "invalid value: integer `300`"),
+            (unknown_base, "No built-in layout named LAYOUT_KLINGON"),
+        ] {
+            match CustomLayout::from_json(file) {
+                Err(Error::InvalidLayoutFile(e)) => assert!(e.contains(message), "{}", e),
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn templates_load_as_the_same_layout() {
+        let text = "Hello, World! 1+1=2 éà";
+        for (format, name) in &[
+            (TemplateFormat::Toml, "TEST_TEMPLATE_TOML"),
+            (TemplateFormat::Json, "TEST_TEMPLATE_JSON"),
+        ] {
+            let file = template("LAYOUT_FRENCH", name, *format).unwrap();
+            let layout = match format {
+                TemplateFormat::Toml => CustomLayout::from_toml(&file),
+                TemplateFormat::Json => CustomLayout::from_json(&file),
+            };
+            let name = register(layout.unwrap()).unwrap();
+
+            assert_eq!(
+                string_to_hid_packets(name, text).unwrap(),
+                string_to_hid_packets("LAYOUT_FRENCH", text).unwrap()
+            );
+            // '~' is typed with a dead key, so it needs an inherited layout
+            assert!(string_to_hid_packets(name, "~").is_err());
+        }
+        assert!(template("LAYOUT_KLINGON", "T", TemplateFormat::Toml).is_err());
+    }
+}
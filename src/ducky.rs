@@ -0,0 +1,278 @@
+//! An interpreter for the common subset of DuckyScript
+//!
+//! Supported commands are `REM`, `STRING`, `STRINGLN`, `DELAY`, `DEFAULT_DELAY`, `REPEAT` and
+//! key presses such as `ENTER`, `GUI r` or `CTRL ALT DELETE`.
+
+use crate::keys::{key_usage, modifier_bit, Chord};
+use crate::writer::HidWriter;
+use crate::{string_to_hid_packets, HidKeyboard, KeyboardError, HID_PACKET_LEN};
+
+use bytes::Bytes;
+
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::time::Duration;
+
+/// A single step of a parsed script
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// HID packets to send to the device
+    Packets(Bytes),
+    /// Wait before sending the next packets
+    Delay(Duration),
+    /// Run the actions of the previous line again this many times
+    Repeat(u64, Vec<Action>),
+}
+
+#[derive(Debug)]
+pub enum ScriptErrorKind {
+    UnknownCommand(String),
+    InvalidArgument(String, String),
+    Layout(crate::Error),
+}
+
+/// An error in a script, with the (1 based) line number it occurred on
+#[derive(Debug)]
+pub struct ScriptError {
+    pub line: usize,
+    pub kind: ScriptErrorKind,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ScriptErrorKind::UnknownCommand(command) => write!(f, "Unknown command {}", command),
+            ScriptErrorKind::InvalidArgument(command, arg) => {
+                write!(f, "Invalid argument for {}: '{}'", command, arg)
+            }
+            ScriptErrorKind::Layout(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for ScriptError {}
+
+/// Parse a script into the actions required to run it on a keyboard with the specified layout.
+pub fn parse(layout_key: &str, script: &str) -> Result<Vec<Action>, ScriptError> {
+    let mut actions = Vec::new();
+    let mut default_delay = None;
+    let mut previous: Vec<Action> = Vec::new();
+
+    for (idx, line) in script.lines().enumerate() {
+        let error = |kind| ScriptError {
+            line: idx + 1,
+            kind,
+        };
+        let line = line.trim_start();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (command, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let number = |arg: &str| {
+            arg.trim().parse::<u64>().map_err(|_| {
+                error(ScriptErrorKind::InvalidArgument(
+                    command.to_string(),
+                    arg.to_string(),
+                ))
+            })
+        };
+
+        let first_key = command.split('-').next().unwrap_or(command);
+
+        let current = match command.to_uppercase().as_str() {
+            "REM" => continue,
+            "DEFAULT_DELAY" | "DEFAULTDELAY" => {
+                default_delay = Some(Duration::from_millis(number(arg)?));
+                continue;
+            }
+            "REPEAT" => {
+                actions.push(Action::Repeat(number(arg)?, previous.clone()));
+                continue;
+            }
+            "DELAY" => vec![Action::Delay(Duration::from_millis(number(arg)?))],
+            "STRING" => vec![Action::Packets(
+                string_to_hid_packets(layout_key, arg)
+                    .map_err(|e| error(ScriptErrorKind::Layout(e)))?,
+            )],
+            "STRINGLN" => vec![Action::Packets(
+                string_to_hid_packets(layout_key, &format!("{}\n", arg))
+                    .map_err(|e| error(ScriptErrorKind::Layout(e)))?,
+            )],
+            _ if modifier_bit(first_key).is_some() || key_usage(first_key).is_some() => {
+                // e.g. 'CTRL ALT DELETE', 'CTRL-SHIFT ESC' or 'GUI r'
+                let chord = line
+                    .split_whitespace()
+                    .flat_map(|token| token.split('-'))
+                    .collect::<Vec<&str>>()
+                    .join("+");
+                let chord = Chord::parse(layout_key, &chord).map_err(|_| {
+                    error(ScriptErrorKind::InvalidArgument(
+                        command.to_string(),
+                        arg.to_string(),
+                    ))
+                })?;
+                vec![Action::Packets(chord.to_hid_packets())]
+            }
+            _ => return Err(error(ScriptErrorKind::UnknownCommand(command.to_string()))),
+        };
+
+        previous = current;
+        if let Some(delay) = default_delay {
+            previous.push(Action::Delay(delay));
+        }
+        actions.extend(previous.iter().cloned());
+    }
+
+    Ok(actions)
+}
+
+/// The number of packets running the actions sends, counting each repeat
+pub fn packet_count(actions: &[Action]) -> usize {
+    actions.iter().fold(0, |count: usize, action| {
+        count.saturating_add(match action {
+            Action::Packets(packets) => packets.len() / HID_PACKET_LEN,
+            Action::Delay(_) => 0,
+            Action::Repeat(times, repeated) => {
+                let times = usize::try_from(*times).unwrap_or(usize::MAX);
+                packet_count(repeated).saturating_mul(times)
+            }
+        })
+    })
+}
+
+/// Run the parsed actions on the keyboard, returning the number of packets sent. The keyboard's
+/// cooldown after the preceding packet counts towards each delay.
+pub fn run<W: HidWriter>(
+    keyboard: &mut HidKeyboard<W>,
+    actions: &[Action],
+) -> Result<usize, KeyboardError> {
+    let mut sent = 0;
+    for action in actions {
+        match action {
+            Action::Packets(packets) => sent += keyboard.send_packets(packets)?,
+            Action::Delay(delay) => keyboard.wait(delay.saturating_sub(keyboard.cooldown()))?,
+            Action::Repeat(times, repeated) => {
+                for _ in 0..*times {
+                    sent += run(keyboard, repeated)?;
+                }
+            }
+        }
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELEASE: [u8; 8] = [0; 8];
+
+    fn packets(actions: &[Action]) -> Vec<u8> {
+        actions
+            .iter()
+            .flat_map(|action| match action {
+                Action::Packets(packets) => packets.to_vec(),
+                Action::Delay(_) => Vec::new(),
+                Action::Repeat(times, repeated) => packets(repeated).repeat(*times as usize),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strings_and_key_combos() {
+        let script =
+            "REM open the run dialog\nGUI r\nSTRING Hi\nENTER\nCTRL-ALT DELETE\nREPEAT 1\n";
+        let actions = parse("LAYOUT_US_ENGLISH", script).unwrap();
+
+        let expected = [
+            [0x08, 0, 0x15, 0, 0, 0, 0, 0], // GUI r
+            RELEASE,
+            [0x02, 0, 0x0b, 0, 0, 0, 0, 0], // H
+            RELEASE,
+            [0, 0, 0x0c, 0, 0, 0, 0, 0], // i
+            RELEASE,
+            [0, 0, 0x28, 0, 0, 0, 0, 0], // ENTER
+            RELEASE,
+            [0x05, 0, 0x4c, 0, 0, 0, 0, 0], // CTRL ALT DELETE
+            RELEASE,
+            [0x05, 0, 0x4c, 0, 0, 0, 0, 0], // REPEAT 1
+            RELEASE,
+        ]
+        .concat();
+        assert_eq!(packets(&actions), expected);
+    }
+
+    #[test]
+    fn stringln_appends_enter() {
+        let actions = parse("LAYOUT_US_ENGLISH", "STRINGLN a").unwrap();
+
+        let expected = [
+            [0, 0, 0x04, 0, 0, 0, 0, 0],
+            RELEASE,
+            [0, 0, 0x28, 0, 0, 0, 0, 0],
+            RELEASE,
+        ]
+        .concat();
+        assert_eq!(packets(&actions), expected);
+    }
+
+    #[test]
+    fn commands_can_be_followed_by_any_whitespace() {
+        for script in &["STRING\u{3000}a", "STRING\u{a0}a", "STRING\ta"] {
+            let actions = parse("LAYOUT_US_ENGLISH", script).unwrap();
+
+            assert_eq!(
+                packets(&actions),
+                [[0, 0, 0x04, 0, 0, 0, 0, 0], RELEASE].concat()
+            );
+        }
+    }
+
+    #[test]
+    fn delays() {
+        let actions = parse("LAYOUT_US_ENGLISH", "DEFAULT_DELAY 10\nDELAY 500\nTAB").unwrap();
+
+        assert_eq!(
+            actions,
+            vec![
+                Action::Delay(Duration::from_millis(500)),
+                Action::Delay(Duration::from_millis(10)),
+                Action::Packets(Bytes::from([[0, 0, 0x2b, 0, 0, 0, 0, 0], RELEASE].concat())),
+                Action::Delay(Duration::from_millis(10)),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeats_are_not_expanded() {
+        let actions = parse("LAYOUT_US_ENGLISH", "STRING a\nREPEAT 1000000000").unwrap();
+
+        assert_eq!(actions.len(), 2);
+        assert_eq!(packet_count(&actions), 2_000_000_002);
+        let actions = parse("LAYOUT_US_ENGLISH", "STRING a\nREPEAT 2").unwrap();
+        assert_eq!(
+            packets(&actions),
+            [[0, 0, 0x04, 0, 0, 0, 0, 0], RELEASE].repeat(3).concat()
+        );
+    }
+
+    #[test]
+    fn unknown_command_names_the_line() {
+        let err = parse("LAYOUT_US_ENGLISH", "REM ok\nSTRING ok\nTYPE fail").unwrap_err();
+
+        assert_eq!(err.line, 3);
+        assert_eq!(err.to_string(), "line 3: Unknown command TYPE");
+    }
+
+    #[test]
+    fn invalid_delay() {
+        let err = parse("LAYOUT_US_ENGLISH", "DELAY soon").unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "line 1: Invalid argument for DELAY: 'soon'"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Special keys embedded in a string using braces, e.g. `login{TAB}password{ENTER}` or
+//! `{CTRL+ALT+DELETE}`
+//!
+//! The braces can contain anything accepted by [`Chord::parse`], i.e. the names of
+//! [`key_names`](crate::keys::key_names) combined with modifiers using `+`, or a media key from
+//! [`media_key_names`](crate::keys::media_key_names) such as `{VOLUME_UP}`. `{{` types a literal
+//! `{`.
+
+use crate::keys::{Chord, MediaKey};
+use crate::{string_to_keystrokes, Error, Keystroke};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// A part of a string containing special keys
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// Literal text to type using the layout
+    Text(String),
+    /// A special key to press and release
+    Key(Chord),
+    /// A media key to press and release on a consumer control device
+    Media(MediaKey),
+}
+
+/// Split a string into runs of literal text and special keys. Characters in key combinations are
+/// looked up in the specified layout.
+pub fn parse(layout_key: &str, string: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens: Vec<Token> = Vec::new();
+    for (_, token) in parse_with_offsets(layout_key, string)? {
+        match (tokens.last_mut(), token) {
+            (Some(Token::Text(text)), Token::Text(more)) => text.push_str(&more),
+            (_, token) => tokens.push(token),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Like [`parse`], but with the byte offset in the string that each token starts at. The
+/// characters of each run of text are at consecutive offsets, so runs are split after a `{{`.
+pub fn parse_with_offsets(layout_key: &str, string: &str) -> Result<Vec<(usize, Token)>, Error> {
+    let mut tokens = Vec::new();
+    let mut text_start = 0;
+    let mut rest = string;
+
+    while let Some(open) = rest.find('{') {
+        let offset = string.len() - rest.len();
+        let after = &rest[open + 1..];
+
+        if let Some(after) = after.strip_prefix('{') {
+            push_text(
+                &mut tokens,
+                text_start,
+                &string[text_start..offset + open + 1],
+            );
+            text_start = offset + open + 2;
+            rest = after;
+            continue;
+        }
+
+        let close = after
+            .find('}')
+            .ok_or_else(|| Error::InvalidKeyName(format!("{{{}", after)))?;
+        let name = &after[..close];
+        let token = match Chord::parse(layout_key, name) {
+            Ok(chord) => Token::Key(chord),
+            Err(e) => MediaKey::parse(name).map(Token::Media).map_err(|_| e)?,
+        };
+
+        push_text(&mut tokens, text_start, &string[text_start..offset + open]);
+        tokens.push((offset + open, token));
+        rest = &after[close + 1..];
+        text_start = string.len() - rest.len();
+    }
+
+    push_text(&mut tokens, text_start, &string[text_start..]);
+    Ok(tokens)
+}
+
+fn push_text(tokens: &mut Vec<(usize, Token)>, start: usize, text: &str) {
+    if !text.is_empty() {
+        tokens.push((start, Token::Text(text.to_string())));
+    }
+}
+
+/// Create the sequence of HID packets required to type the given string, pressing any special
+/// keys it contains.
+pub fn string_with_keys_to_hid_packets(layout_key: &str, string: &str) -> Result<Bytes, Error> {
+    let mut packet_bytes = BytesMut::new();
+
+    for keystroke in string_with_keys_to_keystrokes(layout_key, string)? {
+        packet_bytes.reserve(keystroke.packets.len());
+        packet_bytes.put_slice(&keystroke.packets);
+    }
+
+    Ok(packet_bytes.freeze())
+}
+
+/// Create the HID packets required to type each character of the given string, or press each of
+/// the special keys it contains. Media keys are an error, as they aren't sent to the keyboard.
+pub fn string_with_keys_to_keystrokes(
+    layout_key: &str,
+    string: &str,
+) -> Result<Vec<Keystroke>, Error> {
+    let mut keystrokes = Vec::new();
+
+    for token in parse(layout_key, string)? {
+        match token {
+            Token::Text(text) => keystrokes.extend(string_to_keystrokes(layout_key, &text)?),
+            Token::Key(chord) => keystrokes.push(Keystroke {
+                character: None,
+                packets: chord.to_hid_packets(),
+            }),
+            Token::Media(key) => return Err(Error::MediaKey(key.name().to_string())),
+        }
+    }
+
+    Ok(keystrokes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_text_and_keys() {
+        assert_eq!(
+            parse("LAYOUT_US_ENGLISH", "login{TAB}password{enter}").unwrap(),
+            vec![
+                Token::Text("login".to_string()),
+                Token::Key(Chord {
+                    modifier: 0,
+                    key: 0x2b
+                }),
+                Token::Text("password".to_string()),
+                Token::Key(Chord {
+                    modifier: 0,
+                    key: 0x28
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn media_keys_are_tokens() {
+        assert_eq!(
+            parse("LAYOUT_US_ENGLISH", "a{VOLUME_UP}").unwrap(),
+            vec![
+                Token::Text("a".to_string()),
+                Token::Media(MediaKey::parse("volume_up").unwrap()),
+            ]
+        );
+        match string_with_keys_to_keystrokes("LAYOUT_US_ENGLISH", "{mute}") {
+            Err(Error::MediaKey(name)) => assert_eq!(name, "MUTE"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn double_brace_is_literal() {
+        assert_eq!(
+            parse("LAYOUT_US_ENGLISH", "{{F5}").unwrap(),
+            vec![Token::Text("{F5}".to_string())]
+        );
+    }
+
+    #[test]
+    fn offsets_point_into_the_string() {
+        assert_eq!(
+            parse_with_offsets("LAYOUT_US_ENGLISH", "ab{{c{TAB}d").unwrap(),
+            vec![
+                (0, Token::Text("ab{".to_string())),
+                (4, Token::Text("c".to_string())),
+                (
+                    5,
+                    Token::Key(Chord {
+                        modifier: 0,
+                        key: 0x2b
+                    })
+                ),
+                (10, Token::Text("d".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_key_names_the_token() {
+        match parse("LAYOUT_US_ENGLISH", "a{NOPE}b") {
+            Err(Error::InvalidKeyName(name)) => assert_eq!(name, "NOPE"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn key_combinations() {
+        assert_eq!(
+            parse("LAYOUT_US_ENGLISH", "{CTRL+ALT+DELETE}{gui+r}").unwrap(),
+            vec![
+                Token::Key(Chord {
+                    modifier: 0x05,
+                    key: 0x4c
+                }),
+                Token::Key(Chord {
+                    modifier: 0x08,
+                    key: 0x15
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn key_is_pressed_then_released() {
+        assert_eq!(
+            string_with_keys_to_hid_packets("LAYOUT_US_ENGLISH", "{ESC}")
+                .unwrap()
+                .to_vec(),
+            vec![0, 0, 0x29, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! Randomised delays between keystrokes, so typing looks less robotic than a fixed cooldown
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::time::Duration;
+
+/// How the delays between keystrokes are distributed within their range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distribution {
+    /// Every delay in the range is equally likely
+    Uniform,
+    /// Delays cluster around the middle of the range
+    Gaussian,
+}
+
+/// Picks the delay after each keystroke from a range of durations
+#[derive(Debug, Clone)]
+pub struct Humanizer {
+    min: Duration,
+    max: Duration,
+    distribution: Distribution,
+    punctuation_pauses: bool,
+    word_pause: Duration,
+    sentence_pause: Duration,
+    // The word pause is used for line breaks when this isn't set
+    line_pause: Option<Duration>,
+    correction_pause: Duration,
+    rng: StdRng,
+}
+
+impl Humanizer {
+    /// Create a humanizer picking uniformly distributed delays between `min` and `max`
+    pub fn new(min: Duration, max: Duration) -> Self {
+        let (min, max) = if min <= max { (min, max) } else { (max, min) };
+        Humanizer {
+            min,
+            max,
+            distribution: Distribution::Uniform,
+            punctuation_pauses: true,
+            word_pause: Duration::from_secs(0),
+            sentence_pause: Duration::from_secs(0),
+            line_pause: None,
+            correction_pause: Duration::from_secs(0),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Use the given distribution for the delays
+    pub fn with_distribution(mut self, distribution: Distribution) -> Self {
+        self.distribution = distribution;
+        self
+    }
+
+    /// Don't pause for longer after spaces and punctuation, so every delay is picked from the range
+    pub fn without_punctuation_pauses(mut self) -> Self {
+        self.punctuation_pauses = false;
+        self
+    }
+
+    /// Pause for this much longer after whitespace, between words
+    pub fn with_word_pause(mut self, pause: Duration) -> Self {
+        self.word_pause = pause;
+        self
+    }
+
+    /// Pause for this much longer after `.`, `!` or `?`, at the end of a sentence
+    pub fn with_sentence_pause(mut self, pause: Duration) -> Self {
+        self.sentence_pause = pause;
+        self
+    }
+
+    /// Pause for this much longer after a line break, instead of the word pause, e.g. to give a
+    /// shell time to run each command of a script
+    pub fn with_line_pause(mut self, pause: Duration) -> Self {
+        self.line_pause = Some(pause);
+        self
+    }
+
+    /// Pause for this much longer after a typo, before the Backspace that deletes it, like a
+    /// person noticing the mistake
+    pub fn with_correction_pause(mut self, pause: Duration) -> Self {
+        self.correction_pause = pause;
+        self
+    }
+
+    /// Seed the random delays so the same ones are picked on every run
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Pick the delay after typing the character. Spaces and punctuation pause for half as long
+    /// again, like a person finishing a word, unless [`without_punctuation_pauses`] is used. The
+    /// word, sentence and line pauses are added on top.
+    ///
+    /// [`without_punctuation_pauses`]: Humanizer::without_punctuation_pauses
+    pub fn delay_after(&mut self, character: Option<char>) -> Duration {
+        let min = self.min.as_secs_f64();
+        let max = self.max.as_secs_f64();
+
+        let delay = match self.distribution {
+            _ if min == max => min,
+            Distribution::Uniform => self.rng.gen_range(min..=max),
+            Distribution::Gaussian => {
+                // Box-Muller transform, with nearly all of the delays within the range
+                let u1: f64 = 1.0 - self.rng.gen::<f64>();
+                let u2: f64 = self.rng.gen();
+                let normal = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                ((min + max) / 2.0 + normal * (max - min) / 6.0)
+                    .max(min)
+                    .min(max)
+            }
+        };
+
+        self.with_pauses(delay, character)
+    }
+
+    /// The delay after typing the character on average, the middle of the range with the pauses,
+    /// e.g. to estimate how long typing will take
+    pub fn expected_delay_after(&self, character: Option<char>) -> Duration {
+        let middle = (self.min.as_secs_f64() + self.max.as_secs_f64()) / 2.0;
+        self.with_pauses(middle, character)
+    }
+
+    /// Pick the delay after typing a typo's character, before the Backspace that deletes it: the
+    /// delay after the character with the correction pause on top
+    pub fn delay_before_correction(&mut self, character: Option<char>) -> Duration {
+        self.delay_after(character) + self.correction_pause
+    }
+
+    /// The delay before a correction on average, like [`expected_delay_after`]
+    ///
+    /// [`expected_delay_after`]: Humanizer::expected_delay_after
+    pub fn expected_delay_before_correction(&self, character: Option<char>) -> Duration {
+        self.expected_delay_after(character) + self.correction_pause
+    }
+
+    /// Add the pauses after the character to a delay picked from the range, in seconds
+    fn with_pauses(&self, delay: f64, character: Option<char>) -> Duration {
+        let delay = match character {
+            Some(c)
+                if self.punctuation_pauses && (c.is_whitespace() || c.is_ascii_punctuation()) =>
+            {
+                Duration::from_secs_f64(delay * 1.5)
+            }
+            _ => Duration::from_secs_f64(delay),
+        };
+        match character {
+            Some('\n') | Some('\r') => delay + self.line_pause.unwrap_or(self.word_pause),
+            Some(c) if c.is_whitespace() => delay + self.word_pause,
+            Some('.') | Some('!') | Some('?') => delay + self.sentence_pause,
+            _ => delay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN: Duration = Duration::from_millis(80);
+    const MAX: Duration = Duration::from_millis(200);
+
+    fn delays(humanizer: &mut Humanizer) -> Vec<Duration> {
+        "hello"
+            .chars()
+            .map(|c| humanizer.delay_after(Some(c)))
+            .collect()
+    }
+
+    #[test]
+    fn seeded_delays_are_reproducible() {
+        let mut first = Humanizer::new(MIN, MAX).with_seed(42);
+        let mut second = Humanizer::new(MIN, MAX).with_seed(42);
+
+        assert_eq!(delays(&mut first), delays(&mut second));
+    }
+
+    #[test]
+    fn delays_are_within_the_range() {
+        for distribution in &[Distribution::Uniform, Distribution::Gaussian] {
+            let mut humanizer = Humanizer::new(MIN, MAX)
+                .with_distribution(*distribution)
+                .with_seed(7);
+            for _ in 0..1000 {
+                let delay = humanizer.delay_after(None);
+                assert!(delay >= MIN && delay <= MAX, "{:?} out of range", delay);
+            }
+        }
+    }
+
+    #[test]
+    fn pauses_after_spaces_and_punctuation() {
+        let mut humanizer = Humanizer::new(MIN, MIN);
+
+        assert_eq!(humanizer.delay_after(Some('a')), MIN);
+        assert_eq!(humanizer.delay_after(None), MIN);
+        assert_eq!(humanizer.delay_after(Some(' ')), MIN * 3 / 2);
+        assert_eq!(humanizer.delay_after(Some('.')), MIN * 3 / 2);
+
+        let mut humanizer = Humanizer::new(MIN, MIN).without_punctuation_pauses();
+        assert_eq!(humanizer.delay_after(Some(' ')), MIN);
+    }
+
+    #[test]
+    fn words_and_sentences_pause_for_longer() {
+        let word = Duration::from_millis(150);
+        let sentence = Duration::from_millis(400);
+        let mut humanizer = Humanizer::new(MIN, MIN)
+            .without_punctuation_pauses()
+            .with_word_pause(word)
+            .with_sentence_pause(sentence);
+
+        let delays: Vec<Duration> = "Hi. Ok?\n"
+            .chars()
+            .map(|c| humanizer.delay_after(Some(c)))
+            .collect();
+        assert_eq!(
+            delays,
+            vec![
+                MIN,
+                MIN,
+                MIN + sentence,
+                MIN + word,
+                MIN,
+                MIN,
+                MIN + sentence,
+                MIN + word
+            ]
+        );
+        assert_eq!(humanizer.delay_after(None), MIN);
+    }
+
+    #[test]
+    fn line_breaks_pause_instead_of_words() {
+        let word = Duration::from_millis(150);
+        let line = Duration::from_secs(1);
+        let mut humanizer = Humanizer::new(MIN, MIN)
+            .without_punctuation_pauses()
+            .with_word_pause(word)
+            .with_line_pause(line);
+
+        assert_eq!(humanizer.delay_after(Some(' ')), MIN + word);
+        assert_eq!(humanizer.delay_after(Some('\n')), MIN + line);
+        assert_eq!(humanizer.delay_after(Some('\r')), MIN + line);
+    }
+
+    #[test]
+    fn the_expected_delay_is_the_middle_of_the_range() {
+        let sentence = Duration::from_millis(400);
+        for distribution in &[Distribution::Uniform, Distribution::Gaussian] {
+            let humanizer = Humanizer::new(MIN, MIN * 3)
+                .with_distribution(*distribution)
+                .with_sentence_pause(sentence);
+
+            assert_eq!(humanizer.expected_delay_after(Some('a')), MIN * 2);
+            assert_eq!(
+                humanizer.expected_delay_after(Some('.')),
+                MIN * 3 + sentence
+            );
+        }
+    }
+
+    #[test]
+    fn corrections_pause_for_longer() {
+        let correction = Duration::from_millis(500);
+        let mut humanizer = Humanizer::new(MIN, MIN).with_correction_pause(correction);
+
+        assert_eq!(
+            humanizer.delay_before_correction(Some('a')),
+            MIN + correction
+        );
+        assert_eq!(
+            humanizer.delay_before_correction(Some(' ')),
+            MIN * 3 / 2 + correction
+        );
+        assert_eq!(
+            humanizer.expected_delay_before_correction(Some('a')),
+            MIN + correction
+        );
+        assert_eq!(humanizer.delay_after(Some('a')), MIN);
+    }
+}
@@ -0,0 +1,1159 @@
+use crate::escapes::string_with_keys_to_hid_packets;
+use crate::writer::{HidWriter, PartialWrite};
+use crate::{string_to_hid_packets, Keystroke, HID_PACKET_LEN, RELEASE_KEYS_HID_PACKET};
+
+use std::error;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const CAPS_LOCK_USAGE: u8 = 0x39;
+const CAPS_LOCK_HID_PACKET: [u8; HID_PACKET_LEN] = [0, 0, CAPS_LOCK_USAGE, 0, 0, 0, 0, 0];
+
+/// Errors that can occur while typing on a HID keyboard device
+#[derive(Debug)]
+pub enum KeyboardError {
+    /// The HID device file could not be opened
+    DeviceOpen(PathBuf, io::Error),
+    /// The string could not be converted using the requested layout
+    Layout(crate::Error),
+    /// Writing a packet to the device failed after the given number of packets had been sent
+    Write { sent: usize, error: io::Error },
+    /// The device accepted only `written` of the `expected` bytes of the packet after the given
+    /// number of packets had been sent
+    PartialWrite {
+        sent: usize,
+        written: usize,
+        expected: usize,
+    },
+    /// Typing was interrupted after the given number of packets had been sent, and all keys were
+    /// released
+    Interrupted { sent: usize },
+    /// Any other IO error while writing to the device
+    Io(io::Error),
+}
+
+impl fmt::Display for KeyboardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyboardError::DeviceOpen(path, e) => {
+                write!(f, "Could not open HID device {}: {}", path.display(), e)
+            }
+            KeyboardError::Layout(e) => write!(f, "{}", e),
+            KeyboardError::Write { sent, error } => write!(
+                f,
+                "Could not write to HID device after sending {} packets ({} bytes): {}",
+                sent,
+                sent * HID_PACKET_LEN,
+                error
+            ),
+            KeyboardError::PartialWrite {
+                sent,
+                written,
+                expected,
+            } => write!(
+                f,
+                "Partial write to HID device after sending {} packets: wrote {} of {} bytes",
+                sent, written, expected
+            ),
+            KeyboardError::Interrupted { sent } => {
+                write!(f, "Interrupted after sending {} packets", sent)
+            }
+            KeyboardError::Io(e) => write!(f, "Could not write to HID device: {}", e),
+        }
+    }
+}
+
+impl error::Error for KeyboardError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            KeyboardError::DeviceOpen(_, e)
+            | KeyboardError::Write { error: e, .. }
+            | KeyboardError::Io(e) => Some(e),
+            KeyboardError::Layout(e) => Some(e),
+            KeyboardError::PartialWrite { .. } | KeyboardError::Interrupted { .. } => None,
+        }
+    }
+}
+
+impl KeyboardError {
+    /// The error of a write that failed after the given number of packets had been sent, telling
+    /// apart a packet the device only accepted part of
+    fn write(sent: usize, error: io::Error) -> Self {
+        match PartialWrite::of(&error) {
+            Some(PartialWrite { written, expected }) => KeyboardError::PartialWrite {
+                sent,
+                written,
+                expected,
+            },
+            None => KeyboardError::Write { sent, error },
+        }
+    }
+}
+
+impl From<crate::Error> for KeyboardError {
+    fn from(e: crate::Error) -> Self {
+        KeyboardError::Layout(e)
+    }
+}
+
+impl From<io::Error> for KeyboardError {
+    fn from(e: io::Error) -> Self {
+        KeyboardError::Io(e)
+    }
+}
+
+/// Counts of what a [`HidKeyboard`] has written to its device, from [`HidKeyboard::stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    /// The packets written, the same as [`HidKeyboard::packets_sent`]
+    pub packets: usize,
+    pub bytes: usize,
+    /// The writes that failed and were tried again, see [`HidKeyboard::with_write_retries`]
+    pub retries: usize,
+    /// The time from writing the first packet to writing the last
+    pub elapsed: Duration,
+}
+
+/// A virtual keyboard that types by writing HID packets to a device, e.g. a HID gadget device
+/// file such as /dev/hidg0, or any other [`HidWriter`].
+pub struct HidKeyboard<W: HidWriter = File> {
+    device: W,
+    cooldown: Duration,
+    press_duration: Option<Duration>,
+    delay: Option<Duration>,
+    // Whether a release is still to be sent before the first packet
+    reset_modifiers: bool,
+    write_retries: u32,
+    write_timeout: Option<Duration>,
+    interrupt: Option<Arc<AtomicBool>>,
+    sent: usize,
+    bytes_sent: usize,
+    retried: usize,
+    // When the first and last packets were written
+    writes: Option<(Instant, Instant)>,
+    held: bool,
+    restore_caps_lock: bool,
+    caps_lock_held: bool,
+    // Whether Caps Lock has been pressed an odd number of times
+    caps_lock_toggled: bool,
+}
+
+impl HidKeyboard<File> {
+    /// Open the HID device file at the given path for writing.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, KeyboardError> {
+        let path = path.as_ref();
+        OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map(HidKeyboard::new)
+            .map_err(|e| KeyboardError::DeviceOpen(path.to_path_buf(), e))
+    }
+
+    /// Open the HID device file at the given path, waiting for it to be created and become
+    /// writable, e.g. by udev. Without a timeout this waits forever.
+    pub fn wait_for<P: AsRef<Path>>(
+        path: P,
+        timeout: Option<Duration>,
+    ) -> Result<Self, KeyboardError> {
+        let path = path.as_ref();
+        wait_until_ready(|| OpenOptions::new().write(true).open(path), timeout)
+            .map(HidKeyboard::new)
+            .map_err(|e| KeyboardError::DeviceOpen(path.to_path_buf(), e))
+    }
+}
+
+/// Open a device, trying again while it isn't ready until the timeout, if there is one
+fn wait_until_ready<W>(
+    mut open: impl FnMut() -> io::Result<W>,
+    timeout: Option<Duration>,
+) -> io::Result<W> {
+    let started = Instant::now();
+    loop {
+        match open() {
+            Err(e) if is_not_ready(&e) => {
+                if let Some(timeout) = timeout {
+                    if started.elapsed() >= timeout {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!("timed out after {:?} waiting for it", timeout),
+                        ));
+                    }
+                }
+                thread::sleep(DEVICE_POLL_INTERVAL);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// A HID device file that can be opened again when it goes away while it's being written to,
+/// e.g. when the gadget is unbound from the USB controller and bound again. The write that failed
+/// is made again once it's back, so typing resumes from the packet that wasn't sent.
+pub struct DeviceFile<W = File> {
+    writer: W,
+    open: Box<dyn FnMut() -> io::Result<W>>,
+    // How long to wait for the device to come back, when it's opened again at all
+    reopen: Option<Option<Duration>>,
+}
+
+impl DeviceFile<File> {
+    /// Write to the file opened from the path, which is opened the same way again if it's reopened
+    pub fn new<P: AsRef<Path>>(file: File, path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        DeviceFile::with_opener(file, move || OpenOptions::new().write(true).open(&path))
+    }
+}
+
+impl<W: Write> DeviceFile<W> {
+    /// Write to the writer, opening it again with `open` if it's reopened
+    pub fn with_opener<F: FnMut() -> io::Result<W> + 'static>(writer: W, open: F) -> Self {
+        DeviceFile {
+            writer,
+            open: Box::new(open),
+            reopen: None,
+        }
+    }
+
+    /// Wait for the device to come back and open it again when a write fails because it has gone,
+    /// giving up after the timeout if there is one. Without this the write fails.
+    pub fn reopen_when_gone(mut self, timeout: Option<Duration>) -> Self {
+        self.reopen = Some(timeout);
+        self
+    }
+}
+
+impl<W: Write> Write for DeviceFile<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let timeout = match self.reopen {
+            Some(timeout) => timeout,
+            None => return self.writer.write(buf),
+        };
+        let started = Instant::now();
+        loop {
+            let error = match self.writer.write(buf) {
+                Err(e) if is_gone(&e) => e,
+                result => return result,
+            };
+            let remaining = match timeout {
+                Some(timeout) if started.elapsed() >= timeout => return Err(error),
+                Some(timeout) => Some(timeout - started.elapsed()),
+                None => None,
+            };
+            warn!(
+                "The HID device went away, waiting for it to come back: {}",
+                error
+            );
+            thread::sleep(DEVICE_POLL_INTERVAL);
+            self.writer = wait_until_ready(&mut self.open, remaining)?;
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: HidWriter> HidKeyboard<W> {
+    /// Create a keyboard writing to the given device with no delay or cooldown.
+    pub fn new(device: W) -> Self {
+        HidKeyboard {
+            device,
+            cooldown: Duration::from_millis(0),
+            press_duration: None,
+            delay: None,
+            reset_modifiers: false,
+            write_retries: 0,
+            write_timeout: None,
+            interrupt: None,
+            sent: 0,
+            bytes_sent: 0,
+            retried: 0,
+            writes: None,
+            held: false,
+            restore_caps_lock: false,
+            caps_lock_held: false,
+            caps_lock_toggled: false,
+        }
+    }
+
+    /// Wait for the given duration after sending each packet.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Wait for the given duration after each packet that presses keys, instead of the cooldown,
+    /// so the keys are held for that long before the packet releasing them is sent.
+    pub fn with_press_duration(mut self, press_duration: Duration) -> Self {
+        self.press_duration = Some(press_duration);
+        self
+    }
+
+    /// Wait for the given duration before sending the first packet.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Send a packet releasing all keys and modifiers before the first packet, after any delay, so
+    /// a modifier the host still has held, e.g. from a session that crashed with Shift down,
+    /// doesn't change what's typed.
+    pub fn with_modifier_reset(mut self) -> Self {
+        self.reset_modifiers = true;
+        self
+    }
+
+    /// Retry writing a packet up to the given number of times when the device is temporarily
+    /// unable to accept it, e.g. while the host is suspended. The wait between attempts doubles
+    /// each time, starting at 10ms.
+    pub fn with_write_retries(mut self, retries: u32) -> Self {
+        self.write_retries = retries;
+        self
+    }
+
+    /// Stop retrying a packet once the given duration has passed since the first attempt failed.
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Stop typing once the flag is set, e.g. by a signal handler, releasing all keys so none are
+    /// left held down. Sending a packet or waiting then fails with
+    /// [`KeyboardError::Interrupted`].
+    pub fn with_interrupt(mut self, interrupt: Arc<AtomicBool>) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    /// Press Caps Lock again when the [`ReleaseGuard`] is dropped if the packets sent have pressed
+    /// it an odd number of times, so an interrupted run doesn't leave it toggled on the host.
+    pub fn restore_caps_lock(mut self) -> Self {
+        self.restore_caps_lock = true;
+        self
+    }
+
+    /// The number of packets sent to the device so far.
+    pub fn packets_sent(&self) -> usize {
+        self.sent
+    }
+
+    /// What has been written to the device so far.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            packets: self.sent,
+            bytes: self.bytes_sent,
+            retries: self.retried,
+            elapsed: self
+                .writes
+                .map_or(Duration::from_millis(0), |(first, last)| last - first),
+        }
+    }
+
+    /// The duration waited after sending each packet.
+    pub fn cooldown(&self) -> Duration {
+        self.cooldown
+    }
+
+    /// Send a single HID packet to the device, followed by the cooldown.
+    pub fn send_packet(&mut self, packet: &[u8]) -> Result<(), KeyboardError> {
+        self.start()?;
+        self.check_interrupt()?;
+
+        let sent = self.sent;
+        self.write_with_retries(|device| device.write_packet(packet))
+            .map_err(|error| KeyboardError::write(sent, error))?;
+        trace_packets(sent, packet);
+        self.sent += 1;
+        self.count_write(packet.len());
+        self.held = is_pressed(packet);
+        self.track_caps_lock(packet);
+
+        match self.press_duration {
+            Some(press_duration) if self.held => self.wait(press_duration),
+            _ => self.wait(self.cooldown),
+        }
+    }
+
+    /// Write as many of the packets as the device accepts at once, for when there is no cooldown
+    /// between them.
+    fn send_batch(&mut self, packets: &[u8]) -> Result<(), KeyboardError> {
+        self.start()?;
+
+        let mut remaining = packets;
+        while !remaining.is_empty() {
+            self.check_interrupt()?;
+            let sent = self.sent;
+            let written = self
+                .write_with_retries(|device| device.write_packets(remaining))
+                .map_err(|error| KeyboardError::write(sent, error))?;
+            trace_packets(sent, &remaining[..written]);
+            self.sent += written / HID_PACKET_LEN;
+            self.count_write(written);
+            if let Some(last) = remaining[..written].rchunks(HID_PACKET_LEN).next() {
+                self.held = is_pressed(last);
+            }
+            self.track_caps_lock(&remaining[..written]);
+            remaining = &remaining[written..];
+        }
+        Ok(())
+    }
+
+    /// Wait for the delay and reset the modifiers before the first packet is sent
+    fn start(&mut self) -> Result<(), KeyboardError> {
+        if let Some(delay) = self.delay.take() {
+            self.wait(delay)?;
+        }
+        if std::mem::take(&mut self.reset_modifiers) {
+            self.send_packet(&RELEASE_KEYS_HID_PACKET)?;
+        }
+        Ok(())
+    }
+
+    fn count_write(&mut self, bytes: usize) {
+        let now = Instant::now();
+        self.bytes_sent += bytes;
+        self.writes = Some(match self.writes {
+            Some((first, _)) => (first, now),
+            None => (now, now),
+        });
+    }
+
+    /// Keep track of Caps Lock being toggled by the packets written, each time one presses it
+    /// while the one before didn't
+    fn track_caps_lock(&mut self, packets: &[u8]) {
+        for packet in packets.chunks(HID_PACKET_LEN) {
+            let pressed = packet
+                .get(2..)
+                .is_some_and(|keys| keys.contains(&CAPS_LOCK_USAGE));
+            if pressed && !self.caps_lock_held {
+                self.caps_lock_toggled = !self.caps_lock_toggled;
+            }
+            self.caps_lock_held = pressed;
+        }
+    }
+
+    /// Wait for the given duration, stopping early if typing is interrupted.
+    pub fn wait(&mut self, duration: Duration) -> Result<(), KeyboardError> {
+        if self.interrupt.is_none() {
+            thread::sleep(duration);
+            return Ok(());
+        }
+
+        let deadline = Instant::now() + duration;
+        loop {
+            self.check_interrupt()?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_millis(0) {
+                return Ok(());
+            }
+            thread::sleep(remaining.min(INTERRUPT_POLL_INTERVAL));
+        }
+    }
+
+    fn check_interrupt(&mut self) -> Result<(), KeyboardError> {
+        match &self.interrupt {
+            Some(interrupt) if interrupt.load(Ordering::SeqCst) => {
+                // Best effort, the keys may already be released
+                let _ = self.device.write_packet(&RELEASE_KEYS_HID_PACKET);
+                self.held = false;
+                Err(KeyboardError::Interrupted { sent: self.sent })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn write_with_retries<T>(
+        &mut self,
+        mut write: impl FnMut(&mut W) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut retries = 0;
+        let mut first_failure = None;
+        loop {
+            let error = match write(&mut self.device) {
+                Ok(result) => return Ok(result),
+                Err(e) => e,
+            };
+
+            let failed_at = *first_failure.get_or_insert_with(Instant::now);
+            let timed_out = self
+                .write_timeout
+                .is_some_and(|timeout| failed_at.elapsed() + backoff > timeout);
+            if !is_transient(&error) || retries >= self.write_retries || timed_out {
+                return Err(error);
+            }
+
+            warn!("Retrying HID packet in {:?}: {}", backoff, error);
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            retries += 1;
+            self.retried += 1;
+        }
+    }
+
+    /// Send a buffer of HID packets to the device, returning the number of packets sent. Without
+    /// a cooldown or press duration the packets are written in as few writes as the device
+    /// accepts, otherwise one packet at a time. A buffer that isn't a whole number of packets is an
+    /// error, without writing any of it.
+    pub fn send_packets(&mut self, packets: &[u8]) -> Result<usize, KeyboardError> {
+        // The short packet at the end would be written as a truncated report
+        if !packets.len().is_multiple_of(HID_PACKET_LEN) {
+            return Err(KeyboardError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} bytes of packets isn't a whole number of {} byte HID packets",
+                    packets.len(),
+                    HID_PACKET_LEN
+                ),
+            )));
+        }
+        if self.cooldown == Duration::from_millis(0) && self.press_duration.is_none() {
+            self.send_batch(packets)?;
+            return Ok(packets.chunks(HID_PACKET_LEN).len());
+        }
+
+        let mut sent = 0;
+        for packet in packets.chunks(HID_PACKET_LEN) {
+            self.send_packet(packet)?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    /// Send the packets of a keystroke, letting the device know which character they type.
+    /// Returns the number of packets sent.
+    pub fn send_keystroke(&mut self, keystroke: &Keystroke) -> Result<usize, KeyboardError> {
+        // The reset isn't part of the keystroke
+        self.start()?;
+        self.device.start_keystroke(keystroke.character);
+        let sent = self.send_packets(&keystroke.packets);
+        self.device.start_keystroke(None);
+        sent
+    }
+
+    /// Type the given string using the specified layout, returning the number of packets sent.
+    pub fn type_str(&mut self, layout: &str, s: &str) -> Result<usize, KeyboardError> {
+        let hid_bytes = string_to_hid_packets(layout, s)?;
+        self.send_packets(&hid_bytes)
+    }
+
+    /// Type the given string using the specified layout, pressing any special keys such as
+    /// `{ENTER}` it contains. Returns the number of packets sent.
+    pub fn type_keys(&mut self, layout: &str, s: &str) -> Result<usize, KeyboardError> {
+        let hid_bytes = string_with_keys_to_hid_packets(layout, s)?;
+        self.send_packets(&hid_bytes)
+    }
+
+    /// Send a packet releasing all keys and modifiers.
+    pub fn release_all(&mut self) -> Result<(), KeyboardError> {
+        self.send_packet(&RELEASE_KEYS_HID_PACKET)
+    }
+
+    /// Whether the last packet sent left any keys or modifiers pressed.
+    pub fn keys_held(&self) -> bool {
+        self.held
+    }
+
+    /// Release any keys left pressed when the returned guard is dropped, including when
+    /// unwinding from a panic.
+    pub fn release_on_drop(self) -> ReleaseGuard<W> {
+        ReleaseGuard(self)
+    }
+
+    /// Get a reference to the underlying device.
+    pub fn get_ref(&self) -> &W {
+        &self.device
+    }
+
+    /// Get a mutable reference to the underlying device.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.device
+    }
+
+    /// Consume the keyboard, returning the underlying device.
+    pub fn into_inner(self) -> W {
+        self.device
+    }
+}
+
+/// A keyboard that releases any keys left pressed when it is dropped, created by
+/// [`HidKeyboard::release_on_drop`]
+pub struct ReleaseGuard<W: HidWriter>(HidKeyboard<W>);
+
+impl<W: HidWriter> Deref for ReleaseGuard<W> {
+    type Target = HidKeyboard<W>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<W: HidWriter> DerefMut for ReleaseGuard<W> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<W: HidWriter> Drop for ReleaseGuard<W> {
+    fn drop(&mut self) {
+        if self.0.held {
+            // Nothing can be done if this fails, and the delay, cooldown or interrupt mustn't
+            // stop the keys being released
+            let _ = self.0.device.write_packet(&RELEASE_KEYS_HID_PACKET);
+        }
+        if self.0.restore_caps_lock && self.0.caps_lock_toggled {
+            let _ = self.0.device.write_packet(&CAPS_LOCK_HID_PACKET);
+            let _ = self.0.device.write_packet(&RELEASE_KEYS_HID_PACKET);
+        }
+    }
+}
+
+/// Log each packet written, numbering them from the first
+fn trace_packets(first: usize, packets: &[u8]) {
+    if !log_enabled!(log::Level::Trace) {
+        return;
+    }
+    for (i, packet) in packets.chunks(HID_PACKET_LEN).enumerate() {
+        let hex: Vec<String> = packet.iter().map(|b| format!("{:02x}", b)).collect();
+        trace!("Packet {}: {}", first + i + 1, hex.join(" "));
+    }
+}
+
+/// Whether a packet presses any keys or modifiers
+fn is_pressed(packet: &[u8]) -> bool {
+    packet.iter().any(|&b| b != 0)
+}
+
+/// Write a buffer of HID packets to the writer one packet at a time, waiting for the cooldown after
+/// each of them. Returns the number of packets written.
+pub fn write_packets(
+    writer: &mut dyn Write,
+    packets: &[u8],
+    cooldown: Duration,
+) -> Result<usize, KeyboardError> {
+    HidKeyboard::new(writer)
+        .with_cooldown(cooldown)
+        .send_packets(packets)
+}
+
+/// Type the given string on the writer using the specified layout, waiting for the cooldown after
+/// each packet. Returns the number of packets written.
+pub fn type_string(
+    writer: &mut dyn Write,
+    layout: &str,
+    string: &str,
+    cooldown: Duration,
+) -> Result<usize, KeyboardError> {
+    let hid_bytes = string_to_hid_packets(layout, string)?;
+    write_packets(writer, &hid_bytes, cooldown)
+}
+
+/// Whether an error opening a device means it may still be being set up
+fn is_not_ready(error: &io::Error) -> bool {
+    if error.kind() == io::ErrorKind::NotFound || error.kind() == io::ErrorKind::PermissionDenied {
+        return true;
+    }
+
+    #[cfg(unix)]
+    {
+        // A gadget device that isn't bound to a USB controller yet
+        matches!(error.raw_os_error(), Some(libc::ENXIO) | Some(libc::ENODEV))
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// Whether a write error means the device has gone, e.g. its gadget was unbound, and it may come
+/// back if it's opened again
+fn is_gone(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        matches!(
+            error.raw_os_error(),
+            Some(libc::ENODEV) | Some(libc::ESHUTDOWN) | Some(libc::ENXIO)
+        )
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = error;
+        false
+    }
+}
+
+/// Whether a write error means the device may accept the packet if it is sent again later
+fn is_transient(error: &io::Error) -> bool {
+    if error.kind() == io::ErrorKind::WouldBlock || error.kind() == io::ErrorKind::Interrupted {
+        return true;
+    }
+
+    #[cfg(unix)]
+    {
+        // A gadget device reports these while the host isn't polling it
+        matches!(
+            error.raw_os_error(),
+            Some(libc::EBUSY) | Some(libc::ESHUTDOWN)
+        )
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use tempfile::NamedTempFile;
+
+    /// Fails to write the packets it is given until `failures` reaches zero
+    struct FlakyWriter {
+        failures: usize,
+        kind: io::ErrorKind,
+        packets: Vec<u8>,
+    }
+
+    impl HidWriter for FlakyWriter {
+        fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+            if self.packets.len() >= HID_PACKET_LEN && self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::Error::new(self.kind, "host not polling"));
+            }
+            self.packets.extend_from_slice(packet);
+            Ok(())
+        }
+    }
+
+    fn flaky(failures: usize, kind: io::ErrorKind) -> HidKeyboard<FlakyWriter> {
+        HidKeyboard::new(FlakyWriter {
+            failures,
+            kind,
+            packets: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn type_str_writes_press_and_release_packets() {
+        let file = NamedTempFile::new().unwrap();
+        let mut keyboard = HidKeyboard::open(file.path()).unwrap();
+
+        let sent = keyboard.type_str("LAYOUT_US_ENGLISH", "Hi").unwrap();
+
+        assert_eq!(sent, 4);
+        assert_eq!(
+            fs::read(file.path()).unwrap(),
+            vec![
+                0x02, 0, 0x0b, 0, 0, 0, 0, 0, // H
+                0, 0, 0, 0, 0, 0, 0, 0, // release
+                0, 0, 0x0c, 0, 0, 0, 0, 0, // i
+                0, 0, 0, 0, 0, 0, 0, 0, // release
+            ]
+        );
+    }
+
+    #[test]
+    fn type_string_writes_to_any_writer() {
+        let mut output = Vec::new();
+
+        let sent = type_string(
+            &mut output,
+            "LAYOUT_US_ENGLISH",
+            "a",
+            Duration::from_millis(0),
+        )
+        .unwrap();
+
+        assert_eq!(sent, 2);
+        assert_eq!(
+            output,
+            vec![0, 0, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let mut keyboard = flaky(3, io::ErrorKind::WouldBlock).with_write_retries(3);
+
+        assert_eq!(keyboard.type_str("LAYOUT_US_ENGLISH", "ab").unwrap(), 4);
+        assert_eq!(keyboard.get_ref().packets.len(), 4 * HID_PACKET_LEN);
+    }
+
+    #[test]
+    fn partial_packets_are_rejected() {
+        for cooldown in &[0, 1] {
+            let mut keyboard =
+                HidKeyboard::new(Vec::new()).with_cooldown(Duration::from_millis(*cooldown));
+
+            let e = keyboard.send_packets(&[0x02, 0, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+            match e {
+                Err(KeyboardError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+                e => panic!("expected an error, got {:?}", e),
+            }
+            assert!(keyboard.get_ref().is_empty());
+            assert_eq!(keyboard.packets_sent(), 0);
+        }
+    }
+
+    #[test]
+    fn stats_count_the_writes_and_retries() {
+        let mut keyboard = flaky(3, io::ErrorKind::WouldBlock).with_write_retries(3);
+        assert_eq!(keyboard.stats(), Stats::default());
+
+        keyboard.type_str("LAYOUT_US_ENGLISH", "ab").unwrap();
+
+        let stats = keyboard.stats();
+        assert_eq!(stats.packets, 4);
+        assert_eq!(stats.bytes, 4 * HID_PACKET_LEN);
+        assert_eq!(stats.retries, 3);
+    }
+
+    #[test]
+    fn failed_writes_report_the_packets_sent() {
+        let mut keyboard = flaky(3, io::ErrorKind::WouldBlock).with_write_retries(2);
+
+        match keyboard.type_str("LAYOUT_US_ENGLISH", "ab") {
+            Err(e @ KeyboardError::Write { sent: 1, .. }) => assert_eq!(
+                e.to_string(),
+                "Could not write to HID device after sending 1 packets (8 bytes): host not polling"
+            ),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert_eq!(keyboard.packets_sent(), 1);
+    }
+
+    #[test]
+    fn partial_writes_are_told_apart() {
+        // Room for the first packet and half of the next
+        let mut buf = [0u8; 12];
+        let mut keyboard = HidKeyboard::new(&mut buf[..]).with_cooldown(Duration::from_millis(1));
+
+        match keyboard.type_str("LAYOUT_US_ENGLISH", "a") {
+            Err(e @ KeyboardError::PartialWrite { sent: 1, .. }) => assert_eq!(
+                e.to_string(),
+                "Partial write to HID device after sending 1 packets: wrote 4 of 8 bytes"
+            ),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn other_errors_are_not_retried() {
+        let mut keyboard = flaky(1, io::ErrorKind::BrokenPipe).with_write_retries(5);
+
+        assert!(keyboard.type_str("LAYOUT_US_ENGLISH", "ab").is_err());
+        assert_eq!(keyboard.get_ref().failures, 0);
+    }
+
+    /// A device that has gone until it's opened again, writing to the shared packets once it has
+    #[cfg(unix)]
+    struct Gadget {
+        gone: bool,
+        packets: std::rc::Rc<std::cell::RefCell<Vec<u8>>>,
+    }
+
+    #[cfg(unix)]
+    impl Write for Gadget {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.gone {
+                return Err(io::Error::from_raw_os_error(libc::ENODEV));
+            }
+            self.packets.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn a_device_that_goes_away_is_opened_again() {
+        let packets = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let shared = packets.clone();
+        let device = Gadget {
+            gone: true,
+            packets: packets.clone(),
+        };
+        let device = DeviceFile::with_opener(device, move || {
+            Ok(Gadget {
+                gone: false,
+                packets: shared.clone(),
+            })
+        });
+        let mut keyboard = HidKeyboard::new(device.reopen_when_gone(None));
+
+        assert_eq!(keyboard.type_str("LAYOUT_US_ENGLISH", "a").unwrap(), 2);
+        assert_eq!(
+            *packets.borrow(),
+            [[0, 0, 0x04, 0, 0, 0, 0, 0], [0; 8]].concat()
+        );
+
+        // Without reopening, or when it doesn't come back in time, the write fails
+        let gone = || Gadget {
+            gone: true,
+            packets: std::rc::Rc::default(),
+        };
+        let mut keyboard = HidKeyboard::new(DeviceFile::with_opener(gone(), || {
+            Err(io::Error::from_raw_os_error(libc::ENODEV))
+        }));
+        assert!(matches!(
+            keyboard.type_str("LAYOUT_US_ENGLISH", "a"),
+            Err(KeyboardError::Write { sent: 0, .. })
+        ));
+        let device =
+            DeviceFile::with_opener(gone(), || Err(io::Error::from_raw_os_error(libc::ENODEV)));
+        let mut keyboard =
+            HidKeyboard::new(device.reopen_when_gone(Some(Duration::from_millis(150))));
+        match keyboard.type_str("LAYOUT_US_ENGLISH", "a") {
+            Err(KeyboardError::Write { sent: 0, error }) => {
+                assert_eq!(error.kind(), io::ErrorKind::TimedOut)
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn interrupt_releases_keys() {
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let mut keyboard = HidKeyboard::new(Vec::new()).with_interrupt(interrupt.clone());
+
+        keyboard
+            .send_packet(&[0x02, 0, 0x04, 0, 0, 0, 0, 0])
+            .unwrap();
+        interrupt.store(true, Ordering::SeqCst);
+
+        match keyboard.send_packet(&[0x02, 0, 0x05, 0, 0, 0, 0, 0]) {
+            Err(KeyboardError::Interrupted { sent: 1 }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert_eq!(
+            keyboard.into_inner(),
+            vec![0x02, 0, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    /// Counts the writes it receives, as each would be a syscall on a device file
+    #[derive(Default)]
+    struct CountingWriter {
+        writes: usize,
+        packets: Vec<u8>,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            self.packets.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn packets_are_batched_without_a_cooldown() {
+        let mut batched = HidKeyboard::new(CountingWriter::default());
+        let mut chunked =
+            HidKeyboard::new(CountingWriter::default()).with_cooldown(Duration::from_millis(1));
+
+        assert_eq!(batched.type_str("LAYOUT_US_ENGLISH", "Hello").unwrap(), 10);
+        assert_eq!(chunked.type_str("LAYOUT_US_ENGLISH", "Hello").unwrap(), 10);
+
+        // 10 packets take a single write instead of one each
+        assert_eq!(batched.get_ref().writes, 1);
+        assert_eq!(chunked.get_ref().writes, 10);
+        assert_eq!(batched.get_ref().packets, chunked.get_ref().packets);
+        assert_eq!(batched.packets_sent(), 10);
+    }
+
+    #[test]
+    fn keys_are_held_for_the_press_duration() {
+        let mut keyboard = HidKeyboard::new(CountingWriter::default())
+            .with_press_duration(Duration::from_millis(20));
+
+        let started = Instant::now();
+        assert_eq!(keyboard.type_str("LAYOUT_US_ENGLISH", "ab").unwrap(), 4);
+
+        // Each packet is written on its own, with the presses held
+        assert!(started.elapsed() >= Duration::from_millis(40));
+        assert_eq!(keyboard.get_ref().writes, 4);
+    }
+
+    #[test]
+    fn guard_releases_keys_when_unwinding() {
+        let mut output = Vec::new();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut keyboard = HidKeyboard::new(&mut output).release_on_drop();
+            keyboard
+                .send_packet(&[0x02, 0, 0x0d, 0, 0, 0, 0, 0])
+                .unwrap();
+            assert!(keyboard.keys_held());
+            panic!("interrupted between the press and release packets");
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(
+            output,
+            vec![0x02, 0, 0x0d, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn guard_does_not_release_twice() {
+        let mut output = Vec::new();
+
+        HidKeyboard::new(&mut output)
+            .release_on_drop()
+            .type_str("LAYOUT_US_ENGLISH", "a")
+            .unwrap();
+
+        assert_eq!(
+            output,
+            vec![0, 0, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn guard_restores_caps_lock() {
+        let caps_lock = [0, 0, 0x39, 0, 0, 0, 0, 0];
+        let mut output = Vec::new();
+
+        let mut keyboard = HidKeyboard::new(&mut output)
+            .restore_caps_lock()
+            .release_on_drop();
+        keyboard
+            .send_packets(&[caps_lock, [0; 8]].concat())
+            .unwrap();
+        keyboard.send_packet(&[0, 0, 0x04, 0, 0, 0, 0, 0]).unwrap();
+        drop(keyboard);
+
+        let packets: Vec<&[u8]> = output.chunks(HID_PACKET_LEN).collect();
+        assert_eq!(packets.len(), 6);
+        assert_eq!(&packets[4..], &[&caps_lock[..], &[0; 8][..]]);
+
+        // Toggled back by the packets themselves, so there is nothing to restore
+        let mut output = Vec::new();
+        let mut keyboard = HidKeyboard::new(&mut output)
+            .restore_caps_lock()
+            .release_on_drop();
+        keyboard
+            .send_packets(&[caps_lock, [0; 8], caps_lock, [0; 8]].concat())
+            .unwrap();
+        drop(keyboard);
+        assert_eq!(output.len(), 4 * HID_PACKET_LEN);
+    }
+
+    #[test]
+    fn release_all_writes_empty_packet() {
+        let file = NamedTempFile::new().unwrap();
+        let mut keyboard = HidKeyboard::open(file.path()).unwrap();
+
+        keyboard
+            .send_packet(&[0x02, 0, 0x04, 0, 0, 0, 0, 0])
+            .unwrap();
+        keyboard.release_all().unwrap();
+
+        assert_eq!(
+            fs::read(file.path()).unwrap(),
+            vec![0x02, 0, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn modifier_reset_is_sent_once_before_the_first_packet() {
+        let mut output = Vec::new();
+        let mut keyboard = HidKeyboard::new(&mut output).with_modifier_reset();
+
+        keyboard.type_str("LAYOUT_US_ENGLISH", "A").unwrap();
+        keyboard.type_str("LAYOUT_US_ENGLISH", "a").unwrap();
+        assert_eq!(keyboard.stats().packets, 5);
+        drop(keyboard);
+
+        assert_eq!(
+            output,
+            [
+                &[0u8; 8][..],
+                &[0x02, 0, 0x04, 0, 0, 0, 0, 0],
+                &[0; 8],
+                &[0, 0, 0x04, 0, 0, 0, 0, 0],
+                &[0; 8],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn invalid_layout_is_a_layout_error() {
+        let file = NamedTempFile::new().unwrap();
+        let mut keyboard = HidKeyboard::open(file.path()).unwrap();
+
+        match keyboard.type_str("LAYOUT_NOT_REAL", "a") {
+            Err(KeyboardError::Layout(crate::Error::InvalidLayoutKey(key))) => {
+                assert_eq!(key, "LAYOUT_NOT_REAL")
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(fs::read(file.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn wait_for_opens_the_device_once_it_exists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hidg0");
+
+        let creator = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                fs::write(path, b"").unwrap();
+            })
+        };
+        let mut keyboard = HidKeyboard::wait_for(&path, Some(Duration::from_secs(5))).unwrap();
+        creator.join().unwrap();
+
+        keyboard.release_all().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), vec![0; 8]);
+    }
+
+    #[test]
+    fn wait_for_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hidg0");
+
+        match HidKeyboard::wait_for(&path, Some(Duration::from_millis(10))) {
+            Err(KeyboardError::DeviceOpen(p, e)) => {
+                assert_eq!(p, path);
+                assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+            }
+            _ => panic!("Expected a device open error"),
+        }
+    }
+
+    #[test]
+    fn missing_device_is_an_open_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hidg0");
+
+        match HidKeyboard::open(&path) {
+            Err(KeyboardError::DeviceOpen(p, _)) => assert_eq!(p, path),
+            _ => panic!("Expected a device open error"),
+        }
+    }
+}
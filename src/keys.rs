@@ -0,0 +1,411 @@
+use crate::{string_to_keys_and_modifiers, Error, HID_PACKET_LEN, RELEASE_KEYS_HID_PACKET};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Modifier bits of the first byte of a keyboard HID packet
+pub const MODIFIER_LEFT_CTRL: u8 = 0x01;
+pub const MODIFIER_LEFT_SHIFT: u8 = 0x02;
+pub const MODIFIER_LEFT_ALT: u8 = 0x04;
+pub const MODIFIER_LEFT_GUI: u8 = 0x08;
+pub const MODIFIER_RIGHT_CTRL: u8 = 0x10;
+pub const MODIFIER_RIGHT_SHIFT: u8 = 0x20;
+pub const MODIFIER_RIGHT_ALT: u8 = 0x40;
+pub const MODIFIER_RIGHT_GUI: u8 = 0x80;
+
+// https://www.usb.org/sites/default/files/documents/hut1_12v2.pdf (Keyboard/Keypad Page 0x07)
+const NAMED_KEYS: &[(&str, u8)] = &[
+    ("ENTER", 0x28),
+    ("RETURN", 0x28),
+    ("ESC", 0x29),
+    ("ESCAPE", 0x29),
+    ("BACKSPACE", 0x2A),
+    ("TAB", 0x2B),
+    ("SPACE", 0x2C),
+    ("CAPSLOCK", 0x39),
+    ("F1", 0x3A),
+    ("F2", 0x3B),
+    ("F3", 0x3C),
+    ("F4", 0x3D),
+    ("F5", 0x3E),
+    ("F6", 0x3F),
+    ("F7", 0x40),
+    ("F8", 0x41),
+    ("F9", 0x42),
+    ("F10", 0x43),
+    ("F11", 0x44),
+    ("F12", 0x45),
+    ("PRINTSCREEN", 0x46),
+    ("SCROLLLOCK", 0x47),
+    ("PAUSE", 0x48),
+    ("BREAK", 0x48),
+    ("INSERT", 0x49),
+    ("HOME", 0x4A),
+    ("PAGEUP", 0x4B),
+    ("DELETE", 0x4C),
+    ("DEL", 0x4C),
+    ("END", 0x4D),
+    ("PAGEDOWN", 0x4E),
+    ("RIGHT", 0x4F),
+    ("RIGHTARROW", 0x4F),
+    ("LEFT", 0x50),
+    ("LEFTARROW", 0x50),
+    ("DOWN", 0x51),
+    ("DOWNARROW", 0x51),
+    ("UP", 0x52),
+    ("UPARROW", 0x52),
+    ("NUMLOCK", 0x53),
+    ("MENU", 0x65),
+    ("APP", 0x65),
+];
+
+// Consumer Page 0x0C of the usage tables above
+const MEDIA_KEYS: &[(&str, u16)] = &[
+    ("VOLUME_UP", 0xE9),
+    ("VOLUME_DOWN", 0xEA),
+    ("MUTE", 0xE2),
+    ("PLAY_PAUSE", 0xCD),
+    ("NEXT_TRACK", 0xB5),
+    ("PREV_TRACK", 0xB6),
+    ("STOP", 0xB7),
+    ("EJECT", 0xB8),
+    ("BRIGHTNESS_UP", 0x6F),
+    ("BRIGHTNESS_DOWN", 0x70),
+    ("MAIL", 0x18A),
+    ("CALCULATOR", 0x192),
+    ("BROWSER_SEARCH", 0x221),
+    ("BROWSER_HOME", 0x223),
+    ("BROWSER_BACK", 0x224),
+    ("BROWSER_FORWARD", 0x225),
+    ("BROWSER_REFRESH", 0x227),
+];
+
+/// The number of bytes in a consumer control HID packet, the little endian usage ID of the
+/// pressed media key
+pub const CONSUMER_PACKET_LEN: usize = 2;
+
+const MODIFIER_NAMES: &[(&str, u8)] = &[
+    ("CTRL", MODIFIER_LEFT_CTRL),
+    ("CONTROL", MODIFIER_LEFT_CTRL),
+    ("SHIFT", MODIFIER_LEFT_SHIFT),
+    ("ALT", MODIFIER_LEFT_ALT),
+    ("GUI", MODIFIER_LEFT_GUI),
+    ("WINDOWS", MODIFIER_LEFT_GUI),
+    ("WIN", MODIFIER_LEFT_GUI),
+    ("SUPER", MODIFIER_LEFT_GUI),
+    ("META", MODIFIER_LEFT_GUI),
+    ("COMMAND", MODIFIER_LEFT_GUI),
+    ("RCTRL", MODIFIER_RIGHT_CTRL),
+    ("RSHIFT", MODIFIER_RIGHT_SHIFT),
+    ("RALT", MODIFIER_RIGHT_ALT),
+    ("ALTGR", MODIFIER_RIGHT_ALT),
+    ("RGUI", MODIFIER_RIGHT_GUI),
+];
+
+/// A single key press with the modifiers held while it is pressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    pub modifier: u8,
+    pub key: u8,
+}
+
+impl Chord {
+    /// Parse a chord such as `ctrl+alt+delete`, `gui+l` or `shift+F10`. Key and modifier names
+    /// are case-insensitive. Single characters are looked up in the specified layout so the key
+    /// that produces that character is pressed.
+    pub fn parse(layout_key: &str, chord: &str) -> Result<Chord, Error> {
+        let invalid = || Error::InvalidKeyName(chord.to_string());
+
+        let (modifiers, key) = match chord.strip_suffix("++") {
+            Some(modifiers) => (modifiers, "+"),
+            None => match chord.rfind('+') {
+                Some(idx) => (&chord[..idx], &chord[idx + 1..]),
+                None => ("", chord),
+            },
+        };
+
+        let mut modifier = 0u8;
+        if !modifiers.is_empty() {
+            for name in modifiers.split('+') {
+                modifier |= modifier_bit(name).ok_or_else(invalid)?;
+            }
+        }
+
+        if key.is_empty() {
+            return Err(invalid());
+        }
+
+        if let Some(bit) = modifier_bit(key) {
+            return Ok(Chord {
+                modifier: modifier | bit,
+                key: 0,
+            });
+        }
+
+        if let Some(usage) = key_usage(key) {
+            return Ok(Chord {
+                modifier,
+                key: usage,
+            });
+        }
+
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => {
+                let c = c.to_ascii_lowercase();
+                let key_mod = string_to_keys_and_modifiers(layout_key, &c.to_string())?
+                    .pop()
+                    .ok_or_else(invalid)?;
+                Ok(Chord {
+                    modifier: modifier | key_mod.modifier,
+                    key: key_mod.key,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// The HID packet pressing this chord
+    pub fn press_packet(&self) -> [u8; HID_PACKET_LEN] {
+        [self.modifier, 0, self.key, 0, 0, 0, 0, 0]
+    }
+
+    /// The HID packets pressing and then releasing this chord
+    pub fn to_hid_packets(&self) -> Bytes {
+        let mut packet_bytes = BytesMut::with_capacity(HID_PACKET_LEN * 2);
+        packet_bytes.put_slice(&self.press_packet());
+        packet_bytes.put_slice(&RELEASE_KEYS_HID_PACKET);
+        packet_bytes.freeze()
+    }
+}
+
+/// A media key such as `VOLUME_UP`, which is sent to a consumer control device rather than a
+/// keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MediaKey {
+    pub usage: u16,
+}
+
+impl MediaKey {
+    /// Parse a media key name such as `volume_up` or `PLAY-PAUSE`. Names are case-insensitive and
+    /// dashes can be used instead of underscores.
+    pub fn parse(name: &str) -> Result<MediaKey, Error> {
+        let normalised = name.replace('-', "_");
+        MEDIA_KEYS
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&normalised))
+            .map(|(_, usage)| MediaKey { usage: *usage })
+            .ok_or_else(|| Error::InvalidKeyName(name.to_string()))
+    }
+
+    /// The name of the key, e.g. `VOLUME_UP`
+    pub fn name(&self) -> &'static str {
+        MEDIA_KEYS
+            .iter()
+            .find(|(_, usage)| *usage == self.usage)
+            .map(|(name, _)| *name)
+            .expect("media keys are only created from the table")
+    }
+
+    /// The consumer control packets that press and then release the key
+    pub fn to_hid_packets(&self) -> Bytes {
+        self.to_report_packets(None, CONSUMER_PACKET_LEN)
+    }
+
+    /// The packets that press and then release the key on a consumer control device whose
+    /// reports start with the report ID, if it has one, followed by `len` bytes. The usage is in
+    /// the first two of them, so `len` must be at least 2.
+    pub fn to_report_packets(&self, report_id: Option<u8>, len: usize) -> Bytes {
+        assert!(len >= 2, "consumer control reports hold a 2 byte usage");
+        let packet_len = len + report_id.map_or(0, |_| 1);
+        let mut packet_bytes = BytesMut::with_capacity(packet_len * 2);
+        for usage in &[self.usage, 0] {
+            if let Some(id) = report_id {
+                packet_bytes.put_u8(id);
+            }
+            packet_bytes.put_u16_le(*usage);
+            for _ in 2..len {
+                packet_bytes.put_u8(0);
+            }
+        }
+        packet_bytes.freeze()
+    }
+}
+
+/// Get the HID usage ID of a named key, e.g. `ENTER`, `TAB` or `F5`. Names are case-insensitive.
+pub fn key_usage(name: &str) -> Option<u8> {
+    NAMED_KEYS
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, usage)| *usage)
+}
+
+/// Get the modifier bit of a named modifier, e.g. `CTRL` or `GUI`. Names are case-insensitive.
+pub fn modifier_bit(name: &str) -> Option<u8> {
+    MODIFIER_NAMES
+        .iter()
+        .find(|(modifier, _)| modifier.eq_ignore_ascii_case(name))
+        .map(|(_, bit)| *bit)
+}
+
+/// Parse modifier names joined with `+`, e.g. `ctrl` or `ctrl+alt`, into their modifier bits
+pub fn parse_modifiers(modifiers: &str) -> Result<u8, Error> {
+    modifiers.split('+').try_fold(0, |bits, name| {
+        modifier_bit(name)
+            .map(|bit| bits | bit)
+            .ok_or_else(|| Error::InvalidKeyName(modifiers.to_string()))
+    })
+}
+
+/// The HID packets with the modifiers added to every one of them, as if they were held down
+/// while the packets were sent. Modifiers the packets already have, such as Shift for capital
+/// letters, are kept.
+pub fn hold_modifiers(packets: &[u8], modifier: u8) -> Bytes {
+    let mut held = BytesMut::from(packets);
+    for packet in held.chunks_mut(HID_PACKET_LEN) {
+        packet[0] |= modifier;
+    }
+    held.freeze()
+}
+
+/// Get a list of the supported key names
+pub fn key_names() -> Vec<&'static str> {
+    NAMED_KEYS.iter().map(|(name, _)| *name).collect()
+}
+
+/// Get a list of the supported media key names
+pub fn media_key_names() -> Vec<&'static str> {
+    MEDIA_KEYS.iter().map(|(name, _)| *name).collect()
+}
+
+/// Get a list of the supported modifier names
+pub fn modifier_names() -> Vec<&'static str> {
+    MODIFIER_NAMES.iter().map(|(name, _)| *name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modifiers_and_named_keys() {
+        assert_eq!(
+            Chord::parse("LAYOUT_US_ENGLISH", "Ctrl+ALT+delete").unwrap(),
+            Chord {
+                modifier: MODIFIER_LEFT_CTRL | MODIFIER_LEFT_ALT,
+                key: 0x4c
+            }
+        );
+        assert_eq!(
+            Chord::parse("LAYOUT_US_ENGLISH", "shift+f10").unwrap(),
+            Chord {
+                modifier: MODIFIER_LEFT_SHIFT,
+                key: 0x43
+            }
+        );
+    }
+
+    #[test]
+    fn characters_use_the_layout() {
+        assert_eq!(
+            Chord::parse("LAYOUT_US_ENGLISH", "gui+L").unwrap(),
+            Chord {
+                modifier: MODIFIER_LEFT_GUI,
+                key: 0x0f
+            }
+        );
+        // 'a' is where 'q' is on a QWERTY keyboard
+        assert_eq!(
+            Chord::parse("LAYOUT_FRENCH", "ctrl+a").unwrap(),
+            Chord {
+                modifier: MODIFIER_LEFT_CTRL,
+                key: 0x14
+            }
+        );
+        assert_eq!(
+            Chord::parse("LAYOUT_US_ENGLISH", "ctrl++").unwrap(),
+            Chord {
+                modifier: MODIFIER_LEFT_CTRL | MODIFIER_LEFT_SHIFT,
+                key: 0x2e
+            }
+        );
+    }
+
+    #[test]
+    fn chords_are_a_press_and_a_release() {
+        let chord = Chord::parse("LAYOUT_US_ENGLISH", "CTRL+ALT+T").unwrap();
+
+        assert_eq!(
+            chord.to_hid_packets().to_vec(),
+            [
+                [
+                    MODIFIER_LEFT_CTRL | MODIFIER_LEFT_ALT,
+                    0,
+                    0x17,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0
+                ],
+                [0; HID_PACKET_LEN],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn media_keys_are_consumer_control_packets() {
+        let key = MediaKey::parse("volume-up").unwrap();
+
+        assert_eq!(key, MediaKey { usage: 0xE9 });
+        assert_eq!(key.to_hid_packets().to_vec(), vec![0xE9, 0, 0, 0]);
+        assert_eq!(
+            MediaKey::parse("PLAY_PAUSE")
+                .unwrap()
+                .to_hid_packets()
+                .to_vec(),
+            vec![0xCD, 0, 0, 0]
+        );
+        assert!(MediaKey::parse("VOLUME_SIDEWAYS").is_err());
+    }
+
+    #[test]
+    fn media_keys_fit_the_report() {
+        let key = MediaKey::parse("browser_back").unwrap();
+
+        assert_eq!(key.name(), "BROWSER_BACK");
+        assert_eq!(
+            key.to_report_packets(Some(3), 4).to_vec(),
+            vec![3, 0x24, 0x02, 0, 0, 3, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn held_modifiers_are_added_to_every_packet() {
+        let packets = crate::string_to_hid_packets("LAYOUT_US_ENGLISH", "aB").unwrap();
+        let ctrl_alt = parse_modifiers("ctrl+ALT").unwrap();
+
+        assert_eq!(ctrl_alt, MODIFIER_LEFT_CTRL | MODIFIER_LEFT_ALT);
+        assert_eq!(
+            hold_modifiers(&packets, ctrl_alt).to_vec(),
+            vec![
+                0x05, 0, 0x04, 0, 0, 0, 0, 0, // a
+                0x05, 0, 0, 0, 0, 0, 0, 0, //
+                0x07, 0, 0x05, 0, 0, 0, 0, 0, // B with shift
+                0x05, 0, 0, 0, 0, 0, 0, 0,
+            ]
+        );
+        assert!(parse_modifiers("ctrl+x").is_err());
+        assert!(parse_modifiers("").is_err());
+    }
+
+    #[test]
+    fn invalid_names() {
+        for chord in &["ctrl+", "foo+a", "ctrl+nope", ""] {
+            match Chord::parse("LAYOUT_US_ENGLISH", chord) {
+                Err(Error::InvalidKeyName(name)) => assert_eq!(&name, chord),
+                other => panic!("Unexpected result for {}: {:?}", chord, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! The LED output reports a host sends a keyboard gadget, with the state of its lock keys
+//!
+//! A HID file can be read as well as written: each time a lock key changes, the host writes a 1
+//! byte report with a bit for each LED, which is read from the file. [`LedReader`] reads the
+//! reports without waiting for them, keeping the latest state.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const LED_NUM_LOCK: u8 = 0x01;
+pub const LED_CAPS_LOCK: u8 = 0x02;
+pub const LED_SCROLL_LOCK: u8 = 0x04;
+pub const LED_COMPOSE: u8 = 0x08;
+pub const LED_KANA: u8 = 0x10;
+
+/// The names of the LEDs, in the order of their bits
+pub const LED_NAMES: [(&str, u8); 5] = [
+    ("num_lock", LED_NUM_LOCK),
+    ("caps_lock", LED_CAPS_LOCK),
+    ("scroll_lock", LED_SCROLL_LOCK),
+    ("compose", LED_COMPOSE),
+    ("kana", LED_KANA),
+];
+
+// How often to check for a report while waiting for one
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The LEDs the host has turned on, as the bits of an LED report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Leds(pub u8);
+
+impl Leds {
+    /// Whether the LED with the bit, e.g. [`LED_CAPS_LOCK`], is on
+    pub fn is_on(self, led: u8) -> bool {
+        self.0 & led != 0
+    }
+
+    pub fn caps_lock(self) -> bool {
+        self.is_on(LED_CAPS_LOCK)
+    }
+
+    pub fn num_lock(self) -> bool {
+        self.is_on(LED_NUM_LOCK)
+    }
+
+    pub fn scroll_lock(self) -> bool {
+        self.is_on(LED_SCROLL_LOCK)
+    }
+}
+
+impl fmt::Display for Leds {
+    /// The names of the LEDs that are on, e.g. `num_lock caps_lock`, or `none`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let on: Vec<&str> = LED_NAMES
+            .iter()
+            .filter(|(_, led)| self.is_on(*led))
+            .map(|(name, _)| *name)
+            .collect();
+        if on.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", on.join(" "))
+        }
+    }
+}
+
+/// Reads the LED reports from a HID file opened without blocking, keeping the latest
+pub struct LedReader<R> {
+    reader: R,
+    leds: Option<Leds>,
+}
+
+impl LedReader<File> {
+    /// Open a HID file to read its LED reports
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        // Reading must not wait for the next lock key change
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::custom_flags(&mut options, libc::O_NONBLOCK);
+        Ok(LedReader::new(options.open(path)?))
+    }
+}
+
+impl<R: Read> LedReader<R> {
+    /// Read the LED reports from a reader that returns `WouldBlock` when there are none
+    pub fn new(reader: R) -> Self {
+        LedReader { reader, leds: None }
+    }
+
+    /// Read the reports the host has sent since the last call, returning the latest state, or
+    /// None if the host hasn't sent any yet
+    pub fn poll(&mut self) -> io::Result<Option<Leds>> {
+        let mut buf = [0; 64];
+        loop {
+            match self.reader.read(&mut buf) {
+                Ok(0) => break,
+                // Each read is a single report, where the LEDs come after the report ID if there
+                // is one. Anything longer isn't an LED report, e.g. the packets written to a
+                // regular file standing in for a gadget.
+                Ok(n @ 1..=2) => self.leds = Some(Leds(buf[n - 1])),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.leds)
+    }
+
+    /// Take the LEDs to be in this state until the host sends another report, e.g. after pressing
+    /// a lock key, so the old state isn't acted on again before the host reports the change
+    pub fn assume(&mut self, leds: Leds) {
+        self.leds = Some(leds);
+    }
+
+    /// Like [`poll`](LedReader::poll), but if no report has been read yet, wait up to the timeout
+    /// for one
+    pub fn wait(&mut self, timeout: Duration) -> io::Result<Option<Leds>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(leds) = self.poll()? {
+                return Ok(Some(leds));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::VecDeque;
+
+    /// A HID file with the reports the host has sent, which would block when there are no more
+    struct Reports(VecDeque<Vec<u8>>);
+
+    impl Read for Reports {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.pop_front() {
+                Some(report) => {
+                    buf[..report.len()].copy_from_slice(&report);
+                    Ok(report.len())
+                }
+                None => Err(io::ErrorKind::WouldBlock.into()),
+            }
+        }
+    }
+
+    #[test]
+    fn latest_report_is_kept() {
+        let reports = vec![vec![LED_NUM_LOCK | LED_CAPS_LOCK], vec![LED_NUM_LOCK]];
+        let mut leds = LedReader::new(Reports(reports.into()));
+
+        let state = leds.poll().unwrap().unwrap();
+        assert!(state.num_lock());
+        assert!(!state.caps_lock());
+        assert_eq!(leds.poll().unwrap(), Some(state));
+    }
+
+    #[test]
+    fn no_report_is_unknown() {
+        let mut leds = LedReader::new(Reports(VecDeque::new()));
+
+        assert_eq!(leds.wait(Duration::from_millis(20)).unwrap(), None);
+    }
+
+    #[test]
+    fn report_ids_are_skipped() {
+        let reports = vec![vec![1, LED_CAPS_LOCK], vec![0; 8]];
+        let mut leds = LedReader::new(Reports(reports.into()));
+
+        assert!(leds.poll().unwrap().unwrap().caps_lock());
+        assert_eq!(Leds(LED_CAPS_LOCK | LED_KANA).to_string(), "caps_lock kana");
+        assert_eq!(Leds(0).to_string(), "none");
+    }
+}
@@ -1,11 +1,42 @@
+//! Convert strings into the HID packets a keyboard with a given layout would send, and type them
+//! on a HID device such as /dev/hidg0 with [`HidKeyboard`], [`type_string`] or [`write_packets`].
+
 #[macro_use]
 extern crate log;
 
 use bytes::{BufMut, Bytes, BytesMut};
 use gen_layouts_sys::*;
 
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error;
 use std::fmt;
 
+pub mod capslock;
+pub mod custom;
+pub mod ducky;
+pub mod escapes;
+pub mod humanize;
+mod keyboard;
+pub mod keys;
+pub mod leds;
+pub mod locale;
+pub mod modifiers;
+pub mod mouse;
+#[cfg(all(feature = "native", any(windows, target_os = "macos")))]
+pub mod native;
+pub mod normalize;
+pub mod rollover;
+pub mod typos;
+#[cfg(target_os = "linux")]
+pub mod uinput;
+pub mod unicode;
+pub mod writer;
+
+pub use keyboard::{
+    type_string, write_packets, DeviceFile, HidKeyboard, KeyboardError, ReleaseGuard, Stats,
+};
+
 const UNICODE_ENTER: u16 = 10; // \n
 const UNICODE_TAB: u16 = 9; // \t
                             // https://stackoverflow.com/questions/23320417/what-is-this-character-separator
@@ -13,7 +44,8 @@ const UNICODE_RETURN: u16 = 13; // \r
 const CONTROL_CHARACTER_OFFSET: u16 = 0x40;
 const UNICODE_FIRST_ASCII: u16 = 0x20; // SPACE
 const UNICODE_LAST_ASCII: u16 = 0x7F; // BACKSPACE
-const UNICODE_DIGIT_OFFSET: usize = 48; // 0
+const UNICODE_FIRST_ISO_8859_1: u16 = 0xA0; // NO-BREAK SPACE
+const UNICODE_LAST_ISO_8859_1: u16 = 0xFF; // LATIN SMALL LETTER Y WITH DIAERESIS
 const KEY_MASK: u16 = 0x3F; // Remove SHIFT/ALT/CTRL from keycode
 /// The number of bytes in a keyboard HID packet
 pub const HID_PACKET_LEN: usize = 8;
@@ -24,6 +56,20 @@ const RELEASE_KEYS_HID_PACKET: [u8; 8] = [0u8; 8];
 pub enum Error {
     InvalidLayoutKey(String),
     InvalidCharacter(char),
+    InvalidKeyName(String),
+    InvalidLayoutFile(String),
+    /// A media key where only keyboard keys can be sent
+    MediaKey(String),
+    /// A character above U+FFFF, which Alt+Numpad input can only type as a surrogate pair
+    NeedsSurrogatePair(char),
+    /// A character map file that couldn't be read or parsed
+    InvalidCharMap(String),
+    /// The packets generated with a layout weren't a whole number of HID packets, which would
+    /// leave a short report at the end
+    PartialPacket {
+        layout: String,
+        len: usize,
+    },
 }
 
 #[derive(Debug)]
@@ -52,26 +98,152 @@ impl fmt::Display for Error {
         match self {
             Error::InvalidLayoutKey(key) => write!(f, "No layout defined for {}", key),
             Error::InvalidCharacter(c) => write!(f, "Invalid character: '{}' or [{:?}]", c, c),
+            Error::InvalidKeyName(name) => write!(f, "Invalid key name: {}", name),
+            Error::InvalidLayoutFile(e) => write!(f, "Invalid layout file: {}", e),
+            Error::MediaKey(name) => write!(
+                f,
+                "{} is a media key, which needs a consumer control device",
+                name
+            ),
+            Error::NeedsSurrogatePair(c) => write!(
+                f,
+                "'{}' is above U+FFFF, so Alt+Numpad input can only type it as a surrogate pair",
+                c
+            ),
+            Error::InvalidCharMap(e) => write!(f, "Invalid character map: {}", e),
+            Error::PartialPacket { layout, len } => write!(
+                f,
+                "{} generated {} bytes, which isn't a whole number of {} byte HID packets",
+                layout, len, HID_PACKET_LEN
+            ),
         }
     }
 }
 
-/// Get a list of the supported keyboard layouts
+impl error::Error for Error {}
+
+/// Get a list of the supported keyboard layouts, including any [registered](custom::register)
+/// custom layouts
 pub fn available_layouts() -> Vec<&'static str> {
-    LAYOUT_MAP.keys().map(|k| *k).collect()
+    let mut layouts: Vec<&'static str> = LAYOUT_MAP.keys().copied().collect();
+    layouts.extend(custom::registered());
+    layouts
+}
+
+// Short names for the layouts, mostly country codes
+const LAYOUT_ALIASES: &[(&str, &str)] = &[
+    ("UK", "LAYOUT_UNITED_KINGDOM"),
+    ("GB", "LAYOUT_UNITED_KINGDOM"),
+    ("US", "LAYOUT_US_ENGLISH"),
+    ("US_INTL", "LAYOUT_US_INTERNATIONAL"),
+    ("DE", "LAYOUT_GERMAN"),
+    ("DE_MAC", "LAYOUT_GERMAN_MAC"),
+    ("CH", "LAYOUT_GERMAN_SWISS"),
+    ("CH_FR", "LAYOUT_FRENCH_SWISS"),
+    ("FR", "LAYOUT_FRENCH"),
+    ("BE", "LAYOUT_FRENCH_BELGIAN"),
+    ("CA", "LAYOUT_CANADIAN_FRENCH"),
+    ("CA_MULTI", "LAYOUT_CANADIAN_MULTILINGUAL"),
+    ("ES", "LAYOUT_SPANISH"),
+    ("LATAM", "LAYOUT_SPANISH_LATIN_AMERICA"),
+    ("IT", "LAYOUT_ITALIAN"),
+    ("PT", "LAYOUT_PORTUGUESE"),
+    ("BR", "LAYOUT_PORTUGUESE_BRAZILIAN"),
+    ("IE", "LAYOUT_IRISH"),
+    ("IS", "LAYOUT_ICELANDIC"),
+    ("DK", "LAYOUT_DANISH"),
+    ("NO", "LAYOUT_NORWEGIAN"),
+    ("SE", "LAYOUT_SWEDISH"),
+    ("FI", "LAYOUT_FINNISH"),
+    ("TR", "LAYOUT_TURKISH"),
+];
+
+/// Find the layout with the given name. As well as the exact name, e.g. `LAYOUT_UNITED_KINGDOM`,
+/// this accepts names in any case, without the `LAYOUT_` prefix, or short aliases such as `uk`,
+/// `us`, `de` or `fr`.
+pub fn resolve_layout(name: &str) -> Option<&'static str> {
+    let layouts = available_layouts();
+    if let Some(key) = layouts.iter().find(|key| **key == name) {
+        return Some(key);
+    }
+
+    let name = name.trim().to_uppercase().replace(['-', ' '], "_");
+    let prefixed = format!("LAYOUT_{}", name);
+    layouts
+        .into_iter()
+        .find(|key| key.to_uppercase() == name || *key == prefixed)
+        .or_else(|| {
+            LAYOUT_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == name)
+                .map(|(_, key)| *key)
+        })
+}
+
+/// Get the short aliases accepted by [`resolve_layout`] for the layout, e.g. `UK` and `GB` for
+/// `LAYOUT_UNITED_KINGDOM`
+pub fn layout_aliases(layout_key: &str) -> Vec<&'static str> {
+    LAYOUT_ALIASES
+        .iter()
+        .filter(|(_, key)| *key == layout_key)
+        .map(|(alias, _)| *alias)
+        .collect()
+}
+
+/// Get the layouts with names similar to the given one, most similar first, e.g. to suggest
+/// alternatives for a misspelt name.
+pub fn similar_layouts(name: &str) -> Vec<&'static str> {
+    let name = name.trim().to_uppercase().replace(['-', ' '], "_");
+    let prefixed = format!("LAYOUT_{}", name);
+
+    let max_distance = (prefixed.len() / 3).max(2);
+    let mut similar: Vec<(usize, &'static str)> = available_layouts()
+        .into_iter()
+        .map(|key| {
+            let key_upper = key.to_uppercase();
+            let distance =
+                edit_distance(&name, &key_upper).min(edit_distance(&prefixed, &key_upper));
+            (distance, key)
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    similar.sort();
+    similar.into_iter().take(3).map(|(_, key)| key).collect()
+}
+
+/// The Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
 }
 
 /// Get a list of the key and modifier pairs required to type the given string on a keyboard with
 /// the specified layout.
 pub fn string_to_keys_and_modifiers(layout_key: &str, string: &str) -> Result<Vec<KeyMod>, Error> {
-    let layout = LAYOUT_MAP
-        .get(layout_key)
-        .ok_or_else(|| Error::InvalidLayoutKey(layout_key.to_string()))?;
+    let layout = match LAYOUT_MAP.get(layout_key) {
+        Some(layout) => layout,
+        None => {
+            return custom::get(layout_key)
+                .ok_or_else(|| Error::InvalidLayoutKey(layout_key.to_string()))?
+                .keys_and_modifiers(string)
+        }
+    };
 
     let mut keys_and_modifiers: Vec<KeyMod> = Vec::with_capacity(string.len());
 
     for c in string.chars() {
-        match keycode_for_unicode(layout, c as u16) {
+        // The layouts only map the Basic Multilingual Plane, so nothing above it is truncated into it
+        let code = u16::try_from(u32::from(c)).map_err(|_| Error::InvalidCharacter(c))?;
+        match keycode_for_unicode(layout, code) {
             Keycode::ModifierKeySequence(modifier, sequence) => {
                 for keycode in sequence {
                     keys_and_modifiers.push(KeyMod {
@@ -112,6 +284,52 @@ pub fn string_to_keys_and_modifiers(layout_key: &str, string: &str) -> Result<Ve
     Ok(keys_and_modifiers)
 }
 
+/// Get every character the layout can type, in order, with the key presses that type it. Each
+/// press is a key and the modifiers held with it, most characters take one but those typed with a
+/// dead key take two.
+pub fn keymap(layout_key: &str) -> Result<Vec<(char, Vec<KeyMod>)>, Error> {
+    // The built-in layouts only map characters in the basic multilingual plane
+    let mut characters: Vec<char> = (0..=0xFFFF)
+        .filter_map(char::from_u32)
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect();
+    match custom::get(layout_key) {
+        Some(layout) => {
+            characters.extend(layout.characters());
+            characters.sort_unstable();
+            characters.dedup();
+        }
+        None if !LAYOUT_MAP.contains_key(layout_key) => {
+            return Err(Error::InvalidLayoutKey(layout_key.to_string()))
+        }
+        None => {}
+    }
+
+    let mut keymap = Vec::new();
+    let mut buf = [0u8; 4];
+    for c in characters {
+        match string_to_keys_and_modifiers(layout_key, c.encode_utf8(&mut buf)) {
+            Ok(keys) => {
+                let presses = keys.into_iter().filter(|key| key.key != 0).collect();
+                keymap.push((c, presses));
+            }
+            Err(Error::InvalidCharacter(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(keymap)
+}
+
+/// The character typed by each key and the modifiers held with it, for the characters of the
+/// [`keymap`] that take a single press
+pub(crate) fn key_characters(layout_key: &str) -> Result<HashMap<(u8, u8), char>, Error> {
+    Ok(keymap(layout_key)?
+        .into_iter()
+        .filter(|(_, presses)| presses.len() == 1)
+        .map(|(c, presses)| ((presses[0].modifier, presses[0].key), c))
+        .collect())
+}
+
 /// Create the sequence of HID packets required to type the given string. Impersonating a keyboard
 /// with the specified layout. These packets can be written directly to a HID device file.
 pub fn string_to_hid_packets(layout_key: &str, string: &str) -> Result<Bytes, Error> {
@@ -142,9 +360,45 @@ pub fn string_to_hid_packets(layout_key: &str, string: &str) -> Result<Bytes, Er
         }
     }
 
+    check_whole_packets(layout_key, &packet_bytes)?;
     Ok(packet_bytes.freeze())
 }
 
+/// Fail rather than let a layout's packets end with a short one, which would be written as a
+/// truncated report
+fn check_whole_packets(layout_key: &str, packets: &[u8]) -> Result<(), Error> {
+    if !packets.len().is_multiple_of(HID_PACKET_LEN) {
+        return Err(Error::PartialPacket {
+            layout: layout_key.to_string(),
+            len: packets.len(),
+        });
+    }
+    Ok(())
+}
+
+/// The HID packets that type a single character, or press a single special key
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keystroke {
+    /// The character the packets type, if any
+    pub character: Option<char>,
+    pub packets: Bytes,
+}
+
+/// Create the HID packets required to type each character of the given string, keeping track of
+/// which character each of the packets types.
+pub fn string_to_keystrokes(layout_key: &str, string: &str) -> Result<Vec<Keystroke>, Error> {
+    let mut buf = [0u8; 4];
+    string
+        .chars()
+        .map(|c| {
+            Ok(Keystroke {
+                character: Some(c),
+                packets: string_to_hid_packets(layout_key, c.encode_utf8(&mut buf))?,
+            })
+        })
+        .collect()
+}
+
 fn keycode_for_unicode(layout: &Layout, unicode: u16) -> Keycode {
     match unicode {
         u if u == UNICODE_ENTER || u == UNICODE_RETURN => {
@@ -156,10 +410,18 @@ fn keycode_for_unicode(layout: &Layout, unicode: u16) -> Keycode {
             let keycodes = vec![layout.keycodes[idx]];
             Keycode::ModifierKeySequence(RIGHT_CTRL_MODIFIER, keycodes)
         }
-        u if u >= UNICODE_FIRST_ASCII && u <= UNICODE_LAST_ASCII => {
+        u if (UNICODE_FIRST_ASCII..=UNICODE_LAST_ASCII).contains(&u) => {
             let idx = (u - UNICODE_FIRST_ASCII) as usize;
             Keycode::RegularKey(layout.keycodes[idx])
         }
+        // Accented letters are often typed with a dead key, which deadkey_for_keycode finds
+        u if (UNICODE_FIRST_ISO_8859_1..=UNICODE_LAST_ISO_8859_1).contains(&u) => {
+            let idx = (u - UNICODE_FIRST_ISO_8859_1) as usize;
+            match layout.iso_8859_1_keycodes[idx] {
+                0 => Keycode::InvalidCharacter,
+                keycode => Keycode::RegularKey(keycode),
+            }
+        }
         _ => Keycode::InvalidCharacter,
     }
 }
@@ -239,3 +501,116 @@ fn key_for_keycode(layout: &Layout, keycode: u16) -> u8 {
         None => key as u8,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_layout_names() {
+        assert_eq!(
+            resolve_layout("LAYOUT_UNITED_KINGDOM"),
+            Some("LAYOUT_UNITED_KINGDOM")
+        );
+        assert_eq!(resolve_layout("layout_german"), Some("LAYOUT_GERMAN"));
+        assert_eq!(resolve_layout("us-english"), Some("LAYOUT_US_ENGLISH"));
+        assert_eq!(resolve_layout("uk"), Some("LAYOUT_UNITED_KINGDOM"));
+        assert_eq!(resolve_layout("Fr"), Some("LAYOUT_FRENCH"));
+        assert_eq!(resolve_layout("german-swiss"), Some("LAYOUT_GERMAN_SWISS"));
+        assert_eq!(resolve_layout("US_intl"), Some("LAYOUT_US_INTERNATIONAL"));
+        assert_eq!(resolve_layout("klingon"), None);
+    }
+
+    #[test]
+    fn aliases_name_real_layouts() {
+        for (alias, key) in LAYOUT_ALIASES {
+            assert!(
+                LAYOUT_MAP.contains_key(key),
+                "{} is an alias of {}",
+                alias,
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn keymap_lists_the_characters_in_order() {
+        let us = keymap("LAYOUT_US_ENGLISH").unwrap();
+        let find = |c| us.iter().find(|(k, _)| *k == c).map(|(_, keys)| keys);
+
+        assert!(us.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        let a = find('A').unwrap();
+        assert_eq!((a.len(), a[0].modifier, a[0].key), (1, 0x02, 0x04));
+        assert!(find('\n').is_some());
+        assert!(find('€').is_none());
+        assert!(keymap("LAYOUT_KLINGON").is_err());
+    }
+
+    #[test]
+    fn partial_packets_name_the_layout() {
+        assert!(check_whole_packets("LAYOUT_US_ENGLISH", &[0; 16]).is_ok());
+
+        let e = check_whole_packets("LAYOUT_US_ENGLISH", &[0; 12]).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "LAYOUT_US_ENGLISH generated 12 bytes, which isn't a whole number of 8 byte HID packets"
+        );
+    }
+
+    #[test]
+    fn characters_above_the_bmp_are_invalid() {
+        // U+100E9 has the same low 16 bits as é
+        assert!(matches!(
+            string_to_keys_and_modifiers("LAYOUT_FRENCH", "\u{100e9}"),
+            Err(Error::InvalidCharacter('\u{100e9}'))
+        ));
+        assert!(matches!(
+            string_to_keys_and_modifiers("LAYOUT_US_ENGLISH", "a\u{10061}"),
+            Err(Error::InvalidCharacter('\u{10061}'))
+        ));
+    }
+
+    #[test]
+    fn accented_letters_use_dead_keys() {
+        let presses = |layout, string| -> Vec<(u8, u8)> {
+            string_to_keys_and_modifiers(layout, string)
+                .unwrap()
+                .iter()
+                .map(|press| (press.modifier, press.key))
+                .collect()
+        };
+
+        // è and é have their own keys, û is the circumflex dead key followed by u
+        assert_eq!(
+            presses("LAYOUT_FRENCH", "èéû"),
+            [(0, 0x24), (0, 0x1f), (0, 0x2f), (0, 0x18)]
+        );
+        // The acute accent dead key, then e
+        assert_eq!(presses("LAYOUT_GERMAN", "é"), [(0, 0x2e), (0, 0x08)]);
+        assert_eq!(presses("LAYOUT_GERMAN", "ß"), [(0, 0x2d)]);
+        assert!(string_to_hid_packets("LAYOUT_FRENCH", "crème brûlée").is_ok());
+        assert!(string_to_hid_packets("LAYOUT_US_ENGLISH", "é").is_err());
+    }
+
+    #[test]
+    fn lists_the_aliases_of_a_layout() {
+        assert_eq!(layout_aliases("LAYOUT_UNITED_KINGDOM"), vec!["UK", "GB"]);
+        assert!(layout_aliases("LAYOUT_GERMAN_MAC").contains(&"DE_MAC"));
+        assert!(layout_aliases("LAYOUT_KLINGON").is_empty());
+    }
+
+    #[test]
+    fn suggests_similar_layouts() {
+        assert_eq!(
+            similar_layouts("LAYOUT_UNITED_KINGDON")[0],
+            "LAYOUT_UNITED_KINGDOM"
+        );
+        assert_eq!(similar_layouts("germen")[0], "LAYOUT_GERMAN");
+        assert_eq!(
+            similar_layouts("unitedkingdm"),
+            vec!["LAYOUT_UNITED_KINGDOM"]
+        );
+        assert_eq!(similar_layouts("french-swis")[0], "LAYOUT_FRENCH_SWISS");
+        assert!(similar_layouts("klingon").is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+//! Guess the keyboard layout from a locale such as `en_GB.UTF-8`, or from an X11 keyboard layout
+//! such as `gb`
+
+// Layouts for a language in a particular territory, checked before the language alone
+const TERRITORY_LAYOUTS: &[(&str, &str, &str)] = &[
+    ("en", "GB", "LAYOUT_UNITED_KINGDOM"),
+    ("en", "IE", "LAYOUT_IRISH"),
+    ("de", "CH", "LAYOUT_GERMAN_SWISS"),
+    ("fr", "BE", "LAYOUT_FRENCH_BELGIAN"),
+    ("fr", "CA", "LAYOUT_CANADIAN_FRENCH"),
+    ("fr", "CH", "LAYOUT_FRENCH_SWISS"),
+    ("nl", "BE", "LAYOUT_FRENCH_BELGIAN"),
+    ("it", "CH", "LAYOUT_GERMAN_SWISS"),
+    ("es", "ES", "LAYOUT_SPANISH"),
+    ("pt", "BR", "LAYOUT_PORTUGUESE_BRAZILIAN"),
+];
+
+const LANGUAGE_LAYOUTS: &[(&str, &str)] = &[
+    ("en", "LAYOUT_US_ENGLISH"),
+    ("de", "LAYOUT_GERMAN"),
+    ("fr", "LAYOUT_FRENCH"),
+    // Spain is handled above, most other Spanish speaking countries are in Latin America
+    ("es", "LAYOUT_SPANISH_LATIN_AMERICA"),
+    ("it", "LAYOUT_ITALIAN"),
+    ("pt", "LAYOUT_PORTUGUESE"),
+    ("ga", "LAYOUT_IRISH"),
+    ("is", "LAYOUT_ICELANDIC"),
+    ("da", "LAYOUT_DANISH"),
+    ("nb", "LAYOUT_NORWEGIAN"),
+    ("nn", "LAYOUT_NORWEGIAN"),
+    ("no", "LAYOUT_NORWEGIAN"),
+    ("sv", "LAYOUT_SWEDISH"),
+    ("fi", "LAYOUT_FINNISH"),
+    ("tr", "LAYOUT_TURKISH"),
+];
+
+/// Get the layout usually used with a locale in the `language_TERRITORY.codeset@modifier` form,
+/// e.g. `LAYOUT_UNITED_KINGDOM` for `en_GB.UTF-8`. The `C` and `POSIX` locales, and languages
+/// without a layout, give `None`.
+pub fn layout_for_locale(locale: &str) -> Option<&'static str> {
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    let mut parts = locale.splitn(2, ['_', '-']);
+    let language = parts.next().unwrap_or_default().to_lowercase();
+    let territory = parts.next().map(str::to_uppercase);
+
+    if let Some(territory) = territory {
+        if let Some((_, _, layout)) = TERRITORY_LAYOUTS
+            .iter()
+            .find(|(l, t, _)| *l == language && *t == territory)
+        {
+            return Some(layout);
+        }
+    }
+    LANGUAGE_LAYOUTS
+        .iter()
+        .find(|(l, _)| *l == language)
+        .map(|(_, layout)| *layout)
+}
+
+/// Get the layout for an X11 keyboard layout such as `gb` or `de`. When several are configured,
+/// e.g. `us,de`, the first is used.
+pub fn layout_for_x11(layout: &str) -> Option<&'static str> {
+    let layout = layout.split(',').next().unwrap_or_default().trim();
+    if layout.is_empty() {
+        return None;
+    }
+    crate::resolve_layout(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_locales_have_layouts() {
+        for (locale, layout) in &[
+            ("en_GB.UTF-8", "LAYOUT_UNITED_KINGDOM"),
+            ("en_US.UTF-8", "LAYOUT_US_ENGLISH"),
+            ("en_AU", "LAYOUT_US_ENGLISH"),
+            ("de_DE.UTF-8", "LAYOUT_GERMAN"),
+            ("de_AT", "LAYOUT_GERMAN"),
+            ("de_CH.UTF-8", "LAYOUT_GERMAN_SWISS"),
+            ("fr_FR.UTF-8@euro", "LAYOUT_FRENCH"),
+            ("fr_CA.UTF-8", "LAYOUT_CANADIAN_FRENCH"),
+            ("es_ES.UTF-8", "LAYOUT_SPANISH"),
+            ("es_MX.UTF-8", "LAYOUT_SPANISH_LATIN_AMERICA"),
+            ("pt_BR.UTF-8", "LAYOUT_PORTUGUESE_BRAZILIAN"),
+            ("nb_NO.UTF-8", "LAYOUT_NORWEGIAN"),
+            ("sv_SE", "LAYOUT_SWEDISH"),
+            ("en-gb", "LAYOUT_UNITED_KINGDOM"),
+        ] {
+            assert_eq!(layout_for_locale(locale), Some(*layout), "{}", locale);
+        }
+    }
+
+    #[test]
+    fn unparsable_locales_have_no_layout() {
+        for locale in &["C", "POSIX", "C.UTF-8", "", "ja_JP.UTF-8", "_GB"] {
+            assert_eq!(layout_for_locale(locale), None, "{}", locale);
+        }
+    }
+
+    #[test]
+    fn x11_layouts_are_the_aliases() {
+        assert_eq!(layout_for_x11("gb"), Some("LAYOUT_UNITED_KINGDOM"));
+        assert_eq!(layout_for_x11("us,de"), Some("LAYOUT_US_ENGLISH"));
+        assert_eq!(layout_for_x11("ch"), Some("LAYOUT_GERMAN_SWISS"));
+        assert_eq!(layout_for_x11("n/a"), None);
+        assert_eq!(layout_for_x11(""), None);
+    }
+}
@@ -0,0 +1,97 @@
+//! Holding modifiers between consecutive characters that are typed with them
+//!
+//! Each character is usually typed by pressing its key with its modifiers and then releasing
+//! everything, so typing "HELLO" presses and releases shift five times. Someone typing it holds
+//! shift down for the whole word instead, releasing each letter's key but not shift. This does the
+//! same, keeping the modifiers held in the release that ends a keystroke when the next keystroke is
+//! pressed with the same ones.
+
+use crate::{Keystroke, HID_PACKET_LEN, RELEASE_KEYS_HID_PACKET};
+
+use bytes::{BufMut, BytesMut};
+
+/// Keep the modifiers held between consecutive keystrokes that are pressed with the same ones,
+/// releasing only the keys. The last keystroke of a run still releases everything, so nothing is
+/// left held. This sends the same number of packets.
+pub fn hold(mut keystrokes: Vec<Keystroke>) -> Vec<Keystroke> {
+    for i in 1..keystrokes.len() {
+        let modifier = match keystrokes[i].packets.first() {
+            Some(&modifier) if modifier != 0 => modifier,
+            _ => continue,
+        };
+        let previous = &mut keystrokes[i - 1];
+        if !releases_after(previous, modifier) {
+            continue;
+        }
+        let end = previous.packets.len() - HID_PACKET_LEN;
+        let mut packets = BytesMut::with_capacity(previous.packets.len());
+        packets.put_slice(&previous.packets[..end]);
+        packets.put_u8(modifier);
+        packets.put_slice(&[0u8; HID_PACKET_LEN - 1]);
+        previous.packets = packets.freeze();
+    }
+    keystrokes
+}
+
+/// Whether the keystroke ends by releasing everything after a packet pressed with the modifiers
+fn releases_after(keystroke: &Keystroke, modifier: u8) -> bool {
+    let packets: Vec<&[u8]> = keystroke.packets.chunks(HID_PACKET_LEN).collect();
+    match packets[..] {
+        [.., press, release] => release == RELEASE_KEYS_HID_PACKET && press[0] == modifier,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::string_to_keystrokes;
+
+    const LAYOUT: &str = "LAYOUT_US_ENGLISH";
+
+    fn packets(keystrokes: &[Keystroke]) -> Vec<&[u8]> {
+        keystrokes
+            .iter()
+            .flat_map(|keystroke| keystroke.packets.chunks(HID_PACKET_LEN))
+            .collect()
+    }
+
+    #[test]
+    fn shift_is_held_across_a_run_of_capitals() {
+        let held = hold(string_to_keystrokes(LAYOUT, "HIi").unwrap());
+
+        assert_eq!(
+            packets(&held),
+            [
+                &[2, 0, 0x0b, 0, 0, 0, 0, 0][..],
+                &[2, 0, 0, 0, 0, 0, 0, 0],
+                &[2, 0, 0x0c, 0, 0, 0, 0, 0],
+                &[0, 0, 0, 0, 0, 0, 0, 0],
+                &[0, 0, 0x0c, 0, 0, 0, 0, 0],
+                &[0, 0, 0, 0, 0, 0, 0, 0],
+            ]
+        );
+        // Each character still types with the same keystroke
+        assert_eq!(
+            held.iter().map(|k| k.character).collect::<Vec<_>>(),
+            [Some('H'), Some('I'), Some('i')]
+        );
+    }
+
+    #[test]
+    fn different_modifiers_are_released_as_before() {
+        for text in &["hello", "Hello World", "a!b@", ""] {
+            let keystrokes = string_to_keystrokes(LAYOUT, text).unwrap();
+            let held = hold(keystrokes.clone());
+
+            assert_eq!(packets(&held).len(), packets(&keystrokes).len());
+            if let Some(last) = held.last() {
+                assert!(last.packets.ends_with(&RELEASE_KEYS_HID_PACKET));
+            }
+        }
+        // Lowercase letters have no modifiers to hold
+        let keystrokes = string_to_keystrokes(LAYOUT, "hello").unwrap();
+        assert_eq!(hold(keystrokes.clone()), keystrokes);
+    }
+}
@@ -0,0 +1,156 @@
+//! Boot protocol mouse reports, for a gadget that is also a mouse
+//!
+//! Each report is 4 bytes: the buttons held, the relative X and Y movement and the scroll wheel
+//! ticks, each a signed byte. [`Mouse`] keeps track of the buttons held so a move while a button is
+//! pressed drags with it.
+
+use crate::Error;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// The number of bytes in a boot protocol mouse HID packet
+pub const MOUSE_PACKET_LEN: usize = 4;
+
+/// The furthest a single report can move, or scroll, in each direction
+const MAX_STEP: i32 = 127;
+
+/// A mouse button, by its bit in the first byte of a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Left = 0x01,
+    Right = 0x02,
+    Middle = 0x04,
+}
+
+impl Button {
+    /// Parse a button name, `left`, `right` or `middle`, in any case.
+    pub fn parse(name: &str) -> Result<Button, Error> {
+        match name.to_lowercase().as_str() {
+            "left" => Ok(Button::Left),
+            "right" => Ok(Button::Right),
+            "middle" => Ok(Button::Middle),
+            _ => Err(Error::InvalidKeyName(name.to_string())),
+        }
+    }
+}
+
+/// The state of a mouse, producing the reports that change it
+#[derive(Debug, Default)]
+pub struct Mouse {
+    buttons: u8,
+}
+
+impl Mouse {
+    pub fn new() -> Self {
+        Mouse::default()
+    }
+
+    /// Whether any buttons are held
+    pub fn buttons_held(&self) -> bool {
+        self.buttons != 0
+    }
+
+    /// The reports moving the pointer by x and y, where positive y is down. Moves further than a
+    /// report can hold are split across several.
+    pub fn move_by(&self, x: i32, y: i32) -> Bytes {
+        self.steps(x, y, 0)
+    }
+
+    /// The reports scrolling the wheel by the number of ticks, where positive is up
+    pub fn scroll(&self, ticks: i32) -> Bytes {
+        self.steps(0, 0, ticks)
+    }
+
+    /// The report pressing the button, which stays held until it is released
+    pub fn press(&mut self, button: Button) -> Bytes {
+        self.buttons |= button as u8;
+        self.report(0, 0, 0)
+    }
+
+    /// The report releasing the button
+    pub fn release(&mut self, button: Button) -> Bytes {
+        self.buttons &= !(button as u8);
+        self.report(0, 0, 0)
+    }
+
+    /// The reports pressing and then releasing the button
+    pub fn click(&mut self, button: Button) -> Bytes {
+        let mut packets = BytesMut::with_capacity(MOUSE_PACKET_LEN * 2);
+        packets.put_slice(&self.press(button));
+        packets.put_slice(&self.release(button));
+        packets.freeze()
+    }
+
+    /// The reports clicking the button twice
+    pub fn double_click(&mut self, button: Button) -> Bytes {
+        let mut packets = BytesMut::with_capacity(MOUSE_PACKET_LEN * 4);
+        packets.put_slice(&self.click(button));
+        packets.put_slice(&self.click(button));
+        packets.freeze()
+    }
+
+    /// The report releasing every button
+    pub fn release_all(&mut self) -> Bytes {
+        self.buttons = 0;
+        self.report(0, 0, 0)
+    }
+
+    fn steps(&self, mut x: i32, mut y: i32, mut wheel: i32) -> Bytes {
+        let mut packets = BytesMut::new();
+        while x != 0 || y != 0 || wheel != 0 {
+            let step = |remaining: &mut i32| {
+                let step = (*remaining).clamp(-MAX_STEP, MAX_STEP);
+                *remaining -= step;
+                step as i8
+            };
+            let (dx, dy, dw) = (step(&mut x), step(&mut y), step(&mut wheel));
+            packets.reserve(MOUSE_PACKET_LEN);
+            packets.put_slice(&self.report(dx, dy, dw));
+        }
+        packets.freeze()
+    }
+
+    fn report(&self, x: i8, y: i8, wheel: i8) -> Bytes {
+        let mut packet = BytesMut::with_capacity(MOUSE_PACKET_LEN);
+        packet.put_u8(self.buttons);
+        packet.put_i8(x);
+        packet.put_i8(y);
+        packet.put_i8(wheel);
+        packet.freeze()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_moves_are_split() {
+        let mouse = Mouse::new();
+
+        assert_eq!(mouse.move_by(10, -5).to_vec(), vec![0, 10, 0xfb, 0]);
+        assert_eq!(
+            mouse.move_by(300, -20).to_vec(),
+            vec![0, 127, 0xec, 0, 0, 127, 0, 0, 0, 46, 0, 0]
+        );
+        assert!(mouse.move_by(0, 0).is_empty());
+        assert_eq!(mouse.scroll(-3).to_vec(), vec![0, 0, 0, 0xfd]);
+    }
+
+    #[test]
+    fn buttons_are_held_until_released() {
+        let mut mouse = Mouse::new();
+
+        assert_eq!(mouse.press(Button::Left).to_vec(), vec![1, 0, 0, 0]);
+        assert_eq!(mouse.move_by(5, 0).to_vec(), vec![1, 5, 0, 0]);
+        assert!(mouse.buttons_held());
+        assert_eq!(mouse.release(Button::Left).to_vec(), vec![0, 0, 0, 0]);
+        assert_eq!(
+            mouse.double_click(Button::Right).to_vec(),
+            vec![2, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert!(!mouse.buttons_held());
+        assert!(Button::parse("Middle").is_ok());
+        assert!(Button::parse("fourth").is_err());
+    }
+}
@@ -0,0 +1,364 @@
+//! Typing on this machine on Windows and macOS, by injecting key events with the operating
+//! system's input API, SendInput or CGEventPost, instead of sending HID packets to a gadget
+//!
+//! The operating system applies its own keyboard layout to the keys injected, so [`Native`] types
+//! the character of each keystroke rather than the layout's keys for it. On Windows ASCII is typed
+//! with the key the system layout has for it and anything else as Unicode, while macOS, which has
+//! no call finding the key for a character, types every character as Unicode. Keystrokes without a
+//! character, such as key combinations, and control characters such as Enter press the keys in
+//! their HID packets.
+
+use crate::writer::{key_changes, HidWriter, KeyChange};
+use crate::HID_PACKET_LEN;
+
+use std::io;
+
+/// A key event to inject
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Event {
+    /// A key by its key code, with the HID modifier bits held once it is pressed or released
+    Key {
+        code: u16,
+        pressed: bool,
+        modifiers: u8,
+    },
+    /// A character typed without a key
+    Unicode(char),
+}
+
+/// Types on this machine through the operating system's input API
+#[derive(Debug, Default)]
+pub struct Native {
+    report: [u8; HID_PACKET_LEN],
+    // The character the packets being written type, and whether it has been typed yet
+    character: Option<char>,
+    typed: bool,
+}
+
+impl Native {
+    pub fn new() -> Native {
+        Native::default()
+    }
+}
+
+impl HidWriter for Native {
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        if let Some(c) = self.character.filter(|c| !c.is_control()) {
+            // The character is typed once, in place of all of the layout's packets for it
+            if !self.typed {
+                self.typed = true;
+                platform::post(&platform::character_events(c))?;
+            }
+            return Ok(());
+        }
+
+        let mut modifiers = self.report[0];
+        let mut events = Vec::new();
+        for change in key_changes(&self.report, packet) {
+            let (code, pressed) = match change {
+                KeyChange::Modifier(bit, pressed) => {
+                    modifiers ^= 1 << bit;
+                    (platform::MODIFIER_KEYS[usize::from(bit)], pressed)
+                }
+                KeyChange::Usage(usage, pressed) => (usage_key(usage)?, pressed),
+            };
+            events.push(Event::Key {
+                code,
+                pressed,
+                modifiers,
+            });
+        }
+        platform::post(&events)?;
+
+        let len = packet.len().min(HID_PACKET_LEN);
+        self.report = [0; HID_PACKET_LEN];
+        self.report[..len].copy_from_slice(&packet[..len]);
+        Ok(())
+    }
+
+    fn start_keystroke(&mut self, character: Option<char>) {
+        self.character = character;
+        self.typed = false;
+    }
+}
+
+impl Drop for Native {
+    fn drop(&mut self) {
+        // Injected keys stay held until they are released
+        self.character = None;
+        let _ = self.write_packet(&[0; HID_PACKET_LEN]);
+    }
+}
+
+fn usage_key(usage: u8) -> io::Result<u16> {
+    platform::USAGE_KEYS
+        .get(usize::from(usage))
+        .copied()
+        .filter(|key| *key != platform::NO_KEY)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("HID usage {:#04x} has no key code on this system", usage),
+            )
+        })
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::Event;
+
+    use std::io;
+
+    // The virtual key codes of the modifier bits, from left ctrl to right GUI
+    pub const MODIFIER_KEYS: [u16; 8] = [0xa2, 0xa0, 0xa4, 0x5b, 0xa3, 0xa1, 0xa5, 0x5c];
+
+    // The virtual key code for each HID usage of the keyboard page, where 0 is a usage without a
+    // key
+    pub const NO_KEY: u16 = 0;
+    #[rustfmt::skip]
+    pub const USAGE_KEYS: [u16; 0x74] = [
+        0x0000, 0x0000, 0x0000, 0x0000, 0x0041, 0x0042, 0x0043, 0x0044, 0x0045, 0x0046,
+        0x0047, 0x0048, 0x0049, 0x004a, 0x004b, 0x004c, 0x004d, 0x004e, 0x004f, 0x0050,
+        0x0051, 0x0052, 0x0053, 0x0054, 0x0055, 0x0056, 0x0057, 0x0058, 0x0059, 0x005a,
+        0x0031, 0x0032, 0x0033, 0x0034, 0x0035, 0x0036, 0x0037, 0x0038, 0x0039, 0x0030,
+        0x000d, 0x001b, 0x0008, 0x0009, 0x0020, 0x00bd, 0x00bb, 0x00db, 0x00dd, 0x00dc,
+        0x00dc, 0x00ba, 0x00de, 0x00c0, 0x00bc, 0x00be, 0x00bf, 0x0014, 0x0070, 0x0071,
+        0x0072, 0x0073, 0x0074, 0x0075, 0x0076, 0x0077, 0x0078, 0x0079, 0x007a, 0x007b,
+        0x002c, 0x0091, 0x0013, 0x002d, 0x0024, 0x0021, 0x002e, 0x0023, 0x0022, 0x0027,
+        0x0025, 0x0028, 0x0026, 0x0090, 0x006f, 0x006a, 0x006d, 0x006b, 0x000d, 0x0061,
+        0x0062, 0x0063, 0x0064, 0x0065, 0x0066, 0x0067, 0x0068, 0x0069, 0x0060, 0x006e,
+        0x00e2, 0x005d, 0x0000, 0x0000, 0x007c, 0x007d, 0x007e, 0x007f, 0x0080, 0x0081,
+        0x0082, 0x0083, 0x0084, 0x0085, 0x0086, 0x0087,
+    ];
+
+    const INPUT_KEYBOARD: u32 = 1;
+    const KEYEVENTF_KEYUP: u32 = 0x0002;
+    const KEYEVENTF_UNICODE: u32 = 0x0004;
+    const VK_SHIFT: u16 = 0x10;
+    const VK_CONTROL: u16 = 0x11;
+    const VK_MENU: u16 = 0x12;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct KeybdInput {
+        vk: u16,
+        scan: u16,
+        flags: u32,
+        time: u32,
+        extra_info: usize,
+    }
+
+    // Only here for the size of the INPUT union
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct MouseInput {
+        dx: i32,
+        dy: i32,
+        mouse_data: u32,
+        flags: u32,
+        time: u32,
+        extra_info: usize,
+    }
+
+    #[repr(C)]
+    union InputData {
+        ki: KeybdInput,
+        mi: MouseInput,
+    }
+
+    #[repr(C)]
+    struct Input {
+        kind: u32,
+        data: InputData,
+    }
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn SendInput(count: u32, inputs: *const Input, size: i32) -> u32;
+        fn VkKeyScanW(c: u16) -> i16;
+    }
+
+    /// The key presses typing a character, with the system layout's key and shift state for ASCII
+    pub fn character_events(c: char) -> Vec<Event> {
+        let scan = if c.is_ascii() {
+            unsafe { VkKeyScanW(c as u16) }
+        } else {
+            -1
+        };
+        if scan == -1 {
+            return vec![Event::Unicode(c)];
+        }
+
+        let code = (scan & 0xff) as u16;
+        let modifiers: Vec<u16> = [(1, VK_SHIFT), (2, VK_CONTROL), (4, VK_MENU)]
+            .iter()
+            .filter(|(bit, _)| (scan >> 8) & bit != 0)
+            .map(|(_, key)| *key)
+            .collect();
+        let key = |code, pressed| Event::Key {
+            code,
+            pressed,
+            modifiers: 0,
+        };
+        let mut events: Vec<Event> = modifiers.iter().map(|m| key(*m, true)).collect();
+        events.push(key(code, true));
+        events.push(key(code, false));
+        events.extend(modifiers.iter().rev().map(|m| key(*m, false)));
+        events
+    }
+
+    pub fn post(events: &[Event]) -> io::Result<()> {
+        let keyboard = |vk, scan, flags| Input {
+            kind: INPUT_KEYBOARD,
+            data: InputData {
+                ki: KeybdInput {
+                    vk,
+                    scan,
+                    flags,
+                    time: 0,
+                    extra_info: 0,
+                },
+            },
+        };
+        let mut inputs = Vec::new();
+        for event in events {
+            match *event {
+                Event::Key { code, pressed, .. } => {
+                    let flags = if pressed { 0 } else { KEYEVENTF_KEYUP };
+                    inputs.push(keyboard(code, 0, flags));
+                }
+                Event::Unicode(c) => {
+                    let mut units = [0; 2];
+                    for unit in c.encode_utf16(&mut units) {
+                        inputs.push(keyboard(0, *unit, KEYEVENTF_UNICODE));
+                        inputs.push(keyboard(0, *unit, KEYEVENTF_UNICODE | KEYEVENTF_KEYUP));
+                    }
+                }
+            }
+        }
+        if inputs.is_empty() {
+            return Ok(());
+        }
+
+        let size = std::mem::size_of::<Input>() as i32;
+        let sent = unsafe { SendInput(inputs.len() as u32, inputs.as_ptr(), size) };
+        if sent as usize == inputs.len() {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::Event;
+
+    use std::io;
+    use std::os::raw::c_void;
+    use std::ptr;
+
+    // The key codes of the modifier bits, from left control to right command
+    pub const MODIFIER_KEYS: [u16; 8] = [0x3b, 0x38, 0x3a, 0x37, 0x3e, 0x3c, 0x3d, 0x36];
+
+    // The key code for each HID usage of the keyboard page, from the kVK constants in Carbon's
+    // Events.h. 0 is the A key, so a usage without a key is 0xffff.
+    pub const NO_KEY: u16 = 0xffff;
+    #[rustfmt::skip]
+    pub const USAGE_KEYS: [u16; 0x74] = [
+        0xffff, 0xffff, 0xffff, 0xffff, 0x0000, 0x000b, 0x0008, 0x0002, 0x000e, 0x0003,
+        0x0005, 0x0004, 0x0022, 0x0026, 0x0028, 0x0025, 0x002e, 0x002d, 0x001f, 0x0023,
+        0x000c, 0x000f, 0x0001, 0x0011, 0x0020, 0x0009, 0x000d, 0x0007, 0x0010, 0x0006,
+        0x0012, 0x0013, 0x0014, 0x0015, 0x0017, 0x0016, 0x001a, 0x001c, 0x0019, 0x001d,
+        0x0024, 0x0035, 0x0033, 0x0030, 0x0031, 0x001b, 0x0018, 0x0021, 0x001e, 0x002a,
+        0x002a, 0x0029, 0x0027, 0x0032, 0x002b, 0x002f, 0x002c, 0x0039, 0x007a, 0x0078,
+        0x0063, 0x0076, 0x0060, 0x0061, 0x0062, 0x0064, 0x0065, 0x006d, 0x0067, 0x006f,
+        0xffff, 0xffff, 0xffff, 0x0072, 0x0073, 0x0074, 0x0075, 0x0077, 0x0079, 0x007c,
+        0x007b, 0x007d, 0x007e, 0x0047, 0x004b, 0x0043, 0x004e, 0x0045, 0x004c, 0x0053,
+        0x0054, 0x0055, 0x0056, 0x0057, 0x0058, 0x0059, 0x005b, 0x005c, 0x0052, 0x0041,
+        0x000a, 0xffff, 0xffff, 0x0051, 0x0069, 0x006b, 0x0071, 0x006a, 0x0040, 0x004f,
+        0x0050, 0x005a, 0xffff, 0xffff, 0xffff, 0xffff,
+    ];
+
+    const HID_EVENT_TAP: u32 = 0;
+    // The CGEventFlags for control, shift, option and command, the same for left and right
+    const MODIFIER_FLAGS: [u64; 4] = [0x0004_0000, 0x0002_0000, 0x0008_0000, 0x0010_0000];
+
+    type CGEventRef = *mut c_void;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventCreateKeyboardEvent(source: *const c_void, key: u16, down: bool) -> CGEventRef;
+        fn CGEventKeyboardSetUnicodeString(event: CGEventRef, length: usize, string: *const u16);
+        fn CGEventSetFlags(event: CGEventRef, flags: u64);
+        fn CGEventPost(tap: u32, event: CGEventRef);
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRelease(cf: *const c_void);
+    }
+
+    /// The events typing a character, which is always Unicode as the key for it depends on the
+    /// system layout
+    pub fn character_events(c: char) -> Vec<Event> {
+        vec![Event::Unicode(c)]
+    }
+
+    pub fn post(events: &[Event]) -> io::Result<()> {
+        for event in events {
+            match *event {
+                Event::Key {
+                    code,
+                    pressed,
+                    modifiers,
+                } => {
+                    let flags = (0..8)
+                        .filter(|bit| modifiers & (1 << bit) != 0)
+                        .fold(0, |flags, bit| flags | MODIFIER_FLAGS[bit % 4]);
+                    post_key(code, pressed, flags, &[])?;
+                }
+                Event::Unicode(c) => {
+                    let mut units = [0; 2];
+                    let units = c.encode_utf16(&mut units);
+                    post_key(0, true, 0, units)?;
+                    post_key(0, false, 0, units)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn post_key(code: u16, pressed: bool, flags: u64, text: &[u16]) -> io::Result<()> {
+        let event = unsafe { CGEventCreateKeyboardEvent(ptr::null(), code, pressed) };
+        if event.is_null() {
+            return Err(io::Error::other("could not create a key event"));
+        }
+        unsafe {
+            if !text.is_empty() {
+                CGEventKeyboardSetUnicodeString(event, text.len(), text.as_ptr());
+            }
+            CGEventSetFlags(event, flags);
+            CGEventPost(HID_EVENT_TAP, event);
+            CFRelease(event);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usages_without_keys_are_errors() {
+        assert!(usage_key(0x04).is_ok());
+        let err = usage_key(0xa5).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "HID usage 0xa5 has no key code on this system"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Normalizing text before it is typed, so fewer of its characters are ones the layout can't type
+//!
+//! [`nfc`] composes characters written as a letter followed by combining accents, such as `e` and
+//! U+0301, into the single character a layout has a key for. [`Fold`] replaces typographic
+//! characters that no layout types, such as curly quotes and dashes from word processors, with
+//! their plain ASCII equivalents.
+//!
+//! A character map file adds to or overrides the replacements, as TOML or JSON, e.g.
+//!
+//! ```toml
+//! [fold]
+//! "—" = "-"
+//! "™" = "(TM)"
+//! ```
+
+use crate::{string_to_keystrokes, Error};
+
+use serde::Deserialize;
+use unicode_normalization::UnicodeNormalization;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The typographic characters replaced by [`Fold::ascii`]
+const ASCII_FOLD: &[(char, &str)] = &[
+    ('\u{2018}', "'"),
+    ('\u{2019}', "'"),
+    ('\u{201A}', "'"),
+    ('\u{201B}', "'"),
+    ('\u{2032}', "'"),
+    ('\u{201C}', "\""),
+    ('\u{201D}', "\""),
+    ('\u{201E}', "\""),
+    ('\u{201F}', "\""),
+    ('\u{2033}', "\""),
+    ('\u{00AB}', "\""),
+    ('\u{00BB}', "\""),
+    ('\u{2039}', "'"),
+    ('\u{203A}', "'"),
+    ('\u{2010}', "-"),
+    ('\u{2011}', "-"),
+    ('\u{2012}', "-"),
+    ('\u{2013}', "-"),
+    ('\u{2014}', "--"),
+    ('\u{2015}', "--"),
+    ('\u{2212}', "-"),
+    ('\u{2026}', "..."),
+    ('\u{2022}', "*"),
+    ('\u{00A0}', " "),
+    ('\u{2002}', " "),
+    ('\u{2003}', " "),
+    ('\u{2007}', " "),
+    ('\u{2009}', " "),
+    ('\u{200A}', " "),
+    ('\u{202F}', " "),
+    ('\u{200B}', ""),
+    ('\u{2060}', ""),
+    ('\u{FEFF}', ""),
+    ('\u{00AD}', ""),
+];
+
+/// Normalize the text to Unicode Normalization Form C, where characters are composed wherever
+/// they can be, e.g. `e` followed by a combining acute accent becomes `é`.
+pub fn nfc(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// Replaces characters with others, e.g. typographic quotes with plain ones
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fold {
+    replacements: HashMap<char, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CharMapFile {
+    #[serde(default)]
+    fold: HashMap<char, String>,
+}
+
+impl Fold {
+    /// Replace typographic quotes, dashes, the ellipsis and unusual spaces with their plain ASCII
+    /// equivalents, and leave out zero width characters and soft hyphens.
+    pub fn ascii() -> Self {
+        Fold {
+            replacements: ASCII_FOLD
+                .iter()
+                .map(|(c, replacement)| (*c, replacement.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Add the replacements in a character map file, parsed as JSON if it has a `.json` extension
+    /// and TOML otherwise, overriding any for the same characters.
+    pub fn with_char_map<P: AsRef<Path>>(mut self, path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let invalid = |e: String| Error::InvalidCharMap(format!("{}: {}", path.display(), e));
+        let text = fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let file: CharMapFile = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?
+        } else {
+            toml::from_str(&text).map_err(|e| invalid(e.to_string()))?
+        };
+        self.replacements.extend(file.fold);
+        Ok(self)
+    }
+
+    /// Replace the characters in the text that the layout can't type. Those it can type, such as
+    /// guillemets on a custom layout with keys for them, are kept.
+    pub fn apply(&self, layout_key: &str, text: &str) -> String {
+        let mut folded = String::with_capacity(text.len());
+        let mut buf = [0u8; 4];
+        for c in text.chars() {
+            match self.replacements.get(&c) {
+                Some(replacement)
+                    if string_to_keystrokes(layout_key, c.encode_utf8(&mut buf)).is_err() =>
+                {
+                    folded.push_str(replacement)
+                }
+                _ => folded.push(c),
+            }
+        }
+        folded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = "LAYOUT_US_ENGLISH";
+
+    #[test]
+    fn decomposed_characters_are_composed() {
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+
+        assert_eq!(nfc(decomposed), composed);
+        assert_eq!(nfc(composed), composed);
+        // The dot below comes before the acute accent, whatever order they are in
+        assert_eq!(nfc("a\u{301}\u{323}"), "\u{1ea1}\u{301}");
+        assert_eq!(nfc("\u{1100}\u{1161}\u{11a8}"), "\u{ac01}");
+        assert_eq!(nfc("\u{ac01}"), "\u{ac01}");
+        // Singletons such as the Ångström sign become the letter
+        assert_eq!(nfc("\u{212b}"), "\u{c5}");
+    }
+
+    #[test]
+    fn typographic_characters_are_folded() {
+        let fold = Fold::ascii();
+
+        assert_eq!(
+            fold.apply(
+                LAYOUT,
+                "\u{201c}It\u{2019}s\u{a0}fine\u{201d} \u{2014} a\u{2026}"
+            ),
+            "\"It's fine\" -- a..."
+        );
+        // A layout that types guillemets keeps them
+        let guillemets = crate::custom::CustomLayout::from_toml(
+            "name = \"GUILLEMETS\"\ninherits = \"LAYOUT_US_ENGLISH\"\n\
+             [[keys]]\nchar = \"\u{ab}\"\nkey = 0x2f\n\
+             [[keys]]\nchar = \"\u{bb}\"\nkey = 0x30\n",
+        )
+        .unwrap();
+        let guillemets = crate::custom::register(guillemets).unwrap();
+        assert_eq!(fold.apply(guillemets, "\u{ab}oui\u{bb}"), "\u{ab}oui\u{bb}");
+        assert_eq!(fold.apply(LAYOUT, "\u{ab}oui\u{bb}"), "\"oui\"");
+    }
+
+    #[test]
+    fn char_maps_override_the_fold() {
+        let mut file = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        std::io::Write::write_all(
+            &mut file,
+            "[fold]\n\"\u{2014}\" = \"-\"\n\"\u{2122}\" = \"(TM)\"\n".as_bytes(),
+        )
+        .unwrap();
+
+        let fold = Fold::ascii().with_char_map(file.path()).unwrap();
+
+        assert_eq!(fold.apply(LAYOUT, "a\u{2014}b\u{2122}\u{2019}"), "a-b(TM)'");
+        assert!(matches!(
+            Fold::ascii().with_char_map("missing.toml"),
+            Err(Error::InvalidCharMap(_))
+        ));
+    }
+}
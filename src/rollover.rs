@@ -0,0 +1,179 @@
+//! Typing several characters with each HID packet, using the 6 key rollover of a keyboard report
+//!
+//! A keystroke usually takes two packets, pressing its key and releasing it. Consecutive
+//! characters typed with the same modifiers and different keys can instead be pressed together in
+//! one packet and released together in the next, which hosts read as the keys pressed in the
+//! order they are in the packet. That types up to 6 characters with two packets.
+
+use crate::{Keystroke, HID_PACKET_LEN, RELEASE_KEYS_HID_PACKET};
+
+use bytes::{BufMut, BytesMut};
+
+/// The number of keys a keyboard packet can press at once
+pub const ROLLOVER: usize = 6;
+
+/// Pack the keystrokes into as few as possible. A character typed with a single key press is
+/// packed with the ones after it that use the same modifiers, until a key comes up again, as it
+/// has to be released before it can be pressed again. A packed keystroke types several characters,
+/// so it has none. Other keystrokes, such as named keys or dead key sequences, are kept as they
+/// are.
+pub fn pack(keystrokes: Vec<Keystroke>) -> Vec<Keystroke> {
+    let mut packed = Vec::with_capacity(keystrokes.len());
+    // The keystrokes to press together, with their modifiers and key
+    let mut group: Vec<(Keystroke, (u8, u8))> = Vec::with_capacity(ROLLOVER);
+    for keystroke in keystrokes {
+        let (modifier, key) = match single_press(&keystroke) {
+            Some(press) => press,
+            None => {
+                flush(&mut group, &mut packed);
+                packed.push(keystroke);
+                continue;
+            }
+        };
+        let fits = group.len() < ROLLOVER
+            && group
+                .iter()
+                .all(|(_, press)| press.0 == modifier && press.1 != key);
+        if !fits {
+            flush(&mut group, &mut packed);
+        }
+        group.push((keystroke, (modifier, key)));
+    }
+    flush(&mut group, &mut packed);
+    packed
+}
+
+/// The modifiers and key of a character typed by pressing a key and releasing it
+fn single_press(keystroke: &Keystroke) -> Option<(u8, u8)> {
+    keystroke.character?;
+    match keystroke.packets.chunks(HID_PACKET_LEN).collect::<Vec<_>>()[..] {
+        [press, release]
+            if release == RELEASE_KEYS_HID_PACKET
+                && press[2] != 0
+                && press[3..].iter().all(|&b| b == 0) =>
+        {
+            Some((press[0], press[2]))
+        }
+        _ => None,
+    }
+}
+
+/// Add the keystrokes of the group, pressed together if there are several
+fn flush(group: &mut Vec<(Keystroke, (u8, u8))>, packed: &mut Vec<Keystroke>) {
+    if group.len() < 2 {
+        packed.extend(group.drain(..).map(|(keystroke, _)| keystroke));
+        return;
+    }
+    let mut press = [0u8; HID_PACKET_LEN];
+    press[0] = (group[0].1).0;
+    for (i, (_, (_, key))) in group.iter().enumerate() {
+        press[2 + i] = *key;
+    }
+    let mut packets = BytesMut::with_capacity(HID_PACKET_LEN * 2);
+    packets.put_slice(&press);
+    packets.put_slice(&RELEASE_KEYS_HID_PACKET);
+    packed.push(Keystroke {
+        character: None,
+        packets: packets.freeze(),
+    });
+    group.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{key_characters, string_to_keystrokes};
+
+    use bytes::Bytes;
+
+    const LAYOUT: &str = "LAYOUT_US_ENGLISH";
+
+    /// What a host types from the packets, pressing the keys that weren't pressed in the previous
+    /// packet in the order they are in the packet
+    fn host_types(layout: &str, keystrokes: &[Keystroke]) -> String {
+        let characters = key_characters(layout).unwrap();
+        let mut pressed: Vec<u8> = Vec::new();
+        let mut typed = String::new();
+        for packet in keystrokes
+            .iter()
+            .flat_map(|keystroke| keystroke.packets.chunks(HID_PACKET_LEN))
+        {
+            let keys: Vec<u8> = packet[2..].iter().copied().filter(|&k| k != 0).collect();
+            for key in keys.iter().filter(|key| !pressed.contains(key)) {
+                typed.push(characters[&(packet[0], *key)]);
+            }
+            pressed = keys;
+        }
+        typed
+    }
+
+    fn packet_count(keystrokes: &[Keystroke]) -> usize {
+        keystrokes
+            .iter()
+            .map(|keystroke| keystroke.packets.len() / HID_PACKET_LEN)
+            .sum()
+    }
+
+    #[test]
+    fn packed_keystrokes_type_the_same_text() {
+        for text in &[
+            "abcdefghijklmnopqrstuvwxyz",
+            "Hello, World!",
+            "all...  good\tthings\n",
+            "aaAAaa",
+            "The QUICK brown fox jumps over the lazy dog 1234567890",
+        ] {
+            let keystrokes = string_to_keystrokes(LAYOUT, text).unwrap();
+            let packed = pack(keystrokes.clone());
+
+            assert_eq!(host_types(LAYOUT, &packed), *text);
+            assert!(packet_count(&packed) <= packet_count(&keystrokes));
+        }
+    }
+
+    #[test]
+    fn up_to_six_keys_are_pressed_together() {
+        let packed = pack(string_to_keystrokes(LAYOUT, "abcdefgh").unwrap());
+
+        assert_eq!(packed.len(), 2);
+        assert_eq!(packed[0].packets[..8], [0, 0, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(packed[0].packets[8..], RELEASE_KEYS_HID_PACKET);
+        assert_eq!(packed[1].packets[..8], [0, 0, 0x0a, 0x0b, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn repeats_and_modifier_changes_start_a_new_packet() {
+        let packed = pack(string_to_keystrokes(LAYOUT, "ll").unwrap());
+        assert_eq!(packed, string_to_keystrokes(LAYOUT, "ll").unwrap());
+
+        // H is typed with shift, and o comes after the repeated l
+        let packed = pack(string_to_keystrokes(LAYOUT, "Hello").unwrap());
+        let presses: Vec<&[u8]> = packed
+            .iter()
+            .map(|keystroke| &keystroke.packets[..HID_PACKET_LEN])
+            .collect();
+        assert_eq!(
+            presses,
+            [
+                &[2, 0, 0x0b, 0, 0, 0, 0, 0][..],
+                &[0, 0, 0x08, 0x0f, 0, 0, 0, 0],
+                &[0, 0, 0x0f, 0x12, 0, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn other_keystrokes_are_kept() {
+        let mut keystrokes = string_to_keystrokes(LAYOUT, "ab").unwrap();
+        let backspace = Keystroke {
+            character: None,
+            packets: Bytes::from(&[0, 0, 0x2a, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0][..]),
+        };
+        keystrokes.insert(1, backspace);
+
+        let packed = pack(keystrokes.clone());
+
+        assert_eq!(packed, keystrokes);
+    }
+}
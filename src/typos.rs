@@ -0,0 +1,237 @@
+//! Occasional typos, each a neighbouring key pressed by mistake and then deleted with Backspace
+//! before the intended key, so typing looks like a person's in recordings
+//!
+//! Which keys neighbour each other is taken from where the keys are on a keyboard. The layout
+//! decides what each of them types, so a typo is always a character the layout has on a key next
+//! to the intended one.
+
+use crate::{key_characters, Error, Keystroke, HID_PACKET_LEN, RELEASE_KEYS_HID_PACKET};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use std::collections::HashMap;
+
+const BACKSPACE: u8 = 0x2a;
+
+/// The keys of each row of a keyboard, by usage, and how far the row is from the left edge in
+/// key widths
+const ROWS: [(f32, &[u8]); 4] = [
+    (
+        0.0,
+        &[
+            0x35, 0x1e, 0x1f, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x2d, 0x2e,
+        ],
+    ),
+    (
+        1.5,
+        &[
+            0x14, 0x1a, 0x08, 0x15, 0x17, 0x1c, 0x18, 0x0c, 0x12, 0x13, 0x2f, 0x30, 0x31,
+        ],
+    ),
+    (
+        1.75,
+        &[
+            0x04, 0x16, 0x07, 0x09, 0x0a, 0x0b, 0x0d, 0x0e, 0x0f, 0x33, 0x34, 0x32,
+        ],
+    ),
+    (
+        1.25,
+        &[
+            0x64, 0x1d, 0x1b, 0x06, 0x19, 0x05, 0x11, 0x10, 0x36, 0x37, 0x38,
+        ],
+    ),
+];
+
+/// Adds typos to keystrokes, each character having the same chance of one
+#[derive(Debug, Clone)]
+pub struct Typos {
+    rate: f64,
+    // The characters the layout types with each key and modifiers
+    characters: HashMap<(u8, u8), char>,
+    rng: StdRng,
+}
+
+impl Typos {
+    /// Add typos to the characters typed with the layout, with the chance of each having one from
+    /// 0 to 1
+    pub fn new(layout_key: &str, rate: f64) -> Result<Self, Error> {
+        Ok(Typos {
+            rate: rate.clamp(0.0, 1.0),
+            characters: key_characters(layout_key)?,
+            rng: StdRng::from_entropy(),
+        })
+    }
+
+    /// Seed the random typos so the same ones are made on every run
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Add typos to the keystrokes. Only characters typed with a single key press can have one.
+    pub fn add(&mut self, keystrokes: Vec<Keystroke>) -> Vec<Keystroke> {
+        let mut result = Vec::with_capacity(keystrokes.len());
+        for keystroke in keystrokes {
+            if let Some(typo) = self.typo(&keystroke) {
+                result.push(typo);
+                result.push(Keystroke {
+                    character: None,
+                    packets: press(0, BACKSPACE),
+                });
+            }
+            result.push(keystroke);
+        }
+        result
+    }
+
+    /// The keystroke of a neighbouring key pressed instead of the keystroke's, sometimes
+    fn typo(&mut self, keystroke: &Keystroke) -> Option<Keystroke> {
+        keystroke.character?;
+        let (modifier, key) = match keystroke.packets.chunks(HID_PACKET_LEN).collect::<Vec<_>>()[..]
+        {
+            [press, release]
+                if release == RELEASE_KEYS_HID_PACKET && press[3..].iter().all(|&b| b == 0) =>
+            {
+                (press[0], press[2])
+            }
+            _ => return None,
+        };
+        if !self.rng.gen_bool(self.rate) {
+            return None;
+        }
+
+        let typos: Vec<(u8, char)> = neighbours(key)
+            .into_iter()
+            .filter_map(|key| Some((key, *self.characters.get(&(modifier, key))?)))
+            .collect();
+        let (key, c) = *typos.choose(&mut self.rng)?;
+        Some(Keystroke {
+            character: Some(c),
+            packets: press(modifier, key),
+        })
+    }
+}
+
+/// Whether the keystroke is a Backspace on its own, such as the one deleting each typo
+pub fn is_correction(keystroke: &Keystroke) -> bool {
+    keystroke.character.is_none() && keystroke.packets == press(0, BACKSPACE)
+}
+
+/// The keys next to the key, on its row and the rows above and below
+fn neighbours(key: u8) -> Vec<u8> {
+    let position = |key| {
+        ROWS.iter().enumerate().find_map(|(row, (offset, keys))| {
+            let column = keys.iter().position(|&other| other == key)?;
+            Some((row, offset + column as f32))
+        })
+    };
+    let (row, x) = match position(key) {
+        Some(position) => position,
+        None => return Vec::new(),
+    };
+    ROWS.iter()
+        .flat_map(|(_, keys)| keys.iter().copied())
+        .filter(|&other| other != key)
+        .filter(|&other| {
+            let (other_row, other_x) = position(other).expect("keys are in a row");
+            match other_row.abs_diff(row) {
+                0 => (other_x - x).abs() <= 1.0,
+                1 => (other_x - x).abs() < 1.0,
+                _ => false,
+            }
+        })
+        .collect()
+}
+
+fn press(modifier: u8, key: u8) -> Bytes {
+    let mut packets = BytesMut::with_capacity(HID_PACKET_LEN * 2);
+    packets.put_slice(&[modifier, 0, key, 0, 0, 0, 0, 0]);
+    packets.put_slice(&RELEASE_KEYS_HID_PACKET);
+    packets.freeze()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::string_to_keystrokes;
+
+    const LAYOUT: &str = "LAYOUT_US_ENGLISH";
+    const TEXT: &str = "The quick brown fox jumps over the lazy dog, 1234 times!";
+
+    /// The text a host types from the keystrokes, with Backspace deleting a character
+    fn typed(keystrokes: &[Keystroke]) -> String {
+        let characters = key_characters(LAYOUT).unwrap();
+        let mut text = String::new();
+        for keystroke in keystrokes {
+            for packet in keystroke.packets.chunks(HID_PACKET_LEN) {
+                match packet[2] {
+                    0 => {}
+                    BACKSPACE => {
+                        text.pop();
+                    }
+                    key => text.push(characters[&(packet[0], key)]),
+                }
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn corrected_typos_type_the_same_text() {
+        let keystrokes = string_to_keystrokes(LAYOUT, TEXT).unwrap();
+        let mut typos = Typos::new(LAYOUT, 0.3).unwrap().with_seed(3);
+
+        let with_typos = typos.add(keystrokes.clone());
+
+        let corrections = with_typos.len() - keystrokes.len();
+        assert!(corrections > 0);
+        assert_eq!(corrections % 2, 0);
+        assert_eq!(typed(&with_typos), TEXT);
+        assert!(with_typos
+            .windows(2)
+            .filter(|pair| is_correction(&pair[1]))
+            .all(|pair| pair[0].character.is_some()));
+    }
+
+    #[test]
+    fn typos_are_reproducible_and_never_without_a_rate() {
+        let keystrokes = string_to_keystrokes(LAYOUT, TEXT).unwrap();
+
+        let first = Typos::new(LAYOUT, 0.5)
+            .unwrap()
+            .with_seed(7)
+            .add(keystrokes.clone());
+        let second = Typos::new(LAYOUT, 0.5)
+            .unwrap()
+            .with_seed(7)
+            .add(keystrokes.clone());
+        assert_eq!(first, second);
+
+        let none = Typos::new(LAYOUT, 0.0).unwrap().add(keystrokes.clone());
+        assert_eq!(none, keystrokes);
+    }
+
+    #[test]
+    fn typos_are_on_neighbouring_keys() {
+        let mut neighbours = neighbours(0x0b);
+        neighbours.sort_unstable();
+        // H is next to G and J, Y and U above, and B and N below
+        assert_eq!(neighbours, vec![0x05, 0x0a, 0x0d, 0x11, 0x18, 0x1c]);
+
+        // Capitals are typed with the same Shift
+        let keystrokes = string_to_keystrokes(LAYOUT, "HHHH").unwrap();
+        let with_typos = Typos::new(LAYOUT, 1.0)
+            .unwrap()
+            .with_seed(1)
+            .add(keystrokes);
+        assert_eq!(with_typos.len(), 12);
+        assert!(with_typos
+            .iter()
+            .filter_map(|keystroke| keystroke.character)
+            .all(|c| c.is_uppercase()));
+    }
+}
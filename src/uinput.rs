@@ -0,0 +1,234 @@
+//! A virtual keyboard on this machine, created with Linux's uinput, so the HID packets for a
+//! layout can be typed without a USB gadget
+//!
+//! [`Uinput`] is a [`HidWriter`], turning each HID packet into the key presses and releases that
+//! change the keys held to the ones in the packet.
+
+use crate::writer::{key_changes, HidWriter, KeyChange};
+use crate::HID_PACKET_LEN;
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// The uinput device file on most systems
+pub const UINPUT_PATH: &str = "/dev/uinput";
+
+const DEVICE_NAME: &str = "kbsim virtual keyboard";
+// How long the desktop takes to start listening to a new input device, before which key presses
+// are lost
+const DEVICE_SETTLE_TIME: Duration = Duration::from_millis(200);
+
+// linux/input-event-codes.h
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const SYN_REPORT: u16 = 0;
+const BUS_VIRTUAL: u16 = 0x06;
+
+// linux/uinput.h
+const UI_DEV_CREATE: u64 = 0x5501;
+const UI_DEV_DESTROY: u64 = 0x5502;
+const UI_DEV_SETUP: u64 = 0x405c_5503;
+const UI_SET_EVBIT: u64 = 0x4004_5564;
+const UI_SET_KEYBIT: u64 = 0x4004_5565;
+
+// The Linux key codes of the modifier bits, from left ctrl to right GUI
+const MODIFIER_KEYS: [u16; 8] = [29, 42, 56, 125, 97, 54, 100, 126];
+
+// The Linux key code for each HID usage of the keyboard page, from hid_keyboard in the kernel's
+// drivers/hid/hid-input.c, where 0 is a usage without a key
+#[rustfmt::skip]
+const USAGE_KEYS: [u16; 0x92] = [
+      0,   0,   0,   0,  30,  48,  46,  32,  18,  33,  34,  35,  23,  36,  37,  38,
+     50,  49,  24,  25,  16,  19,  31,  20,  22,  47,  17,  45,  21,  44,   2,   3,
+      4,   5,   6,   7,   8,   9,  10,  11,  28,   1,  14,  15,  57,  12,  13,  26,
+     27,  43,  43,  39,  40,  41,  51,  52,  53,  58,  59,  60,  61,  62,  63,  64,
+     65,  66,  67,  68,  87,  88,  99,  70, 119, 110, 102, 104, 111, 107, 109, 106,
+    105, 108, 103,  69,  98,  55,  74,  78,  96,  79,  80,  81,  75,  76,  77,  71,
+     72,  73,  82,  83,  86, 127, 116, 117, 183, 184, 185, 186, 187, 188, 189, 190,
+    191, 192, 193, 194, 134, 138, 130, 132, 128, 129, 131, 137, 133, 135, 136, 113,
+    115, 114,   0,   0,   0, 121,   0,  89,  93, 124,  92,  94,  95,   0,   0,   0,
+    122, 123,
+];
+
+/// A virtual keyboard created with uinput, which is destroyed when this is dropped
+pub struct Uinput {
+    file: File,
+    report: [u8; HID_PACKET_LEN],
+}
+
+impl Uinput {
+    /// Create a virtual keyboard with the uinput device file, usually [`UINPUT_PATH`]. The error
+    /// explains how to get permission to use the file when that is the problem.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Uinput> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .map_err(|e| open_error(path, e))?;
+        let fd = file.as_raw_fd();
+
+        ioctl(fd, UI_SET_EVBIT, libc::c_ulong::from(EV_KEY))?;
+        for key in MODIFIER_KEYS.iter().chain(USAGE_KEYS.iter()) {
+            if *key != 0 {
+                ioctl(fd, UI_SET_KEYBIT, libc::c_ulong::from(*key))?;
+            }
+        }
+
+        // All zeroes is a valid uinput_setup, before the fields that matter are set
+        let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+        setup.id.bustype = BUS_VIRTUAL;
+        for (c, byte) in setup.name.iter_mut().zip(DEVICE_NAME.bytes()) {
+            *c = byte as libc::c_char;
+        }
+        ioctl(fd, UI_DEV_SETUP, &setup as *const _ as libc::c_ulong)?;
+        ioctl(fd, UI_DEV_CREATE, 0)?;
+        thread::sleep(DEVICE_SETTLE_TIME);
+
+        Ok(Uinput {
+            file,
+            report: [0; HID_PACKET_LEN],
+        })
+    }
+
+    fn write_event(&mut self, kind: u16, code: u16, value: i32) -> io::Result<()> {
+        // All zeroes is a valid input_event, and the kernel fills in the time
+        let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+        event.type_ = kind;
+        event.code = code;
+        event.value = value;
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &event as *const _ as *const u8,
+                std::mem::size_of::<libc::input_event>(),
+            )
+        };
+        self.file.write_all(bytes)
+    }
+}
+
+impl HidWriter for Uinput {
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        for (key, pressed) in key_events(&self.report, packet)? {
+            self.write_event(EV_KEY, key, i32::from(pressed))?;
+        }
+        self.write_event(EV_SYN, SYN_REPORT, 0)?;
+
+        let len = packet.len().min(HID_PACKET_LEN);
+        self.report = [0; HID_PACKET_LEN];
+        self.report[..len].copy_from_slice(&packet[..len]);
+        Ok(())
+    }
+}
+
+impl Drop for Uinput {
+    fn drop(&mut self) {
+        let _ = ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY, 0);
+    }
+}
+
+/// The Linux key codes released and pressed to go from the keys held in one HID packet to the
+/// keys in the next
+fn key_events(previous: &[u8], packet: &[u8]) -> io::Result<Vec<(u16, bool)>> {
+    key_changes(previous, packet)
+        .into_iter()
+        .map(|change| match change {
+            KeyChange::Modifier(bit, pressed) => Ok((MODIFIER_KEYS[usize::from(bit)], pressed)),
+            KeyChange::Usage(usage, pressed) => Ok((usage_key(usage)?, pressed)),
+        })
+        .collect()
+}
+
+fn usage_key(usage: u8) -> io::Result<u16> {
+    USAGE_KEYS
+        .get(usize::from(usage))
+        .copied()
+        .filter(|key| *key != 0)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("HID usage {:#04x} has no Linux key code", usage),
+            )
+        })
+}
+
+fn ioctl(fd: libc::c_int, request: u64, arg: libc::c_ulong) -> io::Result<()> {
+    // The request numbers are all small enough for the narrower request type some libcs use
+    if unsafe { libc::ioctl(fd, request as _, arg) } < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Explain how to get access to the uinput device when it can't be opened
+fn open_error(path: &Path, e: io::Error) -> io::Error {
+    let hint = match e.kind() {
+        io::ErrorKind::PermissionDenied => format!(
+            "add a udev rule such as KERNEL==\"uinput\", GROUP=\"input\", MODE=\"0660\" to \
+             /etc/udev/rules.d/99-uinput.rules and add your user to the input group, or run as \
+             root, to be allowed to write to {}",
+            path.display()
+        ),
+        io::ErrorKind::NotFound => "load the uinput kernel module with modprobe uinput".to_string(),
+        _ => return e,
+    };
+    io::Error::new(e.kind(), format!("{}: {}", e, hint))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: u16 = 30;
+    const KEY_B: u16 = 48;
+    const KEY_LEFTSHIFT: u16 = 42;
+
+    #[test]
+    fn modifiers_are_pressed_before_keys_and_released_after() {
+        let shift_a = [0x02, 0, 0x04, 0, 0, 0, 0, 0];
+
+        assert_eq!(
+            key_events(&[0; 8], &shift_a).unwrap(),
+            vec![(KEY_LEFTSHIFT, true), (KEY_A, true)]
+        );
+        assert_eq!(
+            key_events(&shift_a, &[0; 8]).unwrap(),
+            vec![(KEY_A, false), (KEY_LEFTSHIFT, false)]
+        );
+    }
+
+    #[test]
+    fn only_changed_keys_have_events() {
+        let a = [0, 0, 0x04, 0, 0, 0, 0, 0];
+        let a_b = [0, 0, 0x04, 0x05, 0, 0, 0, 0];
+        let b = [0, 0, 0x05, 0, 0, 0, 0, 0];
+
+        assert_eq!(key_events(&a, &a_b).unwrap(), vec![(KEY_B, true)]);
+        assert_eq!(key_events(&a_b, &b).unwrap(), vec![(KEY_A, false)]);
+        assert_eq!(key_events(&b, &b).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn usages_without_keys_are_errors() {
+        let err = key_events(&[0; 8], &[0, 0, 0xa5, 0, 0, 0, 0, 0]).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "HID usage 0xa5 has no Linux key code");
+    }
+
+    #[test]
+    fn missing_device_explains_how_to_load_it() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let err = Uinput::create(dir.path().join("uinput")).err().unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("modprobe uinput"), "{}", err);
+    }
+}
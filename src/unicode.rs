@@ -0,0 +1,358 @@
+//! Typing characters a layout has no key for by their code point, using the Unicode input of IBus
+//! and GTK on Linux desktops: Ctrl+Shift+U, the code point in hex, then Enter or space, or
+//! Alt+Numpad input on Windows: holding Alt while the code is typed on the numpad
+
+use crate::keys::{Chord, MODIFIER_LEFT_ALT};
+use crate::{
+    string_to_hid_packets, string_to_keys_and_modifiers, Error, Keystroke, HID_PACKET_LEN,
+    RELEASE_KEYS_HID_PACKET,
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+// The usages of the numpad keys, which are 1 to 9 followed by 0
+const KEYPAD_1: u8 = 0x59;
+const KEYPAD_0: u8 = 0x62;
+const KEYPAD_PLUS: u8 = 0x57;
+
+const ALT_HID_PACKET: [u8; HID_PACKET_LEN] = [MODIFIER_LEFT_ALT, 0, 0, 0, 0, 0, 0, 0];
+
+/// The characters of Windows-1252 from 0x80 to 0x9F, which Unicode has elsewhere. The rest of the
+/// code page is the same as the first 256 code points.
+const WINDOWS_1252: [Option<char>; 32] = [
+    Some('€'),
+    None,
+    Some('‚'),
+    Some('ƒ'),
+    Some('„'),
+    Some('…'),
+    Some('†'),
+    Some('‡'),
+    Some('ˆ'),
+    Some('‰'),
+    Some('Š'),
+    Some('‹'),
+    Some('Œ'),
+    None,
+    Some('Ž'),
+    None,
+    None,
+    Some('‘'),
+    Some('’'),
+    Some('“'),
+    Some('”'),
+    Some('•'),
+    Some('–'),
+    Some('—'),
+    Some('˜'),
+    Some('™'),
+    Some('š'),
+    Some('›'),
+    Some('œ'),
+    None,
+    Some('ž'),
+    Some('Ÿ'),
+];
+
+/// How Alt+Numpad input types the characters that aren't in Windows-1252, which are typed as
+/// Alt+0 and their byte in the code page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AltNumpad {
+    /// Type their code point in hex after the numpad +, which Windows only accepts with
+    /// `EnableHexNumpad` set in the registry. Otherwise the code point is typed in decimal, which
+    /// only some applications, such as WordPad, accept.
+    pub hex: bool,
+    /// Type characters above U+FFFF as the two halves of their UTF-16 surrogate pair, which some
+    /// applications combine, instead of failing with [`Error::NeedsSurrogatePair`]
+    pub surrogate_pairs: bool,
+}
+
+/// The key pressed after the code point typed with Ctrl+Shift+U, which both IBus and GTK take as
+/// the end of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IbusCommit {
+    /// The usual key, which also reaches an application that isn't using the input method
+    #[default]
+    Enter,
+    /// For when an Enter reaching an application that isn't using the input method would submit
+    /// a form or send a message
+    Space,
+}
+
+/// The keystroke typing the character with Ctrl+Shift+U, ending the code point with Enter. The
+/// `u` and the hex digits are looked up in the layout, and if it can't type them the error is
+/// [`Error::InvalidCharacter`] for the character itself.
+pub fn ibus_keystroke(layout_key: &str, c: char) -> Result<Keystroke, Error> {
+    ibus_keystroke_with(layout_key, c, IbusCommit::Enter)
+}
+
+/// The keystroke typing the character with Ctrl+Shift+U, ending the code point with the given key
+pub fn ibus_keystroke_with(
+    layout_key: &str,
+    c: char,
+    commit: IbusCommit,
+) -> Result<Keystroke, Error> {
+    let unsupported = |e| match e {
+        Error::InvalidCharacter(_) => Error::InvalidCharacter(c),
+        e => e,
+    };
+    let start = Chord::parse(layout_key, "ctrl+shift+u").map_err(unsupported)?;
+    let digits =
+        string_to_hid_packets(layout_key, &format!("{:x}", u32::from(c))).map_err(unsupported)?;
+    let end = match commit {
+        IbusCommit::Enter => Chord::parse(layout_key, "enter")?,
+        IbusCommit::Space => Chord::parse(layout_key, "space")?,
+    };
+
+    let mut packets = BytesMut::with_capacity(digits.len() + start.to_hid_packets().len() * 2);
+    packets.put_slice(&start.to_hid_packets());
+    packets.put_slice(&digits);
+    packets.put_slice(&end.to_hid_packets());
+    Ok(Keystroke {
+        character: Some(c),
+        packets: packets.freeze(),
+    })
+}
+
+/// The keystroke typing the character with Alt+Numpad, which needs Num Lock on. The layout is
+/// only used for the letters of hex codes, and if it can't type them the error is
+/// [`Error::InvalidCharacter`] for the character itself.
+pub fn alt_numpad_keystroke(
+    layout_key: &str,
+    c: char,
+    options: AltNumpad,
+) -> Result<Keystroke, Error> {
+    let mut packets = Vec::new();
+    if let Some(byte) = windows_1252(c) {
+        packets.extend_from_slice(&alt_code(&format!("0{}", byte)));
+    } else if u32::from(c) > 0xFFFF && !options.surrogate_pairs {
+        return Err(Error::NeedsSurrogatePair(c));
+    } else {
+        for unit in c.encode_utf16(&mut [0; 2]) {
+            if options.hex {
+                packets.extend_from_slice(&alt_hex_code(layout_key, *unit).map_err(
+                    |e| match e {
+                        Error::InvalidCharacter(_) => Error::InvalidCharacter(c),
+                        e => e,
+                    },
+                )?);
+            } else {
+                packets.extend_from_slice(&alt_code(&unit.to_string()));
+            }
+        }
+    }
+    Ok(Keystroke {
+        character: Some(c),
+        packets: Bytes::from(packets),
+    })
+}
+
+/// The byte of the character in Windows-1252
+fn windows_1252(c: char) -> Option<u8> {
+    match u32::from(c) {
+        code @ (0..=0x7F | 0xA0..=0xFF) => Some(code as u8),
+        _ => WINDOWS_1252
+            .iter()
+            .position(|&other| other == Some(c))
+            .map(|idx| 0x80 + idx as u8),
+    }
+}
+
+/// The packets holding Alt while the decimal digits are typed on the numpad
+fn alt_code(digits: &str) -> Bytes {
+    let mut packets = alt_down();
+    for digit in digits.bytes() {
+        packets.put_slice(&alt_with(keypad_digit(digit - b'0')));
+    }
+    packets.put_slice(&RELEASE_KEYS_HID_PACKET);
+    packets.freeze()
+}
+
+/// The packets holding Alt while the numpad + and then the hex digits are typed, the letters on the
+/// layout's keys
+fn alt_hex_code(layout_key: &str, unit: u16) -> Result<Bytes, Error> {
+    let mut packets = alt_down();
+    packets.put_slice(&alt_with(KEYPAD_PLUS));
+    for digit in format!("{:x}", unit).chars() {
+        match digit.to_digit(10) {
+            Some(digit) => packets.put_slice(&alt_with(keypad_digit(digit as u8))),
+            None => {
+                let mut buf = [0; 4];
+                let key = string_to_keys_and_modifiers(layout_key, digit.encode_utf8(&mut buf))?
+                    .pop()
+                    .ok_or(Error::InvalidCharacter(digit))?;
+                packets.put_slice(&[key.modifier | MODIFIER_LEFT_ALT, 0, key.key, 0, 0, 0, 0, 0]);
+                packets.put_slice(&ALT_HID_PACKET);
+            }
+        }
+    }
+    packets.put_slice(&RELEASE_KEYS_HID_PACKET);
+    Ok(packets.freeze())
+}
+
+/// Alt on its own, with room for the packets typing the code while it's held
+fn alt_down() -> BytesMut {
+    let mut packets = BytesMut::with_capacity(HID_PACKET_LEN * 16);
+    packets.put_slice(&ALT_HID_PACKET);
+    packets
+}
+
+/// The packets pressing and releasing the key while Alt is held
+fn alt_with(key: u8) -> [u8; HID_PACKET_LEN * 2] {
+    let mut packets = [0; HID_PACKET_LEN * 2];
+    packets[0] = MODIFIER_LEFT_ALT;
+    packets[2] = key;
+    packets[HID_PACKET_LEN] = MODIFIER_LEFT_ALT;
+    packets
+}
+
+fn keypad_digit(digit: u8) -> u8 {
+    match digit {
+        0 => KEYPAD_0,
+        digit => KEYPAD_1 + digit - 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::HID_PACKET_LEN;
+
+    fn packets(keystroke: &Keystroke) -> Vec<[u8; HID_PACKET_LEN]> {
+        keystroke
+            .packets
+            .chunks(HID_PACKET_LEN)
+            .map(|packet| {
+                let mut array = [0; HID_PACKET_LEN];
+                array.copy_from_slice(packet);
+                array
+            })
+            .collect()
+    }
+
+    #[test]
+    fn code_points_are_typed_in_hex() {
+        let keystroke = ibus_keystroke("LAYOUT_US_ENGLISH", '😀').unwrap();
+
+        assert_eq!(keystroke.character, Some('😀'));
+        assert_eq!(
+            packets(&keystroke),
+            vec![
+                // Ctrl+Shift+U
+                [0x03, 0, 0x18, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                // 1f600
+                [0, 0, 0x1e, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0x09, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0x23, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0x27, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0x27, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                // Enter
+                [0, 0, 0x28, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn code_points_can_end_with_space() {
+        let keystroke = ibus_keystroke_with("LAYOUT_US_ENGLISH", 'é', IbusCommit::Space).unwrap();
+
+        let packets = packets(&keystroke);
+        // Ctrl+Shift+U, e9, then space
+        assert_eq!(packets.len(), 8);
+        assert_eq!(packets[6], [0, 0, 0x2c, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn keys_are_looked_up_in_the_layout() {
+        // AZERTY types digits with Shift, and has A where QWERTY has Q
+        let keystroke = ibus_keystroke("LAYOUT_FRENCH", '\u{2a}').unwrap();
+        assert_eq!(
+            keystroke.packets[HID_PACKET_LEN * 2..HID_PACKET_LEN * 6],
+            string_to_hid_packets("LAYOUT_FRENCH", "2a").unwrap()[..]
+        );
+        assert_eq!(packets(&keystroke)[2][0], 0x02);
+        assert_eq!(packets(&keystroke)[4][2], 0x14);
+    }
+
+    #[test]
+    fn windows_1252_characters_have_a_leading_zero() {
+        let keystroke =
+            alt_numpad_keystroke("LAYOUT_US_ENGLISH", '€', AltNumpad::default()).unwrap();
+        // Alt+0128
+        assert_eq!(
+            packets(&keystroke),
+            vec![
+                [0x04, 0, 0, 0, 0, 0, 0, 0],
+                [0x04, 0, 0x62, 0, 0, 0, 0, 0],
+                [0x04, 0, 0, 0, 0, 0, 0, 0],
+                [0x04, 0, 0x59, 0, 0, 0, 0, 0],
+                [0x04, 0, 0, 0, 0, 0, 0, 0],
+                [0x04, 0, 0x5a, 0, 0, 0, 0, 0],
+                [0x04, 0, 0, 0, 0, 0, 0, 0],
+                [0x04, 0, 0x60, 0, 0, 0, 0, 0],
+                [0x04, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+            ]
+        );
+        assert_eq!(windows_1252('é'), Some(0xE9));
+        assert_eq!(windows_1252('Ÿ'), Some(0x9F));
+        assert_eq!(windows_1252('≈'), None);
+    }
+
+    #[test]
+    fn other_characters_are_typed_by_code_point() {
+        let decimal = alt_numpad_keystroke("LAYOUT_US_ENGLISH", '≈', AltNumpad::default()).unwrap();
+        // Alt+8776, without a leading zero
+        assert_eq!(decimal.packets, alt_code("8776"),);
+
+        let hex = AltNumpad {
+            hex: true,
+            ..AltNumpad::default()
+        };
+        let keystroke = alt_numpad_keystroke("LAYOUT_US_ENGLISH", '\u{2b1c}', hex).unwrap();
+        // Alt, numpad +, 2 b 1 c, with the letters on the main keys
+        assert_eq!(
+            packets(&keystroke),
+            vec![
+                [0x04, 0, 0, 0, 0, 0, 0, 0],
+                [0x04, 0, 0x57, 0, 0, 0, 0, 0],
+                [0x04, 0, 0, 0, 0, 0, 0, 0],
+                [0x04, 0, 0x5a, 0, 0, 0, 0, 0],
+                [0x04, 0, 0, 0, 0, 0, 0, 0],
+                [0x04, 0, 0x05, 0, 0, 0, 0, 0],
+                [0x04, 0, 0, 0, 0, 0, 0, 0],
+                [0x04, 0, 0x59, 0, 0, 0, 0, 0],
+                [0x04, 0, 0, 0, 0, 0, 0, 0],
+                [0x04, 0, 0x06, 0, 0, 0, 0, 0],
+                [0x04, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn supplementary_characters_need_surrogate_pairs() {
+        match alt_numpad_keystroke("LAYOUT_US_ENGLISH", '😀', AltNumpad::default()) {
+            Err(Error::NeedsSurrogatePair('😀')) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let pairs = AltNumpad {
+            surrogate_pairs: true,
+            ..AltNumpad::default()
+        };
+        let keystroke = alt_numpad_keystroke("LAYOUT_US_ENGLISH", '😀', pairs).unwrap();
+        // U+1F600 is D83D DE00
+        let mut expected = alt_code("55357").to_vec();
+        expected.extend_from_slice(&alt_code("56832"));
+        assert_eq!(keystroke.packets.to_vec(), expected);
+    }
+}
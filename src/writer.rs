@@ -0,0 +1,1153 @@
+//! Destinations for the HID packets sent by a [`HidKeyboard`](crate::HidKeyboard)
+//!
+//! Anything implementing [`Write`] is a [`HidWriter`], e.g. a device file, a `Vec<u8>` collecting
+//! the packets in tests or [`io::sink`] to discard them.
+
+use crate::HID_PACKET_LEN;
+
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Somewhere HID packets can be sent
+pub trait HidWriter {
+    /// Send a single HID packet.
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()>;
+
+    /// Send as many of a buffer of HID packets as can be sent at once, returning the number of
+    /// bytes sent. By default this sends only the first packet.
+    fn write_packets(&mut self, packets: &[u8]) -> io::Result<usize> {
+        let packet = &packets[..packets.len().min(HID_PACKET_LEN)];
+        self.write_packet(packet)?;
+        Ok(packet.len())
+    }
+
+    /// Called before the packets of each keystroke are written, with the character they type if
+    /// there is one.
+    fn start_keystroke(&mut self, _character: Option<char>) {}
+}
+
+/// The error of a write the device accepted only part of, or none of, as the source of an
+/// [`io::ErrorKind::WriteZero`] error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialWrite {
+    pub written: usize,
+    pub expected: usize,
+}
+
+impl PartialWrite {
+    /// The partial write an IO error is for, if it's one
+    pub fn of(error: &io::Error) -> Option<PartialWrite> {
+        error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<PartialWrite>())
+            .copied()
+    }
+}
+
+impl fmt::Display for PartialWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "device rejected the packet after {} of {} bytes",
+            self.written, self.expected
+        )
+    }
+}
+
+impl error::Error for PartialWrite {}
+
+impl<W: Write + ?Sized> HidWriter for W {
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let mut written = 0;
+        while written < packet.len() {
+            match self.write(&packet[written..]) {
+                Ok(0) => {
+                    let expected = packet.len();
+                    let partial = PartialWrite { written, expected };
+                    return Err(io::Error::new(io::ErrorKind::WriteZero, partial));
+                }
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.flush()
+    }
+
+    fn write_packets(&mut self, packets: &[u8]) -> io::Result<usize> {
+        // A gadget device accepts one report per write, where a file takes the whole buffer
+        let written = self.write(packets)?;
+        if written == 0 && !packets.is_empty() {
+            let expected = packets.len().min(HID_PACKET_LEN);
+            let partial = PartialWrite { written, expected };
+            return Err(io::Error::new(io::ErrorKind::WriteZero, partial));
+        }
+        self.flush()?;
+        Ok(written)
+    }
+}
+
+/// A key pressed (`true`) or released between two HID packets, for writers that inject key
+/// events rather than sending the packets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyChange {
+    /// A modifier key by its bit in the modifier byte, from left ctrl at 0 to right GUI at 7
+    Modifier(u8, bool),
+    /// A key by its HID usage
+    Usage(u8, bool),
+}
+
+/// The keys released and pressed to go from the keys held in one HID packet to the keys in the
+/// next, releasing keys before pressing any so a modifier is held before its key.
+pub fn key_changes(previous: &[u8], packet: &[u8]) -> Vec<KeyChange> {
+    let modifiers = |report: &[u8]| report.first().copied().unwrap_or(0);
+    let usages = |report: &[u8]| {
+        report
+            .iter()
+            .skip(2)
+            .copied()
+            .filter(|usage| *usage != 0)
+            .collect::<Vec<u8>>()
+    };
+    let (old_modifiers, new_modifiers) = (modifiers(previous), modifiers(packet));
+    let (old_usages, new_usages) = (usages(previous), usages(packet));
+    let held = |modifiers: u8, bit: u8| modifiers & (1 << bit) != 0;
+
+    let mut changes = Vec::new();
+    for usage in old_usages
+        .iter()
+        .filter(|usage| !new_usages.contains(usage))
+    {
+        changes.push(KeyChange::Usage(*usage, false));
+    }
+    for bit in 0..8 {
+        if held(old_modifiers, bit) && !held(new_modifiers, bit) {
+            changes.push(KeyChange::Modifier(bit, false));
+        }
+    }
+    for bit in 0..8 {
+        if !held(old_modifiers, bit) && held(new_modifiers, bit) {
+            changes.push(KeyChange::Modifier(bit, true));
+        }
+    }
+    for usage in new_usages
+        .iter()
+        .filter(|usage| !old_usages.contains(usage))
+    {
+        changes.push(KeyChange::Usage(*usage, true));
+    }
+    changes
+}
+
+/// Writes each HID packet as a line of space separated hex bytes, followed by the character it
+/// types if there is one
+pub struct HexDump<W: Write> {
+    output: W,
+    line_ending: &'static str,
+    label: Option<char>,
+}
+
+impl<W: Write> HexDump<W> {
+    /// Create a hex dump writing lines ending with `\n` to the output.
+    pub fn new(output: W) -> Self {
+        HexDump {
+            output,
+            line_ending: "\n",
+            label: None,
+        }
+    }
+
+    /// End each line with the given string instead, e.g. `\r\n` for a terminal in raw mode.
+    pub fn with_line_ending(mut self, line_ending: &'static str) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    /// Consume the hex dump, returning the underlying output.
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+impl<W: Write> HidWriter for HexDump<W> {
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let hex = to_hex(packet);
+        match self.label {
+            Some(c) => write!(self.output, "{} # {:?}{}", hex, c, self.line_ending)?,
+            None => write!(self.output, "{}{}", hex, self.line_ending)?,
+        }
+        self.output.flush()
+    }
+
+    fn start_keystroke(&mut self, character: Option<char>) {
+        self.label = character;
+    }
+}
+
+/// Writes the HID packets as a JSON array with an object for each of them, giving its `bytes`, the
+/// `char` it types or `null`, and for keyboard packets its `modifiers` byte and pressed `keys` by
+/// usage. Each object is on its own line, and the array is closed when the dump is finished or
+/// dropped.
+pub struct JsonDump<W: Write> {
+    // None once the array has been closed
+    output: Option<W>,
+    line_ending: &'static str,
+    label: Option<char>,
+    written: usize,
+}
+
+impl<W: Write> JsonDump<W> {
+    /// Create a JSON dump writing lines ending with `\n` to the output.
+    pub fn new(output: W) -> Self {
+        JsonDump {
+            output: Some(output),
+            line_ending: "\n",
+            label: None,
+            written: 0,
+        }
+    }
+
+    /// End each line with the given string instead, e.g. `\r\n` for a terminal in raw mode.
+    pub fn with_line_ending(mut self, line_ending: &'static str) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    /// Close the array, returning the underlying output.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.close()?;
+        Ok(self.output.take().expect("the output is only taken here"))
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        let output = match self.output.as_mut() {
+            Some(output) => output,
+            None => return Ok(()),
+        };
+        if self.written == 0 {
+            write!(output, "[]{}", self.line_ending)?;
+        } else {
+            write!(output, "{}]{}", self.line_ending, self.line_ending)?;
+        }
+        output.flush()
+    }
+}
+
+impl<W: Write> HidWriter for JsonDump<W> {
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let output = self
+            .output
+            .as_mut()
+            .ok_or_else(|| io::Error::other("the JSON array has been closed"))?;
+        let mut object = serde_json::json!({
+            "bytes": packet,
+            "char": self.label,
+        });
+        // Other packets, such as a mouse's, have no keys to decode
+        if packet.len() == HID_PACKET_LEN {
+            let keys: Vec<u8> = packet[2..]
+                .iter()
+                .copied()
+                .filter(|&usage| usage != 0)
+                .collect();
+            object["modifiers"] = packet[0].into();
+            object["keys"] = keys.into();
+        }
+        let separator = if self.written == 0 { "[" } else { "," };
+        write!(output, "{}{}{}", separator, self.line_ending, object)?;
+        self.written += 1;
+        output.flush()
+    }
+
+    fn start_keystroke(&mut self, character: Option<char>) {
+        self.label = character;
+    }
+}
+
+impl<W: Write> Drop for JsonDump<W> {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// Writes the HID packets as a C array of bytes, with a packet on each line commented with the
+/// character it types, and a `PACKETS_LEN` constant with its length. Nothing is written until the
+/// dump is finished or dropped, as the length comes before the array.
+pub struct CArrayDump<W: Write> {
+    // None once the array has been written
+    output: Option<W>,
+    lines: Vec<String>,
+    len: usize,
+    label: Option<char>,
+}
+
+impl<W: Write> CArrayDump<W> {
+    /// Create a dump writing the array to the output.
+    pub fn new(output: W) -> Self {
+        CArrayDump {
+            output: Some(output),
+            lines: Vec::new(),
+            len: 0,
+            label: None,
+        }
+    }
+
+    /// Write the array, returning the underlying output.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.close()?;
+        Ok(self.output.take().expect("the output is only taken here"))
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        let output = match self.output.as_mut() {
+            Some(output) => output,
+            None => return Ok(()),
+        };
+        writeln!(output, "#include <stdint.h>")?;
+        writeln!(output)?;
+        writeln!(output, "#define PACKETS_LEN {}", self.len)?;
+        if self.lines.is_empty() {
+            // C arrays can't be empty
+            writeln!(output, "static const uint8_t packets[1] = {{ 0x00 }};")?;
+        } else {
+            writeln!(output, "static const uint8_t packets[PACKETS_LEN] = {{")?;
+            for line in &self.lines {
+                writeln!(output, "    {}", line)?;
+            }
+            writeln!(output, "}};")?;
+        }
+        output.flush()
+    }
+}
+
+impl<W: Write> HidWriter for CArrayDump<W> {
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let mut line = packet
+            .iter()
+            .map(|b| format!("0x{:02x},", b))
+            .collect::<Vec<String>>()
+            .join(" ");
+        if let Some(c) = self.label {
+            line.push_str(&format!(" /* {:?} */", c));
+        }
+        self.lines.push(line);
+        self.len += packet.len();
+        Ok(())
+    }
+
+    fn start_keystroke(&mut self, character: Option<char>) {
+        self.label = character;
+    }
+}
+
+impl<W: Write> Drop for CArrayDump<W> {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// Checks each HID packet by reading it back, from a gadget device that echoes the reports it
+/// sends or from a companion file.
+///
+/// The reader should be non-blocking, as e.g. a device opened with `O_NONBLOCK`. Each read of a
+/// device returns one report, and reports shorter than the packet, such as the 1 byte LED output
+/// reports the host sends a keyboard, are skipped. A packet that is read back differently is an
+/// error. If the first packet can't be read back at all, reading back
+/// isn't supported, which is reported once and the packets are written without checking them from
+/// then on.
+pub struct ReadBack<W, R> {
+    writer: W,
+    // None once reading back turned out not to be supported
+    reader: Option<R>,
+    timeout: Duration,
+    on_unsupported: Box<dyn FnMut(&io::Error)>,
+    written: usize,
+    verified: usize,
+}
+
+impl<W: HidWriter, R: Read> ReadBack<W, R> {
+    /// Write packets to the writer, reading each of them back from the reader within 100ms.
+    pub fn new(writer: W, reader: R) -> Self {
+        ReadBack {
+            writer,
+            reader: Some(reader),
+            timeout: Duration::from_millis(100),
+            on_unsupported: Box::new(|e| warn!("Not verifying the HID packets: {}", e)),
+            written: 0,
+            verified: 0,
+        }
+    }
+
+    /// Wait this long for each packet to be read back instead.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Report that reading back isn't supported with this instead of logging a warning.
+    pub fn on_unsupported<F: FnMut(&io::Error) + 'static>(mut self, on_unsupported: F) -> Self {
+        self.on_unsupported = Box::new(on_unsupported);
+        self
+    }
+
+    /// The number of packets that were read back as they were written.
+    pub fn verified(&self) -> usize {
+        self.verified
+    }
+
+    /// Consume the read-back, returning the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    // Read a packet's worth of bytes, skipping shorter reports, or None if nothing arrives before
+    // the timeout or the end of the input
+    fn read_back(&mut self, len: usize) -> io::Result<Option<Vec<u8>>> {
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return Ok(None),
+        };
+        let mut buf = vec![0; len];
+        let mut read = 0;
+        let started = Instant::now();
+        while read < len {
+            match reader.read(&mut buf[read..]) {
+                Ok(0) => break,
+                Ok(n) if read == 0 && n < len => {
+                    debug!(
+                        "Skipped a {} byte report read back: {}",
+                        n,
+                        to_hex(&buf[..n])
+                    )
+                }
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if started.elapsed() >= self.timeout {
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        buf.truncate(read);
+        Ok(if read == 0 { None } else { Some(buf) })
+    }
+}
+
+impl<W: HidWriter, R: Read> HidWriter for ReadBack<W, R> {
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.writer.write_packet(packet)?;
+        let index = self.written;
+        self.written += 1;
+        if self.reader.is_none() {
+            return Ok(());
+        }
+
+        let error = match self.read_back(packet.len()) {
+            Ok(Some(read)) if read == packet => {
+                self.verified += 1;
+                return Ok(());
+            }
+            Ok(Some(read)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "packet {} was read back as {} instead of {}",
+                        index,
+                        to_hex(&read),
+                        to_hex(packet)
+                    ),
+                ))
+            }
+            Ok(None) => io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("nothing was read back for packet {}", index),
+            ),
+            Err(e) => e,
+        };
+        if self.verified > 0 {
+            return Err(error);
+        }
+        (self.on_unsupported)(&error);
+        self.reader = None;
+        Ok(())
+    }
+
+    fn start_keystroke(&mut self, character: Option<char>) {
+        self.writer.start_keystroke(character);
+    }
+}
+
+/// Writes each HID packet to several writers, e.g. gadget devices connected to different hosts.
+///
+/// A writer that fails is reported and left out from then on, and the packets keep going to the
+/// others until they have all failed. With [`strict`](FanOut::strict) the first failure is an
+/// error instead, and retrying the packet only writes it to the writers that haven't had it yet.
+pub struct FanOut<W> {
+    writers: Vec<Mirror<W>>,
+    strict: bool,
+    on_error: Box<ErrorHandler>,
+    // A packet that failed part way through the writers, and the writer to continue from
+    partial: Option<(Vec<u8>, usize)>,
+}
+
+/// Reports the name and error of a writer that failed
+type ErrorHandler = dyn FnMut(&str, &io::Error);
+
+struct Mirror<W> {
+    name: String,
+    writer: W,
+    failed: bool,
+}
+
+impl<W: HidWriter> FanOut<W> {
+    /// Create a fan-out without any writers, logging a warning when one fails.
+    pub fn new() -> Self {
+        FanOut {
+            writers: Vec::new(),
+            strict: false,
+            on_error: Box::new(|name, e| warn!("Could not write to {}: {}", name, e)),
+            partial: None,
+        }
+    }
+
+    /// Add a writer, with the name used to report its errors.
+    pub fn with_writer<S: Into<String>>(mut self, name: S, writer: W) -> Self {
+        self.writers.push(Mirror {
+            name: name.into(),
+            writer,
+            failed: false,
+        });
+        self
+    }
+
+    /// Fail on the first error from any writer instead of leaving that writer out.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Report a writer that fails with this instead of logging a warning.
+    pub fn on_error<F: FnMut(&str, &io::Error) + 'static>(mut self, on_error: F) -> Self {
+        self.on_error = Box::new(on_error);
+        self
+    }
+
+    /// The names of the writers that have failed and are left out.
+    pub fn failed(&self) -> impl Iterator<Item = &str> {
+        self.writers
+            .iter()
+            .filter(|mirror| mirror.failed)
+            .map(|mirror| mirror.name.as_str())
+    }
+
+    /// Consume the fan-out, returning the writers with their names.
+    pub fn into_inner(self) -> Vec<(String, W)> {
+        self.writers
+            .into_iter()
+            .map(|mirror| (mirror.name, mirror.writer))
+            .collect()
+    }
+}
+
+impl<W: HidWriter> Default for FanOut<W> {
+    fn default() -> Self {
+        FanOut::new()
+    }
+}
+
+impl<W: HidWriter> HidWriter for FanOut<W> {
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let start = match self.partial.take() {
+            Some((partial, next)) if partial == packet => next,
+            _ => 0,
+        };
+        for (i, mirror) in self.writers.iter_mut().enumerate().skip(start) {
+            if mirror.failed {
+                continue;
+            }
+            if let Err(e) = mirror.writer.write_packet(packet) {
+                if self.strict {
+                    self.partial = Some((packet.to_vec(), i));
+                    return Err(io::Error::new(e.kind(), format!("{}: {}", mirror.name, e)));
+                }
+                (self.on_error)(&mirror.name, &e);
+                mirror.failed = true;
+            }
+        }
+
+        if !self.writers.is_empty() && self.writers.iter().all(|mirror| mirror.failed) {
+            return Err(io::Error::other("could not write to any of the devices"));
+        }
+        Ok(())
+    }
+
+    fn start_keystroke(&mut self, character: Option<char>) {
+        for mirror in &mut self.writers {
+            mirror.writer.start_keystroke(character);
+        }
+    }
+}
+
+/// Logs each HID packet written to another writer as a line, with the time since the log was
+/// started, its bytes, the character it types if there is one and the result of writing it.
+///
+/// A packet that fails is written again when it's retried, so it's logged once it's written, with
+/// the number of times it was retried, or as failed once another packet is written or the log is
+/// dropped. The log is only flushed when it's dropped, so it should be buffered. An error writing
+/// to the log is reported once and the packets are written without logging them from then on.
+pub struct PacketLog<W, L: Write> {
+    writer: W,
+    // None once writing to it failed
+    log: Option<L>,
+    json_lines: bool,
+    characters: bool,
+    started: Instant,
+    label: Option<char>,
+    // The packet that failed, the times it has been tried and the last error
+    failed: Option<(Vec<u8>, usize, Option<i32>)>,
+}
+
+/// What happened when a packet was written
+enum Written {
+    Ok { retries: usize },
+    Failed { errno: Option<i32> },
+}
+
+impl<W, L: Write> PacketLog<W, L> {
+    /// Write packets to the writer, logging each of them as text to the log.
+    pub fn new(writer: W, log: L) -> Self {
+        PacketLog {
+            writer,
+            log: Some(log),
+            json_lines: false,
+            characters: true,
+            started: Instant::now(),
+            label: None,
+            failed: None,
+        }
+    }
+
+    /// Log each packet as a line with a JSON object instead, with its `time` in seconds, its hex
+    /// `bytes`, the `char` it types or `null`, its `result`, and the `retries` or `errno` of a
+    /// packet that was retried or failed.
+    pub fn json_lines(mut self) -> Self {
+        self.json_lines = true;
+        self
+    }
+
+    /// Time the packets from this instant instead of when the log was created, e.g. to share a
+    /// clock with another log.
+    pub fn started_at(mut self, started: Instant) -> Self {
+        self.started = started;
+        self
+    }
+
+    /// Leave the characters out of the log, e.g. for a password, still logging their packets.
+    pub fn hide_characters(&mut self) {
+        self.characters = false;
+    }
+
+    /// Get a reference to the writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Log the packet that failed before this one, returning the times this one was retried
+    fn retries(&mut self, packet: &[u8]) -> usize {
+        match self.failed.take() {
+            Some((failed, tries, _)) if failed == packet => tries,
+            Some((failed, _, errno)) => {
+                self.log(&failed, Written::Failed { errno });
+                0
+            }
+            None => 0,
+        }
+    }
+
+    fn fail(&mut self, packet: &[u8], tries: usize, error: &io::Error) {
+        self.failed = Some((packet.to_vec(), tries + 1, error.raw_os_error()));
+    }
+
+    fn log(&mut self, packet: &[u8], written: Written) {
+        let time = self.started.elapsed().as_secs_f64();
+        let character = self.label.filter(|_| self.characters);
+        let log = match self.log.as_mut() {
+            Some(log) => log,
+            None => return,
+        };
+        let result = if self.json_lines {
+            let mut object = serde_json::json!({
+                "time": (time * 1e6).round() / 1e6,
+                "bytes": to_hex(packet),
+                "char": character,
+            });
+            match written {
+                Written::Ok { retries: 0 } => object["result"] = "ok".into(),
+                Written::Ok { retries } => {
+                    object["result"] = "retried".into();
+                    object["retries"] = retries.into();
+                }
+                Written::Failed { errno } => {
+                    object["result"] = "failed".into();
+                    object["errno"] = errno.into();
+                }
+            }
+            writeln!(log, "{}", object)
+        } else {
+            let character = character.map_or("-".to_string(), |c| format!("{:?}", c));
+            let written = match written {
+                Written::Ok { retries: 0 } => "ok".to_string(),
+                Written::Ok { retries } => format!("retried {}", retries),
+                Written::Failed { errno: Some(errno) } => format!("failed {}", errno),
+                Written::Failed { errno: None } => "failed".to_string(),
+            };
+            writeln!(
+                log,
+                "{:.6} {} {} {}",
+                time,
+                to_hex(packet),
+                character,
+                written
+            )
+        };
+        if let Err(e) = result {
+            warn!("Not logging the HID packets any more: {}", e);
+            self.log = None;
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some((failed, _, errno)) = self.failed.take() {
+            self.log(&failed, Written::Failed { errno });
+        }
+        if let Some(log) = self.log.as_mut() {
+            if let Err(e) = log.flush() {
+                warn!("Could not write the HID packet log: {}", e);
+            }
+        }
+    }
+}
+
+impl<W: HidWriter, L: Write> HidWriter for PacketLog<W, L> {
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let retries = self.retries(packet);
+        match self.writer.write_packet(packet) {
+            Ok(()) => {
+                self.log(packet, Written::Ok { retries });
+                Ok(())
+            }
+            Err(e) => {
+                self.fail(packet, retries, &e);
+                Err(e)
+            }
+        }
+    }
+
+    fn write_packets(&mut self, packets: &[u8]) -> io::Result<usize> {
+        let first = &packets[..packets.len().min(HID_PACKET_LEN)];
+        let mut retries = self.retries(first);
+        match self.writer.write_packets(packets) {
+            Ok(written) => {
+                for packet in packets[..written].chunks(HID_PACKET_LEN) {
+                    self.log(packet, Written::Ok { retries });
+                    retries = 0;
+                }
+                Ok(written)
+            }
+            Err(e) => {
+                self.fail(first, retries, &e);
+                Err(e)
+            }
+        }
+    }
+
+    fn start_keystroke(&mut self, character: Option<char>) {
+        self.label = character;
+        self.writer.start_keystroke(character);
+    }
+}
+
+impl<W, L: Write> Drop for PacketLog<W, L> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_dump_labels_keystrokes() {
+        let mut dump = HexDump::new(Vec::new());
+
+        dump.start_keystroke(Some('a'));
+        dump.write_packet(&[0, 0, 0x04, 0, 0, 0, 0, 0]).unwrap();
+        dump.start_keystroke(None);
+        dump.write_packet(&[0; 8]).unwrap();
+
+        assert_eq!(
+            String::from_utf8(dump.into_inner()).unwrap(),
+            "00 00 04 00 00 00 00 00 # 'a'\n00 00 00 00 00 00 00 00\n"
+        );
+    }
+
+    #[test]
+    fn json_dump_is_an_array_of_packets() {
+        let mut dump = JsonDump::new(Vec::new());
+
+        dump.start_keystroke(Some('A'));
+        dump.write_packet(&[0x02, 0, 0x04, 0, 0, 0, 0, 0]).unwrap();
+        dump.start_keystroke(None);
+        dump.write_packet(&[0; 8]).unwrap();
+        dump.write_packet(&[0, 5, 0, 0]).unwrap();
+
+        let json = String::from_utf8(dump.finish().unwrap()).unwrap();
+        assert_eq!(
+            json,
+            "[\n\
+             {\"bytes\":[2,0,4,0,0,0,0,0],\"char\":\"A\",\"keys\":[4],\"modifiers\":2},\n\
+             {\"bytes\":[0,0,0,0,0,0,0,0],\"char\":null,\"keys\":[],\"modifiers\":0},\n\
+             {\"bytes\":[0,5,0,0],\"char\":null}\n\
+             ]\n"
+        );
+        let packets: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(packets.as_array().unwrap().len(), 3);
+
+        assert_eq!(JsonDump::new(Vec::new()).finish().unwrap(), b"[]\n");
+    }
+
+    #[test]
+    fn c_array_dump_has_a_packet_per_line() {
+        let mut dump = CArrayDump::new(Vec::new());
+
+        dump.start_keystroke(Some('/'));
+        dump.write_packet(&[0, 0, 0x38, 0, 0, 0, 0, 0]).unwrap();
+        dump.start_keystroke(None);
+        dump.write_packet(&[0; 8]).unwrap();
+
+        assert_eq!(
+            String::from_utf8(dump.finish().unwrap()).unwrap(),
+            "#include <stdint.h>\n\
+             \n\
+             #define PACKETS_LEN 16\n\
+             static const uint8_t packets[PACKETS_LEN] = {\n    \
+             0x00, 0x00, 0x38, 0x00, 0x00, 0x00, 0x00, 0x00, /* '/' */\n    \
+             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,\n\
+             };\n"
+        );
+        assert!(
+            String::from_utf8(CArrayDump::new(Vec::new()).finish().unwrap())
+                .unwrap()
+                .contains("#define PACKETS_LEN 0\nstatic const uint8_t packets[1] = { 0x00 };")
+        );
+    }
+
+    #[test]
+    fn writers_collect_or_discard_packets() {
+        let mut packets = Vec::new();
+        packets.write_packet(&[1; 8]).unwrap();
+        packets.write_packet(&[2; 8]).unwrap();
+        io::sink().write_packet(&[3; 8]).unwrap();
+
+        assert_eq!(packets, [[1u8; 8], [2; 8]].concat());
+    }
+
+    #[test]
+    fn key_changes_release_before_pressing() {
+        let ctrl_a = [0x01, 0, 0x04, 0, 0, 0, 0, 0];
+        let shift_b = [0x02, 0, 0x05, 0, 0, 0, 0, 0];
+
+        assert_eq!(
+            key_changes(&ctrl_a, &shift_b),
+            vec![
+                KeyChange::Usage(0x04, false),
+                KeyChange::Modifier(0, false),
+                KeyChange::Modifier(1, true),
+                KeyChange::Usage(0x05, true),
+            ]
+        );
+        assert_eq!(key_changes(&shift_b, &shift_b), vec![]);
+    }
+
+    /// Accepts at most 3 bytes per write
+    struct Trickle(Vec<u8>);
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(&buf[..buf.len().min(3)])
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn short_writes_are_continued() {
+        let mut writer = Trickle(Vec::new());
+
+        writer.write_packet(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+
+        assert_eq!(writer.0, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn rejected_writes_are_errors() {
+        let mut buf = [0u8; 4];
+        let mut writer = &mut buf[..];
+
+        let err = writer.write_packet(&[1; 8]).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+        assert_eq!(
+            err.to_string(),
+            "device rejected the packet after 4 of 8 bytes"
+        );
+        assert_eq!(
+            PartialWrite::of(&err),
+            Some(PartialWrite {
+                written: 4,
+                expected: 8
+            })
+        );
+    }
+
+    /// Fails every write once it has accepted this many
+    struct Failing(usize);
+
+    impl Write for Failing {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            self.0 -= 1;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn fan_out_writes_each_packet_to_every_writer() {
+        let mut fan_out = FanOut::new()
+            .with_writer("a", Vec::new())
+            .with_writer("b", Vec::new());
+
+        fan_out.write_packet(&[1; 8]).unwrap();
+        fan_out.write_packet(&[2; 8]).unwrap();
+
+        let packets = [[1u8; 8], [2; 8]].concat();
+        assert_eq!(
+            fan_out.into_inner(),
+            vec![
+                ("a".to_string(), packets.clone()),
+                ("b".to_string(), packets)
+            ]
+        );
+    }
+
+    #[test]
+    fn fan_out_continues_without_a_failed_writer() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let reported = errors.clone();
+        let mut fan_out = FanOut::new()
+            .with_writer("ok", Failing(usize::MAX))
+            .with_writer("broken", Failing(1))
+            .on_error(move |name, _| reported.borrow_mut().push(name.to_string()));
+
+        for _ in 0..3 {
+            fan_out.write_packet(&[1; 8]).unwrap();
+        }
+
+        assert_eq!(*errors.borrow(), vec!["broken"]);
+        assert_eq!(fan_out.failed().collect::<Vec<&str>>(), vec!["broken"]);
+        assert_eq!(fan_out.into_inner()[0].1 .0, usize::MAX - 3);
+    }
+
+    #[test]
+    fn fan_out_fails_once_every_writer_has() {
+        let mut fan_out = FanOut::new()
+            .with_writer("a", Failing(0))
+            .with_writer("b", Failing(0))
+            .on_error(|_, _| {});
+
+        assert!(fan_out.write_packet(&[1; 8]).is_err());
+    }
+
+    #[test]
+    fn strict_fan_out_resumes_from_the_failed_writer() {
+        let mut fan_out = FanOut::new()
+            .with_writer("a", Failing(usize::MAX))
+            .with_writer("b", Failing(0))
+            .strict(true);
+
+        let err = fan_out.write_packet(&[1; 8]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(err.to_string().starts_with("b: "), "{}", err);
+
+        // Retrying doesn't write the packet to a again
+        let _ = fan_out.write_packet(&[1; 8]);
+        assert_eq!(fan_out.into_inner()[0].1 .0, usize::MAX - 1);
+    }
+
+    #[test]
+    fn packets_read_back_are_verified() {
+        let echo = [[1u8; 8], [2; 8]].concat();
+        let mut writer = ReadBack::new(Vec::new(), io::Cursor::new(echo));
+
+        writer.write_packet(&[1; 8]).unwrap();
+        writer.write_packet(&[2; 8]).unwrap();
+
+        assert_eq!(writer.verified(), 2);
+    }
+
+    /// Returns one report from each read, like a gadget device
+    struct Reports(Vec<Vec<u8>>);
+
+    impl Read for Reports {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let report = self.0.remove(0);
+            buf[..report.len()].copy_from_slice(&report);
+            Ok(report.len())
+        }
+    }
+
+    #[test]
+    fn led_reports_read_back_are_skipped() {
+        // Caps Lock turned on and off by the host between the packets
+        let reports = vec![vec![0x02], vec![1u8; 8], vec![0x00], vec![2; 8]];
+        let mut writer = ReadBack::new(Vec::new(), Reports(reports));
+
+        writer.write_packet(&[1; 8]).unwrap();
+        writer.write_packet(&[2; 8]).unwrap();
+
+        assert_eq!(writer.verified(), 2);
+    }
+
+    #[test]
+    fn packets_read_back_differently_are_errors() {
+        let echo = [[1u8; 8], [3; 8]].concat();
+        let mut writer = ReadBack::new(Vec::new(), io::Cursor::new(echo));
+
+        writer.write_packet(&[1; 8]).unwrap();
+        let err = writer.write_packet(&[2; 8]).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "packet 1 was read back as 03 03 03 03 03 03 03 03 instead of 02 02 02 02 02 02 02 02"
+        );
+    }
+
+    #[test]
+    fn read_back_is_skipped_when_unsupported() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let warnings = Rc::new(Cell::new(0));
+        let warned = warnings.clone();
+        let mut writer = ReadBack::new(Vec::new(), io::empty())
+            .on_unsupported(move |_| warned.set(warned.get() + 1));
+
+        writer.write_packet(&[1; 8]).unwrap();
+        writer.write_packet(&[2; 8]).unwrap();
+
+        assert_eq!(warnings.get(), 1);
+        assert_eq!(writer.verified(), 0);
+        assert_eq!(writer.into_inner(), [[1u8; 8], [2; 8]].concat());
+    }
+
+    /// Fails the writes given by their errno, from the end, then accepts the rest
+    struct Flaky(Vec<Option<i32>>);
+
+    impl HidWriter for Flaky {
+        fn write_packet(&mut self, _packet: &[u8]) -> io::Result<()> {
+            match self.0.pop() {
+                Some(Some(errno)) => Err(io::Error::from_raw_os_error(errno)),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// The lines of a packet log without the times
+    fn untimed(log: &[u8]) -> Vec<String> {
+        String::from_utf8(log.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().1.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn packet_log_records_the_result_of_each_write() {
+        let mut log = Vec::new();
+        {
+            // The first packet is retried twice, the second fails for good
+            let mut writer =
+                PacketLog::new(Flaky(vec![Some(5), None, Some(11), Some(11)]), &mut log);
+            writer.start_keystroke(Some('a'));
+            for _ in 0..3 {
+                let _ = writer.write_packet(&[0, 0, 0x04, 0, 0, 0, 0, 0]);
+            }
+            writer.start_keystroke(None);
+            writer.write_packet(&[0; 8]).unwrap_err();
+            writer.write_packet(&[1; 8]).unwrap();
+        }
+
+        assert_eq!(
+            untimed(&log),
+            [
+                "00 00 04 00 00 00 00 00 'a' retried 2",
+                "00 00 00 00 00 00 00 00 - failed 5",
+                "01 01 01 01 01 01 01 01 - ok",
+            ]
+        );
+    }
+
+    #[test]
+    fn packet_log_can_be_json_lines_without_characters() {
+        let mut log = Vec::new();
+        {
+            let mut writer = PacketLog::new(Vec::new(), &mut log).json_lines();
+            writer.hide_characters();
+            writer.start_keystroke(Some('p'));
+            writer
+                .write_packets(&[[0, 0, 0x13, 0, 0, 0, 0, 0], [0; 8]].concat())
+                .unwrap();
+        }
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["bytes"], "00 00 13 00 00 00 00 00");
+        assert_eq!(lines[0]["char"], serde_json::Value::Null);
+        assert_eq!(lines[1]["result"], "ok");
+        assert!(lines[1]["time"].as_f64().unwrap() >= lines[0]["time"].as_f64().unwrap());
+    }
+}
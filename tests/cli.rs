@@ -0,0 +1,1891 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Run kbsim with the input piped to stdin, and no config file
+fn kbsim(args: &[&str], input: &[u8]) -> Output {
+    let config = tempfile::tempdir().unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kbsim"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", config.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn piped_stdin_is_typed_until_the_end_of_input() {
+    let output = kbsim(&["--dry-run", "--layout", "us", "--newline"], b"hi");
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "00 00 0b 00 00 00 00 00 # 'h'\n\
+         00 00 00 00 00 00 00 00 # 'h'\n\
+         00 00 0c 00 00 00 00 00 # 'i'\n\
+         00 00 00 00 00 00 00 00 # 'i'\n\
+         00 00 28 00 00 00 00 00 # '\\n'\n\
+         00 00 00 00 00 00 00 00 # '\\n'\n"
+    );
+}
+
+#[test]
+fn no_newline_overrides_the_config_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("config.toml");
+    std::fs::write(&config, "newline = true\n").unwrap();
+    let config = config.to_str().unwrap();
+
+    let with_enter = kbsim(&["--config", config, "--dry-run", "-l", "us", "a"], b"");
+    assert!(with_enter.status.success(), "{:?}", with_enter);
+    assert_eq!(
+        String::from_utf8(with_enter.stdout)
+            .unwrap()
+            .lines()
+            .count(),
+        4
+    );
+
+    let without = kbsim(
+        &[
+            "--config",
+            config,
+            "--dry-run",
+            "-l",
+            "us",
+            "--no-newline",
+            "a",
+        ],
+        b"",
+    );
+    assert!(without.status.success(), "{:?}", without);
+    assert_eq!(
+        without.stdout,
+        kbsim(&["--dry-run", "-l", "us", "a"], b"").stdout
+    );
+}
+
+#[test]
+fn layouts_are_listed_with_a_filter() {
+    let positional = kbsim(&["list", "swiss"], b"");
+    let named = kbsim(&["list", "--filter", "swiss"], b"");
+
+    assert!(named.status.success(), "{:?}", named);
+    assert_eq!(
+        String::from_utf8(named.stdout.clone()).unwrap(),
+        "LAYOUT_FRENCH_SWISS\nLAYOUT_GERMAN_SWISS\n"
+    );
+    assert_eq!(named.stdout, positional.stdout);
+    assert!(!kbsim(&["list", "swiss", "--filter", "french"], b"")
+        .status
+        .success());
+}
+
+#[test]
+fn piped_stdin_is_written_to_the_device() {
+    let dir = tempfile::tempdir().unwrap();
+    let device = dir.path().join("hidg0");
+    std::fs::write(&device, b"").unwrap();
+
+    let output = kbsim(
+        &["--hid-file", device.to_str().unwrap(), "--layout", "us"],
+        "a".repeat(1000).as_bytes(),
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    let packets = std::fs::read(&device).unwrap();
+    assert_eq!(packets.len(), 1000 * 2 * 8);
+    assert_eq!(
+        &packets[..16],
+        &[0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+    );
+}
+
+#[test]
+fn stdin_stream_is_typed_as_it_is() {
+    // ctrl+c is only special in a terminal
+    let output = kbsim(&["--dry-run", "--layout", "us", "--stdin"], b"a\x03b");
+
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("00 00 04 00 00 00 00 00 # 'a'\n"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.ends_with("00 00 00 00 00 00 00 00 # 'b'\n"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn ctrl_c_is_forwarded_as_a_key_combination() {
+    let output = kbsim(
+        &["--dry-run", "--layout", "us", "--stdin", "--forward-ctrl-c"],
+        b"a\x03b",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "00 00 04 00 00 00 00 00 # 'a'\n\
+         00 00 00 00 00 00 00 00 # 'a'\n\
+         01 00 06 00 00 00 00 00\n\
+         00 00 00 00 00 00 00 00\n\
+         00 00 05 00 00 00 00 00 # 'b'\n\
+         00 00 00 00 00 00 00 00 # 'b'\n"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn packets_are_mirrored_to_every_device() {
+    let dir = tempfile::tempdir().unwrap();
+    let devices = [dir.path().join("hidg0"), dir.path().join("hidg2")];
+    for device in &devices {
+        std::fs::write(device, b"").unwrap();
+    }
+    let args = |strict| {
+        let mut args = vec!["--layout", "us"];
+        for device in &[
+            devices[0].to_str().unwrap(),
+            "/dev/full",
+            devices[1].to_str().unwrap(),
+        ] {
+            args.extend(&["--hid-file", device]);
+        }
+        if strict {
+            args.push("--strict");
+        }
+        args.push("hi");
+        args
+    };
+
+    // /dev/full fails every write
+    let output = kbsim(&args(false), b"");
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Could not write to /dev/full"),
+        "{}",
+        stderr
+    );
+    for device in &devices {
+        assert_eq!(std::fs::read(device).unwrap().len(), 2 * 2 * 8);
+    }
+
+    let output = kbsim(&args(true), b"");
+    assert!(!output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn packets_are_read_back_with_verify() {
+    let dir = tempfile::tempdir().unwrap();
+    let device = dir.path().join("hidg0");
+    let echo = dir.path().join("echo");
+    std::fs::write(&device, b"").unwrap();
+    // The packets typing "hi"
+    let mut packets = vec![0, 0, 0x0b, 0, 0, 0, 0, 0];
+    packets.extend(&[0; 8]);
+    packets.extend(&[0, 0, 0x0c, 0, 0, 0, 0, 0]);
+    packets.extend(&[0; 8]);
+    std::fs::write(&echo, &packets).unwrap();
+    let args = |string| {
+        vec![
+            "--hid-file",
+            device.to_str().unwrap(),
+            "--verify",
+            "--verify-file",
+            echo.to_str().unwrap(),
+            "--layout",
+            "us",
+            string,
+        ]
+    };
+
+    let output = kbsim(&args("hi"), b"");
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = kbsim(&args("ho"), b"");
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("packet 2 was read back as 00 00 0c"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn verify_warns_when_packets_cant_be_read_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let device = dir.path().join("hidg0");
+    std::fs::write(&device, b"").unwrap();
+
+    let output = kbsim(
+        &[
+            "--hid-file",
+            device.to_str().unwrap(),
+            "--verify",
+            "--verify-file",
+            "/dev/null",
+            "--layout",
+            "us",
+            "hi",
+        ],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.matches("Warning: can't read packets back").count(),
+        1
+    );
+    assert_eq!(std::fs::read(&device).unwrap().len(), 4 * 8);
+}
+
+#[test]
+fn serve_needs_the_secret_and_caps_the_bytes() {
+    use std::io::{BufRead, BufReader, Read};
+
+    let config = tempfile::tempdir().unwrap();
+    let secret = config.path().join("secret");
+    std::fs::write(&secret, "hunter2\n").unwrap();
+    let secret = secret.to_str().unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kbsim"))
+        .args(["serve", "--tcp", "127.0.0.1:0", "--secret-file", secret])
+        .args(["--max-bytes", "12", "--dry-run", "--layout", "us"])
+        .env("XDG_CONFIG_HOME", config.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut listening = String::new();
+    stderr.read_line(&mut listening).unwrap();
+    let address = listening.trim().strip_prefix("Listening on ").unwrap();
+
+    let output = kbsim(&["send", "--connect", address, "wrong\na"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    let args = ["send", "--connect", address, "--secret-file", secret];
+    let output = kbsim(&args, b"b\ncdef\n");
+    assert!(output.status.success(), "{:?}", output);
+
+    let mut messages = String::new();
+    for _ in 0..6 {
+        stderr.read_line(&mut messages).unwrap();
+    }
+    child.kill().unwrap();
+    let mut stdout = String::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut stdout)
+        .unwrap();
+    child.wait().unwrap();
+    assert!(messages.contains("didn't send the secret"), "{}", messages);
+    assert!(messages.contains("after --max-bytes 12"), "{}", messages);
+    // Only the line within the cap from the client with the secret is typed
+    assert_eq!(
+        stdout,
+        "00 00 05 00 00 00 00 00 # 'b'\n\
+         00 00 00 00 00 00 00 00 # 'b'\n\
+         00 00 28 00 00 00 00 00 # '\\n'\n\
+         00 00 00 00 00 00 00 00 # '\\n'\n"
+    );
+}
+
+#[test]
+fn serve_types_the_lines_received() {
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpStream;
+
+    let config = tempfile::tempdir().unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kbsim"))
+        .args([
+            "serve",
+            "--listen",
+            "127.0.0.1:0",
+            "--bind-once",
+            "--dry-run",
+            "--layout",
+            "us",
+        ])
+        .env("XDG_CONFIG_HOME", config.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut listening = String::new();
+    stderr.read_line(&mut listening).unwrap();
+    let address = listening.trim().strip_prefix("Listening on ").unwrap();
+
+    TcpStream::connect(address)
+        .unwrap()
+        .write_all(b"a\nb")
+        .unwrap();
+
+    let mut stdout = String::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut stdout)
+        .unwrap();
+    assert!(child.wait().unwrap().success());
+    assert_eq!(
+        stdout,
+        "00 00 04 00 00 00 00 00 # 'a'\n\
+         00 00 00 00 00 00 00 00 # 'a'\n\
+         00 00 28 00 00 00 00 00 # '\\n'\n\
+         00 00 00 00 00 00 00 00 # '\\n'\n\
+         00 00 05 00 00 00 00 00 # 'b'\n\
+         00 00 00 00 00 00 00 00 # 'b'\n"
+    );
+}
+
+#[test]
+fn serve_logs_each_connection() {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpStream;
+
+    let config = tempfile::tempdir().unwrap();
+    let log = config.path().join("serve.jsonl");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kbsim"))
+        .args([
+            "serve",
+            "--listen",
+            "127.0.0.1:0",
+            "--bind-once",
+            "--dry-run",
+        ])
+        .args(["--layout", "us", "--log-format", "jsonl", "--log-file"])
+        .arg(&log)
+        .env("XDG_CONFIG_HOME", config.path())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut listening = String::new();
+    stderr.read_line(&mut listening).unwrap();
+    let address = listening.trim().strip_prefix("Listening on ").unwrap();
+
+    let client = TcpStream::connect(address).unwrap();
+    let peer = client.local_addr().unwrap().to_string();
+    (&client).write_all(b"a\nb").unwrap();
+    drop(client);
+    assert!(child.wait().unwrap().success());
+
+    let log = std::fs::read_to_string(&log).unwrap();
+    let connections: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .filter(|record: &serde_json::Value| record.get("connection").is_some())
+        .collect();
+    assert_eq!(connections.len(), 1, "{}", log);
+    assert_eq!(connections[0]["connection"], peer.as_str());
+    assert_eq!(connections[0]["bytes"], 3);
+    assert_eq!(connections[0]["lines"], 2);
+    assert_eq!(connections[0]["result"], "closed");
+}
+
+#[test]
+#[cfg(unix)]
+fn serve_listens_on_a_unix_socket() {
+    use std::io::{BufRead, BufReader, Read};
+    use std::os::unix::net::UnixStream;
+
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("kbsim.sock");
+    let serve = |force| {
+        let mut args = vec!["serve", "--unix", socket.to_str().unwrap()];
+        if force {
+            args.push("--force");
+        }
+        args.extend(["--bind-once", "--dry-run", "--layout", "us"]);
+        Command::new(env!("CARGO_BIN_EXE_kbsim"))
+            .args(args)
+            .env("XDG_CONFIG_HOME", dir.path())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap()
+    };
+
+    // Only a socket is replaced, a regular file at the path is kept even with --force
+    std::fs::write(&socket, b"keep").unwrap();
+    let output = serve(true).wait_with_output().unwrap();
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("isn't a socket"), "{}", stderr);
+    assert_eq!(std::fs::read(&socket).unwrap(), b"keep");
+    std::fs::remove_file(&socket).unwrap();
+
+    // A socket left behind by a listener that has gone
+    drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+    let output = serve(false).wait_with_output().unwrap();
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("already exists"), "{}", stderr);
+
+    let mut child = serve(true);
+    // Keep reading stderr after the first line, so the messages after it can be written
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut listening = String::new();
+    stderr.read_line(&mut listening).unwrap();
+    UnixStream::connect(&socket)
+        .unwrap()
+        .write_all(b"a")
+        .unwrap();
+
+    let mut stdout = String::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut stdout)
+        .unwrap();
+    let mut rest = String::new();
+    stderr.read_to_string(&mut rest).unwrap();
+    assert!(child.wait().unwrap().success(), "{}", rest);
+    assert_eq!(
+        stdout, "00 00 04 00 00 00 00 00 # 'a'\n00 00 00 00 00 00 00 00 # 'a'\n",
+        "{}",
+        rest
+    );
+    assert!(!socket.exists());
+}
+
+#[test]
+#[cfg(unix)]
+fn sigterm_lets_serve_finish_the_line_sent() {
+    use std::io::{BufRead, BufReader, Read};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("kbsim.sock");
+    let socket_arg = socket.to_str().unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kbsim"))
+        .args(["serve", "--socket", socket_arg, "--socket-mode", "600"])
+        .args(["--dry-run", "--layout", "us", "--cooldown", "50"])
+        .env("XDG_CONFIG_HOME", dir.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line).unwrap();
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let output = kbsim(&["send", "--socket", socket_arg, "abc\n"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    // Stop it once the line has started being typed
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut typed = String::new();
+    stdout.read_line(&mut typed).unwrap();
+    Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+
+    stdout.read_to_string(&mut typed).unwrap();
+    let mut rest = String::new();
+    stderr.read_to_string(&mut rest).unwrap();
+    assert!(child.wait().unwrap().success(), "{}", rest);
+    // The whole line is typed, taking 400ms, even though SIGTERM came first
+    assert_eq!(typed.lines().count(), 8, "{}", typed);
+    assert!(rest.contains("Stopped"), "{}", rest);
+    assert!(!socket.exists());
+}
+
+#[test]
+#[cfg(unix)]
+fn follow_types_what_each_writer_of_a_pipe_sends() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = tempfile::tempdir().unwrap();
+    let fifo = dir.path().join("typeme");
+    let path = std::ffi::CString::new(fifo.to_str().unwrap()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o600) }, 0);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kbsim"))
+        .args([
+            "--follow",
+            fifo.to_str().unwrap(),
+            "--dry-run",
+            "--layout",
+            "us",
+        ])
+        .env("XDG_CONFIG_HOME", dir.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // The second writer finishes the character the first one started
+    for written in [&b"a\xc3"[..], b"\xa4b"] {
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&fifo)
+            .unwrap()
+            .write_all(written)
+            .unwrap();
+    }
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut packets = Vec::new();
+    for _ in 0..4 {
+        let mut packet = String::new();
+        stdout.read_line(&mut packet).unwrap();
+        packets.push(packet);
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+    // The US layout can't type the \u{e4} in between
+    assert_eq!(
+        packets.concat(),
+        "00 00 04 00 00 00 00 00 # 'a'\n\
+         00 00 00 00 00 00 00 00 # 'a'\n\
+         00 00 05 00 00 00 00 00 # 'b'\n\
+         00 00 00 00 00 00 00 00 # 'b'\n"
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn watch_types_the_lines_appended() {
+    use std::io::{BufRead, BufReader, Read};
+
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("build.log");
+    std::fs::write(&log, b"old\n").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kbsim"))
+        .args([
+            "--watch",
+            log.to_str().unwrap(),
+            "--watch-interval",
+            "10",
+            "--dry-run",
+            "--layout",
+            "us",
+        ])
+        .env("XDG_CONFIG_HOME", dir.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut watching = String::new();
+    stderr.read_line(&mut watching).unwrap();
+    assert!(watching.starts_with("Watching"), "{}", watching);
+
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&log)
+        .unwrap()
+        .write_all(b"a\n")
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut packets = Vec::new();
+    for _ in 0..4 {
+        let mut packet = String::new();
+        stdout.read_line(&mut packet).unwrap();
+        packets.push(packet);
+    }
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+
+    let mut rest = String::new();
+    stderr.read_to_string(&mut rest).unwrap();
+    assert!(child.wait().unwrap().success(), "{}", rest);
+    assert!(rest.contains("Stopped watching"), "{}", rest);
+    assert_eq!(
+        packets.concat(),
+        "00 00 04 00 00 00 00 00 # 'a'\n\
+         00 00 00 00 00 00 00 00 # 'a'\n\
+         00 00 28 00 00 00 00 00 # '\\n'\n\
+         00 00 00 00 00 00 00 00 # '\\n'\n"
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn watch_turns_num_lock_on_for_alt_numpad() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = tempfile::tempdir().unwrap();
+    let device = dir.path().join("hidg0");
+    // The host's LED report, with Num Lock off
+    std::fs::write(&device, [0x00]).unwrap();
+    let log = dir.path().join("notes.txt");
+    std::fs::write(&log, b"").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kbsim"))
+        .args(["--watch", log.to_str().unwrap(), "--watch-interval", "10"])
+        .args(["--hid-file", device.to_str().unwrap(), "--layout", "us"])
+        .args(["--unicode-fallback", "alt-numpad"])
+        .env("XDG_CONFIG_HOME", dir.path())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // After the warning that the HID file is a regular file
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut watching = String::new();
+    while !watching.starts_with("Watching") {
+        watching.clear();
+        assert!(stderr.read_line(&mut watching).unwrap() > 0);
+    }
+
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&log)
+        .unwrap()
+        .write_all("é\n".as_bytes())
+        .unwrap();
+    // Num Lock, Alt+0233 and Enter, each pressed and released
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while std::fs::read(&device).unwrap().len() < 14 * 8 && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    child.wait().unwrap();
+
+    let packets = std::fs::read(&device).unwrap();
+    let keys: Vec<u8> = packets.chunks(8).map(|packet| packet[2]).collect();
+    assert_eq!(
+        keys[..14],
+        [0x53, 0, 0, 0x62, 0, 0x5a, 0, 0x5b, 0, 0x5b, 0, 0, 0x28, 0]
+    );
+}
+
+#[test]
+fn mouse_actions_are_sent_in_order() {
+    let output = kbsim(
+        &[
+            "mouse",
+            "--dry-run",
+            "--move",
+            "200,-5",
+            "--press",
+            "left",
+            "--scroll",
+            "-1",
+            "--click",
+            "right",
+        ],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "00 7f fb 00\n\
+         00 49 00 00\n\
+         01 00 00 00\n\
+         01 00 00 ff\n\
+         03 00 00 00\n\
+         01 00 00 00\n\
+         00 00 00 00\n"
+    );
+}
+
+#[test]
+fn media_keys_in_text_are_written_to_the_consumer_device() {
+    let dir = tempfile::tempdir().unwrap();
+    let keyboard = dir.path().join("hidg0");
+    let consumer = dir.path().join("hidg1");
+    std::fs::write(&keyboard, b"").unwrap();
+    std::fs::write(&consumer, b"").unwrap();
+    let keyboard_arg = keyboard.to_str().unwrap();
+
+    let output = kbsim(
+        &[
+            "--hid-file",
+            keyboard_arg,
+            "--consumer-file",
+            consumer.to_str().unwrap(),
+            "--layout",
+            "us",
+            "a{MUTE}b",
+        ],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(std::fs::read(&keyboard).unwrap().len(), 2 * 2 * 8);
+    assert_eq!(std::fs::read(&consumer).unwrap(), vec![0xe2, 0, 0, 0]);
+
+    let output = kbsim(
+        &["--hid-file", keyboard_arg, "--layout", "us", "{MUTE}"],
+        b"",
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--consumer-file"), "{}", stderr);
+}
+
+#[test]
+fn caps_lock_is_turned_off_while_typing() {
+    let dir = tempfile::tempdir().unwrap();
+    let device = dir.path().join("hidg0");
+    // The host's LED report, with Caps Lock on, waiting to be read
+    std::fs::write(&device, [0x02]).unwrap();
+
+    let output = kbsim(
+        &[
+            "--hid-file",
+            device.to_str().unwrap(),
+            "--layout",
+            "us",
+            "--respect-capslock",
+            "a",
+        ],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    let packets = std::fs::read(&device).unwrap();
+    let keys: Vec<u8> = packets.chunks(8).map(|packet| packet[2]).collect();
+    // Caps Lock is pressed to turn it off, then again to turn it back on
+    assert_eq!(keys, vec![0x39, 0, 0x04, 0, 0x39, 0]);
+}
+
+#[test]
+fn leds_are_printed() {
+    let dir = tempfile::tempdir().unwrap();
+    let device = dir.path().join("hidg0");
+    std::fs::write(&device, [0x01, 0x03]).unwrap();
+
+    let output = kbsim(&["leds", "--hid-file", device.to_str().unwrap()], b"");
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "num_lock caps_lock\n"
+    );
+}
+
+#[test]
+fn regular_files_are_written_with_a_warning() {
+    let dir = tempfile::tempdir().unwrap();
+    let notes = dir.path().join("notes.txt");
+    std::fs::write(&notes, b"").unwrap();
+    let args = |strict| {
+        let mut args = vec!["--hid-file", notes.to_str().unwrap(), "--layout", "us"];
+        if strict {
+            args.push("--strict");
+        }
+        args.push("hi");
+        args
+    };
+
+    let output = kbsim(&args(false), b"");
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("is a regular file"), "{}", stderr);
+    assert_eq!(std::fs::read(&notes).unwrap().len(), 2 * 2 * 8);
+
+    let output = kbsim(&args(true), b"");
+    assert!(!output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn uppercase_runs_are_typed_with_caps_lock() {
+    let output = kbsim(
+        &[
+            "--dry-run",
+            "--layout",
+            "us",
+            "--uppercase-strategy",
+            "capslock",
+            "--capslock-threshold",
+            "2",
+            "OK",
+        ],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "00 00 39 00 00 00 00 00\n\
+         00 00 00 00 00 00 00 00\n\
+         00 00 12 00 00 00 00 00 # 'O'\n\
+         00 00 00 00 00 00 00 00 # 'O'\n\
+         00 00 0e 00 00 00 00 00 # 'K'\n\
+         00 00 00 00 00 00 00 00 # 'K'\n\
+         00 00 39 00 00 00 00 00\n\
+         00 00 00 00 00 00 00 00\n"
+    );
+}
+
+#[test]
+fn backslash_escapes_are_interpreted() {
+    let output = kbsim(&["--dry-run", "--layout", "us", "-e", r"a\tb"], b"");
+
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("00 00 2b 00 00 00 00 00 # '\\t'"),
+        "{}",
+        stdout
+    );
+
+    let output = kbsim(&["--dry-run", "--layout", "us", "-e", r"a\qb"], b"");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("at byte 1"), "{}", stderr);
+}
+
+#[test]
+fn braces_from_backslash_escapes_are_typed_as_braces() {
+    let output = kbsim(
+        &[
+            "--dry-run",
+            "--layout",
+            "us",
+            "--backslash-escapes",
+            r"\u{7B}ENTER\u{7D}",
+        ],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("# '{'"), "{}", stdout);
+    assert!(stdout.contains("# '}'"), "{}", stdout);
+    assert!(!stdout.contains("ENTER"), "{}", stdout);
+}
+
+#[test]
+fn held_modifiers_are_in_every_packet() {
+    let output = kbsim(&["--dry-run", "--layout", "us", "--hold", "ctrl", "A"], b"");
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "01 00 00 00 00 00 00 00\n\
+         03 00 04 00 00 00 00 00 # 'A'\n\
+         01 00 00 00 00 00 00 00 # 'A'\n\
+         00 00 00 00 00 00 00 00\n"
+    );
+}
+
+#[test]
+fn durations_can_have_units() {
+    let output = kbsim(
+        &[
+            "--dry-run",
+            "--layout",
+            "us",
+            "-c",
+            "250us",
+            "-d",
+            "0.01s",
+            "a",
+        ],
+        b"",
+    );
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = kbsim(
+        &["--dry-run", "--layout", "us", "-c", "5 parsecs", "a"],
+        b"",
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Invalid duration \"5 parsecs\""));
+}
+
+#[test]
+fn timing_flags_take_durations() {
+    let output = kbsim(
+        &[
+            "--dry-run",
+            "--layout",
+            "us",
+            "-d",
+            "0.01",
+            "--repeat",
+            "2",
+            "--repeat-interval",
+            "0.5ms",
+            "--write-timeout",
+            "1m30s",
+            "a",
+        ],
+        b"",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 4);
+
+    let output = kbsim(
+        &[
+            "--dry-run",
+            "--layout",
+            "us",
+            "--repeat-interval",
+            "soon",
+            "a",
+        ],
+        b"",
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("expected a number of milliseconds such as 1.5, or a duration with units such as 250ms, 2s or 1m30s"));
+}
+
+#[test]
+fn jitter_is_seeded() {
+    let args = [
+        "--dry-run",
+        "--layout",
+        "us",
+        "-c",
+        "1",
+        "--jitter",
+        "2",
+        "--seed",
+        "1",
+        "ab",
+    ];
+    let output = kbsim(&args, b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        output.stdout,
+        kbsim(&["--dry-run", "--layout", "us", "ab"], b"").stdout
+    );
+
+    let output = kbsim(&["--dry-run", "--layout", "us", "--seed", "1", "ab"], b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("only used with --humanize, --jitter or --typo-rate"));
+}
+
+#[test]
+fn keys_can_be_held() {
+    let started = std::time::Instant::now();
+    let output = kbsim(
+        &[
+            "--dry-run",
+            "--layout",
+            "us",
+            "--hold-key",
+            "shift+down",
+            "--for",
+            "100ms",
+        ],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(started.elapsed() >= std::time::Duration::from_millis(100));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "02 00 51 00 00 00 00 00\n\
+         00 00 00 00 00 00 00 00\n"
+    );
+}
+
+#[test]
+fn unsupported_characters_fall_back_to_their_code_point() {
+    let output = kbsim(
+        &[
+            "--dry-run",
+            "--layout",
+            "us",
+            "--unicode-fallback",
+            "ibus",
+            "hi 👋🎉",
+        ],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "00 00 0b 00 00 00 00 00 # 'h'\n\
+         00 00 00 00 00 00 00 00 # 'h'\n\
+         00 00 0c 00 00 00 00 00 # 'i'\n\
+         00 00 00 00 00 00 00 00 # 'i'\n\
+         00 00 2c 00 00 00 00 00 # ' '\n\
+         00 00 00 00 00 00 00 00 # ' '\n\
+         03 00 18 00 00 00 00 00 # '👋'\n\
+         00 00 00 00 00 00 00 00 # '👋'\n\
+         00 00 1e 00 00 00 00 00 # '👋'\n\
+         00 00 00 00 00 00 00 00 # '👋'\n\
+         00 00 09 00 00 00 00 00 # '👋'\n\
+         00 00 00 00 00 00 00 00 # '👋'\n\
+         00 00 21 00 00 00 00 00 # '👋'\n\
+         00 00 00 00 00 00 00 00 # '👋'\n\
+         00 00 21 00 00 00 00 00 # '👋'\n\
+         00 00 00 00 00 00 00 00 # '👋'\n\
+         00 00 05 00 00 00 00 00 # '👋'\n\
+         00 00 00 00 00 00 00 00 # '👋'\n\
+         00 00 28 00 00 00 00 00 # '👋'\n\
+         00 00 00 00 00 00 00 00 # '👋'\n\
+         03 00 18 00 00 00 00 00 # '🎉'\n\
+         00 00 00 00 00 00 00 00 # '🎉'\n\
+         00 00 1e 00 00 00 00 00 # '🎉'\n\
+         00 00 00 00 00 00 00 00 # '🎉'\n\
+         00 00 09 00 00 00 00 00 # '🎉'\n\
+         00 00 00 00 00 00 00 00 # '🎉'\n\
+         00 00 20 00 00 00 00 00 # '🎉'\n\
+         00 00 00 00 00 00 00 00 # '🎉'\n\
+         00 00 25 00 00 00 00 00 # '🎉'\n\
+         00 00 00 00 00 00 00 00 # '🎉'\n\
+         00 00 26 00 00 00 00 00 # '🎉'\n\
+         00 00 00 00 00 00 00 00 # '🎉'\n\
+         00 00 28 00 00 00 00 00 # '🎉'\n\
+         00 00 00 00 00 00 00 00 # '🎉'\n"
+    );
+}
+
+#[test]
+fn words_and_sentences_pause() {
+    let started = std::time::Instant::now();
+    let args = [
+        "--dry-run",
+        "--layout",
+        "us",
+        "--word-pause",
+        "100ms",
+        "--sentence-pause",
+        "0.2s",
+    ];
+    let output = kbsim(&[&args[..], &["Hi. Ok"]].concat(), b"");
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(started.elapsed() >= std::time::Duration::from_millis(300));
+    assert_eq!(
+        output.stdout,
+        kbsim(&["--dry-run", "--layout", "us", "Hi. Ok"], b"").stdout
+    );
+}
+
+#[test]
+fn num_lock_is_turned_on_for_alt_numpad() {
+    let dir = tempfile::tempdir().unwrap();
+    let device = dir.path().join("hidg0");
+    // The host's LED report, with Num Lock off
+    std::fs::write(&device, [0x00]).unwrap();
+
+    let output = kbsim(
+        &[
+            "--hid-file",
+            device.to_str().unwrap(),
+            "--layout",
+            "us",
+            "--unicode-fallback",
+            "alt-numpad",
+            "é",
+        ],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    let packets = std::fs::read(&device).unwrap();
+    let keys: Vec<u8> = packets.chunks(8).map(|packet| packet[2]).collect();
+    // Num Lock on, Alt+0233, and Num Lock off again
+    assert_eq!(
+        keys,
+        vec![0x53, 0, 0, 0x62, 0, 0x5a, 0, 0x5b, 0, 0x5b, 0, 0, 0x53, 0]
+    );
+    assert!(packets
+        .chunks(8)
+        .skip(2)
+        .take(9)
+        .all(|packet| packet[0] == 0x04));
+}
+
+#[test]
+fn typos_are_corrected() {
+    let args = [
+        "--dry-run",
+        "--layout",
+        "us",
+        "--typo-rate",
+        "1",
+        "--seed",
+        "4",
+        "hi",
+    ];
+    let output = String::from_utf8(kbsim(&args, b"").stdout).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+
+    // Each character is a typo, Backspace and then the character
+    assert_eq!(lines.len(), 12);
+    assert_eq!(lines[2], "00 00 2a 00 00 00 00 00");
+    assert_eq!(lines[4], "00 00 0b 00 00 00 00 00 # 'h'");
+    assert_eq!(lines[8], "00 00 2a 00 00 00 00 00");
+    assert_eq!(lines[10], "00 00 0c 00 00 00 00 00 # 'i'");
+    assert_eq!(output, String::from_utf8(kbsim(&args, b"").stdout).unwrap());
+}
+
+#[test]
+fn typos_wait_for_the_correction_pause() {
+    let estimate = |extra: &[&str]| {
+        let mut args = vec![
+            "--dry-run",
+            "--layout",
+            "us",
+            "--cooldown",
+            "10",
+            "--typo-rate",
+            "1",
+            "--seed",
+            "4",
+            "--estimate",
+        ];
+        args.extend_from_slice(extra);
+        args.push("hi");
+        let output = kbsim(&args, b"");
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // 12 packets after the 10ms cooldown, and a second before each of the two Backspaces
+    assert!(estimate(&[]).contains("Estimated duration: 120.00ms\n"));
+    let stdout = estimate(&["--correction-pause", "1s"]);
+    assert!(stdout.contains("Estimated duration: 2.12s\n"), "{}", stdout);
+
+    let output = kbsim(
+        &["--dry-run", "-l", "us", "--correction-pause", "1s", "hi"],
+        b"",
+    );
+    assert!(!output.status.success());
+}
+
+#[test]
+fn unmappable_characters_follow_the_policy() {
+    let typed = |text: &str| kbsim(&["--dry-run", "--layout", "us", text], b"").stdout;
+    let policy = |policy: &str| {
+        kbsim(
+            &[
+                "--dry-run",
+                "--layout",
+                "us",
+                "--on-unmappable",
+                policy,
+                "a€b",
+            ],
+            b"",
+        )
+    };
+
+    let output = policy("replace=?");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(output.stdout, typed("a?b"));
+
+    for skipped in &["skip", "warn"] {
+        let output = policy(skipped);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(!output.status.success());
+        assert_eq!(output.stdout, typed("ab"));
+        assert!(
+            stderr.contains("1 character(s) could not be mapped"),
+            "{}",
+            stderr
+        );
+        assert_eq!(
+            stderr.contains("'€' at byte 1"),
+            *skipped == "warn",
+            "{}",
+            stderr
+        );
+    }
+
+    let output = kbsim(&["--layout", "us", "--on-unmappable", "error", "a€b"], b"");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let output = policy("replace=é");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("can't type the replacement 'é' either"));
+}
+
+#[test]
+fn dry_runs_can_print_json() {
+    let output = kbsim(
+        &["--dry-run", "--format", "json", "--layout", "us", "Hi"],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    let packets: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let packets = packets.as_array().unwrap();
+    assert_eq!(packets.len(), 4);
+    assert_eq!(
+        packets[0],
+        serde_json::json!({ "bytes": [2, 0, 11, 0, 0, 0, 0, 0], "char": "H", "modifiers": 2, "keys": [11] })
+    );
+    assert_eq!(packets[3]["char"], "i");
+    assert_eq!(packets[3]["keys"], serde_json::json!([]));
+
+    let output = kbsim(&["--format", "json", "--layout", "us", "Hi"], b"");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn text_is_normalized_before_it_is_checked() {
+    let dir = tempfile::tempdir().unwrap();
+    let layout = dir.path().join("accents.toml");
+    std::fs::write(
+        &layout,
+        "name = \"ACCENTS\"\ninherits = \"LAYOUT_US_ENGLISH\"\n\n[[keys]]\nchar = \"é\"\nkey = 0x1f\n",
+    )
+    .unwrap();
+    let layout = layout.to_str().unwrap();
+    let typed = |args: &[&str], text: &str| {
+        let mut all = vec!["--layout-file", layout, "--dry-run", "--layout", "ACCENTS"];
+        all.extend(args);
+        all.push(text);
+        kbsim(&all, b"")
+    };
+
+    let composed = typed(&[], "café");
+    assert!(composed.status.success(), "{:?}", composed);
+    let decomposed = typed(&["--normalize"], "cafe\u{301}");
+    assert!(decomposed.status.success(), "{:?}", decomposed);
+    assert_eq!(decomposed.stdout, composed.stdout);
+    assert!(!typed(&[], "cafe\u{301}").status.success());
+
+    let quoted = kbsim(
+        &["--dry-run", "--layout", "us", "--ascii-fold"],
+        "\u{201c}It\u{2019}s\u{201d}".as_bytes(),
+    );
+    assert!(quoted.status.success(), "{:?}", quoted);
+    assert_eq!(
+        quoted.stdout,
+        kbsim(&["--dry-run", "--layout", "us", "\"It's\""], b"").stdout
+    );
+}
+
+#[test]
+fn dry_runs_can_print_a_c_array() {
+    let output = kbsim(
+        &["--dry-run", "--format", "c-array", "--layout", "us", "Hi"],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "#include <stdint.h>\n\
+         \n\
+         #define PACKETS_LEN 32\n\
+         static const uint8_t packets[PACKETS_LEN] = {\n    \
+         0x02, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, /* 'H' */\n    \
+         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, /* 'H' */\n    \
+         0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, /* 'i' */\n    \
+         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, /* 'i' */\n\
+         };\n"
+    );
+}
+
+#[test]
+fn mixed_line_endings_are_typed_the_same() {
+    let typed = |args: &[&str], input: &[u8]| {
+        let mut all = vec!["--dry-run", "--layout", "us"];
+        all.extend(args);
+        let output = kbsim(&all, input);
+        assert!(output.status.success(), "{:?}", output);
+        output.stdout
+    };
+    let expected = typed(&[], b"a\nb\nc\n");
+
+    assert_eq!(typed(&[], b"a\r\nb\rc\n"), expected);
+    assert_eq!(typed(&["a\r\nb\rc", "--newline"], b""), expected);
+    let kept = String::from_utf8(typed(&["--line-endings", "keep"], b"a\r\nb")).unwrap();
+    assert_eq!(kept.matches("# '\\r'").count(), 2);
+    assert_eq!(kept.matches("# '\\n'").count(), 2);
+    assert_eq!(
+        typed(&["--line-endings", "crlf", "--newline", "a"], b""),
+        typed(&["--line-endings", "keep", "a\r\n"], b"")
+    );
+}
+
+#[test]
+fn line_mode_presses_enter_between_lines() {
+    let typed = |args: &[&str], input: &[u8]| {
+        let mut all = vec!["--dry-run", "--layout", "us"];
+        all.extend(args);
+        let output = kbsim(&all, input);
+        assert!(output.status.success(), "{:?}", output);
+        output.stdout
+    };
+    let expected = typed(&["a\nb"], b"");
+
+    assert_eq!(typed(&["-L", "a\r\nb\r\n"], b""), expected);
+    assert_eq!(typed(&["--line-mode"], b"a\nb\n"), expected);
+    assert_eq!(
+        typed(&["-L", "--line-endings", "crlf", "--newline", "a\nb"], b""),
+        typed(&["a\nb\n"], b"")
+    );
+}
+
+#[test]
+fn quiet_leaves_only_errors_on_stderr() {
+    let args = ["--dry-run", "--layout", "us", "--repeat", "2", "a"];
+    let output = kbsim(&args, b"");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Typed STRING 2 time(s)"));
+
+    let output = kbsim(&[&["-q"][..], &args].concat(), b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stderr.is_empty(), "{:?}", output);
+
+    let output = kbsim(&["-q", "--layout", "klingon", "a"], b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("No layout defined for klingon"));
+}
+
+#[test]
+fn verbose_logs_only_go_to_stderr() {
+    let args = ["--dry-run", "--format", "json", "--layout", "us", "a"];
+    let quiet = kbsim(&args, b"");
+
+    let output = kbsim(&[&["-vvv"][..], &args].concat(), b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(output.stdout, quiet.stdout);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Using layout LAYOUT_US_ENGLISH"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("Typed STRING in 2 packets"), "{}", stderr);
+    assert!(
+        stderr.contains("Packet 1: 00 00 04 00 00 00 00 00"),
+        "{}",
+        stderr
+    );
+
+    let output = kbsim(&[&["-v"][..], &args].concat(), b"");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Using layout"), "{}", stderr);
+    assert!(!stderr.contains("Packet 1"), "{}", stderr);
+}
+
+#[test]
+fn raw_packets_can_be_read_from_a_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("packets.hex");
+    std::fs::write(
+        &file,
+        "# Shift+a\n02 00 04 00 00 00 00 00\n\n0000000000000000 # release\n",
+    )
+    .unwrap();
+
+    let output = kbsim(&["raw", "--dry-run", "--file", file.to_str().unwrap()], b"");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "02 00 04 00 00 00 00 00\n00 00 00 00 00 00 00 00\n"
+    );
+
+    let output = kbsim(&["raw", "--dry-run", "--raw-file", "-"], b"00 00 04 00\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Line 1 of -: expected a packet of 8 bytes, got 4 byte(s)"));
+}
+
+#[test]
+fn encoded_input_is_decoded_before_typing() {
+    let typed = |args: &[&str], input: &[u8]| {
+        let mut all = vec!["--dry-run", "--layout", "us"];
+        all.extend(args);
+        let output = kbsim(&all, input);
+        assert!(output.status.success(), "{:?}", output);
+        output.stdout
+    };
+    // Typed as it is, without escapes
+    let expected = typed(&[], b"{a}'b'\n");
+
+    assert_eq!(
+        typed(&["--decode", "base64"], b"e2F9J2In\nCg==\n"),
+        expected
+    );
+    assert_eq!(typed(&["--decode", "hex", "7b617d2762270a"], b""), expected);
+
+    let output = kbsim(&["--dry-run", "--decode", "hex", "ff"], b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("not valid UTF-8"));
+}
+
+#[test]
+fn progress_is_only_drawn_on_a_terminal() {
+    let output = kbsim(&["--dry-run", "--layout", "us", "--progress", "hi"], b"");
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stderr.is_empty(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 4);
+}
+
+#[test]
+fn inspect_flags_characters_the_layout_cannot_type() {
+    let output = kbsim(&["inspect", "--layout", "us", "A\u{e9}"], b"");
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "CHARACTER MODIFIERS  KEY      REPORT\n\
+         'A'       02         04       02 00 04 00 00 00 00 00\n\
+         '\u{e9}'       unmappable\n"
+    );
+
+    let output = kbsim(&["inspect", "--layout", "us", "--format", "json"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    let inspected: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let inspected = inspected.as_array().unwrap();
+    assert_eq!(inspected.len(), 95);
+    assert_eq!(
+        inspected[0]["presses"][0]["report"],
+        "00 00 2c 00 00 00 00 00"
+    );
+    assert!(inspected.iter().all(|c| c["mappable"] == true));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn the_clipboard_of_a_wayland_session_is_read_with_wl_paste() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let wl_paste = dir.path().join("wl-paste");
+    std::fs::write(
+        &wl_paste,
+        "#!/bin/sh\ncase \"$*\" in\n  *--primary*) printf selected ;;\n  *) printf copied ;;\nesac\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&wl_paste, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        dir.path().display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let clipboard = |wayland: bool, args: &[&str]| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_kbsim"));
+        command
+            .args(["--dry-run", "--layout", "us", "--from-clipboard"])
+            .args(args)
+            .env("XDG_CONFIG_HOME", dir.path())
+            .env("PATH", &path)
+            .env_remove("DISPLAY")
+            .env_remove("WAYLAND_DISPLAY");
+        if wayland {
+            command.env("WAYLAND_DISPLAY", "wayland-0");
+        }
+        command.output().unwrap()
+    };
+
+    let copied = clipboard(true, &[]);
+    assert!(copied.status.success(), "{:?}", copied);
+    assert_eq!(
+        copied.stdout,
+        kbsim(&["--dry-run", "--layout", "us", "copied"], b"").stdout
+    );
+    let selected = clipboard(true, &["--clipboard-selection", "primary"]);
+    assert_eq!(
+        selected.stdout,
+        kbsim(&["--dry-run", "--layout", "us", "selected"], b"").stdout
+    );
+
+    let headless = clipboard(false, &[]);
+    assert!(!headless.status.success());
+    let stderr = String::from_utf8(headless.stderr).unwrap();
+    assert!(
+        stderr.contains("neither WAYLAND_DISPLAY nor DISPLAY"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn confirm_shows_the_first_line_and_asks() {
+    use std::os::unix::process::CommandExt;
+
+    // Without a controlling terminal the question is asked on stderr and answered on stdin
+    let confirm = |answer: &[u8]| {
+        let config = tempfile::tempdir().unwrap();
+        let mut command = Command::new(env!("CARGO_BIN_EXE_kbsim"));
+        command
+            .args(["--dry-run", "--layout", "us", "--confirm", "Hi\nthere"])
+            .env("XDG_CONFIG_HOME", config.path())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        unsafe {
+            command.pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            });
+        }
+        let mut child = command.spawn().unwrap();
+        child.stdin.take().unwrap().write_all(answer).unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let refused = confirm(b"n\n");
+    assert!(!refused.status.success());
+    assert!(refused.stdout.is_empty());
+    let stderr = String::from_utf8(refused.stderr).unwrap();
+    assert!(stderr.contains("About to type 8 characters"), "{}", stderr);
+    assert!(stderr.contains("starting \"Hi\"…"), "{}", stderr);
+
+    let confirmed = confirm(b"y\n");
+    assert!(confirmed.status.success(), "{:?}", confirmed);
+    assert_eq!(
+        confirmed.stdout,
+        kbsim(&["--dry-run", "--layout", "us", "Hi\nthere"], b"").stdout
+    );
+
+    let output = kbsim(&["--confirm", "--yes", "Hi"], b"");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn reset_modifiers_releases_everything_first() {
+    let plain = kbsim(&["--dry-run", "--layout", "us", "Hi"], b"");
+    let reset = kbsim(
+        &["--dry-run", "--layout", "us", "--reset-modifiers", "Hi"],
+        b"",
+    );
+
+    assert!(reset.status.success(), "{:?}", reset);
+    let mut expected = b"00 00 00 00 00 00 00 00\n".to_vec();
+    expected.extend(&plain.stdout);
+    assert_eq!(reset.stdout, expected);
+}
+
+#[test]
+fn dump_writes_a_template_for_layout_files() {
+    let output = kbsim(&["dump", "--layout", "de", "--template"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    let template = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        template.starts_with("name = \"CUSTOM_GERMAN\"\n"),
+        "{}",
+        template
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let layout = dir.path().join("german.toml");
+    std::fs::write(&layout, template).unwrap();
+    let typed = kbsim(
+        &[
+            "--layout-file",
+            layout.to_str().unwrap(),
+            "--dry-run",
+            "Grüße @ 3µ",
+        ],
+        b"",
+    );
+    assert!(typed.status.success(), "{:?}", typed);
+    assert_eq!(
+        typed.stdout,
+        kbsim(&["--dry-run", "--layout", "de", "Grüße @ 3µ"], b"").stdout
+    );
+
+    let output = kbsim(
+        &["dump", "--layout", "de", "--template", "--format", "json"],
+        b"",
+    );
+    let template: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(template["name"], "CUSTOM_GERMAN");
+    assert!(template["keys"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!({ "char": "@", "key": 20, "modifiers": ["altgr"] })));
+}
+
+#[test]
+fn char_shows_every_packet_of_a_character() {
+    let output = kbsim(&["char", "--layout", "us", "A"], b"");
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "CHARACTER  'A' (U+0041)\n\
+         PRESSES    02 04\n\
+         PACKETS\n  \
+         02 00 04 00 00 00 00 00  press\n  \
+         00 00 00 00 00 00 00 00  release\n"
+    );
+
+    let output = kbsim(&["char", "--layout", "us", "\u{20ac}"], b"");
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("can't type '\u{20ac}'"));
+}
+
+#[test]
+fn log_file_records_every_packet() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("packets.log");
+    let log_arg = log.to_str().unwrap();
+
+    let output = kbsim(&["-D", "--log-file", log_arg, "A"], b"");
+    assert!(output.status.success(), "{:?}", output);
+    let output = kbsim(
+        &[
+            "-D",
+            "--stdin",
+            "--no-echo",
+            "--log-file",
+            log_arg,
+            "--log-format",
+            "jsonl",
+        ],
+        b"b",
+    );
+    assert!(output.status.success(), "{:?}", output);
+
+    let logged = std::fs::read_to_string(&log).unwrap();
+    let lines: Vec<&str> = logged.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", logged);
+    assert!(
+        lines[0].ends_with(" 02 00 04 00 00 00 00 00 'A' ok"),
+        "{}",
+        lines[0]
+    );
+    assert!(
+        lines[1].ends_with(" 00 00 00 00 00 00 00 00 'A' ok"),
+        "{}",
+        lines[1]
+    );
+    // The log is appended to, and the characters typed without echo are left out
+    let packet: serde_json::Value = serde_json::from_str(lines[2]).unwrap();
+    assert_eq!(packet["bytes"], "00 00 05 00 00 00 00 00");
+    assert_eq!(packet["char"], serde_json::Value::Null);
+    assert_eq!(packet["result"], "ok");
+}
+
+#[test]
+fn estimate_prints_a_summary_without_typing() {
+    let dir = tempfile::tempdir().unwrap();
+    let device = dir.path().join("hidg0");
+    std::fs::File::create(&device).unwrap();
+
+    let output = kbsim(
+        &[
+            "--hid-file",
+            device.to_str().unwrap(),
+            "--layout",
+            "us",
+            "--cooldown",
+            "10",
+            "--delay",
+            "1",
+            "--estimate",
+            "hello",
+        ],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Characters:         5\n\
+         Packets:            10\n\
+         Estimated duration: 1.10s\n\
+         Skipped characters: 0\n"
+    );
+    assert!(std::fs::read(&device).unwrap().is_empty());
+
+    // 6 packets pressing keys held for 100ms, 6 releases after the 10ms cooldown, and the pauses
+    // after the two spaces and the full stop
+    let output = kbsim(
+        &[
+            "--hid-file",
+            device.to_str().unwrap(),
+            "--layout",
+            "us",
+            "--cooldown",
+            "10",
+            "--word-pause",
+            "1s",
+            "--sentence-pause",
+            "2s",
+            "--press-duration",
+            "100",
+            "--estimate",
+            "a b. c",
+        ],
+        b"",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Estimated duration: 4.66s\n"), "{}", stdout);
+}
+
+#[test]
+fn optimize_modifiers_keeps_shift_held_between_capitals() {
+    let output = kbsim(
+        &["--dry-run", "--layout", "us", "--optimize-modifiers", "HIi"],
+        b"",
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "02 00 0b 00 00 00 00 00 # 'H'\n\
+         02 00 00 00 00 00 00 00 # 'H'\n\
+         02 00 0c 00 00 00 00 00 # 'I'\n\
+         00 00 00 00 00 00 00 00 # 'I'\n\
+         00 00 0c 00 00 00 00 00 # 'i'\n\
+         00 00 00 00 00 00 00 00 # 'i'\n"
+    );
+}
+
+#[test]
+fn stats_summarise_what_was_written() {
+    let output = kbsim(
+        &[
+            "--dry-run",
+            "--layout",
+            "us",
+            "--skip-unsupported",
+            "--stats",
+        ],
+        b"hi\xc3\xa9",
+    );
+
+    // Skipping a character still fails, after the summary
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    for line in &[
+        "Characters converted: 2",
+        "Packets generated:    4",
+        "Packets written:      4",
+        "Bytes written:        32",
+        "Retries:              0",
+        "Skipped characters:   1",
+    ] {
+        assert!(stderr.lines().any(|l| l == *line), "{}", stderr);
+    }
+
+    let output = kbsim(&["--dry-run", "--layout", "us", "hi"], b"");
+    assert!(output.stderr.is_empty(), "{:?}", output);
+}
+
+#[test]
+fn ibus_fallback_can_end_with_space() {
+    let typed = |extra: &[&str]| {
+        let mut args = vec!["--dry-run", "--layout", "us", "--unicode-fallback", "ibus"];
+        args.extend_from_slice(extra);
+        args.push("a\u{2713}");
+        let output = kbsim(&args, b"");
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // Ctrl+Shift+U 2713 ends with Enter, unless --ibus-space is given
+    let enter = typed(&[]);
+    assert_eq!(enter.lines().count(), 2 + 12);
+    assert!(enter.contains("00 00 28 00 00 00 00 00 # '\u{2713}'"));
+    let space = typed(&["--ibus-space"]);
+    assert!(space.contains("00 00 2c 00 00 00 00 00 # '\u{2713}'"));
+    assert!(!space.contains("00 00 28 00 00 00 00 00"));
+
+    let output = kbsim(&["--dry-run", "--layout", "us", "--ibus-space", "a"], b"");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn base64_packets_round_trip() {
+    let output = kbsim(
+        &["--dry-run", "--format", "base64", "--layout", "us", "Hi"],
+        b"",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let encoded = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(encoded, "AgALAAAAAAAAAAAAAAAAAAAADAAAAAAAAAAAAAAAAAA=\n");
+
+    let sent = kbsim(
+        &["raw", "--dry-run", "--packets-base64", encoded.trim()],
+        b"",
+    );
+    assert!(sent.status.success(), "{:?}", sent);
+    assert_eq!(
+        String::from_utf8(sent.stdout).unwrap(),
+        "02 00 0b 00 00 00 00 00\n\
+         00 00 00 00 00 00 00 00\n\
+         00 00 0c 00 00 00 00 00\n\
+         00 00 00 00 00 00 00 00\n"
+    );
+
+    for (invalid, error) in &[
+        ("AgAL*AAA", "Invalid base64 '*' at byte 4"),
+        (
+            "AgALAA==",
+            "Expected a whole number of 8 byte packets, got 4 byte(s)",
+        ),
+    ] {
+        let output = kbsim(&["raw", "--dry-run", "--packets-base64", invalid], b"");
+        assert!(!output.status.success());
+        assert!(String::from_utf8(output.stderr).unwrap().contains(error));
+    }
+}
+
+#[test]
+fn fast_typing_presses_characters_together() {
+    let output = kbsim(&["--dry-run", "--layout", "us", "--fast", "Hello"], b"");
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "02 00 0b 00 00 00 00 00 # 'H'\n\
+         00 00 00 00 00 00 00 00 # 'H'\n\
+         00 00 08 0f 00 00 00 00\n\
+         00 00 00 00 00 00 00 00\n\
+         00 00 0f 12 00 00 00 00\n\
+         00 00 00 00 00 00 00 00\n"
+    );
+
+    let output = kbsim(
+        &[
+            "--dry-run",
+            "--layout",
+            "us",
+            "--fast",
+            "--jitter",
+            "10ms",
+            "a",
+        ],
+        b"",
+    );
+    assert!(!output.status.success());
+
+    let output = kbsim(
+        &[
+            "--dry-run",
+            "--layout",
+            "us",
+            "--fast",
+            "--typo-rate",
+            "0.5",
+            "a",
+        ],
+        b"",
+    );
+    assert!(!output.status.success());
+}